@@ -0,0 +1,185 @@
+use super::{AdminCommand, AdminHandle};
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+pub const DEFAULT_CONTROL_PATH: &str = "/run/ringlog.ctl";
+
+/// How long a command waits for the main loop, and a client for its reply.
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Unix socket front-end for an `AdminHandle`, for hosts where no HTTP port
+/// may be opened. Each connection sends one command line and gets one reply
+/// line, `ok <message>` or `error <message>`:
+///
+/// - `stats`: the latest `Status` as JSON
+/// - anything `AdminCommand` parses, e.g. `flush` or `set-log-level debug`
+///
+/// The socket is created with mode 0600 and removed when dropped.
+pub struct ControlSocket {
+    path: PathBuf,
+    /// False for listeners handed over by a supervisor, which owns the file.
+    remove_on_drop: bool,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ControlSocket {
+    pub fn bind<P: AsRef<Path>>(path: P, admin: AdminHandle) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if path.exists() {
+            if UnixStream::connect(&path).is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("{} is in use by a running service", path.display()),
+                ));
+            }
+            // Left behind by a service that did not shut down cleanly.
+            fs::remove_file(&path)?;
+        }
+
+        let listener = bind_private(&path)?;
+        Self::spawn(listener, path, true, admin)
+    }
+
+    /// Serves an already bound listener, e.g. one passed in by systemd socket
+    /// activation. The socket file is left in place when dropped.
+    pub fn from_listener(listener: UnixListener, admin: AdminHandle) -> io::Result<Self> {
+        let path = listener
+            .local_addr()?
+            .as_pathname()
+            .map(Path::to_path_buf)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Control socket listener is not bound to a path",
+                )
+            })?;
+        Self::spawn(listener, path, false, admin)
+    }
+
+    fn spawn(
+        listener: UnixListener,
+        path: PathBuf,
+        remove_on_drop: bool,
+        admin: AdminHandle,
+    ) -> io::Result<Self> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stop = stop.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if stop.load(Ordering::Acquire) {
+                        break;
+                    }
+                    // Each on its own thread, so an idle client cannot hold
+                    // up the others for its read timeout.
+                    if let Ok(stream) = stream {
+                        let admin = admin.clone();
+                        thread::spawn(move || handle(stream, &admin));
+                    }
+                }
+            })
+        };
+
+        Ok(Self {
+            path,
+            remove_on_drop,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        // Wakes the accept loop so it sees the flag.
+        let _ = UnixStream::connect(&self.path);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        if self.remove_on_drop {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Binds the socket in a fresh 0700 directory next to `path`, makes it 0600
+/// and only then renames it into place, so no client can connect while it
+/// still has the umask's mode.
+fn bind_private(path: &Path) -> io::Result<UnixListener> {
+    let name = path.file_name().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not a socket path", path.display()),
+        )
+    })?;
+    let private = path.with_file_name(format!(
+        ".{}.{}.tmp",
+        name.to_string_lossy(),
+        std::process::id()
+    ));
+    fs::DirBuilder::new().mode(0o700).create(&private)?;
+    let staged = private.join(name);
+    let result = UnixListener::bind(&staged).and_then(|listener| {
+        fs::set_permissions(&staged, fs::Permissions::from_mode(0o600))?;
+        fs::rename(&staged, path)?;
+        Ok(listener)
+    });
+    let _ = fs::remove_file(&staged);
+    let _ = fs::remove_dir(&private);
+    result
+}
+
+fn handle(stream: UnixStream, admin: &AdminHandle) -> io::Result<()> {
+    stream.set_read_timeout(Some(COMMAND_TIMEOUT))?;
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+
+    let result = match line.trim() {
+        "stats" => Ok(admin.status().to_json()),
+        line => line
+            .parse::<AdminCommand>()
+            .and_then(|command| admin.execute(command, COMMAND_TIMEOUT)),
+    };
+
+    let mut stream = stream;
+    match result {
+        Ok(message) => writeln!(stream, "ok {}", message),
+        Err(message) => writeln!(stream, "error {}", message),
+    }
+}
+
+/// Sends one command line to the control socket at `path`. The outer error
+/// is a connection problem; the inner one is the service's refusal.
+pub fn send_command<P: AsRef<Path>>(path: P, command: &str) -> io::Result<Result<String, String>> {
+    let mut stream = UnixStream::connect(path)?;
+    stream.set_read_timeout(Some(COMMAND_TIMEOUT + Duration::from_secs(1)))?;
+    writeln!(stream, "{}", command)?;
+
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply)?;
+    let reply = reply.trim_end();
+
+    if let Some(message) = reply.strip_prefix("ok") {
+        Ok(Ok(message.trim_start().to_string()))
+    } else if let Some(message) = reply.strip_prefix("error") {
+        Ok(Err(message.trim_start().to_string()))
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Unexpected reply from control socket: {:?}", reply),
+        ))
+    }
+}
@@ -0,0 +1,101 @@
+use super::{AdminCommand, AdminHandle};
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+/// How long a `/flush` or `/rotate` request waits for the main loop.
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// HTTP front-end for an `AdminHandle`:
+///
+/// - `GET /status`: the latest `Status` as JSON
+/// - `POST /flush`, `POST /rotate`: runs the command and returns its reply
+///
+/// The server runs on its own thread until dropped.
+pub struct HttpAdmin {
+    server: Arc<Server>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl HttpAdmin {
+    pub fn start<A: ToSocketAddrs>(addr: A, admin: AdminHandle) -> io::Result<Self> {
+        let server = Server::http(addr).map_err(io::Error::other)?;
+        let server = Arc::new(server);
+        let thread = {
+            let server = server.clone();
+            thread::spawn(move || {
+                for request in server.incoming_requests() {
+                    handle(request, &admin);
+                }
+            })
+        };
+        Ok(Self {
+            server,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.server.server_addr().to_ip()
+    }
+}
+
+impl Drop for HttpAdmin {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn handle(request: Request, admin: &AdminHandle) {
+    let command = match request.url() {
+        "/status" => {
+            let response = match request.method() {
+                Method::Get => json(200, admin.status().to_json()),
+                _ => text(405, "use GET".to_string()),
+            };
+            let _ = request.respond(response);
+            return;
+        }
+        "/flush" => AdminCommand::Flush,
+        "/rotate" => AdminCommand::Rotate,
+        _ => {
+            let _ = request.respond(text(404, "not found".to_string()));
+            return;
+        }
+    };
+
+    let response = if *request.method() != Method::Post {
+        text(405, "use POST".to_string())
+    } else {
+        match admin.execute(command, COMMAND_TIMEOUT) {
+            Ok(message) => text(200, message),
+            Err(message) => text(500, message),
+        }
+    };
+    let _ = request.respond(response);
+}
+
+fn json(status: u16, body: String) -> Response<io::Cursor<Vec<u8>>> {
+    with_content_type(status, body, "application/json")
+}
+
+fn text(status: u16, body: String) -> Response<io::Cursor<Vec<u8>>> {
+    with_content_type(status, body, "text/plain; charset=utf-8")
+}
+
+fn with_content_type(
+    status: u16,
+    body: String,
+    content_type: &str,
+) -> Response<io::Cursor<Vec<u8>>> {
+    let header = Header::from_bytes("Content-Type", content_type).unwrap();
+    Response::from_string(body)
+        .with_status_code(status)
+        .with_header(header)
+}
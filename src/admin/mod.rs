@@ -0,0 +1,267 @@
+//! Lets operators inspect and control a running service. Front-ends such as
+//! the HTTP server hold an `AdminHandle`; the service's main loop holds the
+//! matching `AdminQueue`, publishes its status there and executes queued
+//! commands between drains, so no service state is shared across threads.
+
+pub mod control;
+#[cfg(feature = "http")]
+pub mod http;
+
+pub use control::{ControlSocket, DEFAULT_CONTROL_PATH, send_command};
+#[cfg(feature = "http")]
+pub use http::HttpAdmin;
+
+use crate::consumer::dispatcher::{ConsumerHealth, DrainStats};
+use std::fmt::{self, Write as _};
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AdminCommand {
+    /// Syncs every consumer's output to disk.
+    Flush,
+    /// Seals the current segment and starts a new one.
+    Rotate,
+    SetLogLevel(LogLevel),
+    /// Stops delivering events to the named consumer (`quarantined: true`),
+    /// or resumes it.
+    QuarantineConsumer {
+        name: String,
+        quarantined: bool,
+    },
+}
+
+impl fmt::Display for AdminCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Flush => f.write_str("flush"),
+            Self::Rotate => f.write_str("rotate"),
+            Self::SetLogLevel(level) => write!(f, "set-log-level {}", level),
+            Self::QuarantineConsumer {
+                name,
+                quarantined: true,
+            } => write!(f, "quarantine-consumer {}", name),
+            Self::QuarantineConsumer {
+                name,
+                quarantined: false,
+            } => write!(f, "release-consumer {}", name),
+        }
+    }
+}
+
+/// Parses the text form used by the control socket, e.g. `set-log-level
+/// debug`. The inverse of `Display`.
+impl FromStr for AdminCommand {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let line = line.trim();
+        let (name, arg) = match line.split_once(char::is_whitespace) {
+            Some((name, arg)) => (name, Some(arg.trim())),
+            None => (line, None),
+        };
+        let require_arg = || arg.ok_or_else(|| format!("{} needs an argument", name));
+
+        match name {
+            "flush" => Ok(Self::Flush),
+            "rotate" => Ok(Self::Rotate),
+            "set-log-level" => require_arg()?.parse().map(Self::SetLogLevel),
+            "quarantine-consumer" | "release-consumer" => Ok(Self::QuarantineConsumer {
+                name: require_arg()?.to_string(),
+                quarantined: name == "quarantine-consumer",
+            }),
+            _ => Err(format!("unknown command: {}", name)),
+        }
+    }
+}
+
+/// Verbosity of the service's own output.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Error => "error",
+            Self::Warn => "warn",
+            Self::Info => "info",
+            Self::Debug => "debug",
+        })
+    }
+}
+
+impl FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "error" => Ok(Self::Error),
+            "warn" => Ok(Self::Warn),
+            "info" => Ok(Self::Info),
+            "debug" => Ok(Self::Debug),
+            _ => Err(format!("unknown log level: {}", s)),
+        }
+    }
+}
+
+/// Snapshot of the service, published by its main loop.
+#[derive(Debug, Default, Clone)]
+pub struct Status {
+    pub ring_capacity: usize,
+    pub ring_used: usize,
+    /// Totals across every drain since startup.
+    pub drain: DrainStats,
+    pub consumers: Vec<ConsumerHealth>,
+    /// Sequence number of the segment being written, if the service writes
+    /// segments.
+    pub segment: Option<u64>,
+    pub log_level: LogLevel,
+}
+
+impl Status {
+    pub fn to_json(&self) -> String {
+        let occupancy = if self.ring_capacity == 0 {
+            0.0
+        } else {
+            self.ring_used as f64 / self.ring_capacity as f64
+        };
+
+        let mut json = String::new();
+        let _ = write!(
+            json,
+            "{{\"ring\":{{\"capacity\":{},\"used\":{},\"occupancy\":{:.4}}},",
+            self.ring_capacity, self.ring_used, occupancy
+        );
+        let _ = write!(
+            json,
+            "\"drain\":{{\"events_read\":{},\"events_delivered\":{},\"events_failed\":{},\"heartbeats\":{}}},",
+            self.drain.events_read,
+            self.drain.events_delivered,
+            self.drain.events_failed,
+            self.drain.heartbeats
+        );
+        json.push_str("\"consumers\":[");
+        for (i, consumer) in self.consumers.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            let _ = write!(
+                json,
+                "{{\"name\":\"{}\",\"healthy\":{},\"quarantined\":{},\"delivered\":{},\"failed\":{},\"consecutive_failures\":{}}}",
+                escape_json(&consumer.name),
+                consumer.is_healthy(),
+                consumer.quarantined,
+                consumer.delivered,
+                consumer.failed,
+                consumer.consecutive_failures
+            );
+        }
+        json.push_str("],\"segment\":");
+        match self.segment {
+            Some(seq) => {
+                let _ = write!(json, "{}", seq);
+            }
+            None => json.push_str("null"),
+        }
+        let _ = write!(json, ",\"log_level\":\"{}\"}}", self.log_level);
+        json
+    }
+}
+
+/// A command waiting for the main loop, which must answer it with `reply`.
+pub struct AdminRequest {
+    command: AdminCommand,
+    reply: Sender<Result<String, String>>,
+}
+
+impl AdminRequest {
+    pub fn command(&self) -> &AdminCommand {
+        &self.command
+    }
+
+    /// Answers the front-end that sent the command. The message is shown to
+    /// the operator as is.
+    pub fn reply(self, result: Result<String, String>) {
+        let _ = self.reply.send(result);
+    }
+}
+
+/// Front-end side of the admin channel.
+#[derive(Clone)]
+pub struct AdminHandle {
+    status: Arc<Mutex<Status>>,
+    requests: Sender<AdminRequest>,
+}
+
+impl AdminHandle {
+    /// The status most recently published by the main loop.
+    pub fn status(&self) -> Status {
+        self.status.lock().unwrap().clone()
+    }
+
+    /// Queues `command` and waits up to `timeout` for the main loop's reply.
+    pub fn execute(&self, command: AdminCommand, timeout: Duration) -> Result<String, String> {
+        let (reply, response) = mpsc::channel();
+        self.requests
+            .send(AdminRequest { command, reply })
+            .map_err(|_| "service is shutting down".to_string())?;
+        response
+            .recv_timeout(timeout)
+            .map_err(|_| format!("service did not answer within {:?}", timeout))?
+    }
+}
+
+/// Main-loop side of the admin channel.
+pub struct AdminQueue {
+    status: Arc<Mutex<Status>>,
+    requests: Receiver<AdminRequest>,
+}
+
+impl AdminQueue {
+    pub fn publish(&self, status: Status) {
+        *self.status.lock().unwrap() = status;
+    }
+
+    /// The next queued command, without blocking.
+    pub fn try_next(&self) -> Option<AdminRequest> {
+        self.requests.try_recv().ok()
+    }
+}
+
+pub fn admin_channel() -> (AdminHandle, AdminQueue) {
+    let status = Arc::new(Mutex::new(Status::default()));
+    let (tx, rx) = mpsc::channel();
+    (
+        AdminHandle {
+            status: status.clone(),
+            requests: tx,
+        },
+        AdminQueue {
+            status,
+            requests: rx,
+        },
+    )
+}
+
+pub(crate) fn escape_json(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
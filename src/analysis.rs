@@ -0,0 +1,149 @@
+//! Offline reporting over replayed events. `windows` groups events into
+//! tumbling time windows and `sliding_windows` into overlapping ones, each
+//! summarized by event and byte counts per type. Timestamps are taken as
+//! nanoseconds, as `SystemClock` writes them; windows are aligned to
+//! multiples of their step since the Unix epoch.
+
+use crate::event::EventView;
+use std::collections::{BTreeMap, VecDeque};
+use std::time::Duration;
+
+/// Events and header plus payload bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Counts {
+    pub events: u64,
+    pub bytes: u64,
+}
+
+impl Counts {
+    fn add(&mut self, bytes: u64) {
+        self.events += 1;
+        self.bytes += bytes;
+    }
+}
+
+/// The events with timestamps in `[start, end)`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Window {
+    pub start: u64,
+    pub end: u64,
+    pub total: Counts,
+    pub by_type: BTreeMap<u8, Counts>,
+}
+
+/// Tumbling windows of `width` over `events`, e.g. `windows(&reader, 60s)`.
+/// Windows without events are skipped. Panics if `width` is zero.
+pub fn windows<'a, I>(events: I, width: Duration) -> Windows<I::IntoIter>
+where
+    I: IntoIterator<Item = EventView<'a>>,
+{
+    sliding_windows(events, width, width)
+}
+
+/// Windows of `width` starting every `step`, so each event falls in
+/// `width / step` of them. The first window starts at the first event's
+/// timestamp rounded down to a multiple of `step`, and so does the first
+/// after a gap with no events. Panics unless `0 < step <= width`.
+pub fn sliding_windows<'a, I>(events: I, width: Duration, step: Duration) -> Windows<I::IntoIter>
+where
+    I: IntoIterator<Item = EventView<'a>>,
+{
+    let width = width.as_nanos() as u64;
+    let step = step.as_nanos() as u64;
+    assert!(
+        0 < step && step <= width,
+        "window step must be non-zero and at most the width"
+    );
+    Windows {
+        events: events.into_iter(),
+        width,
+        step,
+        start: None,
+        buffered: VecDeque::new(),
+        next: None,
+        done: false,
+    }
+}
+
+/// Iterator returned by `windows` and `sliding_windows`. Events are expected
+/// in timestamp order; one earlier than the window being filled counts
+/// towards it.
+pub struct Windows<I> {
+    events: I,
+    width: u64,
+    step: u64,
+    start: Option<u64>,
+    /// Timestamp, type and size of the events in the current window.
+    buffered: VecDeque<(u64, u8, u64)>,
+    /// The first event past the current window.
+    next: Option<(u64, u8, u64)>,
+    done: bool,
+}
+
+impl<'a, I: Iterator<Item = EventView<'a>>> Windows<I> {
+    fn pull(&mut self) -> Option<(u64, u8, u64)> {
+        if let Some(event) = self.next.take() {
+            return Some(event);
+        }
+        if self.done {
+            return None;
+        }
+        match self.events.next() {
+            Some(event) => Some((
+                event.header.timestamp,
+                event.header.event_type,
+                event.header.total_size() as u64,
+            )),
+            None => {
+                self.done = true;
+                None
+            }
+        }
+    }
+}
+
+impl<'a, I: Iterator<Item = EventView<'a>>> Iterator for Windows<I> {
+    type Item = Window;
+
+    fn next(&mut self) -> Option<Window> {
+        let start = match self.start {
+            Some(start) if !self.buffered.is_empty() => start,
+            _ => {
+                let first = self.pull()?;
+                self.next = Some(first);
+                first.0 - first.0 % self.step
+            }
+        };
+        let end = start.saturating_add(self.width);
+        while let Some(event) = self.pull() {
+            if event.0 >= end {
+                self.next = Some(event);
+                break;
+            }
+            self.buffered.push_back(event);
+        }
+
+        let mut window = Window {
+            start,
+            end,
+            ..Default::default()
+        };
+        for &(_, event_type, bytes) in &self.buffered {
+            window.total.add(bytes);
+            window.by_type.entry(event_type).or_default().add(bytes);
+        }
+
+        let next_start = start.saturating_add(self.step);
+        while self
+            .buffered
+            .front()
+            .is_some_and(|event| event.0 < next_start)
+        {
+            self.buffered.pop_front();
+        }
+        self.start = Some(next_start);
+        Some(window)
+    }
+}
@@ -0,0 +1,154 @@
+pub mod s3;
+
+pub use s3::{S3Config, S3Store};
+
+use crate::storage::segment::segment_file_name;
+use crate::storage::{Manifest, MmapReader, list_segments};
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+pub trait ObjectStore {
+    fn put_object(&mut self, key: &str, data: &[u8]) -> io::Result<()>;
+
+    fn create_multipart_upload(&mut self, key: &str) -> io::Result<String>;
+
+    /// Returns the part's ETag, which must be passed back on completion.
+    fn upload_part(
+        &mut self,
+        key: &str,
+        upload_id: &str,
+        part_number: u32,
+        data: &[u8],
+    ) -> io::Result<String>;
+
+    fn complete_multipart_upload(
+        &mut self,
+        key: &str,
+        upload_id: &str,
+        parts: &[(u32, String)],
+    ) -> io::Result<()>;
+
+    fn abort_multipart_upload(&mut self, key: &str, upload_id: &str) -> io::Result<()>;
+}
+
+#[derive(Debug, Clone)]
+pub struct ArchiveConfig {
+    pub prefix: String,
+    pub part_size: usize,
+    pub max_retries: u32,
+    pub retry_backoff: Duration,
+}
+
+impl Default for ArchiveConfig {
+    fn default() -> Self {
+        Self {
+            prefix: String::new(),
+            part_size: 8 * 1024 * 1024,
+            max_retries: 3,
+            retry_backoff: Duration::from_millis(200),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ArchiveReport {
+    pub archived: Vec<(u64, String)>,
+    pub already_archived: usize,
+    pub skipped_unsealed: usize,
+}
+
+pub struct Archiver<S: ObjectStore> {
+    store: S,
+    config: ArchiveConfig,
+}
+
+impl<S: ObjectStore> Archiver<S> {
+    pub fn new(store: S, config: ArchiveConfig) -> Self {
+        Self { store, config }
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Uploads every sealed segment in `dir` that the manifest does not yet
+    /// record as archived. The manifest is saved after each upload so an
+    /// interrupted run resumes where it stopped.
+    pub fn archive_dir<P: AsRef<Path>>(&mut self, dir: P) -> io::Result<ArchiveReport> {
+        let dir = dir.as_ref();
+        let mut manifest = Manifest::load(dir)?;
+        let mut report = ArchiveReport::default();
+
+        for segment in list_segments(dir)? {
+            if manifest
+                .entry(segment.seq)
+                .is_some_and(|e| e.archived_key.is_some())
+            {
+                report.already_archived += 1;
+                continue;
+            }
+
+            let reader = MmapReader::open(&segment.path)?;
+            if !reader.is_sealed() {
+                report.skipped_unsealed += 1;
+                continue;
+            }
+
+            let key = format!("{}{}", self.config.prefix, segment_file_name(segment.seq));
+            self.upload(&key, reader.mapped_bytes())?;
+
+            manifest.entry_mut(segment.seq).archived_key = Some(key.clone());
+            manifest.save()?;
+            report.archived.push((segment.seq, key));
+        }
+
+        Ok(report)
+    }
+
+    pub fn upload(&mut self, key: &str, data: &[u8]) -> io::Result<()> {
+        let part_size = self.config.part_size.max(1);
+        if data.len() <= part_size {
+            return self.with_retry(|store| store.put_object(key, data));
+        }
+
+        let upload_id = self.with_retry(|store| store.create_multipart_upload(key))?;
+
+        let mut parts = Vec::new();
+        for (i, chunk) in data.chunks(part_size).enumerate() {
+            let part_number = i as u32 + 1;
+            let etag = match self
+                .with_retry(|store| store.upload_part(key, &upload_id, part_number, chunk))
+            {
+                Ok(etag) => etag,
+                Err(e) => {
+                    let _ = self.store.abort_multipart_upload(key, &upload_id);
+                    return Err(e);
+                }
+            };
+            parts.push((part_number, etag));
+        }
+
+        let completed =
+            self.with_retry(|store| store.complete_multipart_upload(key, &upload_id, &parts));
+        if completed.is_err() {
+            let _ = self.store.abort_multipart_upload(key, &upload_id);
+        }
+        completed
+    }
+
+    fn with_retry<T>(&mut self, mut op: impl FnMut(&mut S) -> io::Result<T>) -> io::Result<T> {
+        let mut attempt = 0;
+        loop {
+            match op(&mut self.store) {
+                Ok(value) => return Ok(value),
+                Err(e) if attempt >= self.config.max_retries => return Err(e),
+                Err(_) => {
+                    let factor = 2u32.saturating_pow(attempt);
+                    std::thread::sleep(self.config.retry_backoff.saturating_mul(factor));
+                    attempt += 1;
+                }
+            }
+        }
+    }
+}
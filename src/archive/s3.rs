@@ -0,0 +1,299 @@
+use super::ObjectStore;
+use crate::clock::civil_from_days;
+use hmac::{Hmac, KeyInit, Mac};
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone)]
+pub struct S3Config {
+    /// Base URL of the service, e.g. `https://s3.us-east-1.amazonaws.com` or
+    /// `http://localhost:9000` for MinIO. Requests use path-style addressing.
+    pub endpoint: String,
+    pub bucket: String,
+    pub region: String,
+    pub access_key: String,
+    pub secret_key: String,
+}
+
+pub struct S3Store {
+    config: S3Config,
+    agent: ureq::Agent,
+}
+
+impl S3Store {
+    pub fn new(config: S3Config) -> Self {
+        Self {
+            config,
+            agent: ureq::Agent::new(),
+        }
+    }
+
+    fn send(
+        &self,
+        method: &str,
+        key: &str,
+        query: &[(&str, &str)],
+        body: &[u8],
+    ) -> io::Result<ureq::Response> {
+        let endpoint = self.config.endpoint.trim_end_matches('/');
+        let host = endpoint
+            .split_once("://")
+            .map_or(endpoint, |(_, rest)| rest);
+
+        let uri = format!(
+            "/{}/{}",
+            uri_encode(&self.config.bucket, false),
+            uri_encode(key, true)
+        );
+        let mut query: Vec<(String, String)> = query
+            .iter()
+            .map(|(k, v)| (uri_encode(k, false), uri_encode(v, false)))
+            .collect();
+        query.sort();
+        let query = query
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let amz_date = amz_date(now);
+        let payload_hash = hex(&Sha256::digest(body));
+
+        let headers = [
+            ("host", host),
+            ("x-amz-content-sha256", payload_hash.as_str()),
+            ("x-amz-date", amz_date.as_str()),
+        ];
+        let signature = sign(
+            &self.config.secret_key,
+            &self.config.region,
+            &amz_date,
+            method,
+            &uri,
+            &query,
+            &headers,
+            &payload_hash,
+        );
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}/{}/s3/aws4_request, SignedHeaders={}, Signature={}",
+            self.config.access_key,
+            &amz_date[..8],
+            self.config.region,
+            signed_headers(&headers),
+            signature
+        );
+
+        let url = if query.is_empty() {
+            format!("{}{}", endpoint, uri)
+        } else {
+            format!("{}{}?{}", endpoint, uri, query)
+        };
+
+        self.agent
+            .request(method, &url)
+            .set("x-amz-content-sha256", &payload_hash)
+            .set("x-amz-date", &amz_date)
+            .set("Authorization", &authorization)
+            .send_bytes(body)
+            .map_err(|e| match e {
+                ureq::Error::Status(code, response) => io::Error::other(format!(
+                    "S3 {} {} failed with status {}: {}",
+                    method,
+                    key,
+                    code,
+                    response.into_string().unwrap_or_default()
+                )),
+                ureq::Error::Transport(t) => {
+                    io::Error::other(format!("S3 {} {} transport error: {}", method, key, t))
+                }
+            })
+    }
+}
+
+impl ObjectStore for S3Store {
+    fn put_object(&mut self, key: &str, data: &[u8]) -> io::Result<()> {
+        self.send("PUT", key, &[], data).map(|_| ())
+    }
+
+    fn create_multipart_upload(&mut self, key: &str) -> io::Result<String> {
+        let body = self
+            .send("POST", key, &[("uploads", "")], &[])?
+            .into_string()?;
+        xml_element(&body, "UploadId")
+            .map(str::to_string)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("No UploadId in CreateMultipartUpload response for {}", key),
+                )
+            })
+    }
+
+    fn upload_part(
+        &mut self,
+        key: &str,
+        upload_id: &str,
+        part_number: u32,
+        data: &[u8],
+    ) -> io::Result<String> {
+        let part = part_number.to_string();
+        let response = self.send(
+            "PUT",
+            key,
+            &[("partNumber", &part), ("uploadId", upload_id)],
+            data,
+        )?;
+        response.header("ETag").map(str::to_string).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("No ETag for part {} of {}", part_number, key),
+            )
+        })
+    }
+
+    fn complete_multipart_upload(
+        &mut self,
+        key: &str,
+        upload_id: &str,
+        parts: &[(u32, String)],
+    ) -> io::Result<()> {
+        let mut body = String::from("<CompleteMultipartUpload>");
+        for (number, etag) in parts {
+            let _ = write!(
+                body,
+                "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>",
+                number, etag
+            );
+        }
+        body.push_str("</CompleteMultipartUpload>");
+
+        let response = self
+            .send("POST", key, &[("uploadId", upload_id)], body.as_bytes())?
+            .into_string()?;
+        // S3 can report a failed completion with a 200 status and an error body.
+        if response.contains("<Error>") {
+            return Err(io::Error::other(format!(
+                "CompleteMultipartUpload for {} failed: {}",
+                key, response
+            )));
+        }
+        Ok(())
+    }
+
+    fn abort_multipart_upload(&mut self, key: &str, upload_id: &str) -> io::Result<()> {
+        self.send("DELETE", key, &[("uploadId", upload_id)], &[])
+            .map(|_| ())
+    }
+}
+
+/// Computes an AWS Signature Version 4 for the `s3` service. `headers` must
+/// use lowercase names and be sorted by name.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn sign(
+    secret_key: &str,
+    region: &str,
+    amz_date: &str,
+    method: &str,
+    canonical_uri: &str,
+    canonical_query: &str,
+    headers: &[(&str, &str)],
+    payload_hash: &str,
+) -> String {
+    let mut canonical_headers = String::new();
+    for (name, value) in headers {
+        let _ = writeln!(canonical_headers, "{}:{}", name, value.trim());
+    }
+
+    let canonical_request = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        method,
+        canonical_uri,
+        canonical_query,
+        canonical_headers,
+        signed_headers(headers),
+        payload_hash
+    );
+
+    let date = &amz_date[..8];
+    let scope = format!("{}/{}/s3/aws4_request", date, region);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        hex(&Sha256::digest(canonical_request.as_bytes()))
+    );
+
+    let key = hmac_sha256(format!("AWS4{}", secret_key).as_bytes(), date.as_bytes());
+    let key = hmac_sha256(&key, region.as_bytes());
+    let key = hmac_sha256(&key, b"s3");
+    let key = hmac_sha256(&key, b"aws4_request");
+    hex(&hmac_sha256(&key, string_to_sign.as_bytes()))
+}
+
+fn signed_headers(headers: &[(&str, &str)]) -> String {
+    headers
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        let _ = write!(out, "{:02x}", b);
+    }
+    out
+}
+
+fn uri_encode(s: &str, keep_slash: bool) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                out.push(b as char)
+            }
+            b'/' if keep_slash => out.push('/'),
+            _ => {
+                let _ = write!(out, "%{:02X}", b);
+            }
+        }
+    }
+    out
+}
+
+/// Formats unix seconds as the `YYYYMMDDTHHMMSSZ` timestamp SigV4 expects.
+pub(crate) fn amz_date(secs: u64) -> String {
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    let rem = secs % 86_400;
+
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem / 60 % 60,
+        rem % 60
+    )
+}
+
+fn xml_element<'a>(body: &'a str, name: &str) -> Option<&'a str> {
+    let open = format!("<{}>", name);
+    let close = format!("</{}>", name);
+    let start = body.find(&open)? + open.len();
+    let end = start + body[start..].find(&close)?;
+    Some(&body[start..end])
+}
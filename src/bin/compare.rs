@@ -0,0 +1,199 @@
+//! Runs one workload through each queue type and prints a comparison table:
+//! `RingBuffer` on a single thread, `SpscRingBuffer`, `std::sync::mpsc` and,
+//! with the `crossbeam` feature, crossbeam channels. Every consumer receives
+//! the header and an owned copy of the payload, as `read_event` returns.
+
+use ringlog::event::EventHeader;
+use ringlog::ring::{RingBuffer, SpscRingBuffer};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "usage: compare [--events <n>] [--payload-size <bytes>] [--capacity <bytes>]";
+
+struct Workload {
+    events: u64,
+    payload_size: usize,
+    /// Ring capacity in bytes; channels are bounded to the same number of
+    /// events.
+    capacity: usize,
+}
+
+impl Workload {
+    fn channel_bound(&self) -> usize {
+        self.capacity / (EventHeader::SIZE + self.payload_size)
+    }
+
+    fn header(&self, i: u64) -> EventHeader {
+        EventHeader::new(i, 1, self.payload_size as u16)
+    }
+}
+
+struct Row {
+    name: &'static str,
+    threads: usize,
+    elapsed: Duration,
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Box<dyn std::error::Error>> {
+    let mut workload = Workload {
+        events: 10_000_000,
+        payload_size: 64,
+        capacity: 1024 * 1024,
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args.next().ok_or(USAGE)?;
+        match arg.as_str() {
+            "--events" => workload.events = value.parse()?,
+            "--payload-size" => workload.payload_size = value.parse()?,
+            "--capacity" => workload.capacity = value.parse()?,
+            _ => return Err(USAGE.into()),
+        }
+    }
+    if workload.payload_size > u16::MAX as usize {
+        return Err(format!("--payload-size is at most {}", u16::MAX).into());
+    }
+
+    println!(
+        "{} events, {}-byte payloads, {} byte rings / {}-event channels\n",
+        workload.events,
+        workload.payload_size,
+        workload.capacity,
+        workload.channel_bound()
+    );
+
+    let rows = [
+        ring_buffer(&workload)?,
+        spsc_ring_buffer(&workload)?,
+        std_mpsc(&workload),
+        #[cfg(feature = "crossbeam")]
+        crossbeam(&workload),
+    ];
+
+    println!(
+        "{:<20} {:>7} {:>14} {:>10}",
+        "type", "threads", "events/sec", "ns/event"
+    );
+    for row in &rows {
+        let secs = row.elapsed.as_secs_f64();
+        println!(
+            "{:<20} {:>7} {:>13.2}M {:>10.1}",
+            row.name,
+            row.threads,
+            workload.events as f64 / secs / 1_000_000.0,
+            secs * 1e9 / workload.events as f64
+        );
+    }
+    #[cfg(not(feature = "crossbeam"))]
+    println!("\nBuild with `--features crossbeam` to include crossbeam channels.");
+    Ok(())
+}
+
+/// Fills the ring, then drains it, on one thread.
+fn ring_buffer(workload: &Workload) -> Result<Row, Box<dyn std::error::Error>> {
+    let mut ring = RingBuffer::new(workload.capacity)?;
+    let payload = vec![0u8; workload.payload_size];
+    let event_size = EventHeader::SIZE + workload.payload_size;
+
+    let start = Instant::now();
+    let mut written = 0;
+    while written < workload.events {
+        while written < workload.events && ring.available() >= event_size {
+            ring.write_event(&workload.header(written), &payload)?;
+            written += 1;
+        }
+        while let Some(event) = ring.read_event() {
+            black_box(event);
+        }
+    }
+    Ok(Row {
+        name: "RingBuffer",
+        threads: 1,
+        elapsed: start.elapsed(),
+    })
+}
+
+fn spsc_ring_buffer(workload: &Workload) -> Result<Row, Box<dyn std::error::Error>> {
+    let ring = SpscRingBuffer::new(workload.capacity)?;
+    let (mut producer, mut consumer) = ring.split();
+    let payload = vec![0u8; workload.payload_size];
+
+    let start = Instant::now();
+    std::thread::scope(|scope| {
+        scope.spawn(move || {
+            for i in 0..workload.events {
+                let header = workload.header(i);
+                while !producer.write_event(&header, &payload) {
+                    std::hint::spin_loop();
+                }
+            }
+        });
+        let mut read = 0;
+        while read < workload.events {
+            match consumer.read_event() {
+                Some(event) => {
+                    black_box(event);
+                    read += 1;
+                }
+                None => std::hint::spin_loop(),
+            }
+        }
+    });
+    Ok(Row {
+        name: "SpscRingBuffer",
+        threads: 2,
+        elapsed: start.elapsed(),
+    })
+}
+
+fn std_mpsc(workload: &Workload) -> Row {
+    let (sender, receiver) = std::sync::mpsc::sync_channel(workload.channel_bound());
+    let payload = vec![0u8; workload.payload_size];
+
+    let start = Instant::now();
+    std::thread::scope(|scope| {
+        scope.spawn(move || {
+            for i in 0..workload.events {
+                sender.send((workload.header(i), payload.clone())).unwrap();
+            }
+        });
+        for event in receiver {
+            black_box(event);
+        }
+    });
+    Row {
+        name: "std::sync::mpsc",
+        threads: 2,
+        elapsed: start.elapsed(),
+    }
+}
+
+#[cfg(feature = "crossbeam")]
+fn crossbeam(workload: &Workload) -> Row {
+    let (sender, receiver) = crossbeam_channel::bounded(workload.channel_bound());
+    let payload = vec![0u8; workload.payload_size];
+
+    let start = Instant::now();
+    std::thread::scope(|scope| {
+        scope.spawn(move || {
+            for i in 0..workload.events {
+                sender.send((workload.header(i), payload.clone())).unwrap();
+            }
+        });
+        for event in receiver {
+            black_box(event);
+        }
+    });
+    Row {
+        name: "crossbeam-channel",
+        threads: 2,
+        elapsed: start.elapsed(),
+    }
+}
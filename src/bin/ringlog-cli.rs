@@ -0,0 +1,145 @@
+use ringlog::admin::{DEFAULT_CONTROL_PATH, send_command};
+use ringlog::event::{EventView, HexDump, StringTable, TypeNames};
+use ringlog::query::Query;
+use ringlog::storage::{CsvColumn, CsvWriter, MmapReader, SegmentedReader};
+use std::path::Path;
+
+const USAGE: &str = "usage: ringlog-cli [--socket <path>] <command> [args]
+
+commands:
+  stats                          print the service status as JSON
+  flush                          sync written events to disk
+  rotate                         seal the current segment and start a new one
+  set-log-level <level>          error, warn, info or debug
+  quarantine-consumer <name>     stop delivering events to a consumer
+  release-consumer <name>        resume a quarantined consumer
+  query [--hex] <expr> <path>... print events of log files or segment
+                                 directories matching <expr>, e.g.
+                                 'type == 3 && payload.len > 100' or
+                                 'tag == 2'; --hex adds a hex dump of
+                                 each payload
+  export --format csv [--columns <list>] <path>...
+                                 write events of log files or segment
+                                 directories to stdout as CSV; <list> is
+                                 comma-separated, from timestamp, time,
+                                 type, stream, seq, payload and
+                                 field.<key> (default
+                                 timestamp,type,seq,payload)";
+
+fn main() {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let mut socket = DEFAULT_CONTROL_PATH.to_string();
+    if args.first().map(String::as_str) == Some("--socket") {
+        if args.len() < 2 {
+            fail(USAGE);
+        }
+        socket = args.remove(1);
+        args.remove(0);
+    }
+    if args.is_empty() {
+        fail(USAGE);
+    }
+    if args[0] == "query" {
+        let hex = args.get(1).map(String::as_str) == Some("--hex");
+        if hex {
+            args.remove(1);
+        }
+        if args.len() < 3 {
+            fail(USAGE);
+        }
+        if let Err(e) = query(&args[1], &args[2..], hex) {
+            fail(&format!("Error: {}", e));
+        }
+        return;
+    }
+    if args[0] == "export" {
+        if let Err(e) = export(&args[1..]) {
+            fail(&format!("Error: {}", e));
+        }
+        return;
+    }
+
+    match send_command(&socket, &args.join(" ")) {
+        Ok(Ok(message)) => println!("{}", message),
+        Ok(Err(message)) => fail(&format!("Error: {}", message)),
+        Err(e) => fail(&format!("Error: cannot reach {}: {}", socket, e)),
+    }
+}
+
+/// Runs locally against the files; the service is not involved.
+fn query(expr: &str, paths: &[String], hex: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let query = Query::parse(expr)?;
+    let no_strings = StringTable::default();
+    for path in paths {
+        if Path::new(path).is_dir() {
+            let reader = SegmentedReader::open(path)?;
+            for event in query.filter(&reader) {
+                print_event(&event, &TypeNames::new(), &no_strings, hex);
+            }
+        } else {
+            let reader = MmapReader::open(path)?;
+            let (types, strings) = (reader.type_names(), reader.string_table());
+            for event in query.filter(&reader) {
+                print_event(&event, &types, &strings, hex);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Runs locally like `query`, writing one CSV document for all paths.
+fn export(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut format = None;
+    let mut columns = CsvColumn::defaults();
+    let mut args = args.iter();
+    let mut paths = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => format = Some(args.next().ok_or(USAGE)?.as_str()),
+            "--columns" => columns = CsvColumn::parse_list(args.next().ok_or(USAGE)?)?,
+            path => paths.push(path),
+        }
+    }
+    match format {
+        Some("csv") => {}
+        Some(other) => return Err(format!("unsupported export format: {}", other).into()),
+        None => return Err(USAGE.into()),
+    }
+    if paths.is_empty() {
+        return Err(USAGE.into());
+    }
+
+    let stdout = std::io::stdout().lock();
+    let mut csv = CsvWriter::new(stdout, &columns);
+    csv.write_header()?;
+    for path in paths {
+        if Path::new(path).is_dir() {
+            for event in &SegmentedReader::open(path)? {
+                csv.write_event(&event)?;
+            }
+        } else {
+            let reader = MmapReader::open(path)?;
+            for event in reader.iter() {
+                csv.write_event(&event)?;
+            }
+        }
+    }
+    csv.flush()?;
+    Ok(())
+}
+
+/// Prints an event as `ringlog dump` does.
+fn print_event(event: &EventView<'_>, types: &TypeNames, strings: &StringTable, hex: bool) {
+    println!(
+        "{}",
+        event.display().with_types(types).with_strings(strings)
+    );
+    if hex && !event.payload.is_empty() {
+        println!("{}", HexDump(event.payload));
+    }
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1);
+}
@@ -1,9 +1,135 @@
+use ringlog::config::SyncPolicy;
 use ringlog::event::EventHeader;
-use ringlog::ring::SpscRingBuffer;
-use ringlog::storage::MmapWriter;
+use ringlog::ring::{DrainOrder, ShardedRing};
+use ringlog::storage::{MmapWriter, Prefault, WriterOptions};
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "histogram")]
+type Histogram = hdrhistogram::Histogram<u64>;
+
+const USAGE: &str = "usage: stress [options]
+
+  --payload-size <bytes>   payload of each event (default 64)
+  --ring-capacity <bytes>  total ring capacity, split across producers (default 64 MiB)
+  --duration <secs>        how long producers write (default 5)
+  --producers <n>          producer threads, one ring shard each (default 1)
+  --path <file>            file events are persisted to (default /tmp/ringlog_stress.log)
+  --file-size <bytes>      capacity of that file (default 1 GiB)
+  --sync <policy>          async, always, interval or never (default never)
+  --sync-interval <ms>     interval of the `interval` policy (default 1000)
+  --latency                record ring write -> mmap write latency (feature `histogram`)
+  --json                   print results as one JSON object";
+
+/// One stress run's settings, from the command line.
+#[derive(Debug)]
+struct Options {
+    payload_size: usize,
+    ring_capacity: usize,
+    duration: Duration,
+    producers: usize,
+    path: PathBuf,
+    file_size: usize,
+    sync: SyncPolicy,
+    sync_interval: Duration,
+    latency: bool,
+    json: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            payload_size: 64,
+            ring_capacity: 64 * 1024 * 1024,
+            duration: Duration::from_secs(5),
+            producers: 1,
+            path: PathBuf::from("/tmp/ringlog_stress.log"),
+            file_size: 1024 * 1024 * 1024,
+            sync: SyncPolicy::Never,
+            sync_interval: Duration::from_secs(1),
+            latency: false,
+            json: false,
+        }
+    }
+}
+
+impl Options {
+    fn parse(args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Self::default();
+        let mut args = args.peekable();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+            match arg.as_str() {
+                "--payload-size" => options.payload_size = number(&value()?)?,
+                "--ring-capacity" => options.ring_capacity = number(&value()?)?,
+                "--duration" => {
+                    let secs: f64 = value()?
+                        .parse()
+                        .map_err(|_| "--duration needs seconds".to_string())?;
+                    options.duration = Duration::from_secs_f64(secs);
+                }
+                "--producers" => options.producers = number(&value()?)?,
+                "--path" => options.path = PathBuf::from(value()?),
+                "--file-size" => options.file_size = number(&value()?)?,
+                "--sync" => {
+                    options.sync = match value()?.as_str() {
+                        "async" => SyncPolicy::Async,
+                        "always" => SyncPolicy::Always,
+                        "interval" => SyncPolicy::Interval,
+                        "never" => SyncPolicy::Never,
+                        other => return Err(format!("unknown sync policy: {}", other)),
+                    }
+                }
+                "--sync-interval" => {
+                    options.sync_interval = Duration::from_millis(number(&value()?)? as u64)
+                }
+                "--latency" => options.latency = true,
+                "--json" => options.json = true,
+                "--help" | "-h" => return Err(USAGE.to_string()),
+                other => return Err(format!("unknown argument: {}\n\n{}", other, USAGE)),
+            }
+        }
+
+        if options.payload_size > u16::MAX as usize {
+            return Err(format!("--payload-size is at most {}", u16::MAX));
+        }
+        if options.producers == 0 {
+            return Err("--producers must be at least 1".to_string());
+        }
+        #[cfg(not(feature = "histogram"))]
+        if options.latency {
+            return Err("--latency requires building with the `histogram` feature".to_string());
+        }
+        Ok(options)
+    }
+
+    /// Each shard's share of the ring, rounded down to a power of two.
+    fn shard_capacity(&self) -> usize {
+        let share = self.ring_capacity / self.producers;
+        if share == 0 {
+            0
+        } else {
+            1 << (usize::BITS - 1 - share.leading_zeros())
+        }
+    }
+}
+
+fn number(value: &str) -> Result<usize, String> {
+    value
+        .parse()
+        .map_err(|_| format!("expected a number, got {:?}", value))
+}
+
+/// What one run measured.
+struct Results {
+    written: u64,
+    persisted: u64,
+    elapsed: Duration,
+    file_size: u64,
+    histogram: Option<Histogram>,
+}
 
 fn main() {
     if let Err(e) = run() {
@@ -13,7 +139,10 @@ fn main() {
 }
 
 fn run() -> Result<(), Box<dyn std::error::Error>> {
-    println!("SPSC ringlog stress test + mmap\n");
+    let options = Options::parse(std::env::args().skip(1))?;
+    if !options.json {
+        println!("Sharded ringlog stress test + mmap\n");
+    }
 
     let running = Arc::new(AtomicBool::new(true));
     let r = running.clone();
@@ -23,66 +152,222 @@ fn run() -> Result<(), Box<dyn std::error::Error>> {
     })
     .map_err(|e| format!("Failed to set Ctrl+C handler: {}", e))?;
 
-    let ring = SpscRingBuffer::new(64 * 1024 * 1024)
-        .map_err(|e| format!("Failed to create SPSC ring buffer: {}", e))?;
-    let (mut prod, mut cons) = ring.split();
+    let ring = ShardedRing::new(options.producers, options.shard_capacity())
+        .map_err(|e| format!("Failed to create ring buffer: {}", e))?;
+    let results = stress(&ring, &options, &running)?;
+
+    if options.json {
+        println!("{}", to_json(&options, &results));
+    } else {
+        print_results(&options, &results);
+    }
+    Ok(())
+}
+
+fn stress(
+    ring: &ShardedRing,
+    options: &Options,
+    running: &AtomicBool,
+) -> Result<Results, Box<dyn std::error::Error>> {
+    let start = Instant::now();
+    let latency = options.latency;
 
     std::thread::scope(|scope| {
-        let writer_running = running.clone();
-        let writer = scope.spawn(move || {
-            let mut count = 0u64;
-            let payload = [0u8; 64];
+        let writers: Vec<_> = (0..options.producers)
+            .map(|_| {
+                let mut prod = ring.producer().expect("one shard per producer");
+                scope.spawn(move || {
+                    let mut count = 0u64;
+                    let payload = vec![0u8; options.payload_size];
+                    let payload_len = options.payload_size as u16;
 
-            while writer_running.load(Ordering::Relaxed) {
-                let header = EventHeader::new(count, 1, 64);
-                if prod.write_event(&header, &payload) {
-                    count += 1;
-                }
-            }
+                    while running.load(Ordering::Relaxed) {
+                        let timestamp = if latency {
+                            start.elapsed().as_nanos() as u64
+                        } else {
+                            count
+                        };
+                        let header = EventHeader::new(timestamp, 1, payload_len);
+                        if prod.write_event(&header, &payload) {
+                            count += 1;
+                        }
+                    }
 
-            count
-        });
+                    count
+                })
+            })
+            .collect();
 
-        let reader_running = running.clone();
-        let reader = scope.spawn(move || -> Result<u64, std::io::Error> {
-            let mut mmap = MmapWriter::create("/tmp/ringlog_stress.log", 1024 * 1024 * 1024)?;
+        let mut cons = ring.consumer(DrainOrder::RoundRobin).unwrap();
+        let reader = scope.spawn(move || -> std::io::Result<(u64, Option<Histogram>)> {
+            let writer_options = WriterOptions {
+                prefault: Prefault::Populate,
+                ..Default::default()
+            };
+            let mut mmap =
+                MmapWriter::create_with_options(&options.path, options.file_size, &writer_options)?;
             let mut count = 0u64;
+            let mut last_sync = Instant::now();
+            // 1ns to 60s at 3 significant digits.
+            #[cfg(feature = "histogram")]
+            let mut histogram =
+                latency.then(|| Histogram::new_with_bounds(1, 60_000_000_000, 3).unwrap());
+            #[cfg(not(feature = "histogram"))]
+            let histogram: Option<Histogram> = None;
 
             loop {
                 while let Some((header, payload)) = cons.read_event() {
-                    mmap.write_event(&header, &payload);
-                    count += 1;
+                    if mmap.write_event(&header, &payload) {
+                        count += 1;
+                    }
+                    #[cfg(feature = "histogram")]
+                    if let Some(histogram) = histogram.as_mut() {
+                        let now = start.elapsed().as_nanos() as u64;
+                        histogram.saturating_record(now.saturating_sub(header.timestamp).max(1));
+                    }
                 }
 
-                if !reader_running.load(Ordering::Relaxed) && cons.is_empty() {
+                match options.sync {
+                    SyncPolicy::Async => mmap.sync_async()?,
+                    SyncPolicy::Always => mmap.sync()?,
+                    SyncPolicy::Interval if last_sync.elapsed() >= options.sync_interval => {
+                        mmap.sync()?;
+                        last_sync = Instant::now();
+                    }
+                    SyncPolicy::Interval | SyncPolicy::Never => {}
+                }
+
+                if !running.load(Ordering::Relaxed) && ring.is_empty() {
                     break;
                 }
             }
 
             mmap.sync()?;
-            Ok(count)
+            Ok((count, histogram))
         });
 
-        println!("Running for 5 seconds...");
-        std::thread::sleep(Duration::from_secs(5));
+        if !options.json {
+            println!("Running for {:?}...", options.duration);
+        }
+        let deadline = start + options.duration;
+        while running.load(Ordering::Relaxed) && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
         running.store(false, Ordering::SeqCst);
 
-        let written = writer.join().unwrap();
-        let read = reader.join().unwrap()?;
+        let written = writers.into_iter().map(|w| w.join().unwrap()).sum();
+        let elapsed = start.elapsed();
+        let (persisted, histogram) = reader.join().unwrap()?;
 
-        let file_size = std::fs::metadata("/tmp/ringlog_stress.log")
+        let file_size = std::fs::metadata(&options.path)
             .map(|m| m.len())
             .unwrap_or(0);
 
-        println!("\nResults:");
-        println!("  Written to ring: {} events", written);
-        println!("  Persisted to disk: {} events", read);
-        println!(
-            "  Throughput: {:.2}M events/sec",
-            written as f64 / 5.0 / 1_000_000.0
-        );
-        println!("  File size: {:.2} MB", file_size as f64 / 1024.0 / 1024.0);
-        
-        Ok(())
+        Ok(Results {
+            written,
+            persisted,
+            elapsed,
+            file_size,
+            histogram,
+        })
     })
 }
+
+fn print_results(options: &Options, results: &Results) {
+    let secs = results.elapsed.as_secs_f64();
+    println!("\nResults:");
+    println!("  Written to ring: {} events", results.written);
+    println!("  Persisted to disk: {} events", results.persisted);
+    println!(
+        "  Throughput: {:.2}M events/sec",
+        results.written as f64 / secs / 1_000_000.0
+    );
+    println!(
+        "  Bandwidth: {:.2} MB/sec",
+        (results.written * options.payload_size as u64) as f64 / secs / 1024.0 / 1024.0
+    );
+    println!(
+        "  File size: {:.2} MB",
+        results.file_size as f64 / 1024.0 / 1024.0
+    );
+    if let Some(histogram) = &results.histogram {
+        print_latency(histogram);
+    }
+}
+
+/// One line, so CI can append runs to a file and track them over time.
+fn to_json(options: &Options, results: &Results) -> String {
+    let secs = results.elapsed.as_secs_f64();
+    let sync = match options.sync {
+        SyncPolicy::Async => "async",
+        SyncPolicy::Always => "always",
+        SyncPolicy::Interval => "interval",
+        SyncPolicy::Never => "never",
+    };
+    let path = options.path.display().to_string();
+    format!(
+        concat!(
+            "{{\"options\":{{\"payload_size\":{},\"ring_capacity\":{},\"duration_secs\":{},",
+            "\"producers\":{},\"path\":{:?},\"file_size\":{},\"sync\":\"{}\",",
+            "\"sync_interval_ms\":{}}},",
+            "\"written\":{},\"persisted\":{},\"elapsed_secs\":{:.3},",
+            "\"events_per_sec\":{:.0},\"bytes_per_sec\":{:.0},\"file_bytes\":{},\"latency_ns\":{}}}"
+        ),
+        options.payload_size,
+        options.shard_capacity() * options.producers,
+        options.duration.as_secs_f64(),
+        options.producers,
+        path,
+        options.file_size,
+        sync,
+        options.sync_interval.as_millis(),
+        results.written,
+        results.persisted,
+        secs,
+        results.written as f64 / secs,
+        (results.written * options.payload_size as u64) as f64 / secs,
+        results.file_size,
+        results
+            .histogram
+            .as_ref()
+            .map_or("null".to_string(), latency_json),
+    )
+}
+
+/// Without the `histogram` feature no histogram is ever built.
+#[cfg(not(feature = "histogram"))]
+enum Histogram {}
+
+#[cfg(feature = "histogram")]
+const QUANTILES: [(&str, f64); 4] = [("p50", 0.5), ("p99", 0.99), ("p999", 0.999), ("max", 1.0)];
+
+#[cfg(feature = "histogram")]
+fn print_latency(histogram: &Histogram) {
+    let samples = histogram.len();
+    println!("  Latency, ring write -> mmap write ({} samples):", samples);
+    for (label, quantile) in QUANTILES {
+        let micros = histogram.value_at_quantile(quantile) as f64 / 1000.0;
+        println!("    {:<5} {:.2} us", label, micros);
+    }
+}
+
+#[cfg(feature = "histogram")]
+fn latency_json(histogram: &Histogram) -> String {
+    let fields: Vec<String> = QUANTILES
+        .iter()
+        .map(|(label, quantile)| {
+            format!("\"{}\":{}", label, histogram.value_at_quantile(*quantile))
+        })
+        .collect();
+    format!("{{{}}}", fields.join(","))
+}
+
+#[cfg(not(feature = "histogram"))]
+fn print_latency(histogram: &Histogram) {
+    match *histogram {}
+}
+
+#[cfg(not(feature = "histogram"))]
+fn latency_json(histogram: &Histogram) -> String {
+    match *histogram {}
+}
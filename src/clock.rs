@@ -0,0 +1,159 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Source of event timestamps. `now` is called on the hot path and may return
+/// raw ticks; `to_unix_nanos` converts them later, e.g. when the dispatcher
+/// drains events.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> u64;
+
+    fn to_unix_nanos(&self, ticks: u64) -> u64;
+}
+
+/// Wall-clock nanoseconds since the Unix epoch.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    #[inline]
+    fn now(&self) -> u64 {
+        unix_nanos()
+    }
+
+    #[inline]
+    fn to_unix_nanos(&self, ticks: u64) -> u64 {
+        ticks
+    }
+}
+
+/// Reads the CPU cycle counter (`rdtsc` on x86_64, `cntvct_el0` on aarch64),
+/// which costs a few nanoseconds instead of a syscall-backed clock read.
+/// Ticks are anchored to wall-clock time when the clock is created.
+#[derive(Debug, Clone, Copy)]
+pub struct TscClock {
+    base_ticks: u64,
+    base_nanos: u64,
+    /// Nanoseconds per tick as 32.32 fixed point.
+    nanos_per_tick: u128,
+}
+
+impl TscClock {
+    /// Calibrates against the system clock over ~10ms. `None` on other
+    /// architectures, or on x86_64 CPUs without an invariant TSC, whose tick
+    /// rate changes with frequency scaling.
+    pub fn new() -> Option<Self> {
+        Self::calibrate(Duration::from_millis(10))
+    }
+
+    pub fn calibrate(duration: Duration) -> Option<Self> {
+        let frequency = tick_frequency(duration)?;
+        let base_ticks = read_ticks()?;
+        let base_nanos = unix_nanos();
+        Some(Self {
+            base_ticks,
+            base_nanos,
+            nanos_per_tick: (1_000_000_000u128 << 32) / frequency as u128,
+        })
+    }
+
+    /// Ticks per second.
+    pub fn frequency(&self) -> u64 {
+        ((1_000_000_000u128 << 32) / self.nanos_per_tick) as u64
+    }
+}
+
+impl Clock for TscClock {
+    #[inline]
+    fn now(&self) -> u64 {
+        read_ticks().unwrap_or(0)
+    }
+
+    #[inline]
+    fn to_unix_nanos(&self, ticks: u64) -> u64 {
+        let scale = |delta: u64| ((delta as u128 * self.nanos_per_tick) >> 32) as u64;
+        if ticks >= self.base_ticks {
+            self.base_nanos + scale(ticks - self.base_ticks)
+        } else {
+            self.base_nanos
+                .saturating_sub(scale(self.base_ticks - ticks))
+        }
+    }
+}
+
+/// The UTC calendar date of `days` since the Unix epoch, as (year, month,
+/// day), by Howard Hinnant's civil-from-days algorithm.
+pub(crate) fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month as u32, day as u32)
+}
+
+#[inline]
+fn unix_nanos() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[inline]
+fn read_ticks() -> Option<u64> {
+    Some(unsafe { std::arch::x86_64::_rdtsc() })
+}
+
+#[cfg(target_arch = "aarch64")]
+#[inline]
+fn read_ticks() -> Option<u64> {
+    let ticks: u64;
+    unsafe { std::arch::asm!("mrs {}, cntvct_el0", out(reg) ticks) };
+    Some(ticks)
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+#[inline]
+fn read_ticks() -> Option<u64> {
+    None
+}
+
+#[cfg(target_arch = "x86_64")]
+fn tick_frequency(duration: Duration) -> Option<u64> {
+    use std::arch::x86_64::__cpuid;
+
+    // CPUID.80000007H:EDX[8] reports an invariant TSC.
+    let max_extended = __cpuid(0x8000_0000).eax;
+    if max_extended < 0x8000_0007 || __cpuid(0x8000_0007).edx & (1 << 8) == 0 {
+        return None;
+    }
+    measure_frequency(duration)
+}
+
+#[cfg(target_arch = "aarch64")]
+fn tick_frequency(_duration: Duration) -> Option<u64> {
+    let frequency: u64;
+    unsafe { std::arch::asm!("mrs {}, cntfrq_el0", out(reg) frequency) };
+    (frequency != 0).then_some(frequency)
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn tick_frequency(_duration: Duration) -> Option<u64> {
+    None
+}
+
+#[cfg(target_arch = "x86_64")]
+fn measure_frequency(duration: Duration) -> Option<u64> {
+    let start = std::time::Instant::now();
+    let start_ticks = read_ticks()?;
+    std::thread::sleep(duration);
+    let end_ticks = read_ticks()?;
+    let elapsed = start.elapsed().as_nanos();
+    if elapsed == 0 || end_ticks <= start_ticks {
+        return None;
+    }
+    Some(((end_ticks - start_ticks) as u128 * 1_000_000_000 / elapsed) as u64)
+}
@@ -0,0 +1,399 @@
+//! Typed configuration for the ringlog service, loaded from TOML with
+//! `RINGLOG_*` environment overrides. Every setting has a default, so an
+//! empty file is a valid config.
+//!
+//! ```toml
+//! log_level = "info"
+//!
+//! [ring]
+//! capacity = 65536
+//! heartbeat_ms = 1000
+//! crash_dump = "/var/lib/ringlog/crash.log"
+//!
+//! [storage]
+//! dir = "/var/lib/ringlog"
+//! segment_size = 67108864
+//! mode = 0o600
+//! preallocate = false
+//! ephemeral = false
+//!
+//! [rotation]
+//! max_age_secs = 3600
+//!
+//! [retention]
+//! max_segments = 48
+//! max_age_secs = 604800
+//!
+//! [sync]
+//! policy = "interval"   # "async", "always", "interval" or "never"
+//! interval_ms = 1000
+//!
+//! [consumers.segment]
+//! enabled = true
+//!
+//! [consumers.print]
+//! enabled = false
+//! event_types = [1, 7]
+//!
+//! [admin]
+//! http = "127.0.0.1:9100"
+//! control = "/run/ringlog.ctl"
+//!
+//! [quotas]              # per tenant (stream id); 0 is unlimited
+//! ring_bytes = 16384
+//! segment_bytes = 8388608
+//! ```
+//!
+//! Each key can be overridden by an environment variable named after its
+//! section and key, e.g. `RINGLOG_STORAGE_DIR` or `RINGLOG_SYNC_INTERVAL_MS`.
+//! List values are comma-separated.
+
+use crate::admin::LogLevel;
+use crate::ring::Quota;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use toml::{Table, Value};
+
+/// Prefix of environment variables that override config keys.
+pub const ENV_PREFIX: &str = "RINGLOG_";
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RinglogConfig {
+    pub log_level: LogLevel,
+    pub ring: RingConfig,
+    pub storage: StorageConfig,
+    pub rotation: RotationConfig,
+    pub retention: RetentionConfig,
+    pub sync: SyncConfig,
+    pub consumers: ConsumersConfig,
+    pub admin: AdminConfig,
+    pub quotas: QuotaConfig,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RingConfig {
+    /// Bytes; a power of two.
+    pub capacity: usize,
+    /// Interval of dispatcher heartbeat events; `None` disables them.
+    pub heartbeat: Option<Duration>,
+    /// Installs a panic hook that writes events still in the ring to this
+    /// file when the thread driving the service panics.
+    pub crash_dump: Option<PathBuf>,
+}
+
+impl Default for RingConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 64 * 1024,
+            heartbeat: Some(Duration::from_secs(1)),
+            crash_dump: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StorageConfig {
+    pub dir: PathBuf,
+    /// Capacity of each segment file in bytes.
+    pub segment_size: usize,
+    /// Permission bits of segment files.
+    pub mode: u32,
+    pub preallocate: bool,
+    /// Removes the segments written by this run on shutdown instead of
+    /// sealing and keeping them. Segments of earlier runs in `dir` are kept.
+    pub ephemeral: bool,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("/tmp/ringlog"),
+            segment_size: 64 * 1024 * 1024,
+            mode: 0o600,
+            preallocate: false,
+            ephemeral: false,
+        }
+    }
+}
+
+/// Segments always rotate when full; this adds time-based rotation.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RotationConfig {
+    pub max_age: Option<Duration>,
+}
+
+/// Which sealed segments are deleted after a rotation. The segment being
+/// written is never deleted.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RetentionConfig {
+    pub max_segments: Option<usize>,
+    /// Judged by each segment file's modification time.
+    pub max_age: Option<Duration>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyncConfig {
+    pub policy: SyncPolicy,
+    /// Used by `SyncPolicy::Interval`.
+    pub interval: Duration,
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        Self {
+            policy: SyncPolicy::default(),
+            interval: Duration::from_secs(1),
+        }
+    }
+}
+
+/// When the segment being written is synced to disk.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SyncPolicy {
+    /// Schedules writeback (`MS_ASYNC`) after every drain.
+    #[default]
+    Async,
+    /// Waits for writeback (`MS_SYNC`) after every drain.
+    Always,
+    /// Waits for writeback at most once per `SyncConfig::interval`.
+    Interval,
+    /// Leaves writeback to the kernel.
+    Never,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConsumersConfig {
+    /// Writes events to segment files in `storage.dir`.
+    pub segment: bool,
+    pub print: PrintConsumerConfig,
+}
+
+impl Default for ConsumersConfig {
+    fn default() -> Self {
+        Self {
+            segment: true,
+            print: PrintConsumerConfig::default(),
+        }
+    }
+}
+
+/// Prints events to stdout, decoding `FieldWriter` payloads.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PrintConsumerConfig {
+    pub enabled: bool,
+    /// Only these event types are printed; empty prints every type.
+    pub event_types: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AdminConfig {
+    /// Address of the HTTP admin server; needs the `http` feature.
+    pub http: Option<String>,
+    /// Path of the Unix control socket.
+    pub control: Option<PathBuf>,
+}
+
+/// The quota of every tenant (stream id); see `ring::Quota`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QuotaConfig {
+    /// Bounds each tenant's pending events in the ring.
+    pub ring: Quota,
+    /// Bounds what each tenant writes to one segment.
+    pub segment: Quota,
+}
+
+impl RinglogConfig {
+    /// Reads `path` and applies overrides from the process environment.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Failed to read config {}: {}", path.display(), e),
+            )
+        })?;
+        let mut config = Self::from_toml(&text)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+        config.apply_env(std::env::vars())?;
+        Ok(config)
+    }
+
+    pub fn from_toml(text: &str) -> io::Result<Self> {
+        let table: Table = text.parse().map_err(|e| invalid(format!("{}", e)))?;
+        let mut config = Self::default();
+        for (key, value) in &table {
+            if let Value::Table(section) = value {
+                for (name, value) in section {
+                    if key == "consumers"
+                        && let Value::Table(consumer) = value
+                    {
+                        for (setting, value) in consumer {
+                            config.set(&format!("consumers.{}.{}", name, setting), value)?;
+                        }
+                        continue;
+                    }
+                    config.set(&format!("{}.{}", key, name), value)?;
+                }
+            } else {
+                config.set(key, value)?;
+            }
+        }
+        Ok(config)
+    }
+
+    /// Applies `RINGLOG_<SECTION>_<KEY>` variables from `vars`; others are
+    /// ignored. Values are parsed as TOML, falling back to a plain string.
+    pub fn apply_env<I>(&mut self, vars: I) -> io::Result<()>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        for (name, raw) in vars {
+            let Some(var) = name.strip_prefix(ENV_PREFIX) else {
+                continue;
+            };
+            let Some(key) = KEYS
+                .iter()
+                .find(|key| key.replace('.', "_").eq_ignore_ascii_case(var))
+            else {
+                continue;
+            };
+            let value = if key.ends_with("event_types") {
+                let types = raw.split(',').filter(|s| !s.trim().is_empty());
+                Value::Array(types.map(|s| parse_env_value(s.trim())).collect())
+            } else {
+                parse_env_value(&raw)
+            };
+            // Unquoted strings such as a directory named `2024` parse as
+            // other TOML types first.
+            self.set(key, &value)
+                .or_else(|e| self.set(key, &Value::String(raw.clone())).map_err(|_| e))
+                .map_err(|e| invalid(format!("{}: {}", name, e)))?;
+        }
+        Ok(())
+    }
+
+    fn set(&mut self, key: &str, value: &Value) -> io::Result<()> {
+        let result = match key {
+            "log_level" => string(value)
+                .and_then(|s| s.parse().map_err(invalid))
+                .map(|level| self.log_level = level),
+            "ring.capacity" => integer(value).map(|n| self.ring.capacity = n),
+            "ring.heartbeat_ms" => millis(value).map(|d| self.ring.heartbeat = d),
+            "ring.crash_dump" => string(value).map(|s| self.ring.crash_dump = Some(s.into())),
+            "storage.dir" => string(value).map(|s| self.storage.dir = PathBuf::from(s)),
+            "storage.segment_size" => integer(value).map(|n| self.storage.segment_size = n),
+            "storage.mode" => integer(value).map(|n| self.storage.mode = n),
+            "storage.preallocate" => boolean(value).map(|b| self.storage.preallocate = b),
+            "storage.ephemeral" => boolean(value).map(|b| self.storage.ephemeral = b),
+            "rotation.max_age_secs" => secs(value).map(|d| self.rotation.max_age = d),
+            "retention.max_segments" => {
+                integer(value).map(|n| self.retention.max_segments = (n > 0).then_some(n))
+            }
+            "retention.max_age_secs" => secs(value).map(|d| self.retention.max_age = d),
+            "sync.policy" => string(value).and_then(|s| {
+                self.sync.policy = match s {
+                    "async" => SyncPolicy::Async,
+                    "always" => SyncPolicy::Always,
+                    "interval" => SyncPolicy::Interval,
+                    "never" => SyncPolicy::Never,
+                    other => return Err(invalid(format!("unknown sync policy {:?}", other))),
+                };
+                Ok(())
+            }),
+            "sync.interval_ms" => {
+                integer(value).map(|n| self.sync.interval = Duration::from_millis(n))
+            }
+            "consumers.segment.enabled" => boolean(value).map(|b| self.consumers.segment = b),
+            "consumers.print.enabled" => boolean(value).map(|b| self.consumers.print.enabled = b),
+            "consumers.print.event_types" => match value {
+                Value::Array(items) => items
+                    .iter()
+                    .map(integer)
+                    .collect::<io::Result<Vec<u8>>>()
+                    .map(|types| self.consumers.print.event_types = types),
+                _ => Err(invalid("expected an array")),
+            },
+            "quotas.ring_events" => integer(value).map(|n| self.quotas.ring.events = limit(n)),
+            "quotas.ring_bytes" => integer(value).map(|n| self.quotas.ring.bytes = limit(n)),
+            "quotas.segment_events" => {
+                integer(value).map(|n| self.quotas.segment.events = limit(n))
+            }
+            "quotas.segment_bytes" => integer(value).map(|n| self.quotas.segment.bytes = limit(n)),
+            "admin.http" => string(value).map(|s| self.admin.http = Some(s.to_string())),
+            "admin.control" => string(value).map(|s| self.admin.control = Some(s.into())),
+            _ => return Err(invalid(format!("unknown key {}", key))),
+        };
+        result.map_err(|e| invalid(format!("{}: {}", key, e)))
+    }
+}
+
+/// Every key `RinglogConfig` accepts, as dotted paths.
+const KEYS: &[&str] = &[
+    "log_level",
+    "ring.capacity",
+    "ring.heartbeat_ms",
+    "ring.crash_dump",
+    "storage.dir",
+    "storage.segment_size",
+    "storage.mode",
+    "storage.preallocate",
+    "storage.ephemeral",
+    "rotation.max_age_secs",
+    "retention.max_segments",
+    "retention.max_age_secs",
+    "sync.policy",
+    "sync.interval_ms",
+    "consumers.segment.enabled",
+    "consumers.print.enabled",
+    "consumers.print.event_types",
+    "admin.http",
+    "admin.control",
+    "quotas.ring_events",
+    "quotas.ring_bytes",
+    "quotas.segment_events",
+    "quotas.segment_bytes",
+];
+
+/// A quota limit, where 0 means unlimited.
+fn limit(n: u64) -> u64 {
+    if n == 0 { u64::MAX } else { n }
+}
+
+fn invalid<E: Into<Box<dyn std::error::Error + Send + Sync>>>(error: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+fn parse_env_value(raw: &str) -> Value {
+    format!("v = {}", raw)
+        .parse::<Table>()
+        .ok()
+        .and_then(|mut table| table.remove("v"))
+        .unwrap_or_else(|| Value::String(raw.to_string()))
+}
+
+fn string(value: &Value) -> io::Result<&str> {
+    value.as_str().ok_or_else(|| invalid("expected a string"))
+}
+
+fn boolean(value: &Value) -> io::Result<bool> {
+    value.as_bool().ok_or_else(|| invalid("expected a boolean"))
+}
+
+fn integer<T: TryFrom<i64>>(value: &Value) -> io::Result<T> {
+    let n = value
+        .as_integer()
+        .ok_or_else(|| invalid("expected an integer"))?;
+    T::try_from(n).map_err(|_| invalid(format!("{} is out of range", n)))
+}
+
+/// Milliseconds, where 0 disables the setting.
+fn millis(value: &Value) -> io::Result<Option<Duration>> {
+    integer(value).map(|n| (n > 0).then(|| Duration::from_millis(n)))
+}
+
+/// Seconds, where 0 disables the setting.
+fn secs(value: &Value) -> io::Result<Option<Duration>> {
+    integer(value).map(|n| (n > 0).then(|| Duration::from_secs(n)))
+}
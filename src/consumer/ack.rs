@@ -0,0 +1,214 @@
+//! At-least-once delivery. An `AckingConsumer` is handed a `DeliveryId` with
+//! every event and acks or nacks it through its `Acker`, right away or later
+//! from another thread. The dispatcher keeps a copy of each event until it
+//! is acked; nacked events, and events not acked within `AckOptions::timeout`,
+//! are redelivered, and after `max_attempts` deliveries they go to the
+//! dead-letter consumer instead.
+
+use super::dispatcher::{ConsumerHealth, DrainStats};
+use super::{Credit, EventConsumer};
+use crate::event::EventHeader;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::{Duration, Instant};
+
+pub trait AckingConsumer: Send {
+    /// Takes one event. Every delivery must eventually be acked or nacked
+    /// through `acker`, or it times out and counts as a failure.
+    fn deliver(&mut self, id: DeliveryId, header: &EventHeader, payload: &[u8], acker: &Acker);
+
+    fn flush(&mut self) {}
+
+    fn name(&self) -> &str;
+
+    /// See `EventConsumer::credit`.
+    fn credit(&self) -> Credit {
+        Credit::UNLIMITED
+    }
+}
+
+/// Identifies one delivery to one consumer; a redelivered event gets a new
+/// id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DeliveryId(pub u64);
+
+/// Reports the outcome of deliveries back to the dispatcher. Cheap to clone
+/// and safe to use from any thread; outcomes are applied at the start of the
+/// next drain.
+#[derive(Debug, Clone)]
+pub struct Acker {
+    sender: Sender<(DeliveryId, bool)>,
+}
+
+impl Acker {
+    /// The event was handled and will not be redelivered.
+    pub fn ack(&self, id: DeliveryId) {
+        let _ = self.sender.send((id, true));
+    }
+
+    /// The event was not handled; it is redelivered on the next drain.
+    pub fn nack(&self, id: DeliveryId) {
+        let _ = self.sender.send((id, false));
+    }
+}
+
+pub struct AckOptions {
+    /// How long a delivery may stay unacked before it is redelivered.
+    pub timeout: Duration,
+    /// Deliveries per event, including the first, before it is dead-lettered.
+    pub max_attempts: u32,
+    /// Unacked events at which the dispatcher stops reading from its source,
+    /// leaving further events in the ring until acks catch up.
+    pub max_in_flight: usize,
+    /// Receives events that ran out of attempts; without one they are
+    /// dropped.
+    pub dead_letter: Option<Box<dyn EventConsumer>>,
+}
+
+impl Default for AckOptions {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(30),
+            max_attempts: 5,
+            max_in_flight: 10_000,
+            dead_letter: None,
+        }
+    }
+}
+
+struct InFlight {
+    header: EventHeader,
+    payload: Vec<u8>,
+    attempts: u32,
+    deadline: Instant,
+}
+
+impl InFlight {
+    fn sequence(&self) -> Option<u64> {
+        self.header.split_sequence(&self.payload).0
+    }
+}
+
+/// The dispatcher-side state of one acking consumer.
+pub(super) struct AckRoute {
+    consumer: Box<dyn AckingConsumer>,
+    options: AckOptions,
+    acker: Acker,
+    outcomes: Receiver<(DeliveryId, bool)>,
+    in_flight: BTreeMap<DeliveryId, InFlight>,
+    next_id: u64,
+    /// Highest sequence number among acked events.
+    max_acked: Option<u64>,
+}
+
+impl AckRoute {
+    pub(super) fn new(consumer: Box<dyn AckingConsumer>, options: AckOptions) -> Self {
+        let (sender, outcomes) = mpsc::channel();
+        Self {
+            consumer,
+            options,
+            acker: Acker { sender },
+            outcomes,
+            in_flight: BTreeMap::new(),
+            next_id: 0,
+            max_acked: None,
+        }
+    }
+
+    pub(super) fn name(&self) -> &str {
+        self.consumer.name()
+    }
+
+    pub(super) fn credit(&self) -> Credit {
+        self.consumer.credit()
+    }
+
+    pub(super) fn flush(&mut self) {
+        self.consumer.flush();
+        if let Some(dead_letter) = &mut self.options.dead_letter {
+            dead_letter.flush();
+        }
+    }
+
+    #[inline]
+    pub(super) fn is_saturated(&self) -> bool {
+        self.in_flight.len() >= self.options.max_in_flight
+    }
+
+    pub(super) fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    /// The highest sequence below which every sequenced event was acked or
+    /// dead-lettered; acks past an event still in flight do not count.
+    pub(super) fn committed_sequence(&self) -> Option<u64> {
+        let oldest_pending = self.in_flight.values().filter_map(InFlight::sequence).min();
+        match (self.max_acked, oldest_pending) {
+            (Some(acked), Some(pending)) if pending <= acked => pending.checked_sub(1),
+            (acked, _) => acked,
+        }
+    }
+
+    pub(super) fn deliver(&mut self, header: &EventHeader, payload: &[u8]) {
+        self.send(InFlight {
+            header: *header,
+            payload: payload.to_vec(),
+            attempts: 0,
+            deadline: Instant::now(),
+        });
+    }
+
+    fn send(&mut self, mut event: InFlight) {
+        let id = DeliveryId(self.next_id);
+        self.next_id += 1;
+        event.attempts += 1;
+        event.deadline = Instant::now() + self.options.timeout;
+        self.consumer
+            .deliver(id, &event.header, &event.payload, &self.acker);
+        self.in_flight.insert(id, event);
+    }
+
+    /// Applies acks and nacks received since the last call, then redelivers
+    /// or dead-letters events that were nacked or timed out.
+    pub(super) fn settle(&mut self, health: &mut ConsumerHealth, stats: &mut DrainStats) {
+        let mut due = BTreeSet::new();
+        while let Ok((id, acked)) = self.outcomes.try_recv() {
+            if acked {
+                if let Some(event) = self.in_flight.remove(&id) {
+                    stats.events_delivered += 1;
+                    health.record_delivery();
+                    if let Some(seq) = event.sequence() {
+                        self.max_acked = self.max_acked.max(Some(seq));
+                    }
+                }
+            } else if self.in_flight.contains_key(&id) {
+                due.insert(id);
+            }
+        }
+        let now = Instant::now();
+        due.extend(
+            self.in_flight
+                .iter()
+                .filter(|(_, event)| event.deadline <= now)
+                .map(|(id, _)| *id),
+        );
+
+        for id in due {
+            let event = self
+                .in_flight
+                .remove(&id)
+                .expect("due events are in flight");
+            stats.events_failed += 1;
+            health.record_failure();
+            if event.attempts < self.options.max_attempts {
+                stats.events_redelivered += 1;
+                self.send(event);
+            } else {
+                stats.events_dead_lettered += 1;
+                if let Some(dead_letter) = &mut self.options.dead_letter {
+                    dead_letter.consume(&event.header, &event.payload);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,222 @@
+//! A canary for pipeline health. `AnomalyDetector` counts events per type
+//! over fixed intervals, keeps an exponentially weighted moving average of
+//! each type's rate as its baseline, and emits an alert event when a rate
+//! spikes above or flatlines below that baseline.
+
+use super::EventConsumer;
+use crate::clock::{Clock, SystemClock};
+use crate::event::{EventHeader, FieldWriter};
+use crate::ring::RingBuffer;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AnomalyOptions {
+    /// Length of the intervals rates are measured over.
+    pub interval: Duration,
+    /// Weight of the latest interval in the baseline, in `(0, 1]`.
+    pub alpha: f64,
+    /// A rate above `baseline * spike_factor` is a spike.
+    pub spike_factor: f64,
+    /// A rate below `baseline * flatline_factor` is a flatline.
+    pub flatline_factor: f64,
+    /// Baselines below this many events per second raise no alerts, so
+    /// rare event types do not flap.
+    pub min_baseline: f64,
+    /// Intervals a type is measured for before it can raise alerts.
+    pub warmup: u32,
+}
+
+impl Default for AnomalyOptions {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(1),
+            alpha: 0.2,
+            spike_factor: 3.0,
+            flatline_factor: 0.1,
+            min_baseline: 1.0,
+            warmup: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnomalyKind {
+    Spike,
+    Flatline,
+}
+
+impl AnomalyKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Spike => "spike",
+            Self::Flatline => "flatline",
+        }
+    }
+}
+
+/// Where alert events go.
+enum AlertSink {
+    Consumer(Box<dyn EventConsumer>),
+    Ring(Arc<Mutex<RingBuffer>>),
+}
+
+#[derive(Debug, Default)]
+struct TypeRate {
+    count: u64,
+    /// Events per second.
+    baseline: f64,
+    intervals: u32,
+    /// The anomaly last alerted on, until the rate is back to normal.
+    alerting: Option<AnomalyKind>,
+}
+
+/// Consumer raising alerts on unusual event rates. Each alert is an event
+/// of the configured alert type whose `FieldWriter` payload holds `kind`
+/// (`spike` or `flatline`), `event_type`, `rate` and `baseline`, the rates
+/// in events per second. A type alerts once when it turns anomalous and
+/// again only after it has recovered.
+///
+/// Intervals close when the detector sees an event or a flush after the
+/// interval elapsed, so a dispatcher draining at least once per interval
+/// keeps flatline detection timely. Heartbeats and alert events are not
+/// counted.
+pub struct AnomalyDetector {
+    options: AnomalyOptions,
+    alert_type: u8,
+    sink: Option<AlertSink>,
+    rates: BTreeMap<u8, TypeRate>,
+    window_start: Instant,
+    alerts: u64,
+}
+
+impl AnomalyDetector {
+    /// A detector that emits alerts of `alert_type`; without an alert sink
+    /// they are only counted.
+    pub fn new(alert_type: u8, options: AnomalyOptions) -> Self {
+        Self {
+            options,
+            alert_type,
+            sink: None,
+            rates: BTreeMap::new(),
+            window_start: Instant::now(),
+            alerts: 0,
+        }
+    }
+
+    /// Sends alerts to `consumer`.
+    pub fn with_alert_consumer<C: EventConsumer + 'static>(mut self, consumer: C) -> Self {
+        self.sink = Some(AlertSink::Consumer(Box::new(consumer)));
+        self
+    }
+
+    /// Writes alerts to `ring`, e.g. one drained by a separate alerting
+    /// dispatcher. Alerts that do not fit are dropped.
+    pub fn with_alert_ring(mut self, ring: Arc<Mutex<RingBuffer>>) -> Self {
+        self.sink = Some(AlertSink::Ring(ring));
+        self
+    }
+
+    /// Alerts raised so far.
+    pub fn alerts(&self) -> u64 {
+        self.alerts
+    }
+
+    /// The baseline rate of `event_type` in events per second, once it has
+    /// been measured for at least one interval.
+    pub fn baseline(&self, event_type: u8) -> Option<f64> {
+        self.rates
+            .get(&event_type)
+            .filter(|rate| rate.intervals > 0)
+            .map(|rate| rate.baseline)
+    }
+
+    /// Closes the current interval if it elapsed, updating baselines and
+    /// raising alerts.
+    fn advance(&mut self) {
+        let elapsed = self.window_start.elapsed();
+        if elapsed < self.options.interval {
+            return;
+        }
+        self.window_start = Instant::now();
+        let secs = elapsed.as_secs_f64();
+
+        let options = self.options;
+        let mut raised = Vec::new();
+        for (&event_type, state) in &mut self.rates {
+            let rate = state.count as f64 / secs;
+            state.count = 0;
+            let anomaly =
+                if state.intervals < options.warmup || state.baseline < options.min_baseline {
+                    None
+                } else if rate > state.baseline * options.spike_factor {
+                    Some(AnomalyKind::Spike)
+                } else if rate < state.baseline * options.flatline_factor {
+                    Some(AnomalyKind::Flatline)
+                } else {
+                    None
+                };
+            if let Some(kind) = anomaly
+                && state.alerting != Some(kind)
+            {
+                raised.push((kind, event_type, rate, state.baseline));
+            }
+            state.alerting = anomaly;
+
+            state.baseline = if state.intervals == 0 {
+                rate
+            } else {
+                options.alpha * rate + (1.0 - options.alpha) * state.baseline
+            };
+            state.intervals = state.intervals.saturating_add(1);
+        }
+        for (kind, event_type, rate, baseline) in raised {
+            self.alert(kind, event_type, rate, baseline);
+        }
+    }
+
+    fn alert(&mut self, kind: AnomalyKind, event_type: u8, rate: f64, baseline: f64) {
+        self.alerts += 1;
+        let mut fields = FieldWriter::new();
+        fields
+            .str("kind", kind.as_str())
+            .u64("event_type", event_type as u64)
+            .f64("rate", rate)
+            .f64("baseline", baseline);
+        // The keys are fixed and short, so encoding cannot fail.
+        let payload = fields.finish().unwrap_or_default();
+        let header = EventHeader::new(SystemClock.now(), self.alert_type, payload.len() as u16);
+        match &mut self.sink {
+            Some(AlertSink::Consumer(consumer)) => {
+                consumer.consume(&header, &payload);
+            }
+            Some(AlertSink::Ring(ring)) => {
+                let _ = ring.lock().unwrap().write_event(&header, &payload);
+            }
+            None => {}
+        }
+    }
+}
+
+impl EventConsumer for AnomalyDetector {
+    fn consume(&mut self, header: &EventHeader, _payload: &[u8]) -> bool {
+        self.advance();
+        if header.event_type != EventHeader::TYPE_HEARTBEAT && header.event_type != self.alert_type
+        {
+            self.rates.entry(header.event_type).or_default().count += 1;
+        }
+        true
+    }
+
+    fn flush(&mut self) {
+        self.advance();
+        if let Some(AlertSink::Consumer(consumer)) = &mut self.sink {
+            consumer.flush();
+        }
+    }
+
+    fn name(&self) -> &str {
+        "anomaly"
+    }
+}
@@ -0,0 +1,211 @@
+//! A consumer that inserts events into ClickHouse over its HTTP interface,
+//! as `INSERT ... FORMAT RowBinary` batches.
+
+use super::EventConsumer;
+use crate::event::EventHeader;
+use std::io;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone)]
+pub struct ClickHouseConfig {
+    /// Base URL of the HTTP interface, e.g. `http://localhost:8123`.
+    pub url: String,
+    /// Target table, optionally `database.table`; see `create_table_sql`.
+    pub table: String,
+    pub user: Option<String>,
+    pub password: Option<String>,
+    /// Rows per insert; a full batch is sent at once.
+    pub batch_size: usize,
+    /// A partial batch is sent at the first dispatcher flush this long
+    /// after the last insert.
+    pub flush_interval: Duration,
+}
+
+impl ClickHouseConfig {
+    pub const DEFAULT_BATCH_SIZE: usize = 10_000;
+    pub const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+    pub fn new(url: &str, table: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            table: table.to_string(),
+            user: None,
+            password: None,
+            batch_size: Self::DEFAULT_BATCH_SIZE,
+            flush_interval: Self::DEFAULT_FLUSH_INTERVAL,
+        }
+    }
+
+    /// `CREATE TABLE IF NOT EXISTS` for a MergeTree table with the columns
+    /// the consumer inserts.
+    pub fn create_table_sql(&self) -> String {
+        format!(
+            "CREATE TABLE IF NOT EXISTS {} (
+                timestamp DateTime64(9, 'UTC'),
+                type      UInt8,
+                stream    UInt8,
+                flags     UInt8,
+                seq       Nullable(UInt64),
+                trace_id  Nullable(UInt64),
+                span_id   Nullable(UInt64),
+                payload   String
+            ) ENGINE = MergeTree ORDER BY (type, timestamp)",
+            self.table
+        )
+    }
+}
+
+/// Batches events into RowBinary inserts. `payload` holds the application
+/// payload, without sequence or trace prefixes.
+///
+/// A failed insert keeps its rows for the next attempt. While twice
+/// `batch_size` rows are waiting, new events are refused, which the
+/// dispatcher counts as failed deliveries.
+pub struct ClickHouseConsumer {
+    config: ClickHouseConfig,
+    agent: ureq::Agent,
+    rows: Vec<u8>,
+    pending: usize,
+    last_insert: Instant,
+    inserted: u64,
+    failed_inserts: u64,
+}
+
+impl ClickHouseConsumer {
+    /// Panics if `config.batch_size` is zero or `config.table` is not made
+    /// of ASCII letters, digits, `_` and at most one `.`, as it is
+    /// interpolated into SQL.
+    pub fn new(config: ClickHouseConfig) -> Self {
+        assert!(config.batch_size > 0, "batch size must be non-zero");
+        let valid_part = |part: &str| {
+            !part.is_empty()
+                && !part.starts_with(|c: char| c.is_ascii_digit())
+                && part.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')
+        };
+        assert!(
+            config.table.split('.').count() <= 2 && config.table.split('.').all(valid_part),
+            "invalid table name {:?}",
+            config.table
+        );
+        Self {
+            config,
+            agent: ureq::Agent::new(),
+            rows: Vec::new(),
+            pending: 0,
+            last_insert: Instant::now(),
+            inserted: 0,
+            failed_inserts: 0,
+        }
+    }
+
+    /// Rows inserted so far.
+    pub fn inserted(&self) -> u64 {
+        self.inserted
+    }
+
+    /// Rows waiting for the next insert.
+    pub fn pending(&self) -> usize {
+        self.pending
+    }
+
+    pub fn failed_inserts(&self) -> u64 {
+        self.failed_inserts
+    }
+
+    /// Inserts the waiting rows now.
+    pub fn insert(&mut self) -> io::Result<()> {
+        self.last_insert = Instant::now();
+        if self.pending == 0 {
+            return Ok(());
+        }
+        let url = format!("{}/", self.config.url.trim_end_matches('/'));
+        let query = format!("INSERT INTO {} FORMAT RowBinary", self.config.table);
+        let mut request = self.agent.post(&url).query("query", &query);
+        if let Some(user) = &self.config.user {
+            request = request.set("X-ClickHouse-User", user);
+        }
+        if let Some(password) = &self.config.password {
+            request = request.set("X-ClickHouse-Key", password);
+        }
+        let result = request.send_bytes(&self.rows).map_err(|e| match e {
+            ureq::Error::Status(code, response) => io::Error::other(format!(
+                "ClickHouse insert into {} failed with status {}: {}",
+                self.config.table,
+                code,
+                response.into_string().unwrap_or_default()
+            )),
+            ureq::Error::Transport(t) => io::Error::other(format!(
+                "ClickHouse insert into {} transport error: {}",
+                self.config.table, t
+            )),
+        });
+        match result {
+            Ok(_) => {
+                self.inserted += self.pending as u64;
+                self.rows.clear();
+                self.pending = 0;
+                Ok(())
+            }
+            Err(e) => {
+                self.failed_inserts += 1;
+                Err(e)
+            }
+        }
+    }
+
+    fn encode(&mut self, header: &EventHeader, payload: &[u8]) {
+        let seq = header.split_sequence(payload).0;
+        let (trace, body) = header.split_trace(payload);
+        let rows = &mut self.rows;
+        rows.extend_from_slice(&(header.timestamp as i64).to_le_bytes());
+        rows.extend_from_slice(&[header.event_type, header.stream().get(), header.flags]);
+        for value in [seq, trace.map(|t| t.trace_id), trace.map(|t| t.span_id)] {
+            match value {
+                Some(value) => {
+                    rows.push(0);
+                    rows.extend_from_slice(&value.to_le_bytes());
+                }
+                None => rows.push(1),
+            }
+        }
+        // Strings are prefixed with their length as an unsigned LEB128.
+        let mut len = body.len();
+        while len >= 0x80 {
+            rows.push(len as u8 | 0x80);
+            len >>= 7;
+        }
+        rows.push(len as u8);
+        rows.extend_from_slice(body);
+        self.pending += 1;
+    }
+}
+
+impl EventConsumer for ClickHouseConsumer {
+    fn consume(&mut self, header: &EventHeader, payload: &[u8]) -> bool {
+        if self.pending >= self.config.batch_size * 2 {
+            return false;
+        }
+        self.encode(header, payload);
+        if self.pending >= self.config.batch_size {
+            // A failure keeps the rows for the next attempt.
+            let _ = self.insert();
+        }
+        true
+    }
+
+    fn flush(&mut self) {
+        if self.last_insert.elapsed() >= self.config.flush_interval {
+            let _ = self.insert();
+        }
+    }
+
+    fn name(&self) -> &str {
+        "clickhouse"
+    }
+}
+
+impl Drop for ClickHouseConsumer {
+    fn drop(&mut self) {
+        let _ = self.insert();
+    }
+}
@@ -0,0 +1,80 @@
+//! Middleware compressing payloads before fan-out, and decompressing them
+//! for consumers that need the plain payload; see `event::compression`.
+
+use super::middleware::{Action, EventMiddleware};
+use crate::event::EventHeader;
+use crate::event::compression::{compress, decompress};
+use crate::instrument::instrument_event;
+use std::borrow::Cow;
+
+/// Compresses application payloads of at least `threshold` bytes at zstd
+/// `level`, leaving those that would not shrink.
+pub struct Compressor {
+    threshold: usize,
+    level: i32,
+}
+
+impl Compressor {
+    pub fn new(threshold: usize, level: i32) -> Self {
+        Self { threshold, level }
+    }
+}
+
+impl Default for Compressor {
+    /// 256 bytes at level 3.
+    fn default() -> Self {
+        Self::new(256, 3)
+    }
+}
+
+impl EventMiddleware for Compressor {
+    fn on_event(&mut self, header: &mut EventHeader, payload: &mut Cow<'_, [u8]>) -> Action {
+        if header.body(payload).len() < self.threshold {
+            return Action::Continue;
+        }
+        match compress(header, payload, self.level) {
+            Ok(Some((compressed_header, compressed))) => {
+                *header = compressed_header;
+                *payload = Cow::Owned(compressed);
+                Action::Replace
+            }
+            Ok(None) => Action::Continue,
+            Err(_e) => {
+                instrument_event!(WARN, error = %_e, "compression failed, payload sent as is");
+                Action::Continue
+            }
+        }
+    }
+
+    fn name(&self) -> &str {
+        "compressor"
+    }
+}
+
+/// Decompresses events carrying `FLAG_COMPRESSED`. Events that fail to
+/// decompress are dropped.
+#[derive(Debug, Default)]
+pub struct Decompressor;
+
+impl EventMiddleware for Decompressor {
+    fn on_event(&mut self, header: &mut EventHeader, payload: &mut Cow<'_, [u8]>) -> Action {
+        if header.flags & EventHeader::FLAG_COMPRESSED == 0 {
+            return Action::Continue;
+        }
+        match decompress(header, payload) {
+            Ok((plain_header, plain)) => {
+                *header = plain_header;
+                *payload = Cow::Owned(plain.into_owned());
+                Action::Replace
+            }
+            Err(_e) => {
+                instrument_event!(WARN, error = %_e, "decompression failed, event dropped");
+                Action::Drop
+            }
+        }
+    }
+
+    fn name(&self) -> &str {
+        "decompressor"
+    }
+}
@@ -0,0 +1,56 @@
+/// How much a consumer can take in one drain. The dispatcher reads no more
+/// than the smallest credit among its active consumers, leaving the rest in
+/// the ring. The byte limit is checked before each read, so the last event
+/// of a drain may overshoot it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Credit {
+    pub events: usize,
+    /// Header plus payload bytes.
+    pub bytes: usize,
+}
+
+impl Credit {
+    pub const UNLIMITED: Self = Self {
+        events: usize::MAX,
+        bytes: usize::MAX,
+    };
+
+    pub const fn events(events: usize) -> Self {
+        Self {
+            events,
+            bytes: usize::MAX,
+        }
+    }
+
+    pub const fn bytes(bytes: usize) -> Self {
+        Self {
+            events: usize::MAX,
+            bytes,
+        }
+    }
+
+    /// The tighter of both limits.
+    pub fn min(self, other: Self) -> Self {
+        Self {
+            events: self.events.min(other.events),
+            bytes: self.bytes.min(other.bytes),
+        }
+    }
+
+    #[inline]
+    pub(super) fn allows_more(&self) -> bool {
+        self.events > 0 && self.bytes > 0
+    }
+
+    #[inline]
+    pub(super) fn spend(&mut self, total_size: usize) {
+        self.events = self.events.saturating_sub(1);
+        self.bytes = self.bytes.saturating_sub(total_size);
+    }
+}
+
+impl Default for Credit {
+    fn default() -> Self {
+        Self::UNLIMITED
+    }
+}
@@ -0,0 +1,130 @@
+//! Per-consumer "last acked sequence" numbers, persisted so events delivered
+//! before a restart and seen again (a replayed log, a retrying producer) are
+//! skipped by the dispatcher. Only events stamped with
+//! `EventBuilder::sequence` are deduplicated, and sequences are assumed to
+//! increase in the order events are read.
+
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// A file of `<sequence> <consumer name>` lines. Consumers that share a name
+/// share an entry.
+#[derive(Debug)]
+pub struct SequenceStore {
+    path: PathBuf,
+    acked: BTreeMap<String, u64>,
+    dirty: bool,
+}
+
+impl SequenceStore {
+    /// Loads `path`, or starts empty if it does not exist yet.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => {
+                return Err(io::Error::new(
+                    e.kind(),
+                    format!("Failed to read sequence store {}: {}", path.display(), e),
+                ));
+            }
+        };
+
+        let mut acked = BTreeMap::new();
+        for (number, line) in text.lines().enumerate() {
+            let parsed = line
+                .split_once(' ')
+                .and_then(|(seq, name)| Some((seq.parse::<u64>().ok()?, name)));
+            let Some((seq, name)) = parsed else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "{}:{}: expected `<sequence> <name>`",
+                        path.display(),
+                        number + 1
+                    ),
+                ));
+            };
+            acked.insert(name.to_string(), seq);
+        }
+        Ok(Self {
+            path,
+            acked,
+            dirty: false,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The highest sequence `consumer` has acked.
+    pub fn acked(&self, consumer: &str) -> Option<u64> {
+        self.acked.get(consumer).copied()
+    }
+
+    /// Whether `consumer` already acked `sequence`.
+    #[inline]
+    pub fn is_duplicate(&self, consumer: &str, sequence: u64) -> bool {
+        self.acked(consumer).is_some_and(|acked| sequence <= acked)
+    }
+
+    /// Raises `consumer`'s acked sequence to `sequence`; lower values are
+    /// ignored.
+    pub fn record(&mut self, consumer: &str, sequence: u64) {
+        match self.acked.get_mut(consumer) {
+            Some(acked) if *acked >= sequence => {}
+            Some(acked) => {
+                *acked = sequence;
+                self.dirty = true;
+            }
+            None => {
+                self.acked.insert(consumer.to_string(), sequence);
+                self.dirty = true;
+            }
+        }
+    }
+
+    /// Writes the store if it changed since the last save. The file is
+    /// written to a temporary sibling, fsynced and renamed into place.
+    pub fn save(&mut self) -> io::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let mut tmp_name = self.path.as_os_str().to_owned();
+        tmp_name.push(".tmp");
+        let tmp_path = Path::new(&tmp_name);
+
+        let mut text = String::new();
+        for (name, seq) in &self.acked {
+            text.push_str(&format!("{} {}\n", seq, name));
+        }
+        let result = (|| {
+            let mut file = OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(tmp_path)?;
+            file.write_all(text.as_bytes())?;
+            file.sync_all()?;
+            fs::rename(tmp_path, &self.path)
+        })();
+
+        if let Err(e) = result {
+            let _ = fs::remove_file(tmp_path);
+            return Err(io::Error::new(
+                e.kind(),
+                format!(
+                    "Saving sequence store {} failed: {}",
+                    self.path.display(),
+                    e
+                ),
+            ));
+        }
+        self.dirty = false;
+        Ok(())
+    }
+}
@@ -1,8 +1,141 @@
-use super::EventConsumer;
-use crate::ring::{Consumer, RingBuffer};
+use super::ack::{AckOptions, AckRoute, AckingConsumer};
+use super::dedup::SequenceStore;
+use super::middleware::{Action, EventMiddleware};
+use super::priority::Prioritizer;
+use super::{Credit, EventConsumer, EventSource};
+use crate::clock::{Clock, SystemClock};
+use crate::event::{EventHeader, EventView, StreamId, Tags};
+use crate::instrument::{instrument_event, instrument_span};
+use crate::ring::RingSet;
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 pub struct EventDispatcher {
-    consumers: Vec<Box<dyn EventConsumer>>,
+    consumers: Vec<Route>,
+    heartbeat: Option<Heartbeat>,
+    clock: Option<Arc<dyn Clock>>,
+    sequences: Option<SequenceStore>,
+    priorities: Option<Box<dyn Prioritizer>>,
+    delayed: BinaryHeap<Reverse<Delayed>>,
+    delayed_count: u64,
+    ttl: Option<Duration>,
+    type_ttls: BTreeMap<u8, Duration>,
+    expired_sink: Option<Box<dyn EventConsumer>>,
+    middleware: Vec<Box<dyn EventMiddleware>>,
+}
+
+/// A `TYPE_DELAYED` event held until its deliver-not-before time, already
+/// unwrapped. Ordered by that time, then by arrival.
+struct Delayed {
+    not_before: u64,
+    arrival: u64,
+    header: EventHeader,
+    payload: Vec<u8>,
+}
+
+impl Delayed {
+    fn key(&self) -> (u64, u64) {
+        (self.not_before, self.arrival)
+    }
+}
+
+impl PartialEq for Delayed {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Delayed {}
+
+impl PartialOrd for Delayed {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Delayed {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+struct Heartbeat {
+    interval: Duration,
+    last: Instant,
+    sequence: u64,
+}
+
+struct Route {
+    stream: Option<StreamId>,
+    /// Tags an event must all carry to be delivered; empty for any event.
+    tags: Tags,
+    target: Target,
+    health: ConsumerHealth,
+}
+
+enum Target {
+    Plain(Box<dyn EventConsumer>),
+    Acking(AckRoute),
+}
+
+impl Route {
+    fn new(stream: Option<StreamId>, target: Target) -> Self {
+        let name = match &target {
+            Target::Plain(consumer) => consumer.name(),
+            Target::Acking(route) => route.name(),
+        };
+        let health = ConsumerHealth {
+            name: name.to_string(),
+            ..Default::default()
+        };
+        Self {
+            stream,
+            tags: Tags::EMPTY,
+            target,
+            health,
+        }
+    }
+}
+
+/// Lifetime delivery counts for one consumer.
+#[derive(Debug, Default, Clone)]
+pub struct ConsumerHealth {
+    pub name: String,
+    pub delivered: u64,
+    pub failed: u64,
+    /// Failures since the consumer last accepted an event.
+    pub consecutive_failures: u64,
+    pub last_failure: Option<Instant>,
+    /// Set by `EventDispatcher::set_quarantined`; quarantined consumers are
+    /// skipped until released.
+    pub quarantined: bool,
+    /// Deliveries to an acking consumer not yet acked; 0 for other
+    /// consumers.
+    pub unacked: u64,
+}
+
+impl ConsumerHealth {
+    /// Whether the consumer is receiving events and accepted the most recent
+    /// one.
+    #[inline]
+    pub fn is_healthy(&self) -> bool {
+        self.consecutive_failures == 0 && !self.quarantined
+    }
+
+    #[inline]
+    pub(super) fn record_delivery(&mut self) {
+        self.delivered += 1;
+        self.consecutive_failures = 0;
+    }
+
+    pub(super) fn record_failure(&mut self) {
+        self.failed += 1;
+        self.consecutive_failures += 1;
+        self.last_failure = Some(Instant::now());
+    }
 }
 
 impl Default for EventDispatcher {
@@ -15,98 +148,605 @@ impl EventDispatcher {
     pub fn new() -> Self {
         Self {
             consumers: Vec::new(),
+            heartbeat: None,
+            clock: None,
+            sequences: None,
+            priorities: None,
+            delayed: BinaryHeap::new(),
+            delayed_count: 0,
+            ttl: None,
+            type_ttls: BTreeMap::new(),
+            expired_sink: None,
+            middleware: Vec::new(),
         }
     }
 
+    /// Runs `middleware` on every event before consumers see it, after any
+    /// added earlier. Heartbeats bypass middleware.
+    pub fn add_middleware<M: EventMiddleware + 'static>(&mut self, middleware: M) {
+        self.middleware.push(Box::new(middleware));
+    }
+
+    /// Skips events older than `ttl` when they are read, by their timestamp
+    /// or, for delayed events, by when they came due. Ages are measured
+    /// against the `set_clock` clock's current time, both converted by its
+    /// `to_unix_nanos`, so timestamps must come from that clock; without one
+    /// they must be Unix nanoseconds, as `SystemClock` stamps them. `None`
+    /// delivers events of any age.
+    pub fn set_ttl(&mut self, ttl: Option<Duration>) {
+        self.ttl = ttl;
+    }
+
+    /// Overrides the TTL for one event type; `None` removes the override.
+    pub fn set_type_ttl(&mut self, event_type: u8, ttl: Option<Duration>) {
+        match ttl {
+            Some(ttl) => self.type_ttls.insert(event_type, ttl),
+            None => self.type_ttls.remove(&event_type),
+        };
+    }
+
+    /// Receives expired events instead of dropping them.
+    pub fn set_expired_sink(&mut self, sink: Option<Box<dyn EventConsumer>>) {
+        self.expired_sink = sink;
+    }
+
+    /// `TYPE_DELAYED` events read but not yet due. They are held in memory
+    /// only, so they are lost if the dispatcher is dropped.
+    pub fn pending_delayed(&self) -> usize {
+        self.delayed.len()
+    }
+
+    /// When the earliest held event is due, in Unix nanoseconds.
+    pub fn next_delayed(&self) -> Option<u64> {
+        self.delayed.peek().map(|Reverse(event)| event.not_before)
+    }
+
+    /// Makes `drain` and `drain_batch` read their whole batch first and
+    /// deliver it highest priority first, keeping ring order within a
+    /// priority. Events written with `EventBuilder::priority` keep theirs;
+    /// `priorities` assigns the rest. Acking consumers may then go over
+    /// `max_in_flight` by up to one batch. `None` delivers in ring order.
+    pub fn set_priorities(&mut self, priorities: Option<Box<dyn Prioritizer>>) {
+        self.priorities = priorities;
+    }
+
+    /// Skips events whose sequence number (see `EventBuilder::sequence`) a
+    /// consumer already acked, as recorded in `store`. The store advances as
+    /// consumers accept or ack events and is saved whenever consumers are
+    /// flushed, i.e. after `drain` and `drain_set_batch` but not
+    /// `drain_batch`. `None` delivers duplicates again.
+    pub fn set_sequence_store(&mut self, store: Option<SequenceStore>) {
+        self.sequences = store;
+    }
+
+    pub fn sequence_store(&self) -> Option<&SequenceStore> {
+        self.sequences.as_ref()
+    }
+
+    /// Converts event timestamps from `clock` ticks to Unix nanoseconds before
+    /// consumers see them, for producers stamping with a raw-tick clock such
+    /// as `TscClock`.
+    pub fn set_clock(&mut self, clock: Option<Arc<dyn Clock>>) {
+        self.clock = clock;
+    }
+
+    /// Injects a `TYPE_HEARTBEAT` event to every consumer at the end of any
+    /// drain call made at least `interval` after the previous heartbeat, so
+    /// downstream can tell an idle pipeline from a stalled one. `None`
+    /// disables heartbeats.
+    pub fn set_heartbeat(&mut self, interval: Option<Duration>) {
+        self.heartbeat = interval.map(|interval| Heartbeat {
+            interval,
+            last: Instant::now(),
+            sequence: 0,
+        });
+    }
+
     pub fn add_consumer<C: EventConsumer + 'static>(&mut self, consumer: C) {
-        self.consumers.push(Box::new(consumer));
+        self.consumers
+            .push(Route::new(None, Target::Plain(Box::new(consumer))));
+    }
+
+    /// Adds a consumer with at-least-once delivery; see `consumer::ack`. It
+    /// counts an event as delivered once acked. Heartbeats are not sent to
+    /// it.
+    pub fn add_acking_consumer<C: AckingConsumer + 'static>(
+        &mut self,
+        consumer: C,
+        options: AckOptions,
+    ) {
+        let route = AckRoute::new(Box::new(consumer), options);
+        self.consumers.push(Route::new(None, Target::Acking(route)));
+    }
+
+    /// Adds a consumer that only sees events tagged with `stream`.
+    pub fn add_stream_consumer<C: EventConsumer + 'static>(
+        &mut self,
+        stream: StreamId,
+        consumer: C,
+    ) {
+        self.consumers
+            .push(Route::new(Some(stream), Target::Plain(Box::new(consumer))));
+    }
+
+    /// Adds a consumer that only sees events carrying every tag of `tags`;
+    /// see `Tags`.
+    pub fn add_tagged_consumer<C: EventConsumer + 'static>(&mut self, tags: Tags, consumer: C) {
+        let mut route = Route::new(None, Target::Plain(Box::new(consumer)));
+        route.tags = tags;
+        self.consumers.push(route);
+    }
+
+    /// Stops (or resumes) delivering events to every consumer named `name`,
+    /// e.g. one that keeps failing. Returns whether any consumer matched.
+    pub fn set_quarantined(&mut self, name: &str, quarantined: bool) -> bool {
+        let mut found = false;
+        for route in &mut self.consumers {
+            if route.health.name == name {
+                route.health.quarantined = quarantined;
+                found = true;
+            }
+        }
+        found
+    }
+
+    /// Delivery counts for each consumer, in the order they were added.
+    pub fn consumer_health(&self) -> impl Iterator<Item = &ConsumerHealth> {
+        self.consumers.iter().map(|r| &r.health)
     }
 
     #[inline]
-    pub fn drain(&mut self, ring: &mut RingBuffer) -> DrainStats {
-        let mut stats = DrainStats::default();
-        while let Some((header, payload)) = ring.read_event() {
-            stats.events_read += 1;
-            for consumer in &mut self.consumers {
-                if consumer.consume(&header, &payload) {
-                    stats.events_delivered += 1;
-                } else {
-                    stats.events_failed += 1;
-                }
+    fn dispatch(&mut self, header: &EventHeader, payload: &[u8], stats: &mut DrainStats) {
+        let Some((not_before, inner, body)) = header.split_delay(payload) else {
+            return self.deliver(header, payload, None, stats);
+        };
+        if not_before <= SystemClock.now() {
+            self.deliver(&inner, body, Some(not_before), stats);
+        } else {
+            self.delayed_count += 1;
+            self.delayed.push(Reverse(Delayed {
+                not_before,
+                arrival: self.delayed_count,
+                header: inner,
+                payload: body.to_vec(),
+            }));
+            stats.events_delayed += 1;
+        }
+    }
+
+    /// Whether an event of `event_type` that became deliverable at
+    /// `since`, converted by `set_clock`, is past its TTL.
+    #[inline]
+    fn is_expired(&self, event_type: u8, since: u64) -> bool {
+        let Some(ttl) = self.type_ttls.get(&event_type).or(self.ttl.as_ref()) else {
+            return false;
+        };
+        let now = match &self.clock {
+            Some(clock) => clock.to_unix_nanos(clock.now()),
+            None => SystemClock.now(),
+        };
+        now.saturating_sub(since) > ttl.as_nanos() as u64
+    }
+
+    /// Delivers one unwrapped event to every consumer; `due` is when a
+    /// delayed event came due.
+    #[inline]
+    fn deliver(
+        &mut self,
+        header: &EventHeader,
+        payload: &[u8],
+        due: Option<u64>,
+        stats: &mut DrainStats,
+    ) {
+        let start = Instant::now();
+        let mut header = *header;
+        if let Some(clock) = &self.clock {
+            header.timestamp = clock.to_unix_nanos(header.timestamp);
+        }
+        stats.events_read += 1;
+        stats.bytes_read += header.total_size() as u64;
+        *stats.events_by_type.entry(header.event_type).or_insert(0) += 1;
+        if self.is_expired(header.event_type, due.unwrap_or(header.timestamp)) {
+            stats.events_expired += 1;
+            if let Some(sink) = &mut self.expired_sink {
+                sink.consume(&header, payload);
             }
+            return;
         }
-        for consumer in &mut self.consumers {
-            consumer.flush();
+        let mut payload = Cow::Borrowed(payload);
+        if !self.run_middleware(&mut header, &mut payload) {
+            stats.events_filtered += 1;
+            return;
         }
-        stats
+        let (header, payload) = (&header, &*payload);
+        let sequence = match &self.sequences {
+            Some(_) => header.split_sequence(payload).0,
+            None => None,
+        };
+        let mut tags = None;
+        for route in &mut self.consumers {
+            if route.health.quarantined || route.stream.is_some_and(|s| s != header.stream()) {
+                continue;
+            }
+            if !route.tags.is_empty()
+                && !tags
+                    .get_or_insert_with(|| {
+                        Tags::of(&EventView {
+                            header: *header,
+                            payload,
+                        })
+                    })
+                    .contains(route.tags)
+            {
+                continue;
+            }
+            if let (Some(store), Some(seq)) = (&self.sequences, sequence)
+                && store.is_duplicate(&route.health.name, seq)
+            {
+                stats.events_deduplicated += 1;
+                continue;
+            }
+            instrument_span!(
+                TRACE,
+                "ringlog.dispatch",
+                consumer = route.health.name.as_str()
+            );
+            let consumer = match &mut route.target {
+                Target::Plain(consumer) => consumer,
+                Target::Acking(acks) => {
+                    acks.deliver(header, payload);
+                    route.health.unacked = acks.in_flight() as u64;
+                    continue;
+                }
+            };
+            if consumer.consume(header, payload) {
+                stats.events_delivered += 1;
+                route.health.record_delivery();
+                if let (Some(store), Some(seq)) = (&mut self.sequences, sequence) {
+                    store.record(&route.health.name, seq);
+                }
+            } else {
+                stats.events_failed += 1;
+                route.health.record_failure();
+                instrument_event!(
+                    DEBUG,
+                    consumer = route.health.name.as_str(),
+                    event_type = header.event_type,
+                    "delivery failed"
+                );
+            }
+        }
+        stats.record_latency(start.elapsed());
     }
 
+    /// Runs the middleware chain; false if the event was dropped.
     #[inline]
-    pub fn drain_spsc(&mut self, consumer: &mut Consumer<'_>) -> DrainStats {
-        let mut stats = DrainStats::default();
-        while let Some((header, payload)) = consumer.read_event() {
-            stats.events_read += 1;
-            for c in &mut self.consumers {
-                if c.consume(&header, &payload) {
-                    stats.events_delivered += 1;
-                } else {
-                    stats.events_failed += 1;
+    fn run_middleware(&mut self, header: &mut EventHeader, payload: &mut Cow<'_, [u8]>) -> bool {
+        for middleware in &mut self.middleware {
+            match middleware.on_event(header, payload) {
+                Action::Continue => {}
+                Action::Drop => return false,
+                Action::Replace => {
+                    let Ok(payload_len) = u16::try_from(payload.len()) else {
+                        instrument_event!(
+                            WARN,
+                            middleware = middleware.name(),
+                            payload_len = payload.len(),
+                            "rewritten payload too large, event dropped"
+                        );
+                        return false;
+                    };
+                    header.payload_len = payload_len;
+                    if header.has_checksum() {
+                        *header = header.with_checksum(payload);
+                    }
                 }
             }
         }
-        for c in &mut self.consumers {
-            c.flush();
+        true
+    }
+
+    fn maybe_heartbeat(&mut self, stats: &mut DrainStats) {
+        let Some(heartbeat) = &mut self.heartbeat else {
+            return;
+        };
+        if heartbeat.last.elapsed() < heartbeat.interval {
+            return;
         }
-        stats
+        heartbeat.last = Instant::now();
+        heartbeat.sequence += 1;
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64);
+        let header = EventHeader::new(timestamp, EventHeader::TYPE_HEARTBEAT, 8);
+        let payload = heartbeat.sequence.to_le_bytes();
+        for route in &mut self.consumers {
+            if let Target::Plain(consumer) = &mut route.target
+                && !route.health.quarantined
+            {
+                consumer.consume(&header, &payload);
+            }
+        }
+        stats.heartbeats += 1;
     }
 
+    /// Flushes every consumer, then saves the sequence store, so a sequence
+    /// is only persisted once its consumer flushed the event.
+    fn flush(&mut self) {
+        for route in &mut self.consumers {
+            match &mut route.target {
+                Target::Plain(consumer) => consumer.flush(),
+                Target::Acking(acks) => acks.flush(),
+            }
+        }
+        if let Some(sink) = &mut self.expired_sink {
+            sink.flush();
+        }
+        if let Some(store) = &mut self.sequences
+            && let Err(_e) = store.save()
+        {
+            // The store stays dirty and is saved again after the next drain.
+            instrument_event!(WARN, error = %_e, "sequence store not saved");
+        }
+    }
+
+    /// Dispatches held `TYPE_DELAYED` events that are now due, earliest
+    /// first.
+    fn release_delayed(&mut self, stats: &mut DrainStats) {
+        let now = SystemClock.now();
+        while self
+            .delayed
+            .peek()
+            .is_some_and(|Reverse(event)| event.not_before <= now)
+        {
+            let Reverse(event) = self.delayed.pop().unwrap();
+            self.deliver(&event.header, &event.payload, Some(event.not_before), stats);
+        }
+    }
+
+    /// Applies acks, then redelivers or dead-letters what was nacked or
+    /// timed out.
+    fn settle_acks(&mut self, stats: &mut DrainStats) {
+        for route in &mut self.consumers {
+            if let Target::Acking(acks) = &mut route.target {
+                acks.settle(&mut route.health, stats);
+                route.health.unacked = acks.in_flight() as u64;
+                if let (Some(store), Some(seq)) = (&mut self.sequences, acks.committed_sequence()) {
+                    store.record(&route.health.name, seq);
+                }
+            }
+        }
+    }
+
+    /// Whether an acking consumer has as many unacked events as it allows,
+    /// so nothing more should be read.
     #[inline]
-    pub fn drain_batch(&mut self, ring: &mut RingBuffer, limit: usize) -> DrainStats {
-        let mut stats = DrainStats::default();
+    fn acks_saturated(&self) -> bool {
+        self.consumers
+            .iter()
+            .any(|route| matches!(&route.target, Target::Acking(acks) if acks.is_saturated()))
+    }
+
+    /// The smallest credit among consumers that are not quarantined.
+    fn credit(&self) -> Credit {
+        self.consumers
+            .iter()
+            .filter(|route| !route.health.quarantined)
+            .map(|route| match &route.target {
+                Target::Plain(consumer) => consumer.credit(),
+                Target::Acking(acks) => acks.credit(),
+            })
+            .fold(Credit::UNLIMITED, Credit::min)
+    }
+
+    /// Whether another event may be read: credit is left and no acking
+    /// consumer is saturated. Counts a stall when credit ran out.
+    #[inline]
+    fn may_read(&self, credit: &Credit, stats: &mut DrainStats) -> bool {
+        if !credit.allows_more() {
+            stats.credit_stalls += 1;
+            return false;
+        }
+        !self.acks_saturated()
+    }
+
+    /// Reads up to `limit` events within credit and dispatches them, in
+    /// priority order if priorities are set.
+    #[inline]
+    fn read_and_dispatch<S: EventSource + ?Sized>(
+        &mut self,
+        source: &mut S,
+        limit: usize,
+        stats: &mut DrainStats,
+    ) {
+        let mut credit = self.credit();
+        let Some(priorities) = &self.priorities else {
+            for _ in 0..limit {
+                if !self.may_read(&credit, stats) {
+                    break;
+                }
+                let Some((header, payload)) = source.next_event() else {
+                    break;
+                };
+                credit.spend(header.total_size());
+                self.dispatch(&header, &payload, stats);
+            }
+            return;
+        };
+
+        let mut batch = Vec::new();
         for _ in 0..limit {
-            let Some((header, payload)) = ring.read_event() else {
+            if !self.may_read(&credit, stats) {
                 break;
-            };
-            stats.events_read += 1;
-            for consumer in &mut self.consumers {
-                if consumer.consume(&header, &payload) {
-                    stats.events_delivered += 1;
-                } else {
-                    stats.events_failed += 1;
-                }
             }
+            let Some((header, payload)) = source.next_event() else {
+                break;
+            };
+            credit.spend(header.total_size());
+            let priority = header
+                .priority(&payload)
+                .unwrap_or_else(|| priorities.priority(&header));
+            batch.push((priority, header, payload));
         }
+        // Stable, so ring order is kept within a priority.
+        batch.sort_by_key(|(priority, _, _)| Reverse(*priority));
+        for (_, header, payload) in batch {
+            self.dispatch(&header, &payload, stats);
+        }
+    }
+
+    #[inline]
+    pub fn drain<S: EventSource + ?Sized>(&mut self, source: &mut S) -> DrainStats {
+        instrument_span!(DEBUG, "ringlog.drain");
+        let start = Instant::now();
+        let mut stats = DrainStats::default();
+        self.settle_acks(&mut stats);
+        self.release_delayed(&mut stats);
+        self.read_and_dispatch(source, usize::MAX, &mut stats);
+        self.maybe_heartbeat(&mut stats);
+        self.flush();
+        stats.elapsed = start.elapsed();
+        stats.instrument();
         stats
     }
 
     #[inline]
-    pub fn drain_spsc_batch(&mut self, consumer: &mut Consumer<'_>, limit: usize) -> DrainStats {
+    pub fn drain_batch<S: EventSource + ?Sized>(
+        &mut self,
+        source: &mut S,
+        limit: usize,
+    ) -> DrainStats {
+        instrument_span!(DEBUG, "ringlog.drain", limit);
+        let start = Instant::now();
         let mut stats = DrainStats::default();
+        self.settle_acks(&mut stats);
+        self.release_delayed(&mut stats);
+        self.read_and_dispatch(source, limit, &mut stats);
+        self.maybe_heartbeat(&mut stats);
+        stats.elapsed = start.elapsed();
+        stats.instrument();
+        stats
+    }
+
+    /// Drains every ring in `set`, highest priority first.
+    pub fn drain_set(&mut self, set: &mut RingSet) -> RingSetStats {
+        self.drain_set_batch(set, usize::MAX)
+    }
+
+    /// Dispatches up to `limit` events from `set`. The highest-priority ring is
+    /// rechecked before every event, so events arriving mid-batch preempt
+    /// lower-priority ones.
+    pub fn drain_set_batch(&mut self, set: &mut RingSet, limit: usize) -> RingSetStats {
+        instrument_span!(DEBUG, "ringlog.drain", limit);
+        let start = Instant::now();
+        let mut stats = RingSetStats {
+            heartbeat: DrainStats::default(),
+            elapsed: Duration::ZERO,
+            per_ring: set
+                .names()
+                .map(|n| (n.to_string(), DrainStats::default()))
+                .collect(),
+        };
+        self.settle_acks(&mut stats.heartbeat);
+        self.release_delayed(&mut stats.heartbeat);
+        let mut credit = self.credit();
         for _ in 0..limit {
-            let Some((header, payload)) = consumer.read_event() else {
+            if !self.may_read(&credit, &mut stats.heartbeat) {
                 break;
-            };
-            stats.events_read += 1;
-            for c in &mut self.consumers {
-                if c.consume(&header, &payload) {
-                    stats.events_delivered += 1;
-                } else {
-                    stats.events_failed += 1;
-                }
             }
+            let Some((index, header, payload)) = set.read_event() else {
+                break;
+            };
+            credit.spend(header.total_size());
+            self.dispatch(&header, &payload, &mut stats.per_ring[index].1);
         }
+        self.maybe_heartbeat(&mut stats.heartbeat);
+        self.flush();
+        stats.elapsed = start.elapsed();
+        #[cfg(feature = "instrument")]
+        stats.total().instrument();
         stats
     }
 }
 
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Default, Clone)]
+pub struct RingSetStats {
+    /// `(ring name, stats)`, highest priority first.
+    pub per_ring: Vec<(String, DrainStats)>,
+    /// Heartbeats injected during the drain, acks, redeliveries and dead
+    /// letters of earlier deliveries, and delayed events that came due; they
+    /// belong to no ring.
+    pub heartbeat: DrainStats,
+    pub elapsed: Duration,
+}
+
+impl RingSetStats {
+    pub fn total(&self) -> DrainStats {
+        let mut total = DrainStats::default();
+        for (_, stats) in &self.per_ring {
+            total.merge(stats);
+        }
+        total.merge(&self.heartbeat);
+        total.elapsed = self.elapsed;
+        total
+    }
+
+    pub fn ring(&self, name: &str) -> Option<&DrainStats> {
+        self.per_ring
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, s)| s)
+    }
+}
+
+#[derive(Debug, Default, Clone)]
 pub struct DrainStats {
     pub events_read: u64,
     pub events_delivered: u64,
     pub events_failed: u64,
+    /// Header plus payload bytes of every event read.
+    pub bytes_read: u64,
+    pub events_by_type: BTreeMap<u8, u64>,
+    /// Time spent handing single events to all consumers.
+    pub max_dispatch_latency: Duration,
+    pub total_dispatch_latency: Duration,
+    /// Wall time of the whole drain call, including reads and flushes.
+    pub elapsed: Duration,
+    pub heartbeats: u64,
+    /// Deliveries to acking consumers repeated after a nack or timeout.
+    pub events_redelivered: u64,
+    /// Deliveries skipped because the consumer already acked the event's
+    /// sequence number.
+    pub events_deduplicated: u64,
+    /// Events acking consumers never acked within `max_attempts`.
+    pub events_dead_lettered: u64,
+    /// Drains that stopped reading because a consumer's `Credit` ran out.
+    pub credit_stalls: u64,
+    /// `TYPE_DELAYED` events read before they were due and held back; they
+    /// count as read once delivered.
+    pub events_delayed: u64,
+    /// Events skipped because they were past their TTL when read.
+    pub events_expired: u64,
+    /// Events dropped by middleware.
+    pub events_filtered: u64,
 }
 
 impl DrainStats {
+    /// Emits one drain's counts as `tracing` counters.
+    #[inline]
+    fn instrument(&self) {
+        instrument_event!(
+            DEBUG,
+            monotonic_counter.ringlog_events_read = self.events_read,
+            monotonic_counter.ringlog_events_delivered = self.events_delivered,
+            monotonic_counter.ringlog_events_failed = self.events_failed,
+            monotonic_counter.ringlog_bytes_read = self.bytes_read,
+            elapsed_us = self.elapsed.as_micros() as u64,
+            "drain finished"
+        );
+    }
+
     #[inline]
     pub fn success_rate(&self) -> f64 {
         let total = self.events_delivered + self.events_failed;
@@ -116,4 +756,42 @@ impl DrainStats {
             self.events_delivered as f64 / total as f64
         }
     }
+
+    pub fn mean_dispatch_latency(&self) -> Duration {
+        if self.events_read == 0 {
+            Duration::ZERO
+        } else {
+            Duration::from_nanos(
+                (self.total_dispatch_latency.as_nanos() / self.events_read as u128) as u64,
+            )
+        }
+    }
+
+    /// Accumulates `other` into `self`, e.g. across drain iterations.
+    pub fn merge(&mut self, other: &DrainStats) {
+        self.events_read += other.events_read;
+        self.events_delivered += other.events_delivered;
+        self.events_failed += other.events_failed;
+        self.bytes_read += other.bytes_read;
+        for (event_type, count) in &other.events_by_type {
+            *self.events_by_type.entry(*event_type).or_insert(0) += count;
+        }
+        self.max_dispatch_latency = self.max_dispatch_latency.max(other.max_dispatch_latency);
+        self.total_dispatch_latency += other.total_dispatch_latency;
+        self.elapsed += other.elapsed;
+        self.heartbeats += other.heartbeats;
+        self.events_redelivered += other.events_redelivered;
+        self.events_dead_lettered += other.events_dead_lettered;
+        self.events_deduplicated += other.events_deduplicated;
+        self.credit_stalls += other.credit_stalls;
+        self.events_delayed += other.events_delayed;
+        self.events_expired += other.events_expired;
+        self.events_filtered += other.events_filtered;
+    }
+
+    #[inline]
+    fn record_latency(&mut self, latency: Duration) {
+        self.max_dispatch_latency = self.max_dispatch_latency.max(latency);
+        self.total_dispatch_latency += latency;
+    }
 }
@@ -0,0 +1,118 @@
+//! Appends metadata to event payloads as `FieldWriter` fields, so persisted
+//! events describe where they came from without producers writing it.
+
+use super::middleware::{Action, EventMiddleware};
+use crate::event::{EventHeader, FieldWriter};
+use crate::storage::metadata::hostname;
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+
+type Dynamic = Box<dyn Fn(&EventHeader, &mut FieldWriter) + Send>;
+
+/// Middleware appending fields to the payload of every event, or of the
+/// event types given to `for_types`. Static fields are encoded once;
+/// dynamic ones are computed per event. Compressed events, events the
+/// fields would grow past `u16::MAX` bytes, and all events while a field
+/// cannot be encoded (see `FieldWriter`), are passed on unchanged.
+///
+/// The dispatcher runs middleware on the draining thread, so `with_thread`
+/// describes that thread, not the producer's.
+#[derive(Default)]
+pub struct Enricher {
+    fields: FieldWriter,
+    dynamic: Vec<Dynamic>,
+    event_types: Option<BTreeSet<u8>>,
+}
+
+impl Enricher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `host`, this machine's hostname, if it can be read.
+    pub fn with_host(mut self) -> Self {
+        if let Some(host) = hostname() {
+            self.fields.str("host", &host);
+        }
+        self
+    }
+
+    /// Adds `pid` and, if known, `process`, the executable's file name.
+    pub fn with_process(mut self) -> Self {
+        self.fields.u64("pid", std::process::id() as u64);
+        let name = std::env::current_exe().ok().and_then(|exe| {
+            exe.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+        });
+        if let Some(name) = name {
+            self.fields.str("process", &name);
+        }
+        self
+    }
+
+    /// Adds `thread`, the name of the thread the enricher runs on, or its id
+    /// if unnamed.
+    pub fn with_thread(self) -> Self {
+        self.with_dynamic(|_, fields| {
+            let thread = std::thread::current();
+            match thread.name() {
+                Some(name) => fields.str("thread", name),
+                None => fields.str("thread", &format!("{:?}", thread.id())),
+            };
+        })
+    }
+
+    /// Adds a fixed string field.
+    pub fn with_str(mut self, key: &str, value: &str) -> Self {
+        self.fields.str(key, value);
+        self
+    }
+
+    /// Adds fields computed per event by `fields`.
+    pub fn with_dynamic<F>(mut self, fields: F) -> Self
+    where
+        F: Fn(&EventHeader, &mut FieldWriter) + Send + 'static,
+    {
+        self.dynamic.push(Box::new(fields));
+        self
+    }
+
+    /// Only enriches events of these types, e.g. those whose payloads are
+    /// written with `FieldWriter`.
+    pub fn for_types<I: IntoIterator<Item = u8>>(mut self, event_types: I) -> Self {
+        self.event_types = Some(event_types.into_iter().collect());
+        self
+    }
+}
+
+impl EventMiddleware for Enricher {
+    fn on_event(&mut self, header: &mut EventHeader, payload: &mut Cow<'_, [u8]>) -> Action {
+        if header.flags & EventHeader::FLAG_COMPRESSED != 0
+            || self
+                .event_types
+                .as_ref()
+                .is_some_and(|types| !types.contains(&header.event_type))
+        {
+            return Action::Continue;
+        }
+        let mut dynamic = FieldWriter::new();
+        for fields in &self.dynamic {
+            fields(header, &mut dynamic);
+        }
+        let (Ok(fixed), Ok(dynamic)) = (self.fields.as_bytes(), dynamic.as_bytes()) else {
+            return Action::Continue;
+        };
+        let added = fixed.len() + dynamic.len();
+        if added == 0 || payload.len() + added > u16::MAX as usize {
+            return Action::Continue;
+        }
+        let bytes = payload.to_mut();
+        bytes.extend_from_slice(fixed);
+        bytes.extend_from_slice(dynamic);
+        Action::Replace
+    }
+
+    fn name(&self) -> &str {
+        "enricher"
+    }
+}
@@ -0,0 +1,26 @@
+//! Hooks run on every event before consumers see it, in the order they were
+//! added to the dispatcher, e.g. to redact, enrich or transform events
+//! without touching each consumer.
+
+use crate::event::EventHeader;
+use std::borrow::Cow;
+
+/// What happens to an event after a middleware saw it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Pass the event on unchanged.
+    Continue,
+    /// Skip the event; later middleware and consumers do not see it.
+    Drop,
+    /// The header or payload was rewritten. The dispatcher updates
+    /// `payload_len` and any checksum before passing the event on.
+    Replace,
+}
+
+pub trait EventMiddleware: Send {
+    /// Inspects, rewrites or drops one event. Rewrite the payload by
+    /// assigning `Cow::Owned`, then return `Action::Replace`.
+    fn on_event(&mut self, header: &mut EventHeader, payload: &mut Cow<'_, [u8]>) -> Action;
+
+    fn name(&self) -> &str;
+}
@@ -1,5 +1,36 @@
 use crate::event::EventHeader;
+pub mod ack;
+pub mod anomaly;
+#[cfg(feature = "clickhouse")]
+pub mod clickhouse;
+#[cfg(feature = "compression")]
+pub mod compress;
+pub mod credit;
+pub mod dedup;
 pub mod dispatcher;
+pub mod enrich;
+pub mod middleware;
+pub mod priority;
+pub mod redact;
+pub mod source;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+pub use ack::{AckOptions, Acker, AckingConsumer, DeliveryId};
+pub use anomaly::{AnomalyDetector, AnomalyKind, AnomalyOptions};
+#[cfg(feature = "clickhouse")]
+pub use clickhouse::{ClickHouseConfig, ClickHouseConsumer};
+#[cfg(feature = "compression")]
+pub use compress::{Compressor, Decompressor};
+pub use credit::Credit;
+pub use dedup::SequenceStore;
+pub use enrich::Enricher;
+pub use middleware::{Action, EventMiddleware};
+pub use priority::{Prioritizer, Priority, PriorityMap};
+pub use redact::Redactor;
+pub use source::{EventSource, ReplaySource};
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteConsumer;
 
 pub trait EventConsumer: Send {
     fn consume(&mut self, header: &EventHeader, payload: &[u8]) -> bool;
@@ -7,4 +38,10 @@ pub trait EventConsumer: Send {
     fn flush(&mut self) {}
 
     fn name(&self) -> &str;
+
+    /// How much the consumer can take in the next drain; asked once at the
+    /// start of each drain.
+    fn credit(&self) -> Credit {
+        Credit::UNLIMITED
+    }
 }
@@ -0,0 +1,53 @@
+//! Priorities for events written without one. An event written with
+//! `EventBuilder::priority` carries its own; the dispatcher asks a
+//! `Prioritizer` for the rest, from the event type, the stream or anything
+//! else in the header.
+
+pub use crate::event::Priority;
+use crate::event::{EventHeader, StreamId};
+use std::collections::BTreeMap;
+
+/// Assigns a priority to events that carry none; see
+/// `EventDispatcher::set_priorities`.
+pub trait Prioritizer: Send + Sync {
+    fn priority(&self, header: &EventHeader) -> Priority;
+}
+
+impl<F: Fn(&EventHeader) -> Priority + Send + Sync> Prioritizer for F {
+    fn priority(&self, header: &EventHeader) -> Priority {
+        self(header)
+    }
+}
+
+/// Priorities by event type, then by stream; anything else is `Normal`.
+#[derive(Debug, Clone, Default)]
+pub struct PriorityMap {
+    by_type: BTreeMap<u8, Priority>,
+    by_stream: BTreeMap<StreamId, Priority>,
+}
+
+impl PriorityMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_type(mut self, event_type: u8, priority: Priority) -> Self {
+        self.by_type.insert(event_type, priority);
+        self
+    }
+
+    pub fn with_stream(mut self, stream: StreamId, priority: Priority) -> Self {
+        self.by_stream.insert(stream, priority);
+        self
+    }
+}
+
+impl Prioritizer for PriorityMap {
+    fn priority(&self, header: &EventHeader) -> Priority {
+        self.by_type
+            .get(&header.event_type)
+            .or_else(|| self.by_stream.get(&header.stream()))
+            .copied()
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,172 @@
+//! Masks sensitive data before consumers see it. Rules mask byte ranges of
+//! the payload, or fields written by `FieldWriter` whose key matches a
+//! pattern. Masking keeps the payload length, so offsets and field encoding
+//! stay valid: string and byte values are overwritten with the mask byte,
+//! numbers and interned ids with zeros.
+
+use super::middleware::{Action, EventMiddleware};
+use crate::event::{EventHeader, FieldReader, FieldValue};
+use std::borrow::Cow;
+use std::ops::Range;
+
+enum Rule {
+    /// Field keys matching `pattern`, where `*` matches any run of
+    /// characters.
+    Field {
+        event_type: Option<u8>,
+        pattern: String,
+    },
+    Range {
+        event_type: u8,
+        range: Range<usize>,
+    },
+}
+
+/// Middleware applying redaction rules, e.g.
+/// `Redactor::new().mask_field("password").mask_field("*_token")`.
+/// Offsets and fields are relative to the application payload, after any
+/// sequence number or trace context.
+pub struct Redactor {
+    rules: Vec<Rule>,
+    mask: u8,
+}
+
+impl Default for Redactor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Redactor {
+    pub fn new() -> Self {
+        Self {
+            rules: Vec::new(),
+            mask: b'*',
+        }
+    }
+
+    /// Masks fields whose key matches `pattern` in events of any type.
+    pub fn mask_field(mut self, pattern: &str) -> Self {
+        self.rules.push(Rule::Field {
+            event_type: None,
+            pattern: pattern.to_string(),
+        });
+        self
+    }
+
+    /// Masks fields whose key matches `pattern` in events of `event_type`.
+    pub fn mask_field_of(mut self, event_type: u8, pattern: &str) -> Self {
+        self.rules.push(Rule::Field {
+            event_type: Some(event_type),
+            pattern: pattern.to_string(),
+        });
+        self
+    }
+
+    /// Masks `range` of the payload of events of `event_type`; the part past
+    /// the payload's end is ignored.
+    pub fn mask_range(mut self, event_type: u8, range: Range<usize>) -> Self {
+        self.rules.push(Rule::Range { event_type, range });
+        self
+    }
+
+    /// The byte string and byte values are overwritten with; `*` by default.
+    pub fn with_mask(mut self, mask: u8) -> Self {
+        self.mask = mask;
+        self
+    }
+
+    /// Byte ranges of `body` to mask with `self.mask`, and to zero.
+    fn spans(&self, event_type: u8, body: &[u8]) -> (Vec<Range<usize>>, Vec<Range<usize>>) {
+        let mut masked = Vec::new();
+        let mut zeroed = Vec::new();
+        let patterns: Vec<&str> = self
+            .rules
+            .iter()
+            .filter_map(|rule| match rule {
+                Rule::Field {
+                    event_type: t,
+                    pattern,
+                } if t.is_none_or(|t| t == event_type) => Some(pattern.as_str()),
+                _ => None,
+            })
+            .collect();
+        if !patterns.is_empty() {
+            let mut fields = FieldReader::new(body);
+            while let Some(Ok((key, value))) = fields.next() {
+                if !patterns.iter().any(|p| glob_match(p, key)) {
+                    continue;
+                }
+                let end = fields.offset();
+                match value {
+                    FieldValue::Str(s) => masked.push(end - s.len()..end),
+                    FieldValue::Bytes(b) => masked.push(end - b.len()..end),
+                    FieldValue::U64(_) | FieldValue::F64(_) => zeroed.push(end - 8..end),
+                    FieldValue::Interned(_) => zeroed.push(end - 4..end),
+                }
+            }
+        }
+        for rule in &self.rules {
+            if let Rule::Range {
+                event_type: t,
+                range,
+            } = rule
+                && *t == event_type
+            {
+                let end = range.end.min(body.len());
+                if range.start < end {
+                    masked.push(range.start..end);
+                }
+            }
+        }
+        (masked, zeroed)
+    }
+}
+
+impl EventMiddleware for Redactor {
+    fn on_event(&mut self, header: &mut EventHeader, payload: &mut Cow<'_, [u8]>) -> Action {
+        let body = header.body(payload);
+        let prefix = payload.len() - body.len();
+        let (masked, zeroed) = self.spans(header.event_type, body);
+        if masked.is_empty() && zeroed.is_empty() {
+            return Action::Continue;
+        }
+        let bytes = payload.to_mut();
+        for range in masked {
+            bytes[prefix + range.start..prefix + range.end].fill(self.mask);
+        }
+        for range in zeroed {
+            bytes[prefix + range.start..prefix + range.end].fill(0);
+        }
+        Action::Replace
+    }
+
+    fn name(&self) -> &str {
+        "redactor"
+    }
+}
+
+/// Whether `text` matches `pattern`, where `*` matches any run of bytes.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let (pattern, text) = (pattern.as_bytes(), text.as_bytes());
+    let (mut p, mut t) = (0, 0);
+    // Position after the last `*` seen, and the text position it matched up
+    // to, for backtracking.
+    let mut star = None;
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == b'*' {
+            star = Some((p + 1, t));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == text[t] {
+            p += 1;
+            t += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&b| b == b'*')
+}
@@ -0,0 +1,53 @@
+use crate::event::EventHeader;
+use crate::ring::{Consumer, RingBuffer, RingSet};
+use crate::storage::{EventIterator, MmapReader};
+
+/// Anything the dispatcher can drain events from.
+pub trait EventSource {
+    fn next_event(&mut self) -> Option<(EventHeader, Vec<u8>)>;
+}
+
+impl EventSource for RingBuffer {
+    #[inline]
+    fn next_event(&mut self) -> Option<(EventHeader, Vec<u8>)> {
+        self.read_event()
+    }
+}
+
+impl EventSource for Consumer<'_> {
+    #[inline]
+    fn next_event(&mut self) -> Option<(EventHeader, Vec<u8>)> {
+        self.read_event()
+    }
+}
+
+impl EventSource for RingSet {
+    #[inline]
+    fn next_event(&mut self) -> Option<(EventHeader, Vec<u8>)> {
+        self.read_event()
+            .map(|(_, header, payload)| (header, payload))
+    }
+}
+
+/// Replays a log file through the dispatcher, copying each payload out of the
+/// mapping.
+pub struct ReplaySource<'a> {
+    events: EventIterator<'a>,
+}
+
+impl<'a> ReplaySource<'a> {
+    pub fn new(reader: &'a MmapReader) -> Self {
+        Self {
+            events: reader.iter(),
+        }
+    }
+}
+
+impl EventSource for ReplaySource<'_> {
+    #[inline]
+    fn next_event(&mut self) -> Option<(EventHeader, Vec<u8>)> {
+        self.events
+            .next()
+            .map(|event| (event.header, event.payload.to_vec()))
+    }
+}
@@ -0,0 +1,136 @@
+//! A consumer that stores events in a SQLite database, for querying a local
+//! copy of the log with plain SQL:
+//!
+//! ```sql
+//! SELECT type, count(*) FROM events WHERE timestamp > ?1 GROUP BY type;
+//! ```
+
+use super::EventConsumer;
+use crate::event::EventHeader;
+use rusqlite::{Connection, params};
+use std::path::Path;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS events (
+        id        INTEGER PRIMARY KEY,
+        timestamp INTEGER NOT NULL,
+        type      INTEGER NOT NULL,
+        stream    INTEGER NOT NULL,
+        seq       INTEGER,
+        trace_id  INTEGER,
+        payload   BLOB NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS events_timestamp ON events (timestamp);
+    CREATE INDEX IF NOT EXISTS events_type ON events (type);
+";
+
+const INSERT: &str = "INSERT INTO events (timestamp, type, stream, seq, trace_id, payload)
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6)";
+
+/// Inserts events into an `events` table, created with indexes on
+/// `timestamp` and `type` if missing. Each drain's events go into one
+/// transaction, committed when the dispatcher flushes, so a drain of many
+/// events costs one fsync rather than one per event.
+///
+/// Timestamps, sequence numbers and trace ids are stored as SQLite's signed
+/// 64-bit integers; values past `i64::MAX` wrap negative. `payload` holds
+/// the application payload, without sequence or trace prefixes.
+pub struct SqliteConsumer {
+    conn: Connection,
+    batch_size: usize,
+    /// Events inserted in the open transaction.
+    pending: usize,
+    commit_failures: u64,
+}
+
+impl SqliteConsumer {
+    /// Opens or creates the database at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> rusqlite::Result<Self> {
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    /// Uses an open connection, e.g. `Connection::open_in_memory()`.
+    pub fn from_connection(conn: Connection) -> rusqlite::Result<Self> {
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self {
+            conn,
+            batch_size: usize::MAX,
+            pending: 0,
+            commit_failures: 0,
+        })
+    }
+
+    /// Also commits after every `batch_size` events, bounding the size of a
+    /// transaction when a drain is large. Panics if `batch_size` is zero.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        assert!(batch_size > 0, "batch size must be non-zero");
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// The connection, for queries over the stored events.
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+    /// Transactions that failed to commit. Their events were reported as
+    /// delivered but are not in the table.
+    pub fn commit_failures(&self) -> u64 {
+        self.commit_failures
+    }
+
+    fn insert(&mut self, header: &EventHeader, payload: &[u8]) -> rusqlite::Result<()> {
+        if self.conn.is_autocommit() {
+            self.conn.execute_batch("BEGIN")?;
+        }
+        let seq = header.split_sequence(payload).0;
+        let (trace, body) = header.split_trace(payload);
+        self.conn.prepare_cached(INSERT)?.execute(params![
+            header.timestamp as i64,
+            header.event_type,
+            header.stream().get(),
+            seq.map(|seq| seq as i64),
+            trace.map(|trace| trace.trace_id as i64),
+            body,
+        ])?;
+        self.pending += 1;
+        Ok(())
+    }
+
+    fn commit(&mut self) {
+        if self.conn.is_autocommit() {
+            return;
+        }
+        if self.conn.execute_batch("COMMIT").is_err() {
+            self.commit_failures += 1;
+            let _ = self.conn.execute_batch("ROLLBACK");
+        }
+        self.pending = 0;
+    }
+}
+
+impl EventConsumer for SqliteConsumer {
+    fn consume(&mut self, header: &EventHeader, payload: &[u8]) -> bool {
+        if self.insert(header, payload).is_err() {
+            return false;
+        }
+        if self.pending >= self.batch_size {
+            self.commit();
+        }
+        true
+    }
+
+    fn flush(&mut self) {
+        self.commit();
+    }
+
+    fn name(&self) -> &str {
+        "sqlite"
+    }
+}
+
+impl Drop for SqliteConsumer {
+    fn drop(&mut self) {
+        self.commit();
+    }
+}
@@ -0,0 +1,50 @@
+//! One error type for code that uses both the ring and the storage layer.
+
+use crate::ring::RingError;
+use crate::storage::StorageError;
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum Error {
+    Ring(RingError),
+    Storage(StorageError),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ring(e) => e.fmt(f),
+            Self::Storage(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Ring(e) => Some(e),
+            Self::Storage(e) => Some(e),
+        }
+    }
+}
+
+impl From<RingError> for Error {
+    fn from(e: RingError) -> Self {
+        Self::Ring(e)
+    }
+}
+
+impl From<StorageError> for Error {
+    fn from(e: StorageError) -> Self {
+        Self::Storage(e)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Self::Storage(StorageError::Io(e))
+    }
+}
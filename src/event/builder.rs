@@ -0,0 +1,256 @@
+use super::{CompactHeader, EventHeader, Priority, StreamId, TraceContext, crc32_vectored};
+use crate::Result;
+use crate::clock::{Clock, SystemClock};
+use crate::ring::{Producer, RingBuffer, RingError};
+use crate::storage::MmapWriter;
+use std::time::Duration;
+
+/// A destination for `EventBuilder`. Sinks report their own errors, e.g. a
+/// `StorageError` from a log file, wrapped in the crate's `Error`.
+pub trait EventSink {
+    fn write_parts(&mut self, header: &EventHeader, parts: &[&[u8]]) -> Result<()>;
+
+    fn event(&mut self, event_type: u8) -> EventBuilder<'_, Self>
+    where
+        Self: Sized,
+    {
+        EventBuilder::new(self, event_type)
+    }
+
+    /// A `TYPE_MARKER` event named `name`, for finding a point in the log
+    /// later with `find_marker` or `replay_since_marker`:
+    /// `writer.marker("deploy v1.2").write()?`.
+    fn marker<'a>(&'a mut self, name: &'a str) -> EventBuilder<'a, Self>
+    where
+        Self: Sized,
+    {
+        EventBuilder::reserved(self, EventHeader::TYPE_MARKER).payload(name.as_bytes())
+    }
+
+    /// A `TYPE_TOMBSTONE` event asking `compact_deleted` to remove the
+    /// events whose key is `key`: `writer.tombstone(user_id).write()?`.
+    fn tombstone<'a>(&'a mut self, key: &'a [u8]) -> EventBuilder<'a, Self>
+    where
+        Self: Sized,
+    {
+        EventBuilder::reserved(self, EventHeader::TYPE_TOMBSTONE).payload(key)
+    }
+}
+
+/// Builds and writes one event:
+/// `ring.event(ty).flag(EventHeader::FLAG_COMPRESSED).payload(bytes).write()?`.
+/// The timestamp defaults to `SystemClock` and `payload_len` is filled in.
+/// Reserved event types are refused by `write`; use `EventSink::marker`,
+/// `EventSink::tombstone` or `deliver_at` instead.
+pub struct EventBuilder<'a, S: ?Sized> {
+    sink: &'a mut S,
+    header: EventHeader,
+    timestamp: Option<u64>,
+    payload: &'a [u8],
+    sequence: Option<u64>,
+    trace: Option<TraceContext>,
+    not_before: Option<u64>,
+    priority: Option<Priority>,
+    fields: bool,
+    checksum: bool,
+    reserved: bool,
+}
+
+impl<'a, S: EventSink + ?Sized> EventBuilder<'a, S> {
+    pub fn new(sink: &'a mut S, event_type: u8) -> Self {
+        Self {
+            reserved: false,
+            ..Self::reserved(sink, event_type)
+        }
+    }
+
+    /// Like `new`, but allows a reserved event type.
+    fn reserved(sink: &'a mut S, event_type: u8) -> Self {
+        Self {
+            sink,
+            header: EventHeader::new(0, event_type, 0),
+            timestamp: None,
+            payload: &[],
+            sequence: None,
+            trace: None,
+            not_before: None,
+            priority: None,
+            fields: false,
+            checksum: false,
+            reserved: true,
+        }
+    }
+
+    pub fn timestamp(mut self, timestamp: u64) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    /// Stamps the timestamp from `clock` instead of the system clock.
+    pub fn clock(self, clock: &dyn Clock) -> Self {
+        self.timestamp(clock.now())
+    }
+
+    /// Sets flag bits; only those in `EventHeader::FLAG_MASK` are kept.
+    pub fn flag(mut self, flag: u8) -> Self {
+        self.header.flags |= flag & EventHeader::FLAG_MASK;
+        self
+    }
+
+    pub fn stream(mut self, stream: StreamId) -> Self {
+        self.header = self.header.with_stream(stream);
+        self
+    }
+
+    pub fn payload(mut self, payload: &'a [u8]) -> Self {
+        self.payload = payload;
+        self
+    }
+
+    /// Sets a payload written by `FieldWriter` and marks the event as
+    /// carrying fields, which `Tags::of` requires.
+    pub fn fields(mut self, payload: &'a [u8]) -> Self {
+        self.payload = payload;
+        self.fields = true;
+        self
+    }
+
+    /// Prefixes the payload with `sequence`; read it back with
+    /// `EventHeader::split_sequence`.
+    pub fn sequence(mut self, sequence: u64) -> Self {
+        self.sequence = Some(sequence);
+        self
+    }
+
+    /// Attaches trace and span ids; read them back with
+    /// `EventHeader::split_trace`.
+    pub fn trace(mut self, trace: TraceContext) -> Self {
+        self.trace = Some(trace);
+        self
+    }
+
+    /// Holds the event back in the dispatcher until `unix_nanos`; it is
+    /// written as `EventHeader::TYPE_DELAYED` and unwrapped on delivery.
+    pub fn deliver_at(mut self, unix_nanos: u64) -> Self {
+        self.not_before = Some(unix_nanos);
+        self
+    }
+
+    /// `deliver_at` the system clock's now plus `delay`.
+    pub fn delay(self, delay: Duration) -> Self {
+        let not_before = SystemClock.now().saturating_add(delay.as_nanos() as u64);
+        self.deliver_at(not_before)
+    }
+
+    /// Stamps `priority` in the event's extension byte, for priority-aware
+    /// drains; read it back with `EventHeader::priority`.
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.priority = Some(priority);
+        self
+    }
+
+    pub fn checksum(mut self) -> Self {
+        self.checksum = true;
+        self
+    }
+
+    pub fn write(self) -> Result<()> {
+        let mut header = self.header;
+        if !self.reserved && EventHeader::is_reserved_type(header.event_type) {
+            return Err(RingError::ReservedType {
+                event_type: header.event_type,
+            }
+            .into());
+        }
+        header.timestamp = self.timestamp.unwrap_or_else(|| SystemClock.now());
+
+        let delay = self.not_before.map(|not_before| {
+            let mut prefix = [0u8; EventHeader::DELAY_PREFIX_SIZE];
+            prefix[..8].copy_from_slice(&not_before.to_le_bytes());
+            prefix[8] = header.event_type;
+            prefix
+        });
+        let extension = (self.priority.is_some() || self.fields).then(|| {
+            let mut ext = 0;
+            if let Some(priority) = self.priority {
+                ext |= EventHeader::EXT_PRIORITY | priority.bits();
+            }
+            if self.fields {
+                ext |= EventHeader::EXT_FIELDS;
+            }
+            [ext]
+        });
+        let sequence = self.sequence.map(u64::to_le_bytes);
+        let trace = self.trace.map(|t| t.encode());
+        let mut buf: [&[u8]; 5] = [&[]; 5];
+        let mut count = 0;
+        if let Some(delay) = &delay {
+            header.event_type = EventHeader::TYPE_DELAYED;
+            buf[count] = delay;
+            count += 1;
+        }
+        if let Some(extension) = &extension {
+            header.flags |= EventHeader::FLAG_EXTENDED;
+            buf[count] = extension;
+            count += 1;
+        }
+        if let Some(seq) = &sequence {
+            header.flags |= EventHeader::FLAG_SEQUENCE;
+            buf[count] = seq;
+            count += 1;
+        }
+        if let Some(trace) = &trace {
+            header.flags |= EventHeader::FLAG_TRACE;
+            buf[count] = trace;
+            count += 1;
+        }
+        buf[count] = self.payload;
+        let parts = &buf[..=count];
+
+        let payload_len: usize = parts.iter().map(|p| p.len()).sum();
+        header.payload_len =
+            u16::try_from(payload_len).map_err(|_| RingError::PayloadTooLarge {
+                payload_len,
+                max_len: u16::MAX as usize,
+            })?;
+        if self.checksum {
+            header.flags |= EventHeader::FLAG_CHECKSUM;
+            header._reserved = crc32_vectored(parts);
+        }
+
+        self.sink.write_parts(&header, parts)
+    }
+}
+
+impl EventSink for RingBuffer {
+    fn write_parts(&mut self, header: &EventHeader, parts: &[&[u8]]) -> Result<()> {
+        Ok(self.write_event_vectored_unchecked(header, parts)?)
+    }
+}
+
+impl EventSink for Producer<'_> {
+    fn write_parts(&mut self, header: &EventHeader, parts: &[&[u8]]) -> Result<()> {
+        if self.write_event_vectored(header, parts) {
+            Ok(())
+        } else {
+            Err(RingError::NotEnoughSpace {
+                required: header.total_size(),
+                available: self.available(),
+            }
+            .into())
+        }
+    }
+}
+
+impl EventSink for MmapWriter {
+    fn write_parts(&mut self, header: &EventHeader, parts: &[&[u8]]) -> Result<()> {
+        if self.is_compact() && header.payload_len as usize > CompactHeader::MAX_PAYLOAD {
+            return Err(RingError::PayloadTooLarge {
+                payload_len: header.payload_len as usize,
+                max_len: CompactHeader::MAX_PAYLOAD,
+            }
+            .into());
+        }
+        Ok(self.write_event_unchecked(header, parts)?)
+    }
+}
@@ -0,0 +1,103 @@
+use super::EventHeader;
+use super::checksum::crc32_update;
+
+/// 8-byte header used by files created with `WriterOptions::compact`:
+/// timestamp delta from the previous event (u32), event_type, payload_len
+/// (u8), flags, and the low byte of a CRC-32 over the other seven header bytes
+/// and the payload. Halves the framing cost of tiny events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompactHeader {
+    pub delta: u32,
+    pub event_type: u8,
+    pub payload_len: u8,
+    pub flags: u8,
+    pub crc: u8,
+}
+
+impl CompactHeader {
+    pub const SIZE: usize = 8;
+    pub const MAX_PAYLOAD: usize = u8::MAX as usize;
+
+    /// Reserved type in compact files. The payload is a little-endian u64
+    /// timestamp that the next delta counts from; one is written whenever a
+    /// delta does not fit in a u32 or would be negative. Readers consume these
+    /// records and never return them as events.
+    pub const TYPE_TIME_BASE: u8 = 0xFE;
+
+    /// `None` if the payload is longer than `MAX_PAYLOAD`.
+    pub fn new(delta: u32, event_type: u8, flags: u8, parts: &[&[u8]]) -> Option<Self> {
+        let payload_len: usize = parts.iter().map(|p| p.len()).sum();
+        let mut header = Self {
+            delta,
+            event_type,
+            payload_len: u8::try_from(payload_len).ok()?,
+            flags,
+            crc: 0,
+        };
+        header.crc = header.compute_crc(parts);
+        Some(header)
+    }
+
+    /// A time-base record resetting the delta origin to `timestamp`.
+    pub fn time_base(timestamp: u64) -> (Self, [u8; 8]) {
+        let payload = timestamp.to_le_bytes();
+        let header = Self::new(0, Self::TYPE_TIME_BASE, 0, &[&payload]).unwrap();
+        (header, payload)
+    }
+
+    /// Layout: delta (4, little-endian), event_type, payload_len, flags, crc.
+    pub fn encode(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0u8; Self::SIZE];
+        bytes[0..4].copy_from_slice(&self.delta.to_le_bytes());
+        bytes[4] = self.event_type;
+        bytes[5] = self.payload_len;
+        bytes[6] = self.flags;
+        bytes[7] = self.crc;
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8; Self::SIZE]) -> Self {
+        Self {
+            delta: u32::from_le_bytes(bytes[0..4].try_into().unwrap()),
+            event_type: bytes[4],
+            payload_len: bytes[5],
+            flags: bytes[6],
+            crc: bytes[7],
+        }
+    }
+
+    #[inline]
+    pub fn decode_from(bytes: &[u8]) -> Option<Self> {
+        bytes
+            .get(..Self::SIZE)
+            .map(|b| Self::decode(b.try_into().unwrap()))
+    }
+
+    #[inline]
+    pub fn total_size(&self) -> usize {
+        Self::SIZE + self.payload_len as usize
+    }
+
+    pub fn compute_crc(&self, parts: &[&[u8]]) -> u8 {
+        let bytes = self.encode();
+        let crc = crc32_update(!0, &bytes[..Self::SIZE - 1]);
+        !parts.iter().fold(crc, |crc, part| crc32_update(crc, part)) as u8
+    }
+
+    #[inline]
+    pub fn verify(&self, payload: &[u8]) -> bool {
+        self.compute_crc(&[payload]) == self.crc
+    }
+
+    /// The full header for this event, given the timestamp of the previous
+    /// one. The compact CRC is not carried over.
+    pub fn to_header(&self, previous: u64) -> EventHeader {
+        let mut header = EventHeader::new(
+            previous.wrapping_add(self.delta as u64),
+            self.event_type,
+            self.payload_len as u16,
+        );
+        header.flags = self.flags;
+        header
+    }
+}
@@ -0,0 +1,73 @@
+//! zstd compression of event payloads, shared by the `Compressor` and
+//! `Decompressor` middleware and by readers of stored events. Only the
+//! application payload is compressed; a sequence number or trace context in
+//! front of it stays readable, and `FLAG_COMPRESSED` marks the event.
+
+use super::{EventHeader, EventView};
+use std::borrow::Cow;
+use std::io;
+
+/// Compresses the application payload of an event at zstd `level`. `None`
+/// if the event is already compressed or compressing would not shrink it.
+pub fn compress(
+    header: &EventHeader,
+    payload: &[u8],
+    level: i32,
+) -> io::Result<Option<(EventHeader, Vec<u8>)>> {
+    if header.flags & EventHeader::FLAG_COMPRESSED != 0 {
+        return Ok(None);
+    }
+    let body = header.body(payload);
+    let prefix = &payload[..payload.len() - body.len()];
+    let compressed = zstd::bulk::compress(body, level)?;
+    if compressed.len() >= body.len() {
+        return Ok(None);
+    }
+
+    let mut out = Vec::with_capacity(prefix.len() + compressed.len());
+    out.extend_from_slice(prefix);
+    out.extend_from_slice(&compressed);
+    Ok(Some((repack(header, &out), out)))
+}
+
+/// Undoes `compress`, clearing `FLAG_COMPRESSED`. Events that are not
+/// compressed are returned as they are.
+pub fn decompress<'a>(
+    header: &EventHeader,
+    payload: &'a [u8],
+) -> io::Result<(EventHeader, Cow<'a, [u8]>)> {
+    if header.flags & EventHeader::FLAG_COMPRESSED == 0 {
+        return Ok((*header, Cow::Borrowed(payload)));
+    }
+    let body = header.body(payload);
+    let prefix = &payload[..payload.len() - body.len()];
+    let body = zstd::bulk::decompress(body, u16::MAX as usize - prefix.len())?;
+
+    let mut out = Vec::with_capacity(prefix.len() + body.len());
+    out.extend_from_slice(prefix);
+    out.extend_from_slice(&body);
+    let mut header = repack(header, &out);
+    header.flags &= !EventHeader::FLAG_COMPRESSED;
+    Ok((header, Cow::Owned(out)))
+}
+
+/// `header` with `FLAG_COMPRESSED` toggled on, `payload_len` and any
+/// checksum updated for `payload`.
+fn repack(header: &EventHeader, payload: &[u8]) -> EventHeader {
+    let mut header = EventHeader {
+        flags: header.flags | EventHeader::FLAG_COMPRESSED,
+        payload_len: payload.len() as u16,
+        ..*header
+    };
+    if header.has_checksum() {
+        header = header.with_checksum(payload);
+    }
+    header
+}
+
+impl<'a> EventView<'a> {
+    /// The event with its payload decompressed; see `decompress`.
+    pub fn decompressed(&self) -> io::Result<(EventHeader, Cow<'a, [u8]>)> {
+        decompress(&self.header, self.payload)
+    }
+}
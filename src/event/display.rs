@@ -0,0 +1,273 @@
+//! Human-readable forms of events, shared by `ringlog dump`, `ringlog-cli`
+//! and the service's print consumer:
+//!
+//! ```text
+//! 2023-11-14T22:13:20.000000042Z type=login(3) stream=0 seq=7 user="alice"
+//! ```
+
+use super::{CompactHeader, EventHeader, EventView, FieldReader, StringTable};
+use crate::clock::civil_from_days;
+use crate::storage::Metadata;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Payload bytes shown by the inline preview before it is cut off.
+const PREVIEW_BYTES: usize = 64;
+
+/// Names for event types, kept in a file's `Metadata` as `type.<id>=<name>`
+/// entries. The reserved types are always named.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TypeNames {
+    names: BTreeMap<u8, String>,
+}
+
+impl TypeNames {
+    const KEY_PREFIX: &'static str = "type.";
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Names `event_type`, replacing any earlier name.
+    pub fn register(&mut self, event_type: u8, name: &str) -> &mut Self {
+        self.names.insert(event_type, name.to_string());
+        self
+    }
+
+    /// The registered name of `event_type`, or the name of a reserved type.
+    pub fn name(&self, event_type: u8) -> Option<&str> {
+        self.names
+            .get(&event_type)
+            .map(String::as_str)
+            .or_else(|| reserved_name(event_type))
+    }
+
+    pub fn write_to(&self, metadata: &mut Metadata) {
+        for (event_type, name) in &self.names {
+            metadata
+                .entries
+                .insert(format!("{}{}", Self::KEY_PREFIX, event_type), name.clone());
+        }
+    }
+
+    pub fn from_metadata(metadata: &Metadata) -> Self {
+        let names = metadata
+            .entries
+            .iter()
+            .filter_map(|(key, name)| {
+                let event_type = key.strip_prefix(Self::KEY_PREFIX)?.parse().ok()?;
+                Some((event_type, name.clone()))
+            })
+            .collect();
+        Self { names }
+    }
+}
+
+fn reserved_name(event_type: u8) -> Option<&'static str> {
+    match event_type {
+        EventHeader::TYPE_HEARTBEAT => Some("heartbeat"),
+        CompactHeader::TYPE_TIME_BASE => Some("time-base"),
+        EventHeader::TYPE_DELAYED => Some("delayed"),
+        EventHeader::TYPE_TOMBSTONE => Some("tombstone"),
+        EventHeader::TYPE_MARKER => Some("marker"),
+        _ => None,
+    }
+}
+
+/// Unix nanoseconds shown as an RFC 3339 UTC time, e.g.
+/// `2023-11-14T22:13:20.000000042Z`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timestamp(pub u64);
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let secs = self.0 / 1_000_000_000;
+        let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+        let rem = secs % 86_400;
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:09}Z",
+            year,
+            month,
+            day,
+            rem / 3600,
+            rem / 60 % 60,
+            rem % 60,
+            self.0 % 1_000_000_000
+        )
+    }
+}
+
+struct EventType<'a>(u8, Option<&'a TypeNames>);
+
+impl fmt::Display for EventType<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self.1 {
+            Some(names) => names.name(self.0),
+            None => reserved_name(self.0),
+        };
+        match name {
+            Some(name) => write!(f, "{}({})", name, self.0),
+            None => write!(f, "{}", self.0),
+        }
+    }
+}
+
+/// `<time> type=<type> stream=<id> flags=<names> len=<payload_len>`, with
+/// `flags` left out when none are set.
+impl fmt::Display for EventHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} type={} stream={}",
+            Timestamp(self.timestamp),
+            EventType(self.event_type, None),
+            self.stream().get()
+        )?;
+        let flags = [
+            (Self::FLAG_CHECKSUM, "checksum"),
+            (Self::FLAG_SEQUENCE, "seq"),
+            (Self::FLAG_COMPRESSED, "compressed"),
+            (Self::FLAG_TRACE, "trace"),
+            (Self::FLAG_EXTENDED, "ext"),
+        ];
+        let mut set = flags.iter().filter(|(flag, _)| self.flags & flag != 0);
+        if let Some((_, name)) = set.next() {
+            write!(f, " flags={}", name)?;
+            for (_, name) in set {
+                write!(f, "|{}", name)?;
+            }
+        }
+        write!(f, " len={}", self.payload_len)
+    }
+}
+
+/// Renders an event on one line; see `EventView::display`.
+pub struct EventDisplay<'a> {
+    event: EventView<'a>,
+    types: Option<&'a TypeNames>,
+    strings: Option<&'a StringTable>,
+}
+
+impl<'a> EventDisplay<'a> {
+    /// Shows registered type names from `types`.
+    pub fn with_types(mut self, types: &'a TypeNames) -> Self {
+        self.types = Some(types);
+        self
+    }
+
+    /// Resolves interned `FieldWriter` strings through `strings`.
+    pub fn with_strings(mut self, strings: &'a StringTable) -> Self {
+        self.strings = Some(strings);
+        self
+    }
+}
+
+impl<'a> EventView<'a> {
+    /// A `Display` of the event: its time, type, stream, any sequence
+    /// number and trace ids, then the payload as `FieldWriter` fields,
+    /// quoted UTF-8 or hex, shortened if long.
+    pub fn display(&self) -> EventDisplay<'a> {
+        EventDisplay {
+            event: *self,
+            types: None,
+            strings: None,
+        }
+    }
+}
+
+impl fmt::Display for EventDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let header = &self.event.header;
+        let payload = self.event.payload;
+        write!(
+            f,
+            "{} type={} stream={}",
+            Timestamp(header.timestamp),
+            EventType(header.event_type, self.types),
+            header.stream().get()
+        )?;
+        if let Some(seq) = header.split_sequence(payload).0 {
+            write!(f, " seq={}", seq)?;
+        }
+        let (trace, body) = header.split_trace(payload);
+        if let Some(trace) = trace {
+            write!(f, " trace={:016x}/{:016x}", trace.trace_id, trace.span_id)?;
+        }
+        if body.is_empty() {
+            return Ok(());
+        }
+        if header.flags & EventHeader::FLAG_COMPRESSED != 0 {
+            return write!(f, " <{} bytes compressed>", body.len());
+        }
+
+        let mut fields = FieldReader::new(body);
+        if let Some(strings) = self.strings {
+            fields = fields.with_strings(strings);
+        }
+        if fields.clone().all(|field| field.is_ok()) {
+            return write!(f, " {}", fields);
+        }
+        preview(f, body)
+    }
+}
+
+/// Quoted if `body` is printable UTF-8, `0x`-prefixed hex otherwise.
+fn preview(f: &mut fmt::Formatter<'_>, body: &[u8]) -> fmt::Result {
+    let shown = if let Ok(text) = std::str::from_utf8(body)
+        && !text.chars().any(|c| c.is_control() && !c.is_whitespace())
+    {
+        let mut end = text.len().min(PREVIEW_BYTES);
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        write!(f, " {:?}", &text[..end])?;
+        end
+    } else {
+        let end = body.len().min(PREVIEW_BYTES / 2);
+        f.write_str(" 0x")?;
+        for byte in &body[..end] {
+            write!(f, "{:02x}", byte)?;
+        }
+        end
+    };
+    if shown < body.len() {
+        f.write_str("...")?;
+    }
+    Ok(())
+}
+
+/// A `hexdump -C` style view of bytes: offset, 16 bytes in hex and their
+/// printable ASCII, one line per 16 bytes.
+pub struct HexDump<'a>(pub &'a [u8]);
+
+impl fmt::Display for HexDump<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (line, chunk) in self.0.chunks(16).enumerate() {
+            if line > 0 {
+                f.write_str("\n")?;
+            }
+            write!(f, "{:08x} ", line * 16)?;
+            for i in 0..16 {
+                if i == 8 {
+                    f.write_str(" ")?;
+                }
+                match chunk.get(i) {
+                    Some(byte) => write!(f, " {:02x}", byte)?,
+                    None => f.write_str("   ")?,
+                }
+            }
+            f.write_str("  |")?;
+            for &byte in chunk {
+                let c = if byte.is_ascii_graphic() || byte == b' ' {
+                    byte as char
+                } else {
+                    '.'
+                };
+                write!(f, "{}", c)?;
+            }
+            f.write_str("|")?;
+        }
+        Ok(())
+    }
+}
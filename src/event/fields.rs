@@ -0,0 +1,277 @@
+use super::StringTable;
+use std::fmt;
+
+const TAG_U64: u8 = 1;
+const TAG_F64: u8 = 2;
+const TAG_STR: u8 = 3;
+const TAG_BYTES: u8 = 4;
+const TAG_INTERNED: u8 = 5;
+
+/// Packs typed key-value fields into a payload. Each field is encoded as
+/// `[key_len: u8][key][tag: u8][value]`; numbers are 8 bytes little-endian,
+/// strings and bytes carry a `u16` little-endian length prefix. A key over
+/// 255 bytes or a value over `u16::MAX` bytes is not written, and
+/// `as_bytes` and `finish` return the error instead of the payload.
+#[derive(Debug, Default, Clone)]
+pub struct FieldWriter {
+    buf: Vec<u8>,
+    error: Option<FieldError>,
+}
+
+impl FieldWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn u64(&mut self, key: &str, value: u64) -> &mut Self {
+        self.field(key, TAG_U64, &value.to_le_bytes(), false)
+    }
+
+    pub fn f64(&mut self, key: &str, value: f64) -> &mut Self {
+        self.field(key, TAG_F64, &value.to_le_bytes(), false)
+    }
+
+    pub fn str(&mut self, key: &str, value: &str) -> &mut Self {
+        self.field(key, TAG_STR, value.as_bytes(), true)
+    }
+
+    pub fn bytes(&mut self, key: &str, value: &[u8]) -> &mut Self {
+        self.field(key, TAG_BYTES, value, true)
+    }
+
+    /// A reference to a string in the file's `StringTable`, stored as a u32.
+    pub fn interned(&mut self, key: &str, id: u32) -> &mut Self {
+        self.field(key, TAG_INTERNED, &id.to_le_bytes(), false)
+    }
+
+    /// Interns `value` in `strings` and writes the reference.
+    pub fn str_interned(&mut self, key: &str, value: &str, strings: &mut StringTable) -> &mut Self {
+        let id = strings.intern(value);
+        self.interned(key, id)
+    }
+
+    /// The fields written so far, or the first field that could not be.
+    pub fn as_bytes(&self) -> Result<&[u8], FieldError> {
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok(&self.buf),
+        }
+    }
+
+    pub fn finish(self) -> Result<Vec<u8>, FieldError> {
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok(self.buf),
+        }
+    }
+
+    /// Appends a field; `var` values get a `u16` length prefix.
+    fn field(&mut self, key: &str, tag: u8, value: &[u8], var: bool) -> &mut Self {
+        let Ok(key_len) = u8::try_from(key.len()) else {
+            self.error
+                .get_or_insert(FieldError::KeyTooLong { len: key.len() });
+            return self;
+        };
+        let value_len = u16::try_from(value.len());
+        if var && value_len.is_err() {
+            self.error
+                .get_or_insert(FieldError::ValueTooLong { len: value.len() });
+            return self;
+        }
+        self.buf.push(key_len);
+        self.buf.extend_from_slice(key.as_bytes());
+        self.buf.push(tag);
+        if let (true, Ok(len)) = (var, value_len) {
+            self.buf.extend_from_slice(&len.to_le_bytes());
+        }
+        self.buf.extend_from_slice(value);
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FieldValue<'a> {
+    U64(u64),
+    F64(f64),
+    Str(&'a str),
+    Bytes(&'a [u8]),
+    /// A string id not resolved against a `StringTable`.
+    Interned(u32),
+}
+
+impl fmt::Display for FieldValue<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::U64(v) => write!(f, "{}", v),
+            Self::F64(v) => write!(f, "{}", v),
+            Self::Str(s) => write!(f, "{:?}", s),
+            Self::Bytes(b) => {
+                f.write_str("0x")?;
+                b.iter().try_for_each(|byte| write!(f, "{:02x}", byte))
+            }
+            Self::Interned(id) => write!(f, "#{}", id),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldError {
+    Truncated {
+        offset: usize,
+    },
+    UnknownTag {
+        offset: usize,
+        tag: u8,
+    },
+    InvalidUtf8 {
+        offset: usize,
+    },
+    /// A `FieldWriter` key over 255 bytes.
+    KeyTooLong {
+        len: usize,
+    },
+    /// A `FieldWriter` string or bytes value over `u16::MAX` bytes.
+    ValueTooLong {
+        len: usize,
+    },
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Truncated { offset } => write!(f, "Field truncated at offset {}", offset),
+            Self::UnknownTag { offset, tag } => {
+                write!(f, "Unknown field tag {} at offset {}", tag, offset)
+            }
+            Self::InvalidUtf8 { offset } => write!(f, "Invalid UTF-8 at offset {}", offset),
+            Self::KeyTooLong { len } => write!(f, "Field key of {} bytes exceeds 255", len),
+            Self::ValueTooLong { len } => {
+                write!(f, "Field value of {} bytes exceeds {}", len, u16::MAX)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FieldError {}
+
+/// Iterates the fields of a payload written by `FieldWriter`. Stops after the
+/// first malformed field. `Display` renders `key=value` pairs for tooling.
+#[derive(Debug, Clone)]
+pub struct FieldReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    failed: bool,
+    strings: Option<&'a StringTable>,
+}
+
+impl<'a> FieldReader<'a> {
+    pub fn new(payload: &'a [u8]) -> Self {
+        Self {
+            buf: payload,
+            pos: 0,
+            failed: false,
+            strings: None,
+        }
+    }
+
+    /// Resolves interned fields to `FieldValue::Str` through `strings`. Ids
+    /// missing from the table stay `FieldValue::Interned`.
+    pub fn with_strings(mut self, strings: &'a StringTable) -> Self {
+        self.strings = Some(strings);
+        self
+    }
+
+    /// First field named `key`, ignoring malformed data after it.
+    pub fn get(&self, key: &str) -> Option<FieldValue<'a>> {
+        self.restart()
+            .map_while(Result::ok)
+            .find_map(|(k, v)| (k == key).then_some(v))
+    }
+
+    /// Bytes read so far; after a field, the offset just past its value.
+    #[inline]
+    pub fn offset(&self) -> usize {
+        self.pos
+    }
+
+    fn restart(&self) -> Self {
+        Self {
+            pos: 0,
+            failed: false,
+            ..self.clone()
+        }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], FieldError> {
+        let bytes = self
+            .buf
+            .get(self.pos..self.pos + len)
+            .ok_or(FieldError::Truncated { offset: self.pos })?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn str(&mut self, len: usize) -> Result<&'a str, FieldError> {
+        let offset = self.pos;
+        std::str::from_utf8(self.take(len)?).map_err(|_| FieldError::InvalidUtf8 { offset })
+    }
+
+    fn field(&mut self) -> Result<(&'a str, FieldValue<'a>), FieldError> {
+        let key_len = self.take(1)?[0] as usize;
+        let key = self.str(key_len)?;
+        let offset = self.pos;
+        let value = match self.take(1)?[0] {
+            TAG_U64 => FieldValue::U64(u64::from_le_bytes(self.take(8)?.try_into().unwrap())),
+            TAG_F64 => FieldValue::F64(f64::from_le_bytes(self.take(8)?.try_into().unwrap())),
+            TAG_STR => {
+                let len = self.var_len()?;
+                FieldValue::Str(self.str(len)?)
+            }
+            TAG_BYTES => {
+                let len = self.var_len()?;
+                FieldValue::Bytes(self.take(len)?)
+            }
+            TAG_INTERNED => {
+                let id = u32::from_le_bytes(self.take(4)?.try_into().unwrap());
+                match self.strings.and_then(|strings| strings.resolve(id)) {
+                    Some(s) => FieldValue::Str(s),
+                    None => FieldValue::Interned(id),
+                }
+            }
+            tag => return Err(FieldError::UnknownTag { offset, tag }),
+        };
+        Ok((key, value))
+    }
+
+    fn var_len(&mut self) -> Result<usize, FieldError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()) as usize)
+    }
+}
+
+impl<'a> Iterator for FieldReader<'a> {
+    type Item = Result<(&'a str, FieldValue<'a>), FieldError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.pos >= self.buf.len() {
+            return None;
+        }
+        let field = self.field();
+        self.failed = field.is_err();
+        Some(field)
+    }
+}
+
+impl fmt::Display for FieldReader<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, field) in self.restart().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            match field {
+                Ok((key, value)) => write!(f, "{}={}", key, value)?,
+                Err(e) => write!(f, "<{}>", e)?,
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,26 @@
+use super::EventView;
+use flatbuffers::{Follow, InvalidFlatbuffer, Verifiable, VerifierOptions};
+
+impl<'a> EventView<'a> {
+    /// Verifies the payload as a flatbuffer with root table `T` and returns
+    /// the root borrowing straight from the mapped file, so nothing is copied
+    /// or decoded up front. Sequence and trace prefixes are skipped.
+    pub fn flatbuffer<T>(&self) -> Result<T::Inner, InvalidFlatbuffer>
+    where
+        T: 'a + Follow<'a> + Verifiable,
+    {
+        self.flatbuffer_with_opts::<T>(&VerifierOptions::default())
+    }
+
+    /// Like `flatbuffer`, with custom verifier limits for deep or very large
+    /// buffers.
+    pub fn flatbuffer_with_opts<T>(
+        &self,
+        opts: &VerifierOptions,
+    ) -> Result<T::Inner, InvalidFlatbuffer>
+    where
+        T: 'a + Follow<'a> + Verifiable,
+    {
+        flatbuffers::root_with_opts::<T>(opts, self.header.body(self.payload))
+    }
+}
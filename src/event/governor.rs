@@ -0,0 +1,180 @@
+//! Rate limiting at the producer edge. A `Governor` wraps any `EventSink`
+//! and lets each limited event type through at a sustained rate with some
+//! burst, so runaway logging of one type is stopped before it reaches the
+//! ring or the disk. Types without a limit pass straight through.
+
+use super::{EventHeader, EventSink};
+use crate::Result;
+use std::collections::BTreeMap;
+use std::time::Instant;
+
+/// What happens to events over the rate.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Excess {
+    /// Discards them.
+    #[default]
+    Drop,
+    /// Keeps only the latest, written by `Governor::flush` once the rate
+    /// allows; earlier ones count as coalesced.
+    Coalesce,
+}
+
+/// A token bucket: `burst` events at once, refilled at `per_sec`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub per_sec: f64,
+    pub burst: u32,
+    pub excess: Excess,
+}
+
+impl RateLimit {
+    /// Drops events beyond `per_sec`, allowing bursts of `burst`.
+    pub fn new(per_sec: f64, burst: u32) -> Self {
+        Self {
+            per_sec,
+            burst,
+            excess: Excess::Drop,
+        }
+    }
+
+    pub fn coalesce(mut self) -> Self {
+        self.excess = Excess::Coalesce;
+        self
+    }
+}
+
+/// Counters for one event type.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct RateStats {
+    pub allowed: u64,
+    pub dropped: u64,
+    /// Excess events superseded by a later one under `Excess::Coalesce`.
+    pub coalesced: u64,
+}
+
+struct Bucket {
+    limit: RateLimit,
+    tokens: f64,
+    refilled: Instant,
+    /// The latest excess event under `Excess::Coalesce`.
+    pending: Option<(EventHeader, Vec<u8>)>,
+    stats: RateStats,
+}
+
+impl Bucket {
+    fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            tokens: limit.burst as f64,
+            refilled: Instant::now(),
+            pending: None,
+            stats: RateStats::default(),
+        }
+    }
+
+    /// Takes a token if one is available.
+    fn take(&mut self) -> bool {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.limit.per_sec).min(self.limit.burst as f64);
+        self.refilled = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// An `EventSink` that rate-limits event types before passing events on to
+/// `sink`. Events it holds back still report success to the writer.
+pub struct Governor<S> {
+    sink: S,
+    buckets: BTreeMap<u8, Bucket>,
+}
+
+impl<S: EventSink> Governor<S> {
+    pub fn new(sink: S) -> Self {
+        Self {
+            sink,
+            buckets: BTreeMap::new(),
+        }
+    }
+
+    /// Limits `event_type`, replacing any earlier limit and its stats.
+    pub fn with_limit(mut self, event_type: u8, limit: RateLimit) -> Self {
+        self.buckets.insert(event_type, Bucket::new(limit));
+        self
+    }
+
+    pub fn stats(&self, event_type: u8) -> RateStats {
+        self.buckets
+            .get(&event_type)
+            .map_or_else(RateStats::default, |bucket| bucket.stats)
+    }
+
+    /// Events dropped across all types.
+    pub fn dropped(&self) -> u64 {
+        self.buckets
+            .values()
+            .map(|bucket| bucket.stats.dropped)
+            .sum()
+    }
+
+    /// Writes the coalesced events the rate now allows, returning how many.
+    /// The others stay pending.
+    pub fn flush(&mut self) -> Result<usize> {
+        let mut written = 0;
+        for bucket in self.buckets.values_mut() {
+            if bucket.pending.is_none() || !bucket.take() {
+                continue;
+            }
+            let (header, payload) = bucket.pending.take().expect("checked above");
+            self.sink.write_parts(&header, &[&payload])?;
+            bucket.stats.allowed += 1;
+            written += 1;
+        }
+        Ok(written)
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.sink
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.sink
+    }
+
+    /// The wrapped sink; pending coalesced events are discarded.
+    pub fn into_inner(self) -> S {
+        self.sink
+    }
+}
+
+impl<S: EventSink> EventSink for Governor<S> {
+    fn write_parts(&mut self, header: &EventHeader, parts: &[&[u8]]) -> Result<()> {
+        let Some(bucket) = self.buckets.get_mut(&header.event_type) else {
+            return self.sink.write_parts(header, parts);
+        };
+        if bucket.take() {
+            // A newer event supersedes the one held back.
+            if bucket.pending.take().is_some() {
+                bucket.stats.coalesced += 1;
+            }
+            self.sink.write_parts(header, parts)?;
+            bucket.stats.allowed += 1;
+            return Ok(());
+        }
+        match bucket.limit.excess {
+            Excess::Drop => bucket.stats.dropped += 1,
+            Excess::Coalesce => {
+                if bucket.pending.replace((*header, parts.concat())).is_some() {
+                    bucket.stats.coalesced += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+}
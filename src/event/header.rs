@@ -1,3 +1,5 @@
+use super::{Priority, StreamId, TraceContext, crc32};
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
 pub struct EventHeader {
@@ -11,6 +13,63 @@ pub struct EventHeader {
 impl EventHeader {
     pub const SIZE: usize = 16;
 
+    /// `_reserved` holds a CRC-32 of the payload.
+    pub const FLAG_CHECKSUM: u8 = 1 << 0;
+
+    /// The payload starts with a little-endian u64 sequence number; see
+    /// `split_sequence`.
+    pub const FLAG_SEQUENCE: u8 = 1 << 1;
+
+    /// The application payload, after any sequence number and trace
+    /// context, is zstd-compressed; see `event::compression` (feature
+    /// `compression`).
+    pub const FLAG_COMPRESSED: u8 = 1 << 2;
+
+    /// The payload carries a `TraceContext` after any sequence number; see
+    /// `split_trace`.
+    pub const FLAG_TRACE: u8 = 1 << 3;
+
+    /// The payload starts with an extension byte, after any `TYPE_DELAYED`
+    /// prefix and before any sequence number; see `split_extension`. It
+    /// takes the top bit of what was the stream nibble.
+    pub const FLAG_EXTENDED: u8 = 1 << 7;
+
+    /// Flag bits available to `flags`; bits 4 to 6 hold the stream id.
+    pub const FLAG_MASK: u8 = 0x0F;
+
+    /// Extension bit saying bits 0 and 1 hold a `Priority`.
+    pub const EXT_PRIORITY: u8 = 1 << 2;
+
+    /// Extension bit saying the application payload was written by
+    /// `FieldWriter`; see `EventBuilder::fields`.
+    pub const EXT_FIELDS: u8 = 1 << 3;
+
+    /// Reserved event type for heartbeats injected by the dispatcher. The
+    /// payload is the heartbeat sequence number as a little-endian u64.
+    pub const TYPE_HEARTBEAT: u8 = 0xFF;
+
+    /// Reserved event type for events held back until a deliver-not-before
+    /// time, written by `EventBuilder::deliver_at`. The payload is that time
+    /// in Unix nanoseconds as a little-endian u64, then the real event type,
+    /// then the real payload; see `split_delay`.
+    pub const TYPE_DELAYED: u8 = 0xFD;
+
+    /// Reserved event type asking for every event with the key in its
+    /// payload to be deleted; see `storage::compaction`.
+    pub const TYPE_TOMBSTONE: u8 = 0xFC;
+
+    /// Reserved event type for named checkpoints such as "deploy v1.2",
+    /// written by `EventSink::marker`. The payload is the UTF-8 name.
+    pub const TYPE_MARKER: u8 = 0xFB;
+
+    /// The lowest reserved event type. `EventBuilder` and the rings' and
+    /// writers' `write_event` refuse reserved types; markers, tombstones and
+    /// delayed events are written through `EventSink`.
+    pub const FIRST_RESERVED_TYPE: u8 = 0xFB;
+
+    /// Bytes `TYPE_DELAYED` adds in front of the payload.
+    pub const DELAY_PREFIX_SIZE: usize = 9;
+
     pub fn new(timestamp: u64, event_type: u8, payload_len: u16) -> Self {
         Self {
             timestamp,
@@ -21,7 +80,162 @@ impl EventHeader {
         }
     }
 
+    /// Encodes the header in the little-endian on-disk layout: timestamp (8),
+    /// event_type (1), flags (1), payload_len (2), reserved (4).
+    pub fn encode(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0u8; Self::SIZE];
+        bytes[0..8].copy_from_slice(&self.timestamp.to_le_bytes());
+        bytes[8] = self.event_type;
+        bytes[9] = self.flags;
+        bytes[10..12].copy_from_slice(&self.payload_len.to_le_bytes());
+        bytes[12..16].copy_from_slice(&self._reserved.to_le_bytes());
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8; Self::SIZE]) -> Self {
+        Self {
+            timestamp: u64::from_le_bytes(bytes[0..8].try_into().unwrap()),
+            event_type: bytes[8],
+            flags: bytes[9],
+            payload_len: u16::from_le_bytes(bytes[10..12].try_into().unwrap()),
+            _reserved: u32::from_le_bytes(bytes[12..16].try_into().unwrap()),
+        }
+    }
+
+    /// Decodes the header at the start of `bytes`, if there is room for one.
+    #[inline]
+    pub fn decode_from(bytes: &[u8]) -> Option<Self> {
+        bytes
+            .get(..Self::SIZE)
+            .map(|b| Self::decode(b.try_into().unwrap()))
+    }
+
     pub fn total_size(&self) -> usize {
         Self::SIZE + self.payload_len as usize
     }
+
+    pub fn with_checksum(mut self, payload: &[u8]) -> Self {
+        self.flags |= Self::FLAG_CHECKSUM;
+        self._reserved = crc32(payload);
+        self
+    }
+
+    /// Tags the event with `stream`; the other flag bits are kept.
+    pub fn with_stream(mut self, stream: StreamId) -> Self {
+        self.flags = stream.to_flags(self.flags);
+        self
+    }
+
+    #[inline]
+    pub fn stream(&self) -> StreamId {
+        StreamId::from_flags(self.flags)
+    }
+
+    #[inline]
+    pub const fn is_reserved_type(event_type: u8) -> bool {
+        event_type >= Self::FIRST_RESERVED_TYPE
+    }
+
+    #[inline]
+    pub fn is_heartbeat(&self) -> bool {
+        self.event_type == Self::TYPE_HEARTBEAT
+    }
+
+    /// Unwraps a `TYPE_DELAYED` event into its deliver-not-before time, the
+    /// real header and payload. A checksum is recomputed over the unwrapped
+    /// payload. `None` for other events.
+    pub fn split_delay<'a>(&self, payload: &'a [u8]) -> Option<(u64, EventHeader, &'a [u8])> {
+        if self.event_type != Self::TYPE_DELAYED || payload.len() < Self::DELAY_PREFIX_SIZE {
+            return None;
+        }
+        let (prefix, rest) = payload.split_at(Self::DELAY_PREFIX_SIZE);
+        let not_before = u64::from_le_bytes(prefix[..8].try_into().unwrap());
+        let mut header = EventHeader {
+            event_type: prefix[8],
+            payload_len: rest.len() as u16,
+            ..*self
+        };
+        if header.has_checksum() {
+            header = header.with_checksum(rest);
+        }
+        Some((not_before, header, rest))
+    }
+
+    /// Splits the extension byte of a `FLAG_EXTENDED` event off the front of
+    /// `payload`, skipping the prefix of a `TYPE_DELAYED` event.
+    pub fn split_extension<'a>(&self, payload: &'a [u8]) -> (Option<u8>, &'a [u8]) {
+        let payload = match self.event_type {
+            Self::TYPE_DELAYED => payload.get(Self::DELAY_PREFIX_SIZE..).unwrap_or(payload),
+            _ => payload,
+        };
+        match payload.split_first() {
+            Some((&ext, rest)) if self.flags & Self::FLAG_EXTENDED != 0 => (Some(ext), rest),
+            _ => (None, payload),
+        }
+    }
+
+    /// The priority stamped by `EventBuilder::priority`, if any.
+    #[inline]
+    pub fn priority(&self, payload: &[u8]) -> Option<Priority> {
+        let ext = self.split_extension(payload).0?;
+        (ext & Self::EXT_PRIORITY != 0).then(|| Priority::from_bits(ext))
+    }
+
+    /// Whether the event says its application payload is `FieldWriter`
+    /// fields.
+    #[inline]
+    pub fn has_fields(&self, payload: &[u8]) -> bool {
+        self.split_extension(payload)
+            .0
+            .is_some_and(|ext| ext & Self::EXT_FIELDS != 0)
+    }
+
+    /// Splits the sequence number stamped by `EventBuilder::sequence` off the
+    /// front of `payload`, after any extension byte. The rest still holds
+    /// any trace context; use `body` for the application payload alone.
+    pub fn split_sequence<'a>(&self, payload: &'a [u8]) -> (Option<u64>, &'a [u8]) {
+        let (_, payload) = self.split_extension(payload);
+        if self.flags & Self::FLAG_SEQUENCE == 0 || payload.len() < 8 {
+            return (None, payload);
+        }
+        let (seq, rest) = payload.split_at(8);
+        (Some(u64::from_le_bytes(seq.try_into().unwrap())), rest)
+    }
+
+    /// Splits the trace context stamped by `EventBuilder::trace` off
+    /// `payload`, skipping any sequence number before it.
+    pub fn split_trace<'a>(&self, payload: &'a [u8]) -> (Option<TraceContext>, &'a [u8]) {
+        let (_, rest) = self.split_sequence(payload);
+        if self.flags & Self::FLAG_TRACE == 0 || rest.len() < TraceContext::SIZE {
+            return (None, rest);
+        }
+        let (trace, rest) = rest.split_at(TraceContext::SIZE);
+        (Some(TraceContext::decode(trace.try_into().unwrap())), rest)
+    }
+
+    /// The application payload, without the extension, sequence number or
+    /// trace context prefixes.
+    #[inline]
+    pub fn body<'a>(&self, payload: &'a [u8]) -> &'a [u8] {
+        self.split_trace(payload).1
+    }
+
+    /// The name of a `TYPE_MARKER` event; `None` for other events and
+    /// markers whose name is not UTF-8.
+    pub fn marker<'a>(&self, payload: &'a [u8]) -> Option<&'a str> {
+        if self.event_type != Self::TYPE_MARKER {
+            return None;
+        }
+        std::str::from_utf8(self.body(payload)).ok()
+    }
+
+    #[inline]
+    pub fn has_checksum(&self) -> bool {
+        self.flags & Self::FLAG_CHECKSUM != 0
+    }
+
+    #[inline]
+    pub fn checksum(&self) -> Option<u32> {
+        self.has_checksum().then_some(self._reserved)
+    }
 }
@@ -0,0 +1,63 @@
+use crate::storage::Metadata;
+use std::collections::HashMap;
+
+/// Strings stored once per file and referenced from payloads by id. Kept in
+/// the file's `Metadata` as `intern.<id>=<string>` entries, so the table must
+/// be filled before the writer is created; readers rebuild it with
+/// `MmapReader::string_table`.
+#[derive(Debug, Clone, Default)]
+pub struct StringTable {
+    strings: Vec<String>,
+    ids: HashMap<String, u32>,
+}
+
+impl StringTable {
+    const KEY_PREFIX: &'static str = "intern.";
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the id for `s`, assigning the next one if it is new.
+    pub fn intern(&mut self, s: &str) -> u32 {
+        if let Some(&id) = self.ids.get(s) {
+            return id;
+        }
+        let id = self.strings.len() as u32;
+        self.strings.push(s.to_string());
+        self.ids.insert(s.to_string(), id);
+        id
+    }
+
+    pub fn id(&self, s: &str) -> Option<u32> {
+        self.ids.get(s).copied()
+    }
+
+    pub fn resolve(&self, id: u32) -> Option<&str> {
+        self.strings.get(id as usize).map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+
+    pub fn write_to(&self, metadata: &mut Metadata) {
+        for (id, s) in self.strings.iter().enumerate() {
+            metadata
+                .entries
+                .insert(format!("{}{}", Self::KEY_PREFIX, id), s.clone());
+        }
+    }
+
+    pub fn from_metadata(metadata: &Metadata) -> Self {
+        let mut table = Self::new();
+        while let Some(s) = metadata.get(&format!("{}{}", Self::KEY_PREFIX, table.len())) {
+            table.intern(s);
+        }
+        table
+    }
+}
@@ -1,5 +1,39 @@
+pub mod builder;
+pub mod checksum;
+pub mod compact;
+#[cfg(feature = "compression")]
+pub mod compression;
+pub mod display;
+pub mod fields;
+#[cfg(feature = "flatbuffers")]
+mod flatbuf;
+pub mod governor;
 pub mod header;
+pub mod intern;
+pub mod priority;
+#[cfg(feature = "prost")]
+pub mod proto;
+pub mod stream;
+pub mod tags;
+pub mod trace;
+#[cfg(feature = "serde")]
+pub mod typed;
 pub mod view;
 
+pub use builder::{EventBuilder, EventSink};
+pub use checksum::{crc32, crc32_vectored};
+pub use compact::CompactHeader;
+pub use display::{EventDisplay, HexDump, Timestamp, TypeNames};
+pub use fields::{FieldError, FieldReader, FieldValue, FieldWriter};
+pub use governor::{Excess, Governor, RateLimit, RateStats};
 pub use header::EventHeader;
+pub use intern::StringTable;
+pub use priority::Priority;
+#[cfg(feature = "prost")]
+pub use proto::{ProtoConsumer, ProtoEventIterator, ProtoRegistry};
+pub use stream::{StreamId, StreamNames};
+pub use tags::Tags;
+pub use trace::TraceContext;
+#[cfg(feature = "serde")]
+pub use typed::{TypedError, TypedEvent, TypedEventIterator, read_typed, write_typed};
 pub use view::EventView;
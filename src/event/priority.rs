@@ -0,0 +1,29 @@
+/// How urgently an event should be delivered by priority-aware drains; see
+/// `EventBuilder::priority` and `EventDispatcher::set_priorities`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+    /// Control-plane events that must not wait behind bulk telemetry.
+    Critical,
+}
+
+impl Priority {
+    /// The two bits stored in an event's extension byte.
+    #[inline]
+    pub(crate) const fn bits(self) -> u8 {
+        self as u8
+    }
+
+    #[inline]
+    pub(crate) const fn from_bits(bits: u8) -> Self {
+        match bits & 0b11 {
+            0 => Self::Low,
+            1 => Self::Normal,
+            2 => Self::High,
+            _ => Self::Critical,
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use super::EventHeader;
+use crate::consumer::EventConsumer;
+use crate::storage::{EventIterator, MmapReader};
+use prost::{DecodeError, Message};
+use std::collections::BTreeMap;
+
+type Decoder<M> = Box<dyn Fn(&[u8]) -> Result<M, DecodeError> + Send + Sync>;
+
+/// Maps event types to protobuf message types. `M` is usually an enum with
+/// one variant per message, so a single registry covers a whole stream.
+pub struct ProtoRegistry<M> {
+    decoders: BTreeMap<u8, Decoder<M>>,
+}
+
+impl<M> Default for ProtoRegistry<M> {
+    fn default() -> Self {
+        Self {
+            decoders: BTreeMap::new(),
+        }
+    }
+}
+
+impl<M: 'static> ProtoRegistry<M> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decodes payloads of `event_type` as `P` and wraps them with `wrap`.
+    /// Registering a type twice replaces the earlier mapping.
+    pub fn register<P>(mut self, event_type: u8, wrap: fn(P) -> M) -> Self
+    where
+        P: Message + Default + 'static,
+    {
+        self.decoders.insert(
+            event_type,
+            Box::new(move |payload| P::decode(payload).map(wrap)),
+        );
+        self
+    }
+
+    pub fn contains(&self, event_type: u8) -> bool {
+        self.decoders.contains_key(&event_type)
+    }
+
+    /// `None` when `header.event_type` has no registered message.
+    pub fn decode(&self, header: &EventHeader, payload: &[u8]) -> Option<Result<M, DecodeError>> {
+        self.decoders
+            .get(&header.event_type)
+            .map(|decode| decode(header.body(payload)))
+    }
+}
+
+impl MmapReader {
+    /// Decodes every event with a registered type, skipping the rest.
+    pub fn iter_proto<'a, M: 'static>(
+        &'a self,
+        registry: &'a ProtoRegistry<M>,
+    ) -> ProtoEventIterator<'a, M> {
+        ProtoEventIterator {
+            events: self.iter(),
+            registry,
+        }
+    }
+}
+
+pub struct ProtoEventIterator<'a, M> {
+    events: EventIterator<'a>,
+    registry: &'a ProtoRegistry<M>,
+}
+
+impl<M: 'static> Iterator for ProtoEventIterator<'_, M> {
+    type Item = (EventHeader, Result<M, DecodeError>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.events.by_ref().find_map(|event| {
+            let message = self.registry.decode(&event.header, event.payload)?;
+            Some((event.header, message))
+        })
+    }
+}
+
+/// Adapts a handler of decoded messages into an `EventConsumer`. Events with
+/// no registered message are skipped and count as delivered; payloads that
+/// fail to decode count as failed.
+pub struct ProtoConsumer<M, F> {
+    name: String,
+    registry: ProtoRegistry<M>,
+    handler: F,
+}
+
+impl<M, F> ProtoConsumer<M, F>
+where
+    M: 'static,
+    F: FnMut(&EventHeader, M) -> bool + Send,
+{
+    pub fn new(name: impl Into<String>, registry: ProtoRegistry<M>, handler: F) -> Self {
+        Self {
+            name: name.into(),
+            registry,
+            handler,
+        }
+    }
+}
+
+impl<M, F> EventConsumer for ProtoConsumer<M, F>
+where
+    M: 'static,
+    F: FnMut(&EventHeader, M) -> bool + Send,
+{
+    fn consume(&mut self, header: &EventHeader, payload: &[u8]) -> bool {
+        match self.registry.decode(header, payload) {
+            Some(Ok(message)) => (self.handler)(header, message),
+            Some(Err(_)) => false,
+            None => true,
+        }
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
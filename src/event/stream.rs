@@ -0,0 +1,90 @@
+use crate::storage::Metadata;
+
+/// A logical stream sharing a ring and a file with others. Stored in bits 4
+/// to 6 of `EventHeader::flags`, so there are 8 streams; 0 is the default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StreamId(u8);
+
+impl StreamId {
+    pub const DEFAULT: Self = Self(0);
+    pub const MAX: u8 = 7;
+
+    pub const fn new(id: u8) -> Option<Self> {
+        if id <= Self::MAX {
+            Some(Self(id))
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    pub const fn get(self) -> u8 {
+        self.0
+    }
+
+    #[inline]
+    pub(crate) const fn from_flags(flags: u8) -> Self {
+        Self((flags >> 4) & Self::MAX)
+    }
+
+    #[inline]
+    pub(crate) const fn to_flags(self, flags: u8) -> u8 {
+        (flags & !(Self::MAX << 4)) | (self.0 << 4)
+    }
+}
+
+/// Names for stream ids, kept in a file's `Metadata` as `stream.<id>=<name>`
+/// entries so replay can select streams by name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StreamNames {
+    names: Vec<String>,
+}
+
+impl StreamNames {
+    const KEY_PREFIX: &'static str = "stream.";
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the id for `name`, assigning the next free one if it is new.
+    /// `None` once all 16 ids are taken.
+    pub fn register(&mut self, name: &str) -> Option<StreamId> {
+        if let Some(id) = self.id(name) {
+            return Some(id);
+        }
+        let id = StreamId::new(self.names.len() as u8)?;
+        self.names.push(name.to_string());
+        Some(id)
+    }
+
+    pub fn id(&self, name: &str) -> Option<StreamId> {
+        self.names
+            .iter()
+            .position(|n| n == name)
+            .map(|i| StreamId(i as u8))
+    }
+
+    pub fn name(&self, id: StreamId) -> Option<&str> {
+        self.names.get(id.0 as usize).map(String::as_str)
+    }
+
+    pub fn write_to(&self, metadata: &mut Metadata) {
+        for (id, name) in self.names.iter().enumerate() {
+            metadata
+                .entries
+                .insert(format!("{}{}", Self::KEY_PREFIX, id), name.clone());
+        }
+    }
+
+    pub fn from_metadata(metadata: &Metadata) -> Self {
+        let mut names = Vec::new();
+        for id in 0..=StreamId::MAX {
+            match metadata.get(&format!("{}{}", Self::KEY_PREFIX, id)) {
+                Some(name) => names.push(name.to_string()),
+                None => break,
+            }
+        }
+        Self { names }
+    }
+}
@@ -0,0 +1,80 @@
+use super::{EventHeader, EventView, FieldReader, FieldValue, FieldWriter};
+use std::ops::BitOr;
+
+/// A set of up to 64 application-defined tags, e.g. one per subsystem, for
+/// slicing a log without allocating event types. Stored as a u64 bitmask in
+/// the `_tags` field of a `FieldWriter` payload; write it with
+/// `FieldWriter::tags` and `EventBuilder::fields`, and read it back with
+/// `Tags::of`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Tags(u64);
+
+impl Tags {
+    pub const EMPTY: Self = Self(0);
+    /// The payload field holding the bitmask.
+    pub const FIELD: &'static str = "_tags";
+
+    /// The set holding only tag `tag`. Panics if `tag` is 64 or more.
+    pub const fn tag(tag: u32) -> Self {
+        assert!(tag < 64, "tags are numbered 0 to 63");
+        Self(1 << tag)
+    }
+
+    #[inline]
+    pub const fn from_bits(bits: u64) -> Self {
+        Self(bits)
+    }
+
+    #[inline]
+    pub const fn bits(self) -> u64 {
+        self.0
+    }
+
+    #[inline]
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Whether every tag of `other` is in the set; always true for an empty
+    /// `other`.
+    #[inline]
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    #[inline]
+    pub const fn intersects(self, other: Self) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    /// The tags of an event; empty for events not marked as carrying
+    /// fields by `EventBuilder::fields`, without a `_tags` field or with a
+    /// compressed payload.
+    pub fn of(event: &EventView<'_>) -> Self {
+        if event.header.flags & EventHeader::FLAG_COMPRESSED != 0
+            || !event.header.has_fields(event.payload)
+        {
+            return Self::EMPTY;
+        }
+        match FieldReader::new(event.header.body(event.payload)).get(Self::FIELD) {
+            Some(FieldValue::U64(bits)) => Self(bits),
+            _ => Self::EMPTY,
+        }
+    }
+}
+
+impl BitOr for Tags {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl FieldWriter {
+    /// Writes `tags` as the `_tags` field. Writing it first keeps `Tags::of`
+    /// cheap, as lookups scan the fields in order.
+    pub fn tags(&mut self, tags: Tags) -> &mut Self {
+        self.u64(Tags::FIELD, tags.bits())
+    }
+}
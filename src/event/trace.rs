@@ -0,0 +1,30 @@
+/// Distributed-trace correlation ids carried by an event, so a trace from
+/// another system can be joined with local events.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct TraceContext {
+    pub trace_id: u64,
+    pub span_id: u64,
+}
+
+impl TraceContext {
+    pub const SIZE: usize = 16;
+
+    pub const fn new(trace_id: u64, span_id: u64) -> Self {
+        Self { trace_id, span_id }
+    }
+
+    /// Trace id then span id, each little-endian.
+    pub fn encode(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0u8; Self::SIZE];
+        bytes[0..8].copy_from_slice(&self.trace_id.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.span_id.to_le_bytes());
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8; Self::SIZE]) -> Self {
+        Self {
+            trace_id: u64::from_le_bytes(bytes[0..8].try_into().unwrap()),
+            span_id: u64::from_le_bytes(bytes[8..16].try_into().unwrap()),
+        }
+    }
+}
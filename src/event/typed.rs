@@ -0,0 +1,112 @@
+use super::{EventHeader, EventSink, EventView};
+use crate::Error;
+use crate::ring::RingError;
+use crate::storage::{EventIterator, MmapReader, StorageError};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::fmt;
+use std::marker::PhantomData;
+
+/// A struct logged as a postcard-encoded payload. `EVENT_TYPE` doubles as the
+/// schema id: it is written to the header and checked on read.
+pub trait TypedEvent: Serialize + DeserializeOwned {
+    const EVENT_TYPE: u8;
+}
+
+#[derive(Debug)]
+pub enum TypedError {
+    Codec(postcard::Error),
+    WrongType { expected: u8, found: u8 },
+    Ring(RingError),
+    Storage(StorageError),
+}
+
+impl fmt::Display for TypedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Codec(e) => write!(f, "Typed payload codec error: {}", e),
+            Self::WrongType { expected, found } => {
+                write!(f, "Expected event type {}, found {}", expected, found)
+            }
+            Self::Ring(e) => write!(f, "{}", e),
+            Self::Storage(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for TypedError {}
+
+impl From<RingError> for TypedError {
+    fn from(e: RingError) -> Self {
+        Self::Ring(e)
+    }
+}
+
+impl From<Error> for TypedError {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Ring(e) => Self::Ring(e),
+            Error::Storage(e) => Self::Storage(e),
+        }
+    }
+}
+
+impl From<postcard::Error> for TypedError {
+    fn from(e: postcard::Error) -> Self {
+        Self::Codec(e)
+    }
+}
+
+/// Serializes `value` and writes it to `sink` stamped with the system clock.
+pub fn write_typed<S, T>(sink: &mut S, value: &T) -> Result<(), TypedError>
+where
+    S: EventSink,
+    T: TypedEvent,
+{
+    let payload = postcard::to_allocvec(value)?;
+    sink.event(T::EVENT_TYPE).payload(&payload).write()?;
+    Ok(())
+}
+
+pub fn read_typed<T: TypedEvent>(header: &EventHeader, payload: &[u8]) -> Result<T, TypedError> {
+    if header.event_type != T::EVENT_TYPE {
+        return Err(TypedError::WrongType {
+            expected: T::EVENT_TYPE,
+            found: header.event_type,
+        });
+    }
+    Ok(postcard::from_bytes(header.body(payload))?)
+}
+
+impl EventView<'_> {
+    pub fn read_typed<T: TypedEvent>(&self) -> Result<T, TypedError> {
+        read_typed(&self.header, self.payload)
+    }
+}
+
+impl MmapReader {
+    /// Decodes every event of type `T::EVENT_TYPE`, skipping other types.
+    pub fn iter_typed<T: TypedEvent>(&self) -> TypedEventIterator<'_, T> {
+        TypedEventIterator {
+            events: self.iter(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+pub struct TypedEventIterator<'a, T> {
+    events: EventIterator<'a>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T: TypedEvent> Iterator for TypedEventIterator<'_, T> {
+    type Item = Result<(EventHeader, T), TypedError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let event = self
+            .events
+            .by_ref()
+            .find(|e| e.header.event_type == T::EVENT_TYPE)?;
+        Some(event.read_typed().map(|value| (event.header, value)))
+    }
+}
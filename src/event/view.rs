@@ -2,25 +2,22 @@ use super::EventHeader;
 
 #[derive(Debug, Clone, Copy)]
 pub struct EventView<'a> {
-    pub header: &'a EventHeader,
+    pub header: EventHeader,
     pub payload: &'a [u8],
 }
 
 impl<'a> EventView<'a> {
-    /// # Safety
-    /// Caller must guarantee that `buf[offset..]` contains a valid
-    pub unsafe fn from_bytes(buf: &'a [u8], offset: usize) -> Self {
-        let header = {
-            let ptr = unsafe { buf.as_ptr().add(offset) as *const EventHeader };
-            unsafe { &*ptr }
-        };
+    /// Decodes the event at `buf[offset..]`, or `None` if `buf` ends before
+    /// its header or payload does.
+    pub fn from_bytes(buf: &'a [u8], offset: usize) -> Option<Self> {
+        let header = EventHeader::decode_from(buf.get(offset..)?)?;
 
         let ps = offset + EventHeader::SIZE;
         let pe = ps + header.payload_len as usize;
 
-        let payload = &buf[ps..pe];
+        let payload = buf.get(ps..pe)?;
 
-        Self { header, payload }
+        Some(Self { header, payload })
     }
 
     pub fn total_size(&self) -> usize {
@@ -0,0 +1,75 @@
+use super::server::SERVICE_NAME;
+use super::{AppendResponse, Event, ReplayRequest, StatsRequest, StatsResponse};
+use tonic::codec::Streaming;
+use tonic::codegen::http::uri::PathAndQuery;
+use tonic::codegen::{GrpcMethod, StdError};
+use tonic::transport::{Channel, Endpoint};
+use tonic::{IntoRequest, IntoStreamingRequest, Response, Status};
+use tonic_prost::ProstCodec;
+
+/// Client for the `ringlog.v1.Journal` service.
+#[derive(Debug, Clone)]
+pub struct JournalClient {
+    inner: tonic::client::Grpc<Channel>,
+}
+
+impl JournalClient {
+    pub async fn connect<D>(dst: D) -> Result<Self, tonic::transport::Error>
+    where
+        D: TryInto<Endpoint>,
+        D::Error: Into<StdError>,
+    {
+        let channel = Endpoint::new(dst)?.connect().await?;
+        Ok(Self::new(channel))
+    }
+
+    pub fn new(channel: Channel) -> Self {
+        Self {
+            inner: tonic::client::Grpc::new(channel),
+        }
+    }
+
+    pub async fn append(
+        &mut self,
+        events: impl IntoStreamingRequest<Message = Event>,
+    ) -> Result<Response<AppendResponse>, Status> {
+        self.ready().await?;
+        let mut req = events.into_streaming_request();
+        req.extensions_mut()
+            .insert(GrpcMethod::new(SERVICE_NAME, "Append"));
+        let path = PathAndQuery::from_static("/ringlog.v1.Journal/Append");
+        self.inner
+            .client_streaming(req, path, ProstCodec::default())
+            .await
+    }
+
+    pub async fn replay(
+        &mut self,
+        request: impl IntoRequest<ReplayRequest>,
+    ) -> Result<Response<Streaming<Event>>, Status> {
+        self.ready().await?;
+        let mut req = request.into_request();
+        req.extensions_mut()
+            .insert(GrpcMethod::new(SERVICE_NAME, "Replay"));
+        let path = PathAndQuery::from_static("/ringlog.v1.Journal/Replay");
+        self.inner
+            .server_streaming(req, path, ProstCodec::default())
+            .await
+    }
+
+    pub async fn stats(&mut self) -> Result<Response<StatsResponse>, Status> {
+        self.ready().await?;
+        let mut req = StatsRequest {}.into_request();
+        req.extensions_mut()
+            .insert(GrpcMethod::new(SERVICE_NAME, "Stats"));
+        let path = PathAndQuery::from_static("/ringlog.v1.Journal/Stats");
+        self.inner.unary(req, path, ProstCodec::default()).await
+    }
+
+    async fn ready(&mut self) -> Result<(), Status> {
+        self.inner
+            .ready()
+            .await
+            .map_err(|e| Status::unknown(format!("Service was not ready: {}", e)))
+    }
+}
@@ -0,0 +1,219 @@
+//! gRPC access to a ring and its log file, so services in other languages can
+//! use ringlog as a local event journal. The service is defined in
+//! `proto/ringlog.proto`; the message types and service glue below are
+//! written by hand to match it, so no protobuf compiler is needed to build.
+
+pub mod client;
+pub mod server;
+
+pub use client::JournalClient;
+pub use server::JournalServer;
+
+use crate::event::{EventHeader, StreamId};
+use crate::ring::RingBuffer;
+use crate::storage::MmapReader;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::codec::Streaming;
+use tonic::{Request, Response, Status};
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Event {
+    #[prost(uint64, tag = "1")]
+    pub timestamp: u64,
+    #[prost(uint32, tag = "2")]
+    pub event_type: u32,
+    #[prost(uint32, tag = "3")]
+    pub stream: u32,
+    #[prost(bytes = "vec", tag = "4")]
+    pub payload: Vec<u8>,
+}
+
+#[derive(Clone, Copy, PartialEq, prost::Message)]
+pub struct AppendResponse {
+    #[prost(uint64, tag = "1")]
+    pub appended: u64,
+    #[prost(uint64, tag = "2")]
+    pub dropped: u64,
+}
+
+#[derive(Clone, Copy, PartialEq, prost::Message)]
+pub struct ReplayRequest {
+    #[prost(uint64, tag = "1")]
+    pub start_timestamp: u64,
+    /// 0 means no upper bound.
+    #[prost(uint64, tag = "2")]
+    pub end_timestamp: u64,
+    #[prost(uint32, optional, tag = "3")]
+    pub event_type: Option<u32>,
+}
+
+#[derive(Clone, Copy, PartialEq, prost::Message)]
+pub struct StatsRequest {}
+
+#[derive(Clone, Copy, PartialEq, prost::Message)]
+pub struct StatsResponse {
+    #[prost(uint64, tag = "1")]
+    pub ring_capacity: u64,
+    #[prost(uint64, tag = "2")]
+    pub ring_used: u64,
+    #[prost(uint64, tag = "3")]
+    pub events_attempted: u64,
+    #[prost(uint64, tag = "4")]
+    pub events_dropped: u64,
+    #[prost(uint64, tag = "5")]
+    pub log_events: u64,
+    #[prost(uint64, tag = "6")]
+    pub log_bytes: u64,
+    #[prost(bool, tag = "7")]
+    pub log_sealed: bool,
+}
+
+/// Events replayed per channel slot before the scan waits for the client.
+const REPLAY_BUFFER: usize = 256;
+
+/// State behind the `Journal` service: appends go to `ring`, which the
+/// application drains into `log_path` as usual; replays and stats read the
+/// log file directly.
+#[derive(Clone)]
+pub struct Journal {
+    ring: Arc<Mutex<RingBuffer>>,
+    log_path: PathBuf,
+}
+
+impl Journal {
+    pub fn new<P: AsRef<Path>>(ring: Arc<Mutex<RingBuffer>>, log_path: P) -> Self {
+        Self {
+            ring,
+            log_path: log_path.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn ring(&self) -> &Arc<Mutex<RingBuffer>> {
+        &self.ring
+    }
+
+    async fn append(
+        &self,
+        request: Request<Streaming<Event>>,
+    ) -> Result<Response<AppendResponse>, Status> {
+        let mut events = request.into_inner();
+        let mut response = AppendResponse::default();
+
+        while let Some(event) = events.message().await? {
+            let (header, payload) = to_ring_event(event)?;
+            match self.ring.lock().unwrap().write_event(&header, &payload) {
+                Ok(()) => response.appended += 1,
+                Err(_) => response.dropped += 1,
+            }
+        }
+
+        Ok(Response::new(response))
+    }
+
+    async fn replay(
+        &self,
+        request: Request<ReplayRequest>,
+    ) -> Result<Response<ReceiverStream<Result<Event, Status>>>, Status> {
+        let range = request.into_inner();
+        let reader =
+            MmapReader::open(&self.log_path).map_err(|e| Status::internal(e.to_string()))?;
+        let (tx, rx) = mpsc::channel(REPLAY_BUFFER);
+
+        tokio::task::spawn_blocking(move || {
+            let end = match range.end_timestamp {
+                0 => u64::MAX,
+                end => end,
+            };
+            let filter = |h: &EventHeader| {
+                (range.start_timestamp..end).contains(&h.timestamp)
+                    && range.event_type.is_none_or(|t| t == h.event_type as u32)
+            };
+            for event in reader.iter_filtered(filter) {
+                let event = Event {
+                    timestamp: event.header.timestamp,
+                    event_type: event.header.event_type as u32,
+                    stream: event.header.stream().get() as u32,
+                    payload: event.header.body(event.payload).to_vec(),
+                };
+                if tx.blocking_send(Ok(event)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn stats(
+        &self,
+        _request: Request<StatsRequest>,
+    ) -> Result<Response<StatsResponse>, Status> {
+        let mut response = {
+            let ring = self.ring.lock().unwrap();
+            let producer = ring.producer_stats();
+            StatsResponse {
+                ring_capacity: ring.capacity() as u64,
+                ring_used: ring.used() as u64,
+                events_attempted: producer.events_attempted,
+                events_dropped: producer.events_dropped,
+                ..Default::default()
+            }
+        };
+
+        let reader =
+            MmapReader::open(&self.log_path).map_err(|e| Status::internal(e.to_string()))?;
+        let header = reader.file_header();
+        response.log_events = header.event_count;
+        response.log_bytes = header.write_offset;
+        response.log_sealed = header.is_sealed();
+
+        Ok(Response::new(response))
+    }
+}
+
+fn to_ring_event(event: Event) -> Result<(EventHeader, Vec<u8>), Status> {
+    let event_type = u8::try_from(event.event_type).map_err(|_| {
+        Status::invalid_argument(format!("event_type {} exceeds 255", event.event_type))
+    })?;
+    if EventHeader::is_reserved_type(event_type) {
+        return Err(Status::invalid_argument(format!(
+            "event_type {} is reserved",
+            event_type
+        )));
+    }
+    let stream = u8::try_from(event.stream)
+        .ok()
+        .and_then(StreamId::new)
+        .ok_or_else(|| {
+            Status::invalid_argument(format!("stream {} exceeds {}", event.stream, StreamId::MAX))
+        })?;
+    let payload_len = u16::try_from(event.payload.len()).map_err(|_| {
+        Status::invalid_argument(format!(
+            "payload of {} bytes exceeds {}",
+            event.payload.len(),
+            u16::MAX
+        ))
+    })?;
+    let timestamp = match event.timestamp {
+        0 => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64),
+        ts => ts,
+    };
+
+    let header = EventHeader::new(timestamp, event_type, payload_len).with_stream(stream);
+    Ok((header, event.payload))
+}
+
+/// Serves the journal on `addr` until the server fails.
+pub async fn serve(journal: Journal, addr: SocketAddr) -> Result<(), tonic::transport::Error> {
+    tonic::transport::Server::builder()
+        .add_service(JournalServer::new(journal))
+        .serve(addr)
+        .await
+}
@@ -0,0 +1,104 @@
+use super::{AppendResponse, Event, Journal, ReplayRequest, StatsRequest, StatsResponse};
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::body::Body;
+use tonic::codec::Streaming;
+use tonic::codegen::{Body as HttpBody, BoxFuture, StdError, http};
+use tonic::server::{
+    ClientStreamingService, Grpc, NamedService, ServerStreamingService, UnaryService,
+};
+use tonic::{Request, Response, Status};
+use tonic_prost::ProstCodec;
+
+pub const SERVICE_NAME: &str = "ringlog.v1.Journal";
+
+/// Routes `ringlog.v1.Journal` calls to a `Journal`. Add it to a
+/// `tonic::transport::Server` to serve it next to other services.
+#[derive(Clone)]
+pub struct JournalServer {
+    inner: Arc<Journal>,
+}
+
+impl JournalServer {
+    pub fn new(journal: Journal) -> Self {
+        Self {
+            inner: Arc::new(journal),
+        }
+    }
+}
+
+impl NamedService for JournalServer {
+    const NAME: &'static str = SERVICE_NAME;
+}
+
+impl<B> tonic::codegen::Service<http::Request<B>> for JournalServer
+where
+    B: HttpBody + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<Body>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        let inner = self.inner.clone();
+        match req.uri().path() {
+            "/ringlog.v1.Journal/Append" => Box::pin(async move {
+                let mut grpc = Grpc::new(ProstCodec::default());
+                Ok(grpc.client_streaming(AppendSvc(inner), req).await)
+            }),
+            "/ringlog.v1.Journal/Replay" => Box::pin(async move {
+                let mut grpc = Grpc::new(ProstCodec::default());
+                Ok(grpc.server_streaming(ReplaySvc(inner), req).await)
+            }),
+            "/ringlog.v1.Journal/Stats" => Box::pin(async move {
+                let mut grpc = Grpc::new(ProstCodec::default());
+                Ok(grpc.unary(StatsSvc(inner), req).await)
+            }),
+            _ => Box::pin(async move { Ok(Status::unimplemented("").into_http()) }),
+        }
+    }
+}
+
+struct AppendSvc(Arc<Journal>);
+
+impl ClientStreamingService<Event> for AppendSvc {
+    type Response = AppendResponse;
+    type Future = BoxFuture<Response<AppendResponse>, Status>;
+
+    fn call(&mut self, request: Request<Streaming<Event>>) -> Self::Future {
+        let inner = self.0.clone();
+        Box::pin(async move { inner.append(request).await })
+    }
+}
+
+struct ReplaySvc(Arc<Journal>);
+
+impl ServerStreamingService<ReplayRequest> for ReplaySvc {
+    type Response = Event;
+    type ResponseStream = ReceiverStream<Result<Event, Status>>;
+    type Future = BoxFuture<Response<Self::ResponseStream>, Status>;
+
+    fn call(&mut self, request: Request<ReplayRequest>) -> Self::Future {
+        let inner = self.0.clone();
+        Box::pin(async move { inner.replay(request).await })
+    }
+}
+
+struct StatsSvc(Arc<Journal>);
+
+impl UnaryService<StatsRequest> for StatsSvc {
+    type Response = StatsResponse;
+    type Future = BoxFuture<Response<StatsResponse>, Status>;
+
+    fn call(&mut self, request: Request<StatsRequest>) -> Self::Future {
+        let inner = self.0.clone();
+        Box::pin(async move { inner.stats(request).await })
+    }
+}
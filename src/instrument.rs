@@ -0,0 +1,35 @@
+//! `tracing` spans and events from ringlog's own internals: drains, consumer
+//! dispatch, syncs and segment rotation. They are compiled in only with the
+//! `instrument` feature; without it both macros expand to nothing, so their
+//! arguments are not evaluated.
+//!
+//! Counts are emitted as `monotonic_counter.*` fields, which metrics layers
+//! such as `tracing-opentelemetry` turn into counters.
+
+/// Enters a span at the given level until the end of the enclosing block.
+#[cfg(feature = "instrument")]
+macro_rules! instrument_span {
+    ($level:ident, $($args:tt)+) => {
+        let _instrument_span = tracing::span!(tracing::Level::$level, $($args)+).entered();
+    };
+}
+
+#[cfg(not(feature = "instrument"))]
+macro_rules! instrument_span {
+    ($($args:tt)*) => {};
+}
+
+#[cfg(feature = "instrument")]
+macro_rules! instrument_event {
+    ($level:ident, $($args:tt)+) => {
+        tracing::event!(tracing::Level::$level, $($args)+)
+    };
+}
+
+#[cfg(not(feature = "instrument"))]
+macro_rules! instrument_event {
+    ($($args:tt)*) => {};
+}
+
+pub(crate) use instrument_event;
+pub(crate) use instrument_span;
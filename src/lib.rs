@@ -1,15 +1,36 @@
+pub mod admin;
+pub mod analysis;
+#[cfg(feature = "archive")]
+pub mod archive;
+pub mod clock;
+pub mod config;
 pub mod consumer;
+pub mod error;
 pub mod event;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+mod instrument;
+pub mod query;
+pub mod replication;
 pub mod ring;
+pub mod service;
+pub mod signal;
 pub mod storage;
+mod sys;
+#[cfg(feature = "systemd")]
+pub mod systemd;
+#[cfg(feature = "websocket")]
+pub mod websocket;
+
+pub use error::{Error, Result};
 
 #[cfg(test)]
 mod tests {
     use crate::consumer::EventConsumer;
     use crate::consumer::dispatcher::EventDispatcher;
     use crate::event::EventHeader;
-    use crate::ring::RingBuffer;
-    use crate::storage::{MmapReader, MmapWriter};
+    use crate::ring::{RingBuffer, RingError, SpscRingBuffer};
+    use crate::storage::{FileHeader, MmapReader, MmapWriter};
     use std::sync::atomic::{AtomicU64, Ordering};
 
     static TEST_COUNTER: AtomicU64 = AtomicU64::new(0);
@@ -148,268 +169,7499 @@ mod tests {
             assert_eq!(count, 4);
         }
 
+        #[test]
+        fn write_vectored_concatenates_parts() {
+            let mut ring = RingBuffer::new(128).unwrap();
+            let header = EventHeader::new(7, 2, 40);
+            let filler = EventHeader::new(0, 1, 64);
+
+            ring.write_event(&filler, &[0u8; 64]).unwrap();
+            ring.read_event().unwrap();
+
+            ring.write_event_vectored(&header, &[b"head-", &[0x55; 30], b"tail!"])
+                .unwrap();
+            let (h, p) = ring.read_event().unwrap();
+
+            assert_eq!(h.timestamp, 7);
+            assert_eq!(&p[..5], b"head-");
+            assert_eq!(&p[5..35], &[0x55; 30]);
+            assert_eq!(&p[35..], b"tail!");
+        }
+
+        #[test]
+        fn write_vectored_rejects_length_mismatch() {
+            let mut ring = RingBuffer::new(1024).unwrap();
+            let header = EventHeader::new(0, 1, 10);
+
+            let result = ring.write_event_vectored(&header, &[b"abc", b"def"]);
+            assert!(matches!(
+                result,
+                Err(RingError::PayloadLengthMismatch {
+                    header_len: 10,
+                    payload_len: 6
+                })
+            ));
+            assert!(ring.is_empty());
+        }
+
         #[test]
         fn capacity_must_be_power_of_two() {
             let result = RingBuffer::new(1000);
             assert!(result.is_err());
         }
-    }
-
-    mod event_header {
-        use super::*;
 
         #[test]
-        fn size_is_16_bytes() {
-            assert_eq!(EventHeader::SIZE, 16);
+        fn constructors_agree_on_valid_capacities() {
+            assert_eq!(RingBuffer::new(256).unwrap().capacity(), 256);
+            assert_eq!(RingBuffer::try_new(256).unwrap().capacity(), 256);
+            assert_eq!(RingBuffer::new_unchecked(256).capacity(), 256);
+            assert_eq!(RingBuffer::with_capacity_pow2(8).capacity(), 256);
+            assert!(matches!(
+                RingBuffer::try_new(16),
+                Err(RingError::InvalidCapacity { capacity: 16, .. })
+            ));
         }
 
         #[test]
-        fn total_size_includes_payload() {
-            let header = EventHeader::new(0, 1, 100);
-            assert_eq!(header.total_size(), 116);
+        fn with_at_least_rounds_up_to_a_power_of_two() {
+            assert_eq!(RingBuffer::with_at_least(1000).capacity(), 1024);
+            assert_eq!(RingBuffer::with_at_least(4096).capacity(), 4096);
+            assert_eq!(
+                RingBuffer::with_at_least(0).capacity(),
+                EventHeader::SIZE * 2
+            );
+            assert_eq!(SpscRingBuffer::with_at_least(3 << 20).capacity(), 4 << 20);
+            assert_eq!(SpscRingBuffer::with_at_least(1).capacity(), 64);
         }
 
         #[test]
-        fn new_sets_fields_correctly() {
-            let header = EventHeader::new(12345, 7, 256);
-            assert_eq!(header.timestamp, 12345);
-            assert_eq!(header.event_type, 7);
-            assert_eq!(header.payload_len, 256);
-            assert_eq!(header.flags, 0);
+        #[should_panic(expected = "must be a power of two")]
+        fn new_unchecked_panics_on_invalid_capacity() {
+            RingBuffer::new_unchecked(1000);
         }
-    }
 
-    mod dispatcher {
-        use super::*;
+        #[test]
+        #[should_panic(expected = "too small")]
+        fn with_capacity_pow2_panics_below_minimum() {
+            RingBuffer::with_capacity_pow2(2);
+        }
 
         #[test]
-        fn drain_empty_buffer() {
-            let mut ring = RingBuffer::new(1024).unwrap();
-            let mut dispatcher = EventDispatcher::new();
-            dispatcher.add_consumer(CountingConsumer::new());
+        fn producer_stats_track_writes() {
+            let mut ring = RingBuffer::new(64).unwrap();
 
-            let stats = dispatcher.drain(&mut ring);
+            for len in [4u16, 12] {
+                let header = EventHeader::new(0, 1, len);
+                ring.write_event(&header, &vec![0u8; len as usize]).unwrap();
+            }
+            let header = EventHeader::new(0, 1, 40);
+            assert!(ring.write_event(&header, &[0u8; 40]).is_err());
 
-            assert_eq!(stats.events_read, 0);
-            assert_eq!(stats.events_delivered, 0);
+            let stats = ring.producer_stats();
+            assert_eq!(stats.events_attempted, 3);
+            assert_eq!(stats.events_dropped, 1);
+            assert_eq!(stats.bytes_written, 48);
+            assert_eq!(stats.largest_payload, 12);
         }
 
         #[test]
-        fn drain_delivers_to_consumer() {
-            let mut ring = RingBuffer::new(1024).unwrap();
-            let mut dispatcher = EventDispatcher::new();
-            dispatcher.add_consumer(CountingConsumer::new());
+        fn full_policy_drop_newest_and_oldest() {
+            use crate::ring::FullPolicy;
 
-            for i in 0..5 {
+            let mut ring = RingBuffer::new(64)
+                .unwrap()
+                .with_full_policy(FullPolicy::DropNewest);
+            for i in 0..4 {
                 let header = EventHeader::new(i, 1, 4);
                 ring.write_event(&header, b"test").unwrap();
             }
+            assert_eq!(ring.read_event().unwrap().0.timestamp, 0);
+            assert_eq!(ring.producer_stats().events_dropped, 1);
 
-            let stats = dispatcher.drain(&mut ring);
+            let mut ring = RingBuffer::new(64)
+                .unwrap()
+                .with_full_policy(FullPolicy::DropOldest);
+            for i in 0..5 {
+                let header = EventHeader::new(i, 1, 4);
+                ring.write_event(&header, b"test").unwrap();
+            }
+            assert_eq!(ring.read_event().unwrap().0.timestamp, 2);
+            assert_eq!(ring.producer_stats().events_evicted, 2);
 
-            assert_eq!(stats.events_read, 5);
-            assert_eq!(stats.events_delivered, 5);
-            assert_eq!(stats.events_failed, 0);
+            let header = EventHeader::new(0, 1, 64);
+            assert!(ring.write_event(&header, &[0u8; 64]).is_err());
         }
 
         #[test]
-        fn drain_tracks_failures() {
+        fn clear_and_truncate_drop_oldest_events() {
             let mut ring = RingBuffer::new(1024).unwrap();
-            let mut dispatcher = EventDispatcher::new();
-            dispatcher.add_consumer(FailingConsumer);
+            let write = |ring: &mut RingBuffer, range: std::ops::Range<u64>| {
+                for i in range {
+                    ring.write_event(&EventHeader::new(i, 1, 4), b"test")
+                        .unwrap();
+                }
+            };
+
+            write(&mut ring, 0..10);
+            assert_eq!(ring.truncate_oldest(3), 3);
+            assert_eq!(ring.read_event().unwrap().0.timestamp, 3);
+
+            let event_size = EventHeader::SIZE + 4;
+            assert_eq!(ring.truncate_to_bytes(2 * event_size + 1), 4);
+            assert_eq!(ring.used(), 2 * event_size);
+            assert_eq!(ring.read_event().unwrap().0.timestamp, 8);
 
+            assert_eq!(ring.truncate_oldest(5), 1);
+            assert!(ring.is_empty());
+
+            write(&mut ring, 10..20);
+            ring.clear();
+            assert!(ring.is_empty());
+            assert!(ring.read_event().is_none());
+            write(&mut ring, 20..21);
+            assert_eq!(ring.read_event().unwrap().0.timestamp, 20);
+        }
+
+        #[test]
+        fn iter_shows_pending_events_without_consuming_them() {
+            let mut ring = RingBuffer::new(128).unwrap();
+            // Move the tail so later payloads wrap around the end.
             for i in 0..3 {
-                let header = EventHeader::new(i, 1, 4);
-                ring.write_event(&header, b"test").unwrap();
+                ring.write_event(&EventHeader::new(i, 1, 20), &[0u8; 20])
+                    .unwrap();
+                ring.read_event().unwrap();
+            }
+            let payloads: Vec<Vec<u8>> = (0..3u8).map(|i| vec![i; 20]).collect();
+            for (i, payload) in payloads.iter().enumerate() {
+                let header = EventHeader::new(10 + i as u64, 2, 20);
+                ring.write_event(&header, payload).unwrap();
             }
 
-            let stats = dispatcher.drain(&mut ring);
+            let seen: Vec<_> = ring.iter().map(|e| e.view().payload.to_vec()).collect();
+            assert_eq!(seen, payloads);
+            assert!(
+                ring.iter()
+                    .any(|e| matches!(e.payload, std::borrow::Cow::Owned(_)))
+            );
+            assert_eq!((&ring).into_iter().count(), 3);
 
-            assert_eq!(stats.events_read, 3);
-            assert_eq!(stats.events_delivered, 0);
-            assert_eq!(stats.events_failed, 3);
+            let snapshot = ring.to_vec();
+            let mut drained = Vec::new();
+            while let Some(event) = ring.read_event() {
+                drained.push(event);
+            }
+            assert_eq!(snapshot.len(), 3);
+            for ((a, pa), (b, pb)) in snapshot.iter().zip(&drained) {
+                assert_eq!(a.timestamp, b.timestamp);
+                assert_eq!(pa, pb);
+            }
+            assert_eq!(ring.iter().count(), 0);
         }
 
         #[test]
-        fn drain_batch_respects_limit() {
-            let mut ring = RingBuffer::new(1024).unwrap();
-            let mut dispatcher = EventDispatcher::new();
-            dispatcher.add_consumer(CountingConsumer::new());
-
-            for i in 0..10 {
-                let header = EventHeader::new(i, 1, 4);
-                ring.write_event(&header, b"test").unwrap();
+        fn transfer_moves_events_in_order_until_full() {
+            let mut shard = RingBuffer::new(128).unwrap();
+            let mut aggregate = RingBuffer::new(256).unwrap();
+            // Offset both rings so the copied range wraps on each side.
+            for ring in [&mut shard, &mut aggregate] {
+                for i in 0..5 {
+                    ring.write_event(&EventHeader::new(i, 1, 4), b"skip")
+                        .unwrap();
+                    ring.read_event().unwrap();
+                }
+            }
+            for i in 0..5u8 {
+                let header = EventHeader::new(100 + i as u64, 2, 4);
+                shard.write_event(&header, &[i; 4]).unwrap();
             }
 
-            let stats = dispatcher.drain_batch(&mut ring, 3);
+            assert_eq!(shard.transfer_to(&mut aggregate, 2), 2);
+            assert_eq!(shard.transfer_to(&mut aggregate, usize::MAX), 3);
+            assert!(shard.is_empty());
+            assert_eq!(shard.transfer_to(&mut aggregate, 1), 0);
+            assert_eq!(aggregate.producer_stats().events_attempted, 10);
 
-            assert_eq!(stats.events_read, 3);
-            assert!(!ring.is_empty());
+            for i in 0..5u8 {
+                let (header, payload) = aggregate.read_event().unwrap();
+                assert_eq!(header.timestamp, 100 + i as u64);
+                assert_eq!(payload, [i; 4]);
+            }
+            assert!(aggregate.is_empty());
+
+            let mut small = RingBuffer::new(64).unwrap();
+            for i in 0..3 {
+                shard
+                    .write_event(&EventHeader::new(i, 1, 4), b"test")
+                    .unwrap();
+            }
+            let fits = small.available() / (EventHeader::SIZE + 4);
+            assert_eq!(shard.transfer_to(&mut small, 3), fits);
+            assert_eq!(shard.iter().count(), 3 - fits);
         }
 
         #[test]
-        fn multiple_consumers() {
-            let mut ring = RingBuffer::new(1024).unwrap();
-            let mut dispatcher = EventDispatcher::new();
-            dispatcher.add_consumer(CountingConsumer::new());
-            dispatcher.add_consumer(CountingConsumer::new());
-
-            let header = EventHeader::new(0, 1, 4);
-            ring.write_event(&header, b"test").unwrap();
+        fn dump_writes_pending_events_to_a_log_file() {
+            let path = temp_path();
+            let mut ring = RingBuffer::new(256).unwrap();
+            for i in 0..4 {
+                ring.write_event(&EventHeader::new(i, 1, 4), b"test")
+                    .unwrap();
+            }
+            ring.read_event().unwrap();
 
-            let stats = dispatcher.drain(&mut ring);
+            assert_eq!(ring.dump_to_file(&path).unwrap(), 3);
+            assert_eq!(ring.iter().count(), 3);
+            let reader = MmapReader::open(&path).unwrap();
+            assert!(reader.is_sealed());
+            let timestamps: Vec<_> = reader.iter().map(|e| e.header.timestamp).collect();
+            assert_eq!(timestamps, [1, 2, 3]);
 
-            assert_eq!(stats.events_read, 1);
-            assert_eq!(stats.events_delivered, 2);
+            drop(reader);
+            std::fs::remove_file(&path).ok();
         }
 
         #[test]
-        fn success_rate_calculation() {
-            use crate::consumer::dispatcher::DrainStats;
+        fn saved_ring_restores_pending_events_in_place() {
+            let path = temp_path();
+            let mut ring = RingBuffer::new(128).unwrap();
+            // Leave the pending region wrapped around the end.
+            for i in 0..5 {
+                ring.write_event(&EventHeader::new(i, 1, 4), b"skip")
+                    .unwrap();
+                ring.read_event().unwrap();
+            }
+            for i in 0..3u8 {
+                let header = EventHeader::new(10 + i as u64, 2, 8);
+                ring.write_event(&header, &[i; 8]).unwrap();
+            }
 
-            let stats = DrainStats {
-                events_read: 10,
-                events_delivered: 8,
-                events_failed: 2,
-            };
+            assert_eq!(ring.save(&path).unwrap(), ring.used());
+            let mut restored = RingBuffer::restore(&path).unwrap();
+            assert_eq!(restored.capacity(), 128);
+            assert_eq!(restored.used(), ring.used());
+            assert_eq!(restored.to_vec().len(), 3);
+            for i in 0..3u8 {
+                let (header, payload) = restored.read_event().unwrap();
+                assert_eq!(header.timestamp, 10 + i as u64);
+                assert_eq!(payload, [i; 8]);
+            }
+            assert!(restored.is_empty());
 
-            assert!((stats.success_rate() - 0.8).abs() < 0.001);
+            let mut bytes = std::fs::read(&path).unwrap();
+            *bytes.last_mut().unwrap() ^= 0xFF;
+            std::fs::write(&path, &bytes).unwrap();
+            let err = RingBuffer::restore(&path).err().unwrap();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+            std::fs::remove_file(&path).ok();
         }
 
         #[test]
-        fn success_rate_empty() {
-            use crate::consumer::dispatcher::DrainStats;
+        fn watermarks_report_level_changes() {
+            use crate::ring::Watermarks;
+            use std::sync::{Arc, Mutex};
 
-            let stats = DrainStats::default();
-            assert!((stats.success_rate() - 1.0).abs() < 0.001);
+            let levels = Arc::new(Mutex::new(Vec::new()));
+            let seen = levels.clone();
+            let watermarks = Watermarks::new(&[0.5, 0.75])
+                .on_change(move |level, _occupancy| seen.lock().unwrap().push(level));
+            let mut ring = RingBuffer::new(128).unwrap().with_watermarks(watermarks);
+
+            for i in 0..5 {
+                let header = EventHeader::new(i, 1, 4);
+                ring.write_event(&header, b"test").unwrap();
+            }
+            assert_eq!(ring.pressure(), 2);
+            assert!(ring.occupancy() > 0.75);
+
+            while ring.read_event().is_some() {}
+            assert_eq!(ring.pressure(), 0);
+            assert_eq!(*levels.lock().unwrap(), vec![1, 2, 1, 0]);
         }
     }
 
-    mod mmap_storage {
+    mod quota {
         use super::*;
-        use std::fs;
-
-        #[test]
-        fn create_and_write() {
-            let path = temp_path();
+        use crate::event::StreamId;
+        use crate::ring::{FullPolicy, Quota, TenantQuotas};
 
-            {
-                let mut writer = MmapWriter::create(&path, 4096).unwrap();
+        fn event(stream: u8) -> EventHeader {
+            EventHeader::new(0, 1, 8).with_stream(StreamId::new(stream).unwrap())
+        }
 
-                for i in 0..5 {
-                    let header = EventHeader::new(i * 1000, 1, 8);
-                    let payload = i.to_le_bytes();
-                    assert!(writer.write_event(&header, &payload));
-                }
+        #[test]
+        fn a_noisy_tenant_cannot_take_the_ring() {
+            let noisy = StreamId::new(1).unwrap();
+            let quotas = TenantQuotas::new(Quota::events(2))
+                .with_stream(StreamId::DEFAULT, Quota::UNLIMITED);
+            let mut ring = RingBuffer::new(4096).unwrap().with_quotas(quotas);
 
-                let fh = writer.file_header();
-                assert_eq!(fh.event_count, 5);
+            ring.write_event(&event(1), b"testdata").unwrap();
+            ring.write_event(&event(1), b"testdata").unwrap();
+            assert!(matches!(
+                ring.write_event(&event(1), b"testdata"),
+                Err(RingError::QuotaExceeded { stream }) if stream == noisy
+            ));
+            for _ in 0..5 {
+                ring.write_event(&event(0), b"testdata").unwrap();
             }
+            ring.write_event(&event(2), b"testdata").unwrap();
 
-            fs::remove_file(&path).ok();
+            let stats = ring.tenant_stats(noisy);
+            assert_eq!((stats.events_written, stats.events_rejected), (2, 1));
+            assert_eq!(stats.bytes_held, 2 * event(1).total_size() as u64);
+            assert_eq!(ring.active_tenants().len(), 3);
+
+            // Draining frees the quota again.
+            ring.read_event().unwrap();
+            ring.write_event(&event(1), b"testdata").unwrap();
+            ring.clear();
+            assert_eq!(ring.tenant_stats(noisy).events_held, 0);
+            assert_eq!(ring.tenant_stats(noisy).events_written, 3);
         }
 
         #[test]
-        fn write_and_read_back() {
-            let path = temp_path();
+        fn byte_quotas_hold_under_eviction() {
+            let size = event(0).total_size();
+            let mut ring = RingBuffer::new(128)
+                .unwrap()
+                .with_full_policy(FullPolicy::DropOldest)
+                .with_quotas(TenantQuotas::new(Quota::bytes(2 * size as u64)));
 
-            {
-                let mut writer = MmapWriter::create(&path, 4096).unwrap();
-
-                for i in 0..10u64 {
-                    let header = EventHeader::new(i * 1000, 1, 8);
-                    let payload = i.to_le_bytes();
-                    writer.write_event(&header, &payload);
-                }
+            for _ in 0..6 {
+                let _ = ring.write_event(&event(0), b"testdata");
+            }
+            let stats = ring.tenant_stats(StreamId::DEFAULT);
+            assert_eq!(stats.events_held, ring.iter().count() as u64);
+            assert!(stats.bytes_held <= 2 * size as u64);
+            assert_eq!(stats.events_rejected, 4);
+        }
 
-                writer.sync().unwrap();
+        #[test]
+        fn transfers_stop_at_the_destination_quota() {
+            let noisy = StreamId::new(1).unwrap();
+            let mut shard = RingBuffer::new(4096).unwrap();
+            let quotas = TenantQuotas::new(Quota::UNLIMITED).with_stream(noisy, Quota::events(2));
+            let mut aggregate = RingBuffer::new(4096).unwrap().with_quotas(quotas);
+            for stream in [0, 1, 1, 1, 0] {
+                shard.write_event(&event(stream), b"testdata").unwrap();
             }
 
-            {
-                let reader = MmapReader::open(&path).unwrap();
-                assert_eq!(reader.event_count(), 10);
+            assert_eq!(shard.transfer_to(&mut aggregate, usize::MAX), 3);
+            assert_eq!(shard.iter().count(), 2);
+            let stats = aggregate.tenant_stats(noisy);
+            assert_eq!((stats.events_held, stats.events_rejected), (2, 1));
 
-                let mut sum = 0u64;
-                let count = reader.replay(|event| {
-                    let val = u64::from_le_bytes(event.payload.try_into().unwrap());
-                    sum += val;
-                });
+            aggregate.read_event().unwrap();
+            aggregate.read_event().unwrap();
+            assert_eq!(shard.transfer_to(&mut aggregate, usize::MAX), 2);
+            assert!(shard.is_empty());
+        }
+    }
 
-                assert_eq!(count, 10);
-                assert_eq!(sum, 45);
+    mod spsc {
+        use super::*;
+
+        #[test]
+        fn write_vectored_round_trip() {
+            let ring = SpscRingBuffer::new(64).unwrap();
+            let (mut producer, mut consumer) = ring.split();
+
+            for i in 0..5u64 {
+                let header = EventHeader::new(i, 1, 12);
+                assert!(producer.write_event_vectored(&header, &[&i.to_le_bytes(), b"body"]));
+                let (h, p) = consumer.read_event().unwrap();
+                assert_eq!(h.timestamp, i);
+                assert_eq!(&p[..8], &i.to_le_bytes());
+                assert_eq!(&p[8..], b"body");
             }
 
-            fs::remove_file(&path).ok();
+            let header = EventHeader::new(0, 1, 5);
+            assert!(!producer.write_event_vectored(&header, &[b"abc"]));
+            assert!(consumer.is_empty());
         }
 
         #[test]
-        fn iterator_works() {
+        fn consumer_dump_drains_into_a_log_file() {
             let path = temp_path();
+            let ring = SpscRingBuffer::new(256).unwrap();
+            let (mut producer, mut consumer) = ring.split();
+            for i in 0..3 {
+                assert!(producer.write_event(&EventHeader::new(i, 1, 4), b"test"));
+            }
 
-            {
-                let mut writer = MmapWriter::create(&path, 4096).unwrap();
+            assert_eq!(consumer.dump_to_file(&path).unwrap(), 3);
+            assert!(consumer.is_empty());
+            assert_eq!(MmapReader::open(&path).unwrap().iter().count(), 3);
 
-                for i in 0..5u64 {
+            std::fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn drop_oldest_evicts_under_concurrent_reads() {
+            use crate::ring::FullPolicy;
+
+            let ring = SpscRingBuffer::new(256)
+                .unwrap()
+                .with_full_policy(FullPolicy::DropOldest);
+            let (mut producer, mut consumer) = ring.split();
+
+            std::thread::scope(|scope| {
+                let reader = scope.spawn(move || {
+                    let mut last = None;
+                    let mut seen = 0u64;
+                    loop {
+                        let Some((header, payload)) = consumer.read_event() else {
+                            std::thread::yield_now();
+                            continue;
+                        };
+                        assert_eq!(payload, header.timestamp.to_le_bytes());
+                        assert!(last.is_none_or(|l| header.timestamp > l));
+                        last = Some(header.timestamp);
+                        seen += 1;
+                        if header.timestamp == 9_999 {
+                            return seen;
+                        }
+                    }
+                });
+
+                for i in 0..10_000u64 {
                     let header = EventHeader::new(i, 1, 8);
-                    writer.write_event(&header, &i.to_le_bytes());
+                    assert!(producer.write_event(&header, &i.to_le_bytes()));
                 }
+                let seen = reader.join().unwrap();
+                assert_eq!(seen + ring.producer_stats().events_evicted, 10_000);
+            });
+        }
 
-                writer.sync().unwrap();
-            }
+        #[test]
+        fn block_policy_waits_for_consumer() {
+            use crate::ring::FullPolicy;
+            use std::time::Duration;
 
-            {
-                let reader = MmapReader::open(&path).unwrap();
-                let events: Vec<_> = reader.iter().collect();
+            let ring = SpscRingBuffer::new(64)
+                .unwrap()
+                .with_full_policy(FullPolicy::Block(Duration::from_secs(5)));
+            let (mut producer, mut consumer) = ring.split();
+            let header = EventHeader::new(0, 1, 24);
+            assert!(producer.write_event(&header, &[0u8; 24]));
 
-                assert_eq!(events.len(), 5);
-                for (i, event) in events.iter().enumerate() {
-                    assert_eq!(event.header.timestamp, i as u64);
-                }
-            }
+            std::thread::scope(|scope| {
+                scope.spawn(move || {
+                    std::thread::sleep(Duration::from_millis(20));
+                    consumer.read_event().unwrap();
+                });
+                assert!(producer.write_event(&header, &[0u8; 24]));
+            });
 
-            fs::remove_file(&path).ok();
+            let ring = SpscRingBuffer::new(64)
+                .unwrap()
+                .with_full_policy(FullPolicy::Block(Duration::from_millis(10)));
+            let (mut producer, _consumer) = ring.split();
+            assert!(producer.write_event(&header, &[0u8; 24]));
+            assert!(!producer.write_event(&header, &[0u8; 24]));
+            assert_eq!(ring.producer_stats().events_dropped, 1);
         }
 
         #[test]
-        fn reopen_existing_file() {
-            let path = temp_path();
+        fn producer_stats_count_drops() {
+            let ring = SpscRingBuffer::new(64).unwrap();
+            let (mut producer, _consumer) = ring.split();
 
-            {
-                let mut writer = MmapWriter::create(&path, 4096).unwrap();
-                let header = EventHeader::new(0, 1, 4);
-                writer.write_event(&header, b"test");
-                writer.sync().unwrap();
-            }
+            let header = EventHeader::new(0, 1, 24);
+            assert!(producer.write_event(&header, &[0u8; 24]));
+            assert!(!producer.write_event(&header, &[0u8; 24]));
 
-            {
-                let mut writer = MmapWriter::open(&path).unwrap();
-                let header = EventHeader::new(1, 1, 4);
-                writer.write_event(&header, b"more");
-                writer.sync().unwrap();
-            }
+            let stats = producer.stats();
+            assert_eq!(stats.events_attempted, 2);
+            assert_eq!(stats.events_dropped, 1);
+            assert_eq!(stats.bytes_written, 40);
+            assert_eq!(stats.largest_payload, 24);
+            assert_eq!(ring.producer_stats(), stats);
+        }
+    }
 
-            {
-                let reader = MmapReader::open(&path).unwrap();
-                assert_eq!(reader.event_count(), 2);
-            }
+    mod doorbell {
+        use crate::ring::Doorbell;
+        use std::time::{Duration, Instant};
 
-            fs::remove_file(&path).ok();
+        #[test]
+        fn wait_times_out_unless_rung() {
+            let bell = Doorbell::new();
+            let epoch = bell.epoch();
+            let start = Instant::now();
+            assert!(!bell.wait(epoch, Some(Duration::from_millis(20))));
+            assert!(start.elapsed() >= Duration::from_millis(20));
+
+            bell.ring();
+            assert!(bell.wait(epoch, Some(Duration::from_secs(5))));
         }
 
         #[test]
-        fn buffer_full_returns_false() {
-            let path = temp_path();
+        fn ring_wakes_a_sleeper_in_shared_memory() {
+            let len = 4096;
+            let ptr = unsafe {
+                libc::mmap(
+                    std::ptr::null_mut(),
+                    len,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_SHARED | libc::MAP_ANONYMOUS,
+                    -1,
+                    0,
+                )
+            };
+            assert_ne!(ptr, libc::MAP_FAILED);
+            let bell: &Doorbell = unsafe { Doorbell::from_ptr(ptr as *mut u8) };
 
-            {
-                let mut writer = MmapWriter::create(&path, 4096).unwrap();
-                let header = EventHeader::new(0, 1, 2048);
-                let payload = [0u8; 2048];
+            let epoch = bell.epoch();
+            let start = Instant::now();
+            std::thread::scope(|scope| {
+                scope.spawn(|| {
+                    std::thread::sleep(Duration::from_millis(20));
+                    bell.ring();
+                });
+                assert!(bell.wait(epoch, Some(Duration::from_secs(10))));
+            });
+            assert!(start.elapsed() < Duration::from_secs(10));
 
-                let first = writer.write_event(&header, &payload);
-                assert!(first);
+            unsafe { libc::munmap(ptr, len) };
+        }
+    }
 
-                let second = writer.write_event(&header, &payload);
-                assert!(!second);
+    mod latest_value {
+        use super::*;
+        use crate::ring::{LatestValueSlot, LatestValues};
+
+        #[test]
+        fn slot_keeps_only_the_latest_value() {
+            let slot = LatestValueSlot::new(16);
+            assert!(slot.load().is_none());
+
+            slot.store(&EventHeader::new(1, 3, 5), b"first").unwrap();
+            slot.store(&EventHeader::new(2, 3, 3), b"two").unwrap();
+            let (header, payload) = slot.load().unwrap();
+            assert_eq!(header.timestamp, 2);
+            assert_eq!(payload, b"two");
+            assert_eq!(slot.load().unwrap().1, b"two");
+            assert_eq!(slot.version(), 2);
+
+            assert!(matches!(
+                slot.store(&EventHeader::new(3, 3, 17), &[0u8; 17]),
+                Err(RingError::PayloadTooLarge { max_len: 16, .. })
+            ));
+
+            let values = LatestValues::new(8);
+            values.record(&EventHeader::new(5, 7, 4), b"cpu1").unwrap();
+            values.record(&EventHeader::new(6, 9, 4), b"mem1").unwrap();
+            values.record(&EventHeader::new(7, 7, 4), b"cpu2").unwrap();
+            assert_eq!(values.get(7).unwrap().1, b"cpu2");
+            assert_eq!(values.get(9).unwrap().1, b"mem1");
+            assert!(values.get(1).is_none());
+        }
+
+        #[test]
+        fn readers_never_see_torn_values() {
+            let slot = LatestValueSlot::new(64);
+            let done = std::sync::atomic::AtomicBool::new(false);
+
+            std::thread::scope(|scope| {
+                for _ in 0..3 {
+                    scope.spawn(|| {
+                        while !done.load(Ordering::Relaxed) {
+                            if let Some((header, payload)) = slot.load() {
+                                let fill = header.timestamp as u8;
+                                assert_eq!(payload.len(), 64);
+                                assert!(payload.iter().all(|&b| b == fill));
+                            }
+                        }
+                    });
+                }
+                for i in 0..20_000u64 {
+                    let header = EventHeader::new(i, 1, 64);
+                    slot.store(&header, &[i as u8; 64]).unwrap();
+                }
+                done.store(true, Ordering::Relaxed);
+            });
+            assert_eq!(slot.version(), 20_000);
+        }
+    }
+
+    mod sharded {
+        use super::*;
+        use crate::ring::{DrainOrder, ShardedRing};
+
+        #[test]
+        fn each_thread_gets_its_own_shard() {
+            let ring = ShardedRing::new(4, 4096).unwrap();
+            let barrier = std::sync::Barrier::new(4);
+
+            std::thread::scope(|scope| {
+                for t in 0..4u64 {
+                    let (ring, barrier) = (&ring, &barrier);
+                    scope.spawn(move || {
+                        let mut producer = ring.producer().unwrap();
+                        barrier.wait();
+                        for i in 0..50u64 {
+                            let header = EventHeader::new(t * 1000 + i, t as u8, 8);
+                            assert!(producer.write_event(&header, &i.to_le_bytes()));
+                        }
+                    });
+                }
+            });
+
+            let mut consumer = ring.consumer(DrainOrder::RoundRobin).unwrap();
+            assert!(ring.consumer(DrainOrder::RoundRobin).is_none());
+            let mut per_shard = [0u64; 4];
+            while let Some((header, payload)) = consumer.read_event() {
+                let i = u64::from_le_bytes(payload.try_into().unwrap());
+                assert_eq!(i, per_shard[header.event_type as usize]);
+                per_shard[header.event_type as usize] += 1;
+            }
+            assert_eq!(per_shard, [50; 4]);
+        }
+
+        #[test]
+        fn shards_are_released_on_drop() {
+            let ring = ShardedRing::new(2, 1024).unwrap();
+            let a = ring.producer().unwrap();
+            let b = ring.producer().unwrap();
+            assert_ne!(a.shard_index(), b.shard_index());
+            assert!(ring.producer().is_none());
+
+            drop(a);
+            assert!(ring.producer().is_some());
+        }
+
+        #[test]
+        fn timestamp_order_merges_shards() {
+            let ring = ShardedRing::new(2, 1024).unwrap();
+            let mut a = ring.producer().unwrap();
+            let mut b = ring.producer().unwrap();
+            for ts in [1, 4, 5] {
+                assert!(a.write_event(&EventHeader::new(ts, 1, 4), b"test"));
+            }
+            for ts in [2, 3, 6] {
+                assert!(b.write_event(&EventHeader::new(ts, 1, 4), b"test"));
+            }
+
+            let mut consumer = ring.consumer(DrainOrder::Timestamp).unwrap();
+            let order: Vec<u64> = std::iter::from_fn(|| consumer.read_event())
+                .map(|(h, _)| h.timestamp)
+                .collect();
+            assert_eq!(order, vec![1, 2, 3, 4, 5, 6]);
+        }
+    }
+
+    mod event_header {
+        use super::*;
+
+        #[test]
+        fn size_is_16_bytes() {
+            assert_eq!(EventHeader::SIZE, 16);
+        }
+
+        #[test]
+        fn total_size_includes_payload() {
+            let header = EventHeader::new(0, 1, 100);
+            assert_eq!(header.total_size(), 116);
+        }
+
+        #[test]
+        fn new_sets_fields_correctly() {
+            let header = EventHeader::new(12345, 7, 256);
+            assert_eq!(header.timestamp, 12345);
+            assert_eq!(header.event_type, 7);
+            assert_eq!(header.payload_len, 256);
+            assert_eq!(header.flags, 0);
+        }
+
+        #[test]
+        fn encodes_little_endian() {
+            let header = EventHeader::new(0x0102_0304_0506_0708, 7, 0x0A0B);
+            let bytes = header.encode();
+            assert_eq!(&bytes[..8], &[8, 7, 6, 5, 4, 3, 2, 1]);
+            assert_eq!(bytes[8], 7);
+            assert_eq!(&bytes[10..12], &[0x0B, 0x0A]);
+
+            let decoded = EventHeader::decode(&bytes);
+            assert_eq!(decoded.timestamp, header.timestamp);
+            assert_eq!(decoded.payload_len, header.payload_len);
+        }
+
+        #[test]
+        fn views_decode_from_bytes_and_reject_short_input() {
+            let header = EventHeader::new(9, 3, 4);
+            let mut buf = vec![0xAA];
+            buf.extend_from_slice(&header.encode());
+            buf.extend_from_slice(b"data");
+
+            let view = crate::event::EventView::from_bytes(&buf, 1).unwrap();
+            assert_eq!(view.header.timestamp, 9);
+            assert_eq!(view.payload, b"data");
+            assert!(crate::event::EventView::from_bytes(&buf[..buf.len() - 1], 1).is_none());
+            assert!(crate::event::EventView::from_bytes(&buf, buf.len() + 1).is_none());
+        }
+
+        #[test]
+        fn file_header_round_trips() {
+            let mut header = FileHeader::new(-5);
+            header.event_count = 3;
+            header.write_offset = 0x1234;
+            let bytes = header.encode();
+            assert_eq!(&bytes[..4], b"EVIL");
+            assert_eq!(&bytes[24..26], &[0x34, 0x12]);
+
+            let decoded = FileHeader::decode(&bytes);
+            assert!(decoded.validate());
+            assert_eq!(decoded.created_at, -5);
+            assert_eq!(decoded.event_count, 3);
+            assert_eq!(decoded.write_offset, 0x1234);
+        }
+
+        #[test]
+        fn crc32_matches_reference() {
+            assert_eq!(crate::event::crc32(b"123456789"), 0xCBF4_3926);
+        }
+
+        #[test]
+        fn with_checksum_sets_flag() {
+            let header = EventHeader::new(0, 1, 4).with_checksum(b"test");
+            assert!(header.has_checksum());
+            assert_eq!(header.checksum(), Some(crate::event::crc32(b"test")));
+            assert_eq!(EventHeader::new(0, 1, 4).checksum(), None);
+        }
+    }
+
+    mod compact_header {
+        use super::*;
+        use crate::event::{CompactHeader, EventSink};
+        use crate::storage::{VerifyIssue, WriterOptions};
+
+        fn compact_options() -> WriterOptions {
+            WriterOptions {
+                compact: true,
+                ..Default::default()
+            }
+        }
+
+        #[test]
+        fn encode_decode_round_trip() {
+            let header = CompactHeader::new(1_000, 4, 0x21, &[b"ab", b"c"]).unwrap();
+            let decoded = CompactHeader::decode(&header.encode());
+            assert_eq!(decoded, header);
+            assert!(decoded.verify(b"abc"));
+            assert!(!decoded.verify(b"abd"));
+            assert_eq!(decoded.to_header(500).timestamp, 1_500);
+            assert!(CompactHeader::new(0, 1, 0, &[&[0u8; 256]]).is_none());
+        }
+
+        #[test]
+        fn writes_and_reads_compact_file() {
+            let path = temp_path();
+            let mut writer =
+                MmapWriter::create_with_options(&path, 4096, &compact_options()).unwrap();
+            // The large jump and the regression both need a time-base record.
+            let timestamps = [10, 20, 10_000_000_000, 5, 6];
+            for (i, &ts) in timestamps.iter().enumerate() {
+                let header = EventHeader::new(ts, i as u8, 2).with_checksum(b"xy");
+                assert!(writer.write_event(&header, b"xy"));
+            }
+            let used = writer.write_offset() - writer.file_header().data_start();
+            assert_eq!(
+                used,
+                5 * (CompactHeader::SIZE + 2) + 2 * (CompactHeader::SIZE + 8)
+            );
+            drop(writer);
+
+            let reader = MmapReader::open(&path).unwrap();
+            assert!(reader.file_header().is_compact());
+            let read: Vec<_> = reader
+                .iter()
+                .map(|e| (e.header.timestamp, e.header.event_type, e.payload.to_vec()))
+                .collect();
+            let expected: Vec<_> = timestamps
+                .iter()
+                .enumerate()
+                .map(|(i, &ts)| (ts, i as u8, b"xy".to_vec()))
+                .collect();
+            assert_eq!(read, expected);
+            let rev: Vec<u64> = reader.iter_rev().map(|e| e.header.timestamp).collect();
+            assert_eq!(rev, vec![6, 5, 10_000_000_000, 20, 10]);
+
+            let report = reader.verify();
+            assert_eq!(report.checksums_verified, 5);
+            assert_eq!(report.issues.len(), 1);
+            assert!(matches!(
+                report.issues[0],
+                VerifyIssue::TimestampRegression { current: 5, .. }
+            ));
+            assert_eq!(
+                reader.stats().total_bytes,
+                5 * (CompactHeader::SIZE as u64 + 2)
+            );
+
+            drop(reader);
+            std::fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn reopen_continues_deltas_and_recovers() {
+            let path = temp_path();
+            // Unlocked, so the forgotten writer does not keep a lock a crash
+            // would have released.
+            let options = WriterOptions {
+                lock: false,
+                ..compact_options()
+            };
+            let mut writer = MmapWriter::create_with_options(&path, 4096, &options).unwrap();
+            writer.set_deferred_header(true);
+            writer.write_event(&EventHeader::new(100, 1, 1), b"a");
+            writer.write_event(&EventHeader::new(200, 1, 1), b"b");
+            std::mem::forget(writer);
+
+            let mut writer = MmapWriter::open(&path).unwrap();
+            assert_eq!(writer.file_header().event_count, 2);
+            let before = writer.write_offset();
+            writer.write_event(&EventHeader::new(300, 1, 1), b"c");
+            assert_eq!(writer.write_offset() - before, CompactHeader::SIZE + 1);
+            drop(writer);
+
+            let reader = MmapReader::open(&path).unwrap();
+            let timestamps: Vec<u64> = reader.iter().map(|e| e.header.timestamp).collect();
+            assert_eq!(timestamps, vec![100, 200, 300]);
+            assert!(reader.verify().is_ok());
+
+            drop(reader);
+            std::fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn rejects_events_that_do_not_fit() {
+            let path = temp_path();
+            let mut writer =
+                MmapWriter::create_with_options(&path, 4096, &compact_options()).unwrap();
+            assert!(matches!(
+                writer.event(1).payload(&[0u8; 300]).write(),
+                Err(crate::Error::Ring(RingError::PayloadTooLarge {
+                    max_len: 255,
+                    ..
+                }))
+            ));
+            let header = EventHeader::new(1, CompactHeader::TYPE_TIME_BASE, 0);
+            assert!(!writer.write_event(&header, &[]));
+            assert_eq!(writer.file_header().event_count, 0);
+
+            drop(writer);
+            std::fs::remove_file(&path).ok();
+        }
+    }
+
+    mod event_builder {
+        use super::*;
+        use crate::event::{EventSink, StreamId, TraceContext, crc32};
+
+        #[test]
+        fn reserved_types_are_refused() {
+            let mut ring = RingBuffer::new(1024).unwrap();
+            for event_type in EventHeader::FIRST_RESERVED_TYPE..=u8::MAX {
+                assert!(matches!(
+                    ring.event(event_type).write(),
+                    Err(crate::Error::Ring(RingError::ReservedType { event_type: t })) if t == event_type
+                ));
+                let header = EventHeader::new(0, event_type, 0);
+                assert!(ring.write_event(&header, &[]).is_err());
+                assert!(ring.write_event_vectored(&header, &[]).is_err());
+            }
+            assert!(ring.is_empty());
+
+            ring.marker("deploy").write().unwrap();
+            ring.tombstone(b"user-1").write().unwrap();
+            ring.event(1).deliver_at(0).write().unwrap();
+            let types: Vec<_> = std::iter::from_fn(|| ring.read_event())
+                .map(|(header, _)| header.event_type)
+                .collect();
+            assert_eq!(
+                types,
+                [
+                    EventHeader::TYPE_MARKER,
+                    EventHeader::TYPE_TOMBSTONE,
+                    EventHeader::TYPE_DELAYED
+                ]
+            );
+
+            let path = temp_path();
+            let mut writer = MmapWriter::create(&path, 4096).unwrap();
+            let header = EventHeader::new(0, EventHeader::TYPE_HEARTBEAT, 0);
+            assert!(matches!(
+                writer.try_write_event(&header, &[]),
+                Err(crate::storage::StorageError::InvalidHeader { .. })
+            ));
+            assert!(!writer.write_event_vectored(&header, &[]));
+            assert_eq!(
+                writer.write_batch(&[(EventHeader::new(0, 1, 0), &[]), (header, &[])]),
+                1
+            );
+            writer.tombstone(b"user-1").write().unwrap();
+            assert_eq!(writer.file_header().event_count, 2);
+
+            drop(writer);
+            std::fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn builds_header_from_payload() {
+            let mut ring = RingBuffer::new(1024).unwrap();
+            ring.event(3)
+                .flag(EventHeader::FLAG_COMPRESSED)
+                .stream(StreamId::new(2).unwrap())
+                .timestamp(42)
+                .payload(b"hello")
+                .checksum()
+                .write()
+                .unwrap();
+
+            let (header, payload) = ring.read_event().unwrap();
+            assert_eq!(header.timestamp, 42);
+            assert_eq!(header.event_type, 3);
+            assert_eq!(header.payload_len, 5);
+            assert_eq!(header.stream().get(), 2);
+            assert_ne!(header.flags & EventHeader::FLAG_COMPRESSED, 0);
+            assert_eq!(header.checksum(), Some(crc32(b"hello")));
+            assert_eq!(payload, b"hello");
+        }
+
+        #[test]
+        fn stamps_sequence_and_time() {
+            let path = temp_path();
+            let mut writer = MmapWriter::create(&path, 4096).unwrap();
+            writer
+                .event(1)
+                .sequence(9)
+                .payload(b"data")
+                .checksum()
+                .write()
+                .unwrap();
+
+            let header = writer.file_header();
+            assert_eq!(header.event_count, 1);
+            drop(writer);
+
+            let reader = MmapReader::open(&path).unwrap();
+            let event = reader.iter().next().unwrap();
+            assert!(event.header.timestamp > 0);
+            assert_eq!(
+                event.header.split_sequence(event.payload),
+                (Some(9), &b"data"[..])
+            );
+            assert!(reader.verify().is_ok());
+
+            drop(reader);
+            std::fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn trace_context_follows_sequence() {
+            let mut ring = RingBuffer::new(1024).unwrap();
+            let trace = TraceContext::new(0xABCD, 7);
+            ring.event(2)
+                .sequence(3)
+                .trace(trace)
+                .payload(b"body")
+                .write()
+                .unwrap();
+
+            let (header, payload) = ring.read_event().unwrap();
+            assert_eq!(header.payload_len as usize, 8 + TraceContext::SIZE + 4);
+            assert_eq!(header.split_sequence(&payload).0, Some(3));
+            assert_eq!(header.split_trace(&payload), (Some(trace), &b"body"[..]));
+            assert_eq!(header.body(&payload), b"body");
+        }
+
+        #[test]
+        fn find_by_trace_returns_matching_events() {
+            let path = temp_path();
+            let mut writer = MmapWriter::create(&path, 4096).unwrap();
+            for (i, trace_id) in [1u64, 2, 1].into_iter().enumerate() {
+                writer
+                    .event(1)
+                    .trace(TraceContext::new(trace_id, i as u64))
+                    .payload(b"x")
+                    .write()
+                    .unwrap();
+            }
+            writer.event(1).payload(b"untraced").write().unwrap();
+            drop(writer);
+
+            let reader = MmapReader::open(&path).unwrap();
+            let spans: Vec<u64> = reader
+                .find_by_trace(1)
+                .map(|e| e.header.split_trace(e.payload).0.unwrap().span_id)
+                .collect();
+            assert_eq!(spans, vec![0, 2]);
+            assert_eq!(reader.find_by_trace(9).count(), 0);
+
+            drop(reader);
+            std::fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn rejects_oversized_and_full() {
+            let ring = SpscRingBuffer::new(64).unwrap();
+            let (mut producer, _consumer) = ring.split();
+            let big = vec![0u8; 70_000];
+
+            assert!(matches!(
+                producer.event(1).payload(&big).write(),
+                Err(crate::Error::Ring(RingError::PayloadTooLarge { .. }))
+            ));
+            assert!(matches!(
+                producer.event(1).payload(&[0u8; 60]).write(),
+                Err(crate::Error::Ring(RingError::NotEnoughSpace { .. }))
+            ));
+        }
+    }
+
+    mod governor {
+        use super::*;
+        use crate::event::{EventSink, Governor, RateLimit};
+        use std::time::Duration;
+
+        #[test]
+        fn drops_bursts_over_the_rate_of_limited_types_only() {
+            let ring = RingBuffer::new(4096).unwrap();
+            let mut governor = Governor::new(ring).with_limit(7, RateLimit::new(0.001, 3));
+            for _ in 0..10 {
+                governor.event(7).payload(b"debug").write().unwrap();
+                governor.event(1).payload(b"info").write().unwrap();
+            }
+
+            let stats = governor.stats(7);
+            assert_eq!((stats.allowed, stats.dropped), (3, 7));
+            assert_eq!(governor.dropped(), 7);
+            assert_eq!(governor.stats(1), Default::default());
+            assert_eq!(governor.get_ref().iter().count(), 13);
+        }
+
+        #[test]
+        fn coalesces_excess_into_the_latest_event() {
+            let ring = RingBuffer::new(4096).unwrap();
+            let limit = RateLimit::new(100.0, 1).coalesce();
+            let mut governor = Governor::new(ring).with_limit(7, limit);
+            for i in 0u8..5 {
+                governor.event(7).payload(&[i]).write().unwrap();
+            }
+            assert_eq!(governor.flush().unwrap(), 0);
+
+            std::thread::sleep(Duration::from_millis(20));
+            assert_eq!(governor.flush().unwrap(), 1);
+            let stats = governor.stats(7);
+            assert_eq!((stats.allowed, stats.coalesced, stats.dropped), (2, 3, 0));
+
+            let mut ring = governor.into_inner();
+            let payloads: Vec<_> = std::iter::from_fn(|| ring.read_event())
+                .map(|(_, payload)| payload)
+                .collect();
+            assert_eq!(payloads, vec![vec![0], vec![4]]);
+        }
+    }
+
+    mod fields {
+        use super::*;
+        use crate::event::{
+            EventSink, FieldError, FieldReader, FieldValue, FieldWriter, StringTable,
+        };
+        use crate::storage::{Metadata, WriterOptions};
+
+        #[test]
+        fn round_trips_fields() {
+            let mut writer = FieldWriter::new();
+            writer
+                .u64("count", 42)
+                .f64("ratio", 0.5)
+                .str("user", "alice")
+                .bytes("blob", &[0xDE, 0xAD]);
+            let payload = writer.finish().unwrap();
+
+            let fields: Vec<_> = FieldReader::new(&payload).map(Result::unwrap).collect();
+            assert_eq!(
+                fields,
+                vec![
+                    ("count", FieldValue::U64(42)),
+                    ("ratio", FieldValue::F64(0.5)),
+                    ("user", FieldValue::Str("alice")),
+                    ("blob", FieldValue::Bytes(&[0xDE, 0xAD])),
+                ]
+            );
+            let reader = FieldReader::new(&payload);
+            assert_eq!(reader.get("user"), Some(FieldValue::Str("alice")));
+            assert_eq!(reader.get("missing"), None);
+            assert_eq!(
+                reader.to_string(),
+                "count=42 ratio=0.5 user=\"alice\" blob=0xdead"
+            );
+        }
+
+        #[test]
+        fn oversized_fields_fail_the_writer() {
+            let long_key = "k".repeat(256);
+            let mut writer = FieldWriter::new();
+            writer.u64(&long_key, 1).str("ok", "kept");
+            assert_eq!(writer.as_bytes(), Err(FieldError::KeyTooLong { len: 256 }));
+
+            let mut writer = FieldWriter::new();
+            writer.bytes("blob", &vec![0; 70_000]).u64(&long_key, 1);
+            assert_eq!(
+                writer.finish(),
+                Err(FieldError::ValueTooLong { len: 70_000 })
+            );
+        }
+
+        #[test]
+        fn reports_malformed_payloads() {
+            let mut writer = FieldWriter::new();
+            writer.u64("a", 1).str("b", "text");
+            let payload = writer.finish().unwrap();
+
+            let truncated = &payload[..payload.len() - 1];
+            let results: Vec<_> = FieldReader::new(truncated).collect();
+            assert_eq!(results.len(), 2);
+            assert!(matches!(results[1], Err(FieldError::Truncated { .. })));
+
+            let bad_tag = [1, b'x', 9];
+            assert_eq!(
+                FieldReader::new(&bad_tag).next(),
+                Some(Err(FieldError::UnknownTag { offset: 2, tag: 9 }))
+            );
+            assert_eq!(
+                FieldReader::new(&bad_tag).to_string(),
+                "<Unknown field tag 9 at offset 2>"
+            );
+        }
+
+        #[test]
+        fn resolves_interned_strings_from_metadata() {
+            let mut strings = StringTable::new();
+            let module = strings.intern("net::tcp");
+            assert_eq!(strings.intern("net::tcp"), module);
+            let mut metadata = Metadata::new("test");
+            strings.write_to(&mut metadata);
+
+            let path = temp_path();
+            let options = WriterOptions {
+                metadata: Some(metadata),
+                ..Default::default()
+            };
+            let mut writer = MmapWriter::create_with_options(&path, 4096, &options).unwrap();
+            let mut fields = FieldWriter::new();
+            fields
+                .str_interned("module", "net::tcp", &mut strings)
+                .interned("host", 99);
+            writer
+                .event(1)
+                .payload(fields.as_bytes().unwrap())
+                .write()
+                .unwrap();
+            drop(writer);
+
+            let reader = MmapReader::open(&path).unwrap();
+            let table = reader.string_table();
+            assert_eq!(table.resolve(module), Some("net::tcp"));
+            let event = reader.iter().next().unwrap();
+            let resolved = FieldReader::new(event.payload).with_strings(&table);
+            assert_eq!(resolved.get("module"), Some(FieldValue::Str("net::tcp")));
+            assert_eq!(resolved.to_string(), "module=\"net::tcp\" host=#99");
+            assert_eq!(
+                FieldReader::new(event.payload).get("module"),
+                Some(FieldValue::Interned(module))
+            );
+
+            drop(reader);
+            std::fs::remove_file(&path).ok();
+        }
+    }
+
+    mod display {
+        use super::*;
+        use crate::event::{
+            EventSink, EventView, FieldWriter, HexDump, StreamId, Timestamp, TypeNames,
+        };
+        use crate::storage::{Metadata, WriterOptions};
+        use std::fs;
+
+        const TS: u64 = 1_700_000_000_000_000_042;
+
+        #[test]
+        fn formats_timestamps_and_headers() {
+            assert_eq!(Timestamp(TS).to_string(), "2023-11-14T22:13:20.000000042Z");
+            assert_eq!(Timestamp(0).to_string(), "1970-01-01T00:00:00.000000000Z");
+
+            let mut header = EventHeader::new(TS, 3, 12).with_stream(StreamId::new(2).unwrap());
+            assert_eq!(
+                header.to_string(),
+                "2023-11-14T22:13:20.000000042Z type=3 stream=2 len=12"
+            );
+            header.flags |= EventHeader::FLAG_SEQUENCE | EventHeader::FLAG_TRACE;
+            header.event_type = EventHeader::TYPE_HEARTBEAT;
+            assert!(
+                header
+                    .to_string()
+                    .ends_with(" type=heartbeat(255) stream=2 flags=seq|trace len=12")
+            );
+        }
+
+        #[test]
+        fn previews_payloads() {
+            let show = |event_type: u8, payload: &[u8]| {
+                EventView {
+                    header: EventHeader::new(TS, event_type, payload.len() as u16),
+                    payload,
+                }
+                .display()
+                .to_string()
+                .split_once(" stream=0")
+                .unwrap()
+                .1
+                .to_string()
+            };
+
+            let mut fields = FieldWriter::new();
+            fields.str("user", "alice").u64("count", 2);
+            assert_eq!(
+                show(1, fields.as_bytes().unwrap()),
+                r#" user="alice" count=2"#
+            );
+            assert_eq!(show(1, b"disk full"), r#" "disk full""#);
+            assert_eq!(show(EventHeader::TYPE_MARKER, b"deploy"), r#" "deploy""#);
+            assert_eq!(show(1, &[0, 1, 0xFF]), " 0x0001ff");
+            assert_eq!(show(1, &[0; 40]), format!(" 0x{}...", "00".repeat(32)));
+            assert_eq!(
+                show(1, "a".repeat(70).as_bytes()),
+                format!(" \"{}\"...", "a".repeat(64))
+            );
+            assert_eq!(show(1, b""), "");
+        }
+
+        #[test]
+        fn names_types_from_metadata() {
+            let mut types = TypeNames::new();
+            types.register(3, "login");
+            let mut metadata = Metadata::new("app");
+            types.write_to(&mut metadata);
+
+            let path = temp_path();
+            let options = WriterOptions {
+                metadata: Some(metadata),
+                ..Default::default()
+            };
+            let mut writer = MmapWriter::create_with_options(&path, 4096, &options).unwrap();
+            writer.event(3).timestamp(TS).sequence(7).write().unwrap();
+            drop(writer);
+
+            let reader = MmapReader::open(&path).unwrap();
+            let types = reader.type_names();
+            assert_eq!(types.name(3), Some("login"));
+            assert_eq!(types.name(EventHeader::TYPE_MARKER), Some("marker"));
+            assert_eq!(types.name(4), None);
+            let event = reader.iter().next().unwrap();
+            assert_eq!(
+                event.display().with_types(&types).to_string(),
+                "2023-11-14T22:13:20.000000042Z type=login(3) stream=0 seq=7"
+            );
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn hex_dumps() {
+            let dump = HexDump(b"Hello, world!\n\x00\x01\x02").to_string();
+            assert_eq!(
+                dump,
+                "00000000  48 65 6c 6c 6f 2c 20 77  6f 72 6c 64 21 0a 00 01  |Hello, world!...|\n\
+                 00000010  02                                                |.|"
+            );
+            assert_eq!(HexDump(b"").to_string(), "");
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    mod typed {
+        use super::*;
+        use crate::event::{EventSink, TypedError, TypedEvent, read_typed, write_typed};
+        use serde::{Deserialize, Serialize};
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Login {
+            user: String,
+            attempts: u32,
+        }
+
+        impl TypedEvent for Login {
+            const EVENT_TYPE: u8 = 7;
+        }
+
+        #[test]
+        fn round_trips_through_ring() {
+            let mut ring = RingBuffer::new(1024).unwrap();
+            let login = Login {
+                user: "alice".into(),
+                attempts: 3,
+            };
+            write_typed(&mut ring, &login).unwrap();
+
+            let (header, payload) = ring.read_event().unwrap();
+            assert_eq!(header.event_type, Login::EVENT_TYPE);
+            assert_eq!(read_typed::<Login>(&header, &payload).unwrap(), login);
+
+            let other = EventHeader::new(1, 8, payload.len() as u16);
+            assert!(matches!(
+                read_typed::<Login>(&other, &payload),
+                Err(TypedError::WrongType {
+                    expected: 7,
+                    found: 8
+                })
+            ));
+        }
+
+        #[test]
+        fn iterates_matching_events_from_file() {
+            let path = temp_path();
+            let mut writer = MmapWriter::create(&path, 4096).unwrap();
+            for attempts in 0..3 {
+                let login = Login {
+                    user: "bob".into(),
+                    attempts,
+                };
+                write_typed(&mut writer, &login).unwrap();
+                writer.event(1).payload(b"noise").write().unwrap();
+            }
+            drop(writer);
+
+            let reader = MmapReader::open(&path).unwrap();
+            let attempts: Vec<u32> = reader
+                .iter_typed::<Login>()
+                .map(|r| r.unwrap().1.attempts)
+                .collect();
+            assert_eq!(attempts, vec![0, 1, 2]);
+
+            drop(reader);
+            std::fs::remove_file(&path).ok();
+        }
+    }
+
+    #[cfg(feature = "prost")]
+    mod proto {
+        use super::*;
+        use crate::consumer::EventConsumer;
+        use crate::event::{EventSink, ProtoConsumer, ProtoRegistry};
+        use prost::Message;
+
+        #[derive(Clone, PartialEq, prost::Message)]
+        struct Login {
+            #[prost(string, tag = "1")]
+            user: String,
+        }
+
+        #[derive(Clone, PartialEq, prost::Message)]
+        struct Metric {
+            #[prost(double, tag = "1")]
+            value: f64,
+        }
+
+        #[derive(Debug, PartialEq)]
+        enum Msg {
+            Login(Login),
+            Metric(Metric),
+        }
+
+        fn registry() -> ProtoRegistry<Msg> {
+            ProtoRegistry::new()
+                .register(1, Msg::Login)
+                .register(2, Msg::Metric)
+        }
+
+        #[test]
+        fn reader_decodes_mapped_types() {
+            let path = temp_path();
+            let mut writer = MmapWriter::create(&path, 4096).unwrap();
+            let login = Login {
+                user: "carol".into(),
+            };
+            writer
+                .event(1)
+                .payload(&login.encode_to_vec())
+                .write()
+                .unwrap();
+            writer.event(9).payload(b"unmapped").write().unwrap();
+            let metric = Metric { value: 1.5 };
+            writer
+                .event(2)
+                .payload(&metric.encode_to_vec())
+                .write()
+                .unwrap();
+            drop(writer);
+
+            let reader = MmapReader::open(&path).unwrap();
+            let registry = registry();
+            let messages: Vec<Msg> = reader
+                .iter_proto(&registry)
+                .map(|(_, m)| m.unwrap())
+                .collect();
+            assert_eq!(messages, vec![Msg::Login(login), Msg::Metric(metric)]);
+
+            drop(reader);
+            std::fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn consumer_reports_decode_failures() {
+            let mut seen = Vec::new();
+            let mut consumer = ProtoConsumer::new("proto", registry(), |_, msg| {
+                seen.push(msg);
+                true
+            });
+            let header = EventHeader::new(0, 2, 0);
+            let payload = Metric { value: 2.0 }.encode_to_vec();
+            assert!(consumer.consume(&header, &payload));
+            assert!(!consumer.consume(&EventHeader::new(0, 1, 1), &[0xFF]));
+            assert!(consumer.consume(&EventHeader::new(0, 5, 1), &[0xFF]));
+            assert_eq!(consumer.name(), "proto");
+            drop(consumer);
+            assert_eq!(seen, vec![Msg::Metric(Metric { value: 2.0 })]);
+        }
+    }
+
+    #[cfg(feature = "flatbuffers")]
+    mod flatbuffers_view {
+        use super::*;
+        use crate::event::EventSink;
+        use flatbuffers::{
+            FlatBufferBuilder, Follow, InvalidFlatbuffer, Table, Verifiable, Verifier,
+        };
+
+        // Hand-written equivalent of flatc output for `table Point { x: int; y: int; }`.
+        struct Point<'a> {
+            table: Table<'a>,
+        }
+
+        impl<'a> Follow<'a> for Point<'a> {
+            type Inner = Point<'a>;
+
+            unsafe fn follow(buf: &'a [u8], loc: usize) -> Self::Inner {
+                Point {
+                    table: unsafe { Table::new(buf, loc) },
+                }
+            }
+        }
+
+        impl Point<'_> {
+            fn x(&self) -> i32 {
+                unsafe { self.table.get::<i32>(4, Some(0)).unwrap() }
+            }
+
+            fn y(&self) -> i32 {
+                unsafe { self.table.get::<i32>(6, Some(0)).unwrap() }
+            }
+        }
+
+        impl Verifiable for Point<'_> {
+            fn run_verifier(v: &mut Verifier, pos: usize) -> Result<(), InvalidFlatbuffer> {
+                v.visit_table(pos)?
+                    .visit_field::<i32>("x", 4, false)?
+                    .visit_field::<i32>("y", 6, false)?
+                    .finish();
+                Ok(())
+            }
+        }
+
+        fn point(x: i32, y: i32) -> Vec<u8> {
+            let mut fbb = FlatBufferBuilder::new();
+            let start = fbb.start_table();
+            fbb.push_slot::<i32>(4, x, 0);
+            fbb.push_slot::<i32>(6, y, 0);
+            let root = fbb.end_table(start);
+            fbb.finish(root, None);
+            fbb.finished_data().to_vec()
+        }
+
+        #[test]
+        fn reads_verified_roots_from_file() {
+            let path = temp_path();
+            let mut writer = MmapWriter::create(&path, 4096).unwrap();
+            writer.event(1).payload(&point(3, -7)).write().unwrap();
+            writer
+                .event(1)
+                .sequence(5)
+                .payload(&point(10, 20))
+                .write()
+                .unwrap();
+            writer.event(1).payload(&[0xFF; 3]).write().unwrap();
+            drop(writer);
+
+            let reader = MmapReader::open(&path).unwrap();
+            let events: Vec<_> = reader.iter().collect();
+            let first = events[0].flatbuffer::<Point>().unwrap();
+            assert_eq!((first.x(), first.y()), (3, -7));
+            let second = events[1].flatbuffer::<Point>().unwrap();
+            assert_eq!((second.x(), second.y()), (10, 20));
+            assert!(events[2].flatbuffer::<Point>().is_err());
+
+            drop(reader);
+            std::fs::remove_file(&path).ok();
+        }
+    }
+
+    mod acks {
+        use super::*;
+        use crate::consumer::dispatcher::DrainStats;
+        use crate::consumer::{AckOptions, Acker, AckingConsumer, DeliveryId};
+        use std::sync::{Arc, Mutex};
+        use std::time::Duration;
+
+        type Pending = Arc<Mutex<Vec<(DeliveryId, Acker)>>>;
+
+        /// Acks events whose payload is not `b"nack"`, or hands every
+        /// delivery to `pending` when that is set, to be acked later.
+        struct Sink {
+            received: Arc<Mutex<Vec<u64>>>,
+            pending: Option<Pending>,
+        }
+
+        impl AckingConsumer for Sink {
+            fn deliver(
+                &mut self,
+                id: DeliveryId,
+                header: &EventHeader,
+                payload: &[u8],
+                acker: &Acker,
+            ) {
+                self.received.lock().unwrap().push(header.timestamp);
+                match &self.pending {
+                    Some(pending) => pending.lock().unwrap().push((id, acker.clone())),
+                    None if payload == b"nack" => acker.nack(id),
+                    None => acker.ack(id),
+                }
+            }
+
+            fn name(&self) -> &str {
+                "sink"
+            }
+        }
+
+        struct DeadLetters(Arc<Mutex<Vec<u64>>>);
+
+        impl EventConsumer for DeadLetters {
+            fn consume(&mut self, header: &EventHeader, _payload: &[u8]) -> bool {
+                self.0.lock().unwrap().push(header.timestamp);
+                true
+            }
+
+            fn name(&self) -> &str {
+                "dead-letters"
+            }
+        }
+
+        #[test]
+        fn nacked_events_are_redelivered_then_dead_lettered() {
+            let received = Arc::new(Mutex::new(Vec::new()));
+            let dead = Arc::new(Mutex::new(Vec::new()));
+            let mut dispatcher = EventDispatcher::new();
+            dispatcher.add_acking_consumer(
+                Sink {
+                    received: received.clone(),
+                    pending: None,
+                },
+                AckOptions {
+                    max_attempts: 3,
+                    dead_letter: Some(Box::new(DeadLetters(dead.clone()))),
+                    ..Default::default()
+                },
+            );
+
+            let mut ring = RingBuffer::new(1024).unwrap();
+            ring.write_event(&EventHeader::new(1, 1, 2), b"ok").unwrap();
+            ring.write_event(&EventHeader::new(2, 1, 4), b"nack")
+                .unwrap();
+            let stats = dispatcher.drain(&mut ring);
+            assert_eq!(stats.events_read, 2);
+            assert_eq!(stats.events_delivered, 0);
+            assert_eq!(dispatcher.consumer_health().next().unwrap().unacked, 2);
+
+            let mut totals = DrainStats::default();
+            for _ in 0..3 {
+                totals.merge(&dispatcher.drain(&mut ring));
+            }
+            assert_eq!(totals.events_delivered, 1);
+            assert_eq!(totals.events_failed, 3);
+            assert_eq!(totals.events_redelivered, 2);
+            assert_eq!(totals.events_dead_lettered, 1);
+            assert_eq!(*received.lock().unwrap(), [1, 2, 2, 2]);
+            assert_eq!(*dead.lock().unwrap(), [2]);
+
+            let health = dispatcher.consumer_health().next().unwrap();
+            assert_eq!(health.delivered, 1);
+            assert_eq!(health.unacked, 0);
+        }
+
+        #[test]
+        fn late_acks_from_other_threads_and_timeouts() {
+            let received = Arc::new(Mutex::new(Vec::new()));
+            let pending = Arc::new(Mutex::new(Vec::new()));
+            let mut dispatcher = EventDispatcher::new();
+            dispatcher.add_acking_consumer(
+                Sink {
+                    received: received.clone(),
+                    pending: Some(pending.clone()),
+                },
+                AckOptions {
+                    timeout: Duration::from_millis(50),
+                    max_in_flight: 2,
+                    ..Default::default()
+                },
+            );
+
+            let mut ring = RingBuffer::new(1024).unwrap();
+            for i in 0..3 {
+                ring.write_event(&EventHeader::new(i, 1, 4), b"test")
+                    .unwrap();
+            }
+            // Two unacked events stop the drain; the third stays queued.
+            assert_eq!(dispatcher.drain(&mut ring).events_read, 2);
+            assert_eq!(ring.iter().count(), 1);
+
+            let (first, acker) = pending.lock().unwrap().remove(0);
+            std::thread::spawn(move || acker.ack(first)).join().unwrap();
+            let stats = dispatcher.drain(&mut ring);
+            assert_eq!(stats.events_delivered, 1);
+            assert_eq!(stats.events_read, 1);
+            assert!(ring.is_empty());
+
+            std::thread::sleep(Duration::from_millis(60));
+            let stats = dispatcher.drain(&mut ring);
+            assert_eq!(stats.events_redelivered, 2);
+            assert_eq!(*received.lock().unwrap(), [0, 1, 2, 1, 2]);
+        }
+    }
+
+    mod dedup {
+        use super::*;
+        use crate::consumer::SequenceStore;
+        use crate::consumer::dispatcher::DrainStats;
+        use crate::consumer::{AckOptions, Acker, AckingConsumer, DeliveryId};
+        use crate::event::EventSink;
+        use std::sync::{Arc, Mutex};
+
+        fn write_sequenced(ring: &mut RingBuffer, sequences: std::ops::Range<u64>) {
+            for seq in sequences {
+                ring.event(1)
+                    .sequence(seq)
+                    .payload(b"data")
+                    .write()
+                    .unwrap();
+            }
+        }
+
+        /// Acks every delivery except those of `hold`, which stay pending.
+        struct Acking {
+            hold: u64,
+            received: Arc<Mutex<Vec<u64>>>,
+        }
+
+        impl AckingConsumer for Acking {
+            fn deliver(&mut self, id: DeliveryId, h: &EventHeader, p: &[u8], acker: &Acker) {
+                let seq = h.split_sequence(p).0.unwrap();
+                self.received.lock().unwrap().push(seq);
+                if seq != self.hold {
+                    acker.ack(id);
+                }
+            }
+
+            fn name(&self) -> &str {
+                "acking"
+            }
+        }
+
+        #[test]
+        fn replayed_events_are_skipped_after_a_restart() {
+            let path = temp_path();
+            let mut ring = RingBuffer::new(4096).unwrap();
+
+            let mut dispatcher = EventDispatcher::new();
+            dispatcher.add_consumer(CountingConsumer::new());
+            dispatcher.set_sequence_store(Some(SequenceStore::open(&path).unwrap()));
+            write_sequenced(&mut ring, 0..5);
+            assert_eq!(dispatcher.drain(&mut ring).events_delivered, 5);
+            drop(dispatcher);
+
+            let store = SequenceStore::open(&path).unwrap();
+            assert_eq!(store.acked("counter"), Some(4));
+            let mut dispatcher = EventDispatcher::new();
+            dispatcher.add_consumer(CountingConsumer::new());
+            dispatcher.add_consumer(FailingConsumer);
+            dispatcher.set_sequence_store(Some(store));
+            write_sequenced(&mut ring, 3..8);
+            let stats = dispatcher.drain(&mut ring);
+            assert_eq!(stats.events_deduplicated, 2);
+            assert_eq!(stats.events_delivered, 3);
+            assert_eq!(stats.events_failed, 5);
+
+            let store = dispatcher.sequence_store().unwrap();
+            assert_eq!(store.acked("counter"), Some(7));
+            assert_eq!(store.acked("failing"), None);
+
+            std::fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn acks_past_a_pending_event_are_not_committed() {
+            let path = temp_path();
+            let received = Arc::new(Mutex::new(Vec::new()));
+            let mut dispatcher = EventDispatcher::new();
+            dispatcher.add_acking_consumer(
+                Acking {
+                    hold: 2,
+                    received: received.clone(),
+                },
+                AckOptions::default(),
+            );
+            dispatcher.set_sequence_store(Some(SequenceStore::open(&path).unwrap()));
+
+            let mut ring = RingBuffer::new(4096).unwrap();
+            write_sequenced(&mut ring, 0..5);
+            let mut totals = DrainStats::default();
+            totals.merge(&dispatcher.drain(&mut ring));
+            totals.merge(&dispatcher.drain(&mut ring));
+            assert_eq!(totals.events_delivered, 4);
+            // 3 and 4 were acked, but 2 is still pending.
+            let store = SequenceStore::open(&path).unwrap();
+            assert_eq!(store.acked("acking"), Some(1));
+
+            write_sequenced(&mut ring, 0..6);
+            dispatcher.drain(&mut ring);
+            assert_eq!(*received.lock().unwrap(), [0, 1, 2, 3, 4, 2, 3, 4, 5]);
+
+            std::fs::remove_file(&path).ok();
+        }
+    }
+
+    mod credit {
+        use super::*;
+        use crate::consumer::Credit;
+
+        struct Limited {
+            credit: Credit,
+            seen: usize,
+        }
+
+        impl EventConsumer for Limited {
+            fn consume(&mut self, _header: &EventHeader, _payload: &[u8]) -> bool {
+                self.seen += 1;
+                true
+            }
+
+            fn name(&self) -> &str {
+                "limited"
+            }
+
+            fn credit(&self) -> Credit {
+                self.credit
+            }
+        }
+
+        fn fill(ring: &mut RingBuffer, events: usize) {
+            for i in 0..events {
+                let header = EventHeader::new(i as u64, 1, 16);
+                ring.write_event(&header, &[0u8; 16]).unwrap();
+            }
+        }
+
+        #[test]
+        fn drains_stop_at_the_smallest_credit() {
+            let mut ring = RingBuffer::new(4096).unwrap();
+            fill(&mut ring, 10);
+            let mut dispatcher = EventDispatcher::new();
+            dispatcher.add_consumer(CountingConsumer::new());
+            dispatcher.add_consumer(Limited {
+                credit: Credit::events(4),
+                seen: 0,
+            });
+
+            let stats = dispatcher.drain(&mut ring);
+            assert_eq!(stats.events_read, 4);
+            assert_eq!(stats.credit_stalls, 1);
+            assert_eq!(ring.iter().count(), 6);
+            assert_eq!(dispatcher.drain_batch(&mut ring, 100).events_read, 4);
+            assert_eq!(dispatcher.drain(&mut ring).events_read, 2);
+            assert!(ring.is_empty());
+        }
+
+        #[test]
+        fn byte_credit_overshoots_by_at_most_one_event() {
+            let mut ring = RingBuffer::new(4096).unwrap();
+            fill(&mut ring, 10);
+            let event_size = EventHeader::SIZE + 16;
+            let mut dispatcher = EventDispatcher::new();
+            dispatcher.add_consumer(Limited {
+                credit: Credit::bytes(event_size * 2 + 1),
+                seen: 0,
+            });
+            assert_eq!(dispatcher.drain(&mut ring).events_read, 3);
+        }
+
+        #[test]
+        fn quarantined_consumers_do_not_limit_reads() {
+            let mut ring = RingBuffer::new(4096).unwrap();
+            fill(&mut ring, 10);
+            let mut dispatcher = EventDispatcher::new();
+            dispatcher.add_consumer(CountingConsumer::new());
+            dispatcher.add_consumer(Limited {
+                credit: Credit::events(0),
+                seen: 0,
+            });
+            assert_eq!(dispatcher.drain(&mut ring).events_read, 0);
+            assert!(dispatcher.set_quarantined("limited", true));
+            assert_eq!(dispatcher.drain(&mut ring).events_read, 10);
+        }
+    }
+
+    mod priority {
+        use super::*;
+        use crate::consumer::{Priority, PriorityMap};
+        use crate::event::{EventSink, StreamId};
+        use std::sync::{Arc, Mutex};
+
+        struct Recording(Arc<Mutex<Vec<u64>>>);
+
+        impl EventConsumer for Recording {
+            fn consume(&mut self, header: &EventHeader, _payload: &[u8]) -> bool {
+                self.0.lock().unwrap().push(header.timestamp);
+                true
+            }
+
+            fn name(&self) -> &str {
+                "recording"
+            }
+        }
+
+        #[test]
+        fn batches_are_delivered_highest_priority_first() {
+            let mut ring = RingBuffer::new(4096).unwrap();
+            let control = StreamId::new(1).unwrap();
+            for (timestamp, event_type, stream) in [
+                (0, 1, StreamId::DEFAULT),
+                (1, 7, StreamId::DEFAULT),
+                (2, 1, control),
+                (3, 2, StreamId::DEFAULT),
+                (4, 7, StreamId::DEFAULT),
+                (5, 1, StreamId::DEFAULT),
+            ] {
+                let header = EventHeader::new(timestamp, event_type, 0).with_stream(stream);
+                ring.write_event(&header, &[]).unwrap();
+            }
+
+            let seen = Arc::new(Mutex::new(Vec::new()));
+            let mut dispatcher = EventDispatcher::new();
+            dispatcher.add_consumer(Recording(seen.clone()));
+            dispatcher.set_priorities(Some(Box::new(
+                PriorityMap::new()
+                    .with_type(7, Priority::Critical)
+                    .with_type(2, Priority::Low)
+                    .with_stream(control, Priority::High),
+            )));
+
+            assert_eq!(dispatcher.drain_batch(&mut ring, 4).events_read, 4);
+            assert_eq!(*seen.lock().unwrap(), [1, 2, 0, 3]);
+            dispatcher.drain(&mut ring);
+            assert_eq!(*seen.lock().unwrap(), [1, 2, 0, 3, 4, 5]);
+        }
+
+        #[test]
+        fn closures_assign_priorities() {
+            let mut ring = RingBuffer::new(4096).unwrap();
+            for timestamp in 0..4 {
+                ring.write_event(&EventHeader::new(timestamp, 1, 0), &[])
+                    .unwrap();
+            }
+            let seen = Arc::new(Mutex::new(Vec::new()));
+            let mut dispatcher = EventDispatcher::new();
+            dispatcher.add_consumer(Recording(seen.clone()));
+            dispatcher.set_priorities(Some(Box::new(|h: &EventHeader| {
+                if h.timestamp % 2 == 1 {
+                    Priority::High
+                } else {
+                    Priority::Normal
+                }
+            })));
+            dispatcher.drain(&mut ring);
+            assert_eq!(*seen.lock().unwrap(), [1, 3, 0, 2]);
+        }
+
+        #[test]
+        fn events_carry_their_own_priority() {
+            let mut ring = RingBuffer::new(4096).unwrap();
+            ring.event(1).timestamp(0).payload(b"bulk").write().unwrap();
+            ring.event(1)
+                .timestamp(1)
+                .priority(Priority::Critical)
+                .sequence(9)
+                .payload(b"stop")
+                .write()
+                .unwrap();
+            ring.event(7)
+                .timestamp(2)
+                .priority(Priority::Low)
+                .write()
+                .unwrap();
+            ring.event(1)
+                .timestamp(3)
+                .priority(Priority::High)
+                .deliver_at(0)
+                .write()
+                .unwrap();
+
+            let seen = Arc::new(Mutex::new(Vec::new()));
+            let mut dispatcher = EventDispatcher::new();
+            dispatcher.add_consumer(Recording(seen.clone()));
+            // The map's Critical for type 7 loses to the event's own Low.
+            dispatcher.set_priorities(Some(Box::new(
+                PriorityMap::new().with_type(7, Priority::Critical),
+            )));
+            dispatcher.drain(&mut ring);
+            assert_eq!(*seen.lock().unwrap(), [1, 3, 0, 2]);
+
+            let mut ring = RingBuffer::new(4096).unwrap();
+            ring.event(1)
+                .priority(Priority::High)
+                .sequence(9)
+                .payload(b"stop")
+                .write()
+                .unwrap();
+            let (header, payload) = ring.read_event().unwrap();
+            assert_eq!(header.priority(&payload), Some(Priority::High));
+            assert_eq!(header.split_sequence(&payload).0, Some(9));
+            assert_eq!(header.body(&payload), b"stop");
+            assert_eq!(header.stream(), StreamId::DEFAULT);
+        }
+    }
+
+    mod delayed {
+        use super::*;
+        use crate::clock::{Clock, SystemClock};
+        use crate::event::EventSink;
+        use std::sync::{Arc, Mutex};
+        use std::time::Duration;
+
+        type Seen = Arc<Mutex<Vec<(u8, Option<u64>, Vec<u8>)>>>;
+
+        struct Recording(Seen);
+
+        impl EventConsumer for Recording {
+            fn consume(&mut self, header: &EventHeader, payload: &[u8]) -> bool {
+                let (seq, body) = header.split_sequence(payload);
+                if let Some(crc) = header.checksum() {
+                    assert_eq!(crc, crate::event::crc32(payload));
+                }
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push((header.event_type, seq, body.to_vec()));
+                true
+            }
+
+            fn name(&self) -> &str {
+                "recording"
+            }
+        }
+
+        #[test]
+        fn delayed_events_wait_until_due() {
+            let mut ring = RingBuffer::new(4096).unwrap();
+            ring.event(3)
+                .delay(Duration::from_millis(50))
+                .sequence(9)
+                .checksum()
+                .payload(b"retry")
+                .write()
+                .unwrap();
+            ring.event(4)
+                .deliver_at(SystemClock.now() - 1)
+                .payload(b"due")
+                .write()
+                .unwrap();
+            ring.event(5).payload(b"now").write().unwrap();
+
+            let seen = Seen::default();
+            let mut dispatcher = EventDispatcher::new();
+            dispatcher.add_consumer(Recording(seen.clone()));
+            let stats = dispatcher.drain(&mut ring);
+            assert_eq!(stats.events_delayed, 1);
+            assert_eq!(stats.events_read, 2);
+            assert_eq!(dispatcher.pending_delayed(), 1);
+            assert!(dispatcher.next_delayed().unwrap() > SystemClock.now());
+
+            std::thread::sleep(Duration::from_millis(60));
+            assert_eq!(dispatcher.drain(&mut ring).events_read, 1);
+            assert_eq!(dispatcher.pending_delayed(), 0);
+            assert_eq!(
+                *seen.lock().unwrap(),
+                [
+                    (4, None, b"due".to_vec()),
+                    (5, None, b"now".to_vec()),
+                    (3, Some(9), b"retry".to_vec()),
+                ]
+            );
+        }
+
+        #[test]
+        fn sequences_are_found_behind_the_delay_prefix() {
+            let mut ring = RingBuffer::new(4096).unwrap();
+            ring.event(1)
+                .deliver_at(1)
+                .sequence(7)
+                .payload(b"x")
+                .write()
+                .unwrap();
+            let (header, payload) = ring.read_event().unwrap();
+            assert_eq!(header.event_type, EventHeader::TYPE_DELAYED);
+            assert_eq!(header.split_sequence(&payload), (Some(7), &b"x"[..]));
+            let (not_before, inner, body) = header.split_delay(&payload).unwrap();
+            assert_eq!((not_before, inner.event_type, body.len()), (1, 1, 9));
+        }
+    }
+
+    mod ttl {
+        use super::*;
+        use crate::clock::{Clock, SystemClock};
+        use std::sync::Arc;
+        use std::time::Duration;
+
+        struct Sink(Arc<AtomicU64>);
+
+        impl EventConsumer for Sink {
+            fn consume(&mut self, _header: &EventHeader, _payload: &[u8]) -> bool {
+                self.0.fetch_add(1, Ordering::Relaxed);
+                true
+            }
+
+            fn name(&self) -> &str {
+                "expired"
+            }
+        }
+
+        fn write_aged(ring: &mut RingBuffer, event_type: u8, age: Duration) {
+            let timestamp = SystemClock.now() - age.as_nanos() as u64;
+            ring.write_event(&EventHeader::new(timestamp, event_type, 0), &[])
+                .unwrap();
+        }
+
+        #[test]
+        fn stale_events_expire_into_the_sink() {
+            let mut ring = RingBuffer::new(4096).unwrap();
+            write_aged(&mut ring, 1, Duration::from_secs(60));
+            write_aged(&mut ring, 1, Duration::ZERO);
+            write_aged(&mut ring, 2, Duration::from_secs(60));
+            write_aged(&mut ring, 3, Duration::from_secs(3600));
+
+            let expired = Arc::new(AtomicU64::new(0));
+            let mut dispatcher = EventDispatcher::new();
+            dispatcher.add_consumer(CountingConsumer::new());
+            dispatcher.set_ttl(Some(Duration::from_secs(10)));
+            dispatcher.set_type_ttl(2, Some(Duration::from_secs(120)));
+            dispatcher.set_type_ttl(3, Some(Duration::from_secs(1)));
+            dispatcher.set_type_ttl(3, None);
+            dispatcher.set_expired_sink(Some(Box::new(Sink(expired.clone()))));
+
+            let stats = dispatcher.drain(&mut ring);
+            assert_eq!(stats.events_read, 4);
+            assert_eq!(stats.events_expired, 2);
+            assert_eq!(stats.events_delivered, 2);
+            assert_eq!(expired.load(Ordering::Relaxed), 2);
+        }
+
+        #[test]
+        fn ttls_use_the_dispatcher_clock() {
+            /// Nanoseconds since boot, as a monotonic clock would stamp them.
+            struct BootClock;
+
+            impl Clock for BootClock {
+                fn now(&self) -> u64 {
+                    100_000_000_000
+                }
+
+                fn to_unix_nanos(&self, ticks: u64) -> u64 {
+                    ticks
+                }
+            }
+
+            let mut ring = RingBuffer::new(4096).unwrap();
+            for timestamp in [99_000_000_000, 50_000_000_000] {
+                ring.write_event(&EventHeader::new(timestamp, 1, 0), &[])
+                    .unwrap();
+            }
+            let mut dispatcher = EventDispatcher::new();
+            dispatcher.add_consumer(CountingConsumer::new());
+            dispatcher.set_ttl(Some(Duration::from_secs(10)));
+            dispatcher.set_clock(Some(Arc::new(BootClock)));
+            let stats = dispatcher.drain(&mut ring);
+            assert_eq!((stats.events_expired, stats.events_delivered), (1, 1));
+        }
+
+        #[test]
+        fn without_a_ttl_nothing_expires() {
+            let mut ring = RingBuffer::new(4096).unwrap();
+            write_aged(&mut ring, 1, Duration::from_secs(3600));
+            let mut dispatcher = EventDispatcher::new();
+            dispatcher.add_consumer(CountingConsumer::new());
+            let stats = dispatcher.drain(&mut ring);
+            assert_eq!((stats.events_expired, stats.events_delivered), (0, 1));
+        }
+    }
+
+    mod middleware {
+        use super::*;
+        use crate::consumer::{Action, EventMiddleware};
+        use std::borrow::Cow;
+        use std::sync::{Arc, Mutex};
+
+        type Seen = Arc<Mutex<Vec<(u8, Vec<u8>)>>>;
+
+        struct Recording(Seen);
+
+        impl EventConsumer for Recording {
+            fn consume(&mut self, header: &EventHeader, payload: &[u8]) -> bool {
+                assert_eq!(header.payload_len as usize, payload.len());
+                if let Some(crc) = header.checksum() {
+                    assert_eq!(crc, crate::event::crc32(payload));
+                }
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push((header.event_type, payload.to_vec()));
+                true
+            }
+
+            fn name(&self) -> &str {
+                "recording"
+            }
+        }
+
+        /// Drops type 9 and uppercases everything else.
+        struct Shout;
+
+        impl EventMiddleware for Shout {
+            fn on_event(
+                &mut self,
+                header: &mut EventHeader,
+                payload: &mut Cow<'_, [u8]>,
+            ) -> Action {
+                if header.event_type == 9 {
+                    return Action::Drop;
+                }
+                *payload = Cow::Owned(payload.to_ascii_uppercase());
+                Action::Replace
+            }
+
+            fn name(&self) -> &str {
+                "shout"
+            }
+        }
+
+        /// Appends `!` and retypes events to 2.
+        struct Exclaim;
+
+        impl EventMiddleware for Exclaim {
+            fn on_event(
+                &mut self,
+                header: &mut EventHeader,
+                payload: &mut Cow<'_, [u8]>,
+            ) -> Action {
+                header.event_type = 2;
+                payload.to_mut().push(b'!');
+                Action::Replace
+            }
+
+            fn name(&self) -> &str {
+                "exclaim"
+            }
+        }
+
+        #[test]
+        fn middleware_runs_in_order_before_consumers() {
+            let mut ring = RingBuffer::new(4096).unwrap();
+            for (event_type, payload) in [(1, &b"hello"[..]), (9, b"secret"), (1, b"bye")] {
+                let header =
+                    EventHeader::new(0, event_type, payload.len() as u16).with_checksum(payload);
+                ring.write_event(&header, payload).unwrap();
+            }
+
+            let seen = Seen::default();
+            let mut dispatcher = EventDispatcher::new();
+            dispatcher.add_consumer(Recording(seen.clone()));
+            dispatcher.add_middleware(Shout);
+            dispatcher.add_middleware(Exclaim);
+            let stats = dispatcher.drain(&mut ring);
+
+            assert_eq!(stats.events_read, 3);
+            assert_eq!(stats.events_filtered, 1);
+            assert_eq!(
+                *seen.lock().unwrap(),
+                [(2, b"HELLO!".to_vec()), (2, b"BYE!".to_vec())]
+            );
+        }
+    }
+
+    mod redact {
+        use super::*;
+        use crate::consumer::Redactor;
+        use crate::event::{EventSink, FieldReader, FieldValue, FieldWriter};
+        use std::sync::{Arc, Mutex};
+
+        type Seen = Arc<Mutex<Vec<Vec<u8>>>>;
+
+        struct Recording(Seen);
+
+        impl EventConsumer for Recording {
+            fn consume(&mut self, header: &EventHeader, payload: &[u8]) -> bool {
+                self.0.lock().unwrap().push(header.body(payload).to_vec());
+                true
+            }
+
+            fn name(&self) -> &str {
+                "recording"
+            }
+        }
+
+        fn drain(redactor: Redactor, ring: &mut RingBuffer) -> Vec<Vec<u8>> {
+            let seen = Seen::default();
+            let mut dispatcher = EventDispatcher::new();
+            dispatcher.add_middleware(redactor);
+            dispatcher.add_consumer(Recording(seen.clone()));
+            dispatcher.drain(ring);
+            seen.lock().unwrap().clone()
+        }
+
+        #[test]
+        fn matching_fields_are_masked() {
+            let mut fields = FieldWriter::new();
+            fields
+                .str("user", "alice")
+                .str("password", "hunter2")
+                .u64("card_number", 4111)
+                .bytes("api_token", b"\x01\x02");
+            let payload = fields.finish().unwrap();
+            let mut ring = RingBuffer::new(4096).unwrap();
+            ring.event(1).sequence(3).payload(&payload).write().unwrap();
+            ring.event(2).payload(&payload).write().unwrap();
+
+            let redactor = Redactor::new()
+                .mask_field("password")
+                .mask_field("*_token")
+                .mask_field_of(1, "card*");
+            let seen = drain(redactor, &mut ring);
+
+            let first: Vec<_> = FieldReader::new(&seen[0]).map(Result::unwrap).collect();
+            assert_eq!(
+                first,
+                [
+                    ("user", FieldValue::Str("alice")),
+                    ("password", FieldValue::Str("*******")),
+                    ("card_number", FieldValue::U64(0)),
+                    ("api_token", FieldValue::Bytes(b"**")),
+                ]
+            );
+            let second = FieldReader::new(&seen[1]);
+            assert_eq!(second.get("card_number"), Some(FieldValue::U64(4111)));
+            assert_eq!(second.get("password"), Some(FieldValue::Str("*******")));
+        }
+
+        #[test]
+        fn byte_ranges_are_masked_per_type() {
+            let mut ring = RingBuffer::new(4096).unwrap();
+            ring.event(5).payload(b"0123456789").write().unwrap();
+            ring.event(6).payload(b"0123456789").write().unwrap();
+            let redactor = Redactor::new()
+                .mask_range(5, 2..4)
+                .mask_range(5, 8..20)
+                .with_mask(b'#');
+            let seen = drain(redactor, &mut ring);
+            assert_eq!(seen, [b"01##4567##".to_vec(), b"0123456789".to_vec()]);
+        }
+    }
+
+    mod enrich {
+        use super::*;
+        use crate::consumer::Enricher;
+        use crate::event::{EventSink, FieldReader, FieldValue, FieldWriter};
+        use std::sync::{Arc, Mutex};
+
+        type Seen = Arc<Mutex<Vec<Vec<u8>>>>;
+
+        struct Recording(Seen);
+
+        impl EventConsumer for Recording {
+            fn consume(&mut self, header: &EventHeader, payload: &[u8]) -> bool {
+                assert_eq!(header.payload_len as usize, payload.len());
+                self.0.lock().unwrap().push(header.body(payload).to_vec());
+                true
+            }
+
+            fn name(&self) -> &str {
+                "recording"
+            }
+        }
+
+        #[test]
+        fn fields_are_appended_to_selected_types() {
+            let mut fields = FieldWriter::new();
+            fields.str("msg", "hi");
+            let payload = fields.finish().unwrap();
+            let mut ring = RingBuffer::new(4096).unwrap();
+            ring.event(1).sequence(1).payload(&payload).write().unwrap();
+            ring.event(2).payload(b"raw").write().unwrap();
+
+            let seen = Seen::default();
+            let mut dispatcher = EventDispatcher::new();
+            dispatcher.add_middleware(
+                Enricher::new()
+                    .with_process()
+                    .with_str("region", "eu-west-1")
+                    .with_thread()
+                    .with_dynamic(|header, fields| {
+                        fields.u64("type", header.event_type as u64);
+                    })
+                    .for_types([1]),
+            );
+            dispatcher.add_consumer(Recording(seen.clone()));
+            std::thread::Builder::new()
+                .name("drainer".into())
+                .spawn(move || {
+                    dispatcher.drain(&mut ring);
+                })
+                .unwrap()
+                .join()
+                .unwrap();
+
+            let seen = seen.lock().unwrap();
+            let fields = FieldReader::new(&seen[0]);
+            assert_eq!(fields.get("msg"), Some(FieldValue::Str("hi")));
+            assert_eq!(
+                fields.get("pid"),
+                Some(FieldValue::U64(std::process::id() as u64))
+            );
+            assert_eq!(fields.get("region"), Some(FieldValue::Str("eu-west-1")));
+            assert_eq!(fields.get("thread"), Some(FieldValue::Str("drainer")));
+            assert_eq!(fields.get("type"), Some(FieldValue::U64(1)));
+            assert!(fields.clone().all(|field| field.is_ok()));
+            assert_eq!(seen[1], b"raw");
+        }
+
+        #[test]
+        fn oversized_enrichment_leaves_events_unchanged() {
+            let mut ring = RingBuffer::new(4096).unwrap();
+            ring.event(1).payload(b"raw").write().unwrap();
+
+            let seen = Seen::default();
+            let mut dispatcher = EventDispatcher::new();
+            dispatcher.add_middleware(Enricher::new().with_str("note", &"x".repeat(70_000)));
+            dispatcher.add_consumer(Recording(seen.clone()));
+            dispatcher.drain(&mut ring);
+
+            assert_eq!(*seen.lock().unwrap(), [b"raw".to_vec()]);
+        }
+    }
+
+    #[cfg(feature = "compression")]
+    mod compression {
+        use super::*;
+        use crate::consumer::{Compressor, Decompressor};
+        use crate::event::EventSink;
+        use std::sync::{Arc, Mutex};
+
+        type Seen = Arc<Mutex<Vec<(EventHeader, Vec<u8>)>>>;
+
+        struct Recording(Seen);
+
+        impl EventConsumer for Recording {
+            fn consume(&mut self, header: &EventHeader, payload: &[u8]) -> bool {
+                self.0.lock().unwrap().push((*header, payload.to_vec()));
+                true
+            }
+
+            fn name(&self) -> &str {
+                "recording"
+            }
+        }
+
+        #[test]
+        fn large_payloads_are_compressed_for_consumers() {
+            let big = vec![b'a'; 2000];
+            let mut ring = RingBuffer::new(16384).unwrap();
+            ring.event(1)
+                .sequence(4)
+                .checksum()
+                .payload(&big)
+                .write()
+                .unwrap();
+            ring.event(2).payload(b"small").write().unwrap();
+
+            let seen = Seen::default();
+            let mut dispatcher = EventDispatcher::new();
+            dispatcher.add_middleware(Compressor::new(64, 3));
+            dispatcher.add_consumer(Recording(seen.clone()));
+            dispatcher.drain(&mut ring);
+
+            let seen = seen.lock().unwrap().clone();
+            let (header, payload) = &seen[0];
+            assert_ne!(header.flags & EventHeader::FLAG_COMPRESSED, 0);
+            assert!(payload.len() < 100);
+            assert_eq!(header.checksum(), Some(crate::event::crc32(payload)));
+            assert_eq!(header.split_sequence(payload).0, Some(4));
+            assert_eq!(seen[1].0.flags & EventHeader::FLAG_COMPRESSED, 0);
+
+            let restored = Seen::default();
+            let mut dispatcher = EventDispatcher::new();
+            dispatcher.add_middleware(Decompressor);
+            dispatcher.add_consumer(Recording(restored.clone()));
+            ring.write_event(header, payload).unwrap();
+            dispatcher.drain(&mut ring);
+            let (plain_header, plain) = restored.lock().unwrap()[0].clone();
+            assert_eq!(plain_header.flags & EventHeader::FLAG_COMPRESSED, 0);
+            assert_eq!(plain_header.body(&plain), &big[..]);
+            assert_eq!(plain_header.checksum(), Some(crate::event::crc32(&plain)));
+        }
+
+        #[test]
+        fn stored_events_decompress_on_read() {
+            let path = temp_path();
+            let big = vec![7u8; 1000];
+            let header = EventHeader::new(1, 1, big.len() as u16);
+            let (header, payload) = crate::event::compression::compress(&header, &big, 3)
+                .unwrap()
+                .unwrap();
+            let mut writer = MmapWriter::create(&path, 1 << 16).unwrap();
+            writer.try_write_event(&header, &payload).unwrap();
+            drop(writer);
+
+            let reader = MmapReader::open(&path).unwrap();
+            let event = reader.iter().next().unwrap();
+            let (header, plain) = event.decompressed().unwrap();
+            assert_eq!(header.payload_len, 1000);
+            assert_eq!(&plain[..], &big[..]);
+
+            std::fs::remove_file(&path).ok();
+        }
+    }
+
+    #[cfg(feature = "sqlite")]
+    mod sqlite {
+        use super::*;
+        use crate::consumer::SqliteConsumer;
+        use crate::event::{EventSink, TraceContext};
+        use std::fs;
+
+        #[test]
+        fn stores_each_drain_in_one_transaction() {
+            let path = temp_path();
+            let mut ring = RingBuffer::new(4096).unwrap();
+            let mut dispatcher = EventDispatcher::new();
+            dispatcher.add_consumer(SqliteConsumer::open(&path).unwrap());
+
+            ring.event(1)
+                .timestamp(10)
+                .sequence(7)
+                .trace(TraceContext::new(42, 1))
+                .payload(b"first")
+                .write()
+                .unwrap();
+            ring.event(2)
+                .timestamp(20)
+                .payload(b"second")
+                .write()
+                .unwrap();
+            let stats = dispatcher.drain(&mut ring);
+            assert_eq!(stats.events_delivered, 2);
+
+            // Committed by the drain's flush, so visible to another connection.
+            let conn = rusqlite::Connection::open(&path).unwrap();
+            let mut stmt = conn
+                .prepare("SELECT timestamp, type, seq, trace_id, payload FROM events ORDER BY id")
+                .unwrap();
+            type Row = (i64, u8, Option<i64>, Option<i64>, Vec<u8>);
+            let rows: Vec<Row> = stmt
+                .query_map([], |row| {
+                    Ok((
+                        row.get(0)?,
+                        row.get(1)?,
+                        row.get(2)?,
+                        row.get(3)?,
+                        row.get(4)?,
+                    ))
+                })
+                .unwrap()
+                .map(Result::unwrap)
+                .collect();
+            assert_eq!(
+                rows,
+                vec![
+                    (10, 1, Some(7), Some(42), b"first".to_vec()),
+                    (20, 2, None, None, b"second".to_vec()),
+                ]
+            );
+            let indexes: i64 = conn
+                .query_row(
+                    "SELECT count(*) FROM sqlite_master WHERE type = 'index' AND tbl_name = 'events'",
+                    [],
+                    |row| row.get(0),
+                )
+                .unwrap();
+            assert_eq!(indexes, 2);
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn commits_every_batch() {
+            let mut consumer =
+                SqliteConsumer::from_connection(rusqlite::Connection::open_in_memory().unwrap())
+                    .unwrap()
+                    .with_batch_size(2);
+            for i in 0..3 {
+                assert!(consumer.consume(&EventHeader::new(i, 1, 1), b"x"));
+            }
+            assert!(!consumer.connection().is_autocommit());
+            consumer.flush();
+            assert!(consumer.connection().is_autocommit());
+
+            let count: i64 = consumer
+                .connection()
+                .query_row("SELECT count(*) FROM events", [], |row| row.get(0))
+                .unwrap();
+            assert_eq!(count, 3);
+            assert_eq!(consumer.commit_failures(), 0);
+        }
+    }
+
+    #[cfg(feature = "clickhouse")]
+    mod clickhouse {
+        use super::*;
+        use crate::consumer::{ClickHouseConfig, ClickHouseConsumer};
+        use crate::event::{EventSink, TraceContext};
+        use std::io::{BufRead, BufReader, Read, Write};
+        use std::net::TcpListener;
+        use std::thread;
+        use std::time::Duration;
+
+        /// A request's head and body.
+        type Request = (String, Vec<u8>);
+
+        /// Answers one request per status with `Connection: close`, and
+        /// returns the requests.
+        fn serve(statuses: Vec<u16>) -> (String, thread::JoinHandle<Vec<Request>>) {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+            let handle = thread::spawn(move || {
+                let mut requests = Vec::new();
+                for status in statuses {
+                    let (stream, _) = listener.accept().unwrap();
+                    let mut reader = BufReader::new(stream);
+                    let mut head = String::new();
+                    let mut len = 0;
+                    loop {
+                        let mut line = String::new();
+                        reader.read_line(&mut line).unwrap();
+                        if line == "\r\n" {
+                            break;
+                        }
+                        if let Some((name, value)) = line.split_once(':')
+                            && name.eq_ignore_ascii_case("content-length")
+                        {
+                            len = value.trim().parse().unwrap();
+                        }
+                        head.push_str(&line);
+                    }
+                    let mut body = vec![0; len];
+                    reader.read_exact(&mut body).unwrap();
+                    write!(
+                        reader.get_mut(),
+                        "HTTP/1.1 {} X\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                        status
+                    )
+                    .unwrap();
+                    requests.push((head, body));
+                }
+                requests
+            });
+            (url, handle)
+        }
+
+        #[test]
+        fn inserts_full_batches_as_row_binary() {
+            let (url, server) = serve(vec![200]);
+            let mut config = ClickHouseConfig::new(&url, "logs.events");
+            config.user = Some("loader".into());
+            config.batch_size = 2;
+            config.flush_interval = Duration::from_secs(3600);
+            let mut consumer = ClickHouseConsumer::new(config);
+
+            let path = temp_path();
+            let mut writer = MmapWriter::create(&path, 4096).unwrap();
+            writer
+                .event(1)
+                .timestamp(10)
+                .sequence(7)
+                .trace(TraceContext::new(42, 3))
+                .payload(b"hi")
+                .write()
+                .unwrap();
+            drop(writer);
+            let reader = MmapReader::open(&path).unwrap();
+            let traced = reader.iter().next().unwrap();
+            assert!(consumer.consume(&traced.header, traced.payload));
+            consumer.flush();
+            assert_eq!(consumer.pending(), 1);
+            assert!(consumer.consume(&EventHeader::new(20, 2, 1), b"x"));
+            assert_eq!(consumer.pending(), 0);
+            assert_eq!(consumer.inserted(), 2);
+
+            let requests = server.join().unwrap();
+            let (head, body) = &requests[0];
+            assert!(head.starts_with("POST /?query=INSERT"), "{}", head);
+            assert!(head.contains("logs.events") && head.contains("RowBinary"));
+            assert!(head.contains("X-ClickHouse-User: loader"));
+
+            let mut expected = Vec::new();
+            expected.extend_from_slice(&10i64.to_le_bytes());
+            expected.extend_from_slice(&[1, 0, traced.header.flags]);
+            for value in [7u64, 42, 3] {
+                expected.push(0);
+                expected.extend_from_slice(&value.to_le_bytes());
+            }
+            expected.extend_from_slice(b"\x02hi");
+            expected.extend_from_slice(&20i64.to_le_bytes());
+            expected.extend_from_slice(&[2, 0, 0, 1, 1, 1]);
+            expected.extend_from_slice(b"\x01x");
+            assert_eq!(body, &expected);
+            std::fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn retries_failed_inserts_and_bounds_pending_rows() {
+            let (url, server) = serve(vec![500, 500, 200]);
+            let mut config = ClickHouseConfig::new(&url, "events");
+            config.batch_size = 1;
+            config.flush_interval = Duration::ZERO;
+            let mut consumer = ClickHouseConsumer::new(config);
+
+            assert!(consumer.consume(&EventHeader::new(1, 1, 1), b"a"));
+            assert!(consumer.consume(&EventHeader::new(2, 1, 1), b"b"));
+            assert_eq!(consumer.failed_inserts(), 2);
+            assert_eq!(consumer.pending(), 2);
+            assert!(!consumer.consume(&EventHeader::new(3, 1, 1), b"c"));
+
+            consumer.flush();
+            assert_eq!(consumer.pending(), 0);
+            assert_eq!(consumer.inserted(), 2);
+            let requests = server.join().unwrap();
+            assert_eq!(requests[2].1.len(), 2 * (8 + 3 + 3 + 2));
+        }
+
+        #[test]
+        #[should_panic(expected = "invalid table name")]
+        fn rejects_unsafe_table_names() {
+            ClickHouseConsumer::new(ClickHouseConfig::new(
+                "http://localhost:8123",
+                "events; DROP",
+            ));
+        }
+    }
+
+    mod anomaly {
+        use super::*;
+        use crate::consumer::{AnomalyDetector, AnomalyOptions};
+        use crate::event::{FieldReader, FieldValue};
+        use std::sync::{Arc, Mutex};
+        use std::time::Duration;
+
+        struct Alerts(Arc<Mutex<Vec<String>>>);
+
+        impl EventConsumer for Alerts {
+            fn consume(&mut self, header: &EventHeader, payload: &[u8]) -> bool {
+                assert_eq!(header.event_type, 200);
+                let kind = match FieldReader::new(payload).get("kind") {
+                    Some(FieldValue::Str(kind)) => kind.to_string(),
+                    other => panic!("unexpected kind {:?}", other),
+                };
+                self.0.lock().unwrap().push(kind);
+                true
+            }
+
+            fn name(&self) -> &str {
+                "alerts"
+            }
+        }
+
+        #[test]
+        fn alerts_once_on_spikes_and_flatlines() {
+            let alerts = Arc::new(Mutex::new(Vec::new()));
+            let options = AnomalyOptions {
+                interval: Duration::from_millis(20),
+                warmup: 2,
+                ..Default::default()
+            };
+            let mut detector =
+                AnomalyDetector::new(200, options).with_alert_consumer(Alerts(alerts.clone()));
+            let header = EventHeader::new(0, 1, 0);
+            let interval = || std::thread::sleep(Duration::from_millis(25));
+
+            for _ in 0..3 {
+                for _ in 0..10 {
+                    detector.consume(&header, &[]);
+                }
+                interval();
+            }
+            assert!(detector.baseline(1).unwrap() > 0.0);
+            for _ in 0..200 {
+                detector.consume(&header, &[]);
+            }
+            interval();
+            detector.flush();
+            assert_eq!(*alerts.lock().unwrap(), vec!["spike"]);
+
+            // Silence: one flatline alert, however long it lasts.
+            for _ in 0..2 {
+                interval();
+                detector.flush();
+            }
+            assert_eq!(*alerts.lock().unwrap(), vec!["spike", "flatline"]);
+            assert_eq!(detector.alerts(), 2);
+        }
+
+        #[test]
+        fn alerts_can_go_to_a_ring() {
+            let ring = Arc::new(Mutex::new(RingBuffer::new(4096).unwrap()));
+            let options = AnomalyOptions {
+                interval: Duration::from_millis(10),
+                warmup: 0,
+                min_baseline: 0.0,
+                ..Default::default()
+            };
+            let mut detector = AnomalyDetector::new(200, options).with_alert_ring(ring.clone());
+            detector.consume(&EventHeader::new(0, 1, 0), &[]);
+            for _ in 0..2 {
+                std::thread::sleep(Duration::from_millis(15));
+                detector.flush();
+            }
+            let (header, _) = ring.lock().unwrap().read_event().unwrap();
+            assert_eq!(header.event_type, 200);
+        }
+    }
+
+    mod dispatcher {
+        use super::*;
+
+        #[test]
+        fn consumer_health_tracks_each_consumer() {
+            let mut ring = RingBuffer::new(1024).unwrap();
+            let mut dispatcher = EventDispatcher::new();
+            dispatcher.add_consumer(CountingConsumer::new());
+            dispatcher.add_consumer(FailingConsumer);
+
+            for i in 0..3 {
+                ring.write_event(&EventHeader::new(i, 1, 4), b"test")
+                    .unwrap();
+            }
+            dispatcher.drain(&mut ring);
+
+            let health: Vec<_> = dispatcher.consumer_health().collect();
+            assert_eq!(health[0].name, "counter");
+            assert_eq!(health[0].delivered, 3);
+            assert!(health[0].is_healthy());
+            assert_eq!(health[1].name, "failing");
+            assert_eq!(health[1].failed, 3);
+            assert_eq!(health[1].consecutive_failures, 3);
+            assert!(health[1].last_failure.is_some());
+            assert!(!health[1].is_healthy());
+        }
+
+        #[test]
+        fn quarantined_consumers_are_skipped() {
+            let mut ring = RingBuffer::new(1024).unwrap();
+            let mut dispatcher = EventDispatcher::new();
+            dispatcher.add_consumer(CountingConsumer::new());
+            dispatcher.add_consumer(FailingConsumer);
+
+            assert!(dispatcher.set_quarantined("failing", true));
+            assert!(!dispatcher.set_quarantined("missing", true));
+            ring.write_event(&EventHeader::new(1, 1, 4), b"test")
+                .unwrap();
+            let stats = dispatcher.drain(&mut ring);
+            assert_eq!(stats.events_delivered, 1);
+            assert_eq!(stats.events_failed, 0);
+
+            let health: Vec<_> = dispatcher.consumer_health().collect();
+            assert!(health[1].quarantined);
+            assert!(!health[1].is_healthy());
+
+            dispatcher.set_quarantined("failing", false);
+            ring.write_event(&EventHeader::new(2, 1, 4), b"test")
+                .unwrap();
+            assert_eq!(dispatcher.drain(&mut ring).events_failed, 1);
+        }
+
+        #[test]
+        fn drain_empty_buffer() {
+            let mut ring = RingBuffer::new(1024).unwrap();
+            let mut dispatcher = EventDispatcher::new();
+            dispatcher.add_consumer(CountingConsumer::new());
+
+            let stats = dispatcher.drain(&mut ring);
+
+            assert_eq!(stats.events_read, 0);
+            assert_eq!(stats.events_delivered, 0);
+        }
+
+        #[test]
+        fn drain_delivers_to_consumer() {
+            let mut ring = RingBuffer::new(1024).unwrap();
+            let mut dispatcher = EventDispatcher::new();
+            dispatcher.add_consumer(CountingConsumer::new());
+
+            for i in 0..5 {
+                let header = EventHeader::new(i, 1, 4);
+                ring.write_event(&header, b"test").unwrap();
+            }
+
+            let stats = dispatcher.drain(&mut ring);
+
+            assert_eq!(stats.events_read, 5);
+            assert_eq!(stats.events_delivered, 5);
+            assert_eq!(stats.events_failed, 0);
+        }
+
+        #[test]
+        fn drain_tracks_failures() {
+            let mut ring = RingBuffer::new(1024).unwrap();
+            let mut dispatcher = EventDispatcher::new();
+            dispatcher.add_consumer(FailingConsumer);
+
+            for i in 0..3 {
+                let header = EventHeader::new(i, 1, 4);
+                ring.write_event(&header, b"test").unwrap();
+            }
+
+            let stats = dispatcher.drain(&mut ring);
+
+            assert_eq!(stats.events_read, 3);
+            assert_eq!(stats.events_delivered, 0);
+            assert_eq!(stats.events_failed, 3);
+        }
+
+        #[test]
+        fn drain_batch_respects_limit() {
+            let mut ring = RingBuffer::new(1024).unwrap();
+            let mut dispatcher = EventDispatcher::new();
+            dispatcher.add_consumer(CountingConsumer::new());
+
+            for i in 0..10 {
+                let header = EventHeader::new(i, 1, 4);
+                ring.write_event(&header, b"test").unwrap();
+            }
+
+            let stats = dispatcher.drain_batch(&mut ring, 3);
+
+            assert_eq!(stats.events_read, 3);
+            assert!(!ring.is_empty());
+        }
+
+        #[test]
+        fn multiple_consumers() {
+            let mut ring = RingBuffer::new(1024).unwrap();
+            let mut dispatcher = EventDispatcher::new();
+            dispatcher.add_consumer(CountingConsumer::new());
+            dispatcher.add_consumer(CountingConsumer::new());
+
+            let header = EventHeader::new(0, 1, 4);
+            ring.write_event(&header, b"test").unwrap();
+
+            let stats = dispatcher.drain(&mut ring);
+
+            assert_eq!(stats.events_read, 1);
+            assert_eq!(stats.events_delivered, 2);
+        }
+
+        #[test]
+        fn drain_spsc_consumer() {
+            let ring = SpscRingBuffer::new(1024).unwrap();
+            let (mut producer, mut consumer) = ring.split();
+            let mut dispatcher = EventDispatcher::new();
+            dispatcher.add_consumer(CountingConsumer::new());
+
+            for i in 0..4 {
+                let header = EventHeader::new(i, 1, 4);
+                assert!(producer.write_event(&header, b"test"));
+            }
+
+            let stats = dispatcher.drain_batch(&mut consumer, 3);
+            assert_eq!(stats.events_read, 3);
+            let stats = dispatcher.drain(&mut consumer);
+            assert_eq!(stats.events_read, 1);
+        }
+
+        #[test]
+        fn drain_replays_file() {
+            use crate::consumer::ReplaySource;
+
+            let path = temp_path();
+            {
+                let mut writer = MmapWriter::create(&path, 4096).unwrap();
+                for i in 0..5 {
+                    let header = EventHeader::new(i, 1, 8);
+                    writer.write_event(&header, b"testdata");
+                }
+            }
+
+            let reader = MmapReader::open(&path).unwrap();
+            let mut dispatcher = EventDispatcher::new();
+            dispatcher.add_consumer(CountingConsumer::new());
+
+            let stats = dispatcher.drain(&mut ReplaySource::new(&reader));
+            assert_eq!(stats.events_read, 5);
+            assert_eq!(stats.events_delivered, 5);
+
+            drop(reader);
+            std::fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn heartbeat_is_injected_when_idle() {
+            struct HeartbeatConsumer(std::sync::Arc<std::sync::Mutex<Vec<u64>>>);
+
+            impl EventConsumer for HeartbeatConsumer {
+                fn consume(&mut self, header: &EventHeader, payload: &[u8]) -> bool {
+                    assert!(header.is_heartbeat());
+                    let seq = u64::from_le_bytes(payload.try_into().unwrap());
+                    self.0.lock().unwrap().push(seq);
+                    true
+                }
+
+                fn name(&self) -> &str {
+                    "heartbeat"
+                }
+            }
+
+            let mut ring = RingBuffer::new(1024).unwrap();
+            let seen = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+            let mut dispatcher = EventDispatcher::new();
+            dispatcher.add_consumer(HeartbeatConsumer(seen.clone()));
+
+            assert_eq!(dispatcher.drain(&mut ring).heartbeats, 0);
+
+            dispatcher.set_heartbeat(Some(std::time::Duration::ZERO));
+            let stats = dispatcher.drain(&mut ring);
+            dispatcher.drain(&mut ring);
+            assert_eq!(stats.heartbeats, 1);
+            assert_eq!(stats.events_read, 0);
+            assert_eq!(*seen.lock().unwrap(), vec![1, 2]);
+
+            dispatcher.set_heartbeat(Some(std::time::Duration::from_secs(3600)));
+            assert_eq!(dispatcher.drain(&mut ring).heartbeats, 0);
+        }
+
+        #[test]
+        fn success_rate_calculation() {
+            use crate::consumer::dispatcher::DrainStats;
+
+            let stats = DrainStats {
+                events_read: 10,
+                events_delivered: 8,
+                events_failed: 2,
+                ..Default::default()
+            };
+
+            assert!((stats.success_rate() - 0.8).abs() < 0.001);
+        }
+
+        #[test]
+        fn drain_tracks_bytes_and_types() {
+            let mut ring = RingBuffer::new(1024).unwrap();
+            let mut dispatcher = EventDispatcher::new();
+            dispatcher.add_consumer(CountingConsumer::new());
+
+            for i in 0..3 {
+                let header = EventHeader::new(i, (i % 2) as u8, 4);
+                ring.write_event(&header, b"test").unwrap();
+            }
+
+            let mut total = dispatcher.drain(&mut ring);
+            assert_eq!(total.bytes_read, 3 * 20);
+            assert_eq!(total.events_by_type.get(&0), Some(&2));
+            assert!(total.max_dispatch_latency <= total.elapsed);
+            assert!(total.mean_dispatch_latency() <= total.max_dispatch_latency);
+
+            let header = EventHeader::new(3, 1, 4);
+            ring.write_event(&header, b"test").unwrap();
+            total.merge(&dispatcher.drain(&mut ring));
+            assert_eq!(total.events_read, 4);
+            assert_eq!(total.events_by_type.get(&1), Some(&2));
+        }
+
+        #[test]
+        fn success_rate_empty() {
+            use crate::consumer::dispatcher::DrainStats;
+
+            let stats = DrainStats::default();
+            assert!((stats.success_rate() - 1.0).abs() < 0.001);
+        }
+    }
+
+    mod admin {
+        use super::temp_path;
+        use crate::admin::{
+            AdminCommand, AdminHandle, AdminQueue, ControlSocket, LogLevel, Status, admin_channel,
+            send_command,
+        };
+        use crate::consumer::dispatcher::ConsumerHealth;
+        use std::thread;
+        use std::time::Duration;
+
+        /// Answers commands the way the service's main loop does, until the
+        /// handles are dropped.
+        fn spawn_main_loop(queue: AdminQueue) -> thread::JoinHandle<Vec<AdminCommand>> {
+            thread::spawn(move || {
+                let mut seen = Vec::new();
+                for _ in 0..200 {
+                    while let Some(request) = queue.try_next() {
+                        seen.push(request.command().clone());
+                        let reply = match request.command() {
+                            AdminCommand::Flush => Ok("flushed".to_string()),
+                            AdminCommand::Rotate => Err("disk full".to_string()),
+                            command => Ok(command.to_string()),
+                        };
+                        request.reply(reply);
+                    }
+                    queue.publish(Status {
+                        ring_capacity: 1024,
+                        ring_used: 256,
+                        consumers: vec![ConsumerHealth {
+                            name: "mmap \"main\"".to_string(),
+                            delivered: 7,
+                            ..Default::default()
+                        }],
+                        segment: Some(3),
+                        ..Default::default()
+                    });
+                    thread::sleep(Duration::from_millis(5));
+                }
+                seen
+            })
+        }
+
+        fn wait_for_status(admin: &AdminHandle) -> Status {
+            for _ in 0..100 {
+                let status = admin.status();
+                if status.ring_capacity > 0 {
+                    return status;
+                }
+                thread::sleep(Duration::from_millis(5));
+            }
+            panic!("status never published");
+        }
+
+        #[test]
+        fn commands_round_trip_through_the_queue() {
+            let (admin, queue) = admin_channel();
+            let main_loop = spawn_main_loop(queue);
+
+            let timeout = Duration::from_secs(5);
+            assert_eq!(
+                admin.execute(AdminCommand::Flush, timeout),
+                Ok("flushed".to_string())
+            );
+            assert_eq!(
+                admin.execute(AdminCommand::Rotate, timeout),
+                Err("disk full".to_string())
+            );
+            assert_eq!(wait_for_status(&admin).segment, Some(3));
+
+            drop(admin);
+            assert_eq!(
+                main_loop.join().unwrap(),
+                vec![AdminCommand::Flush, AdminCommand::Rotate]
+            );
+        }
+
+        #[test]
+        fn status_renders_as_json() {
+            let status = Status {
+                ring_capacity: 1000,
+                ring_used: 250,
+                consumers: vec![ConsumerHealth {
+                    name: "a\"b".to_string(),
+                    failed: 2,
+                    consecutive_failures: 1,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            };
+            let json = status.to_json();
+            assert!(
+                json.starts_with("{\"ring\":{\"capacity\":1000,\"used\":250,\"occupancy\":0.2500}")
+            );
+            assert!(json.contains("{\"name\":\"a\\\"b\",\"healthy\":false,"));
+            assert!(json.ends_with("\"segment\":null,\"log_level\":\"info\"}"));
+        }
+
+        #[test]
+        fn commands_parse_from_text() {
+            for line in [
+                "flush",
+                "rotate",
+                "set-log-level debug",
+                "quarantine-consumer mmap",
+                "release-consumer mmap",
+            ] {
+                let command: AdminCommand = line.parse().unwrap();
+                assert_eq!(command.to_string(), line);
+            }
+            assert_eq!(
+                "  set-log-level   WARN ".parse(),
+                Ok(AdminCommand::SetLogLevel(LogLevel::Warn))
+            );
+            assert!("set-log-level".parse::<AdminCommand>().is_err());
+            assert!("set-log-level loud".parse::<AdminCommand>().is_err());
+            assert!("reboot".parse::<AdminCommand>().is_err());
+        }
+
+        #[test]
+        fn control_socket_runs_commands() {
+            let path = format!("{}.ctl", temp_path());
+            let (admin, queue) = admin_channel();
+            let main_loop = spawn_main_loop(queue);
+            let socket = ControlSocket::bind(&path, admin.clone()).unwrap();
+            wait_for_status(&admin);
+
+            assert_eq!(
+                send_command(&path, "flush").unwrap(),
+                Ok("flushed".to_string())
+            );
+            assert_eq!(
+                send_command(&path, "rotate").unwrap(),
+                Err("disk full".to_string())
+            );
+            assert_eq!(
+                send_command(&path, "quarantine-consumer mmap").unwrap(),
+                Ok("quarantine-consumer mmap".to_string())
+            );
+            assert!(send_command(&path, "reboot").unwrap().is_err());
+            let stats = send_command(&path, "stats").unwrap().unwrap();
+            assert!(stats.contains("\"segment\":3"));
+
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+
+            // An idle client does not hold up the others.
+            let idle = std::os::unix::net::UnixStream::connect(&path).unwrap();
+            assert_eq!(
+                send_command(&path, "flush").unwrap(),
+                Ok("flushed".to_string())
+            );
+            drop(idle);
+
+            // A second service cannot take over a live socket.
+            assert!(ControlSocket::bind(&path, admin.clone()).is_err());
+            drop(socket);
+            assert!(!std::path::Path::new(&path).exists());
+
+            drop(admin);
+            main_loop.join().unwrap();
+        }
+
+        #[test]
+        fn control_socket_serves_a_supervisor_listener() {
+            use std::os::unix::net::UnixListener;
+
+            let path = format!("{}.ctl", temp_path());
+            let (admin, queue) = admin_channel();
+            let main_loop = spawn_main_loop(queue);
+            let listener = UnixListener::bind(&path).unwrap();
+            let socket = ControlSocket::from_listener(listener, admin.clone()).unwrap();
+            wait_for_status(&admin);
+
+            assert_eq!(
+                send_command(&path, "flush").unwrap(),
+                Ok("flushed".to_string())
+            );
+            // The supervisor owns the socket file.
+            drop(socket);
+            assert!(std::path::Path::new(&path).exists());
+            std::fs::remove_file(&path).ok();
+
+            drop(admin);
+            main_loop.join().unwrap();
+        }
+
+        #[cfg(feature = "http")]
+        #[test]
+        fn http_server_routes_commands_and_status() {
+            use crate::admin::HttpAdmin;
+            use std::io::{Read, Write};
+            use std::net::TcpStream;
+
+            let (admin, queue) = admin_channel();
+            let main_loop = spawn_main_loop(queue);
+            let server = HttpAdmin::start("127.0.0.1:0", admin.clone()).unwrap();
+            let addr = server.local_addr().unwrap();
+            wait_for_status(&admin);
+
+            let request = |method: &str, path: &str| {
+                let mut stream = TcpStream::connect(addr).unwrap();
+                write!(
+                    stream,
+                    "{} {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    method, path
+                )
+                .unwrap();
+                let mut response = String::new();
+                stream.read_to_string(&mut response).unwrap();
+                response
+            };
+
+            let status = request("GET", "/status");
+            assert!(status.starts_with("HTTP/1.1 200"));
+            assert!(status.contains("\"segment\":3"));
+
+            let flush = request("POST", "/flush");
+            assert!(flush.starts_with("HTTP/1.1 200"));
+            assert!(flush.ends_with("flushed"));
+            assert!(request("POST", "/rotate").starts_with("HTTP/1.1 500"));
+            assert!(request("GET", "/flush").starts_with("HTTP/1.1 405"));
+            assert!(request("GET", "/nope").starts_with("HTTP/1.1 404"));
+
+            drop(server);
+            drop(admin);
+            main_loop.join().unwrap();
+        }
+    }
+
+    #[cfg(feature = "systemd")]
+    mod systemd {
+        use super::*;
+        use crate::systemd::{Watchdog, notify_ready};
+        use std::fs;
+        use std::os::unix::net::UnixDatagram;
+        use std::time::Duration;
+
+        #[test]
+        fn notifications_reach_the_notify_socket() {
+            let path = format!("{}.notify", temp_path());
+            let socket = UnixDatagram::bind(&path).unwrap();
+            // No other test reads these variables.
+            unsafe {
+                std::env::set_var("NOTIFY_SOCKET", &path);
+                std::env::set_var("WATCHDOG_USEC", "2000000");
+                std::env::set_var("WATCHDOG_PID", std::process::id().to_string());
+            }
+
+            notify_ready().unwrap();
+            let mut watchdog = Watchdog::from_env().unwrap();
+            assert_eq!(watchdog.interval(), Duration::from_secs(1));
+            watchdog.tick().unwrap();
+            // Not due again until an interval has passed.
+            watchdog.tick().unwrap();
+
+            let mut buf = [0u8; 64];
+            let len = socket.recv(&mut buf).unwrap();
+            assert_eq!(&buf[..len], b"READY=1\n");
+            let len = socket.recv(&mut buf).unwrap();
+            assert_eq!(&buf[..len], b"WATCHDOG=1\n");
+            socket.set_nonblocking(true).unwrap();
+            assert!(socket.recv(&mut buf).is_err());
+
+            unsafe {
+                std::env::remove_var("NOTIFY_SOCKET");
+                std::env::remove_var("WATCHDOG_USEC");
+                std::env::remove_var("WATCHDOG_PID");
+            }
+            fs::remove_file(&path).ok();
+        }
+    }
+
+    mod config {
+        use crate::admin::LogLevel;
+        use crate::config::{RinglogConfig, SyncPolicy};
+        use std::path::Path;
+        use std::time::Duration;
+
+        #[test]
+        fn toml_sets_typed_fields_and_keeps_defaults() {
+            let config = RinglogConfig::from_toml(
+                r#"
+                log_level = "debug"
+
+                [ring]
+                capacity = 4096
+                heartbeat_ms = 0
+
+                [storage]
+                dir = "/var/lib/ringlog"
+                mode = 0o640
+
+                [retention]
+                max_segments = 3
+
+                [sync]
+                policy = "interval"
+                interval_ms = 250
+
+                [consumers.print]
+                enabled = true
+                event_types = [1, 7]
+                "#,
+            )
+            .unwrap();
+
+            assert_eq!(config.log_level, LogLevel::Debug);
+            assert_eq!(config.ring.capacity, 4096);
+            assert_eq!(config.ring.heartbeat, None);
+            assert_eq!(config.storage.dir, Path::new("/var/lib/ringlog"));
+            assert_eq!(config.storage.mode, 0o640);
+            assert_eq!(config.storage.segment_size, 64 * 1024 * 1024);
+            assert_eq!(config.retention.max_segments, Some(3));
+            assert_eq!(config.sync.policy, SyncPolicy::Interval);
+            assert_eq!(config.sync.interval, Duration::from_millis(250));
+            assert!(config.consumers.segment);
+            assert_eq!(config.consumers.print.event_types, vec![1, 7]);
+            assert_eq!(
+                RinglogConfig::from_toml("").unwrap(),
+                RinglogConfig::default()
+            );
+        }
+
+        #[test]
+        fn environment_overrides_the_file() {
+            let mut config = RinglogConfig::from_toml("[storage]\ndir = \"/a\"").unwrap();
+            let vars = [
+                ("RINGLOG_STORAGE_DIR", "/b"),
+                ("RINGLOG_RING_CAPACITY", "8192"),
+                ("RINGLOG_CONSUMERS_PRINT_EVENT_TYPES", "2,3"),
+                ("RINGLOG_ADMIN_CONTROL", "/tmp/2024"),
+                ("HOME", "/root"),
+            ];
+            config
+                .apply_env(vars.map(|(k, v)| (k.to_string(), v.to_string())))
+                .unwrap();
+
+            assert_eq!(config.storage.dir, Path::new("/b"));
+            assert_eq!(config.ring.capacity, 8192);
+            assert_eq!(config.consumers.print.event_types, vec![2, 3]);
+            assert_eq!(
+                config.admin.control.as_deref(),
+                Some(Path::new("/tmp/2024"))
+            );
+
+            let bad = [("RINGLOG_RING_CAPACITY".to_string(), "lots".to_string())];
+            assert!(config.apply_env(bad).is_err());
+        }
+
+        #[test]
+        fn bad_keys_and_values_are_rejected() {
+            let err = RinglogConfig::from_toml("[ring]\ncapacty = 1").unwrap_err();
+            assert!(err.to_string().contains("ring.capacty"));
+            let err = RinglogConfig::from_toml("[ring]\ncapacity = \"big\"").unwrap_err();
+            assert!(err.to_string().contains("expected an integer"));
+            assert!(RinglogConfig::from_toml("[sync]\npolicy = \"sometimes\"").is_err());
+            assert!(RinglogConfig::from_toml("[consumers.print]\nevent_types = [300]").is_err());
+            assert!(RinglogConfig::from_toml("[ring").is_err());
+        }
+    }
+
+    mod service {
+        use super::*;
+        use crate::admin::{AdminCommand, admin_channel};
+        use crate::config::RinglogConfig;
+        use crate::service::RinglogService;
+        use crate::storage::{SegmentedReader, list_segments};
+        use std::fs;
+        use std::time::Duration;
+
+        fn config(dir: &str) -> RinglogConfig {
+            let mut config = RinglogConfig::default();
+            config.ring.heartbeat = None;
+            config.storage.dir = dir.into();
+            config.storage.segment_size = 64 * 1024;
+            config
+        }
+
+        #[test]
+        fn undrained_events_are_dumped_when_the_driving_thread_panics() {
+            let dir = temp_path();
+            let dump = temp_path();
+            let mut config = config(&dir);
+            config.ring.crash_dump = Some(dump.clone().into());
+
+            let result = std::panic::catch_unwind(|| {
+                let mut service = RinglogService::new(config.clone()).unwrap();
+                for i in 0..5 {
+                    let header = EventHeader::new(i, 1, 4);
+                    service.ring_mut().write_event(&header, b"ping").unwrap();
+                }
+                panic!("collector crashed");
+            });
+            assert!(result.is_err());
+            let reader = MmapReader::open(&dump).unwrap();
+            assert_eq!(reader.iter().count(), 5);
+            drop(reader);
+            fs::remove_file(&dump).ok();
+
+            // Neither a plain drop nor a panic on another thread dumps.
+            let mut service = RinglogService::new(config.clone()).unwrap();
+            service
+                .ring_mut()
+                .write_event(&EventHeader::new(0, 1, 4), b"ping")
+                .unwrap();
+            drop(service);
+            let mut service = RinglogService::new(config).unwrap();
+            service
+                .ring_mut()
+                .write_event(&EventHeader::new(0, 1, 4), b"ping")
+                .unwrap();
+            assert!(std::thread::spawn(|| panic!("bystander")).join().is_err());
+            service.shutdown().unwrap();
+            assert!(!std::path::Path::new(&dump).exists());
+
+            fs::remove_dir_all(&dir).ok();
+        }
+
+        #[test]
+        fn polled_events_reach_segments_and_are_kept_on_shutdown() {
+            let dir = temp_path();
+            let mut service = RinglogService::new(config(&dir)).unwrap();
+
+            for i in 0..10 {
+                let header = EventHeader::new(i, 1, 4);
+                service.ring_mut().write_event(&header, b"ping").unwrap();
+            }
+            let stats = service.poll().unwrap();
+            assert_eq!(stats.events_delivered, 10);
+            assert_eq!(service.status().segment, Some(1));
+
+            let report = service.shutdown().unwrap();
+            assert!(report.kept);
+            assert_eq!(report.segments, 1);
+            assert_eq!(report.events, 10);
+            let reader = MmapReader::open(crate::storage::segment_path(&dir, 1)).unwrap();
+            assert!(reader.is_sealed());
+
+            fs::remove_dir_all(&dir).ok();
+        }
+
+        #[test]
+        fn rotation_applies_retention() {
+            let dir = temp_path();
+            let mut config = config(&dir);
+            config.retention.max_segments = Some(2);
+            let mut service = RinglogService::new(config).unwrap();
+
+            for _ in 0..3 {
+                service.rotate().unwrap();
+            }
+            let seqs: Vec<u64> = list_segments(&dir).unwrap().iter().map(|s| s.seq).collect();
+            assert_eq!(seqs, vec![3, 4]);
+
+            // Rotations from admin commands go through the same path.
+            let (admin, queue) = admin_channel();
+            let command = std::thread::spawn(move || {
+                admin.execute(AdminCommand::Rotate, Duration::from_secs(5))
+            });
+            while !command.is_finished() {
+                service.handle_admin_commands(&queue);
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            assert_eq!(
+                command.join().unwrap(),
+                Ok("rotated to segment 5".to_string())
+            );
+            assert_eq!(list_segments(&dir).unwrap().len(), 2);
+
+            fs::remove_dir_all(&dir).ok();
+        }
+
+        #[test]
+        fn diagnostics_cover_ring_consumers_storage_and_errors() {
+            let dir = temp_path();
+            let mut service = RinglogService::new(config(&dir)).unwrap();
+            service.dispatcher_mut().add_consumer(FailingConsumer);
+
+            let header = EventHeader::new(1, 1, 4);
+            service.ring_mut().write_event(&header, b"ping").unwrap();
+            service.poll().unwrap();
+            service.flush().unwrap();
+
+            let diagnostics = service.diagnostics();
+            assert_eq!(diagnostics.ring.producer.events_attempted, 1);
+            assert_eq!(diagnostics.drain.events_read, 1);
+            assert_eq!(diagnostics.errors.delivery, 1);
+            let failing = &diagnostics.consumers[1];
+            assert_eq!(failing.name, "failing");
+            assert!(!failing.healthy && failing.since_last_failure.is_some());
+            let storage = diagnostics.storage.as_ref().unwrap();
+            assert_eq!((storage.segment, storage.segments), (1, 1));
+            assert!(storage.segment_used > 0 && storage.bytes_on_disk > 0);
+            assert!(storage.last_sync.is_some());
+
+            let json = diagnostics.to_json();
+            assert!(json.starts_with("{\"uptime_secs\":"));
+            assert!(json.contains("\"name\":\"failing\""));
+            assert!(json.ends_with(
+                "\"errors\":{\"delivery\":1,\"sync\":0,\"rotation\":0,\"retention\":0}}"
+            ));
+
+            fs::remove_dir_all(&dir).ok();
+        }
+
+        #[test]
+        fn segment_quotas_reset_on_rotation() {
+            use crate::event::StreamId;
+
+            let dir = temp_path();
+            let mut config = config(&dir);
+            let quotas = RinglogConfig::from_toml("[quotas]\nsegment_events = 2").unwrap();
+            config.quotas = quotas.quotas;
+            let mut service = RinglogService::new(config).unwrap();
+            let noisy = StreamId::new(4).unwrap();
+
+            let write = |service: &mut RinglogService, stream| {
+                let header = EventHeader::new(0, 1, 4).with_stream(stream);
+                service.ring_mut().write_event(&header, b"ping").unwrap();
+            };
+            for _ in 0..3 {
+                write(&mut service, noisy);
+            }
+            write(&mut service, StreamId::DEFAULT);
+            service.poll().unwrap();
+            service.rotate().unwrap();
+            write(&mut service, noisy);
+            service.poll().unwrap();
+
+            let diagnostics = service.diagnostics();
+            let tenant = diagnostics.tenants.iter().find(|t| t.stream == 4).unwrap();
+            assert_eq!(tenant.ring.events_written, 4);
+            let segment = tenant.segment.unwrap();
+            assert_eq!((segment.events_written, segment.events_rejected), (3, 1));
+            assert_eq!(segment.events_held, 1);
+            // The over-quota event is reported as a failed delivery.
+            assert_eq!(diagnostics.errors.delivery, 1);
+            assert!(
+                diagnostics
+                    .to_json()
+                    .contains("\"tenants\":[{\"stream\":0,")
+            );
+
+            let report = service.shutdown().unwrap();
+            assert_eq!(report.events, 4);
+            fs::remove_dir_all(&dir).ok();
+        }
+
+        #[test]
+        fn ephemeral_logs_are_removed_and_reload_keeps_fixed_settings() {
+            let dir = temp_path();
+            let mut config = config(&dir);
+            config.storage.ephemeral = true;
+            let mut service = RinglogService::new(config.clone()).unwrap();
+
+            let mut reloaded = config.clone();
+            reloaded.retention.max_segments = Some(1);
+            reloaded.ring.capacity *= 2;
+            assert_eq!(service.reload(reloaded), vec!["ring.capacity"]);
+            assert_eq!(service.config().retention.max_segments, Some(1));
+            assert_eq!(service.config().ring.capacity, config.ring.capacity);
+
+            let report = service.shutdown().unwrap();
+            assert!(!report.kept);
+            assert!(!std::path::Path::new(&dir).exists());
+        }
+
+        #[test]
+        fn ephemeral_runs_keep_segments_of_earlier_runs() {
+            let dir = temp_path();
+            let mut config = config(&dir);
+            let mut service = RinglogService::new(config.clone()).unwrap();
+            service
+                .ring_mut()
+                .write_event(&EventHeader::new(1, 1, 4), b"ping")
+                .unwrap();
+            service.poll().unwrap();
+            let kept = service.shutdown().unwrap();
+            assert!(kept.kept);
+            let persisted = list_segments(&dir).unwrap();
+            assert_eq!(persisted.len(), 1);
+
+            config.storage.ephemeral = true;
+            let mut service = RinglogService::new(config).unwrap();
+            service
+                .ring_mut()
+                .write_event(&EventHeader::new(1, 1, 4), b"ping")
+                .unwrap();
+            service.poll().unwrap();
+            service.rotate().unwrap();
+            assert_eq!(list_segments(&dir).unwrap().len(), 3);
+            let report = service.shutdown().unwrap();
+            assert!(!report.kept);
+
+            let left: Vec<_> = list_segments(&dir)
+                .unwrap()
+                .into_iter()
+                .map(|s| s.path)
+                .collect();
+            assert_eq!(left, vec![persisted[0].path.clone()]);
+            assert_eq!(
+                SegmentedReader::open(&dir).unwrap().event_count(),
+                kept.events
+            );
+            fs::remove_dir_all(&dir).ok();
+        }
+    }
+
+    #[cfg(feature = "instrument")]
+    mod instrument {
+        use super::*;
+        use crate::config::RinglogConfig;
+        use crate::service::RinglogService;
+        use std::sync::Mutex;
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Metadata, Subscriber};
+
+        /// Records the names of spans and events it sees.
+        #[derive(Default)]
+        struct Recorder {
+            names: Mutex<Vec<String>>,
+            next_id: AtomicU64,
+        }
+
+        impl Subscriber for &'static Recorder {
+            fn enabled(&self, _: &Metadata<'_>) -> bool {
+                true
+            }
+
+            fn new_span(&self, span: &Attributes<'_>) -> Id {
+                self.names
+                    .lock()
+                    .unwrap()
+                    .push(span.metadata().name().to_string());
+                Id::from_u64(self.next_id.fetch_add(1, Ordering::Relaxed) + 1)
+            }
+
+            fn record(&self, _: &Id, _: &Record<'_>) {}
+
+            fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+            fn event(&self, event: &Event<'_>) {
+                let fields = event.metadata().fields();
+                let names = fields
+                    .iter()
+                    .map(|f| f.name())
+                    .collect::<Vec<_>>()
+                    .join(",");
+                self.names.lock().unwrap().push(names);
+            }
+
+            fn enter(&self, _: &Id) {}
+
+            fn exit(&self, _: &Id) {}
+        }
+
+        #[test]
+        fn drains_syncs_and_rotations_are_traced() {
+            let recorder: &'static Recorder = Box::leak(Box::default());
+            let dir = temp_path();
+            let mut config = RinglogConfig::default();
+            config.storage.dir = dir.clone().into();
+            config.storage.segment_size = 64 * 1024;
+            config.ring.heartbeat = None;
+
+            tracing::subscriber::with_default(recorder, || {
+                let mut service = RinglogService::new(config).unwrap();
+                let header = EventHeader::new(1, 1, 4);
+                service.ring_mut().write_event(&header, b"ping").unwrap();
+                service.poll().unwrap();
+                service.flush().unwrap();
+                service.rotate().unwrap();
+            });
+
+            let names = recorder.names.lock().unwrap();
+            for span in [
+                "ringlog.drain",
+                "ringlog.dispatch",
+                "ringlog.sync",
+                "ringlog.rotate",
+            ] {
+                assert!(
+                    names.iter().any(|n| n == span),
+                    "no {} span in {:?}",
+                    span,
+                    names
+                );
+            }
+            assert!(
+                names
+                    .iter()
+                    .any(|n| n.contains("monotonic_counter.ringlog_events_read"))
+            );
+            assert!(
+                names
+                    .iter()
+                    .any(|n| n.contains("monotonic_counter.ringlog_rotations"))
+            );
+
+            std::fs::remove_dir_all(&dir).ok();
+        }
+    }
+
+    mod signal {
+        use crate::signal::{self, Signal};
+
+        #[test]
+        fn raised_signal_is_taken_once() {
+            signal::install(&[Signal::User1]).unwrap();
+            assert!(!signal::take(Signal::User1));
+
+            unsafe { libc::raise(libc::SIGUSR1) };
+            unsafe { libc::raise(libc::SIGUSR1) };
+            assert!(signal::take(Signal::User1));
+            assert!(!signal::take(Signal::User1));
+        }
+    }
+
+    mod streams {
+        use super::*;
+        use crate::event::{StreamId, StreamNames};
+        use crate::storage::{Metadata, WriterOptions};
+        use std::fs;
+
+        #[test]
+        fn stream_shares_flags_with_checksum() {
+            let audit = StreamId::new(3).unwrap();
+            let header = EventHeader::new(0, 1, 4)
+                .with_checksum(b"test")
+                .with_stream(audit);
+
+            assert_eq!(header.stream(), audit);
+            assert!(header.has_checksum());
+            assert_eq!(EventHeader::new(0, 1, 4).stream(), StreamId::DEFAULT);
+            assert!(StreamId::new(8).is_none());
+
+            let last = StreamId::new(StreamId::MAX).unwrap();
+            let header = EventHeader {
+                flags: EventHeader::FLAG_EXTENDED,
+                ..EventHeader::new(0, 1, 0)
+            }
+            .with_stream(last);
+            assert_eq!(header.stream(), last);
+            assert_eq!(
+                header.flags & EventHeader::FLAG_EXTENDED,
+                EventHeader::FLAG_EXTENDED
+            );
+        }
+
+        #[test]
+        fn dispatcher_routes_by_stream() {
+            let mut ring = RingBuffer::new(1024).unwrap();
+            let mut dispatcher = EventDispatcher::new();
+            let metrics = StreamId::new(2).unwrap();
+            dispatcher.add_stream_consumer(metrics, FailingConsumer);
+            dispatcher.add_consumer(CountingConsumer::new());
+
+            for i in 0..4 {
+                let stream = if i % 2 == 0 {
+                    metrics
+                } else {
+                    StreamId::DEFAULT
+                };
+                let header = EventHeader::new(i, 1, 4).with_stream(stream);
+                ring.write_event(&header, b"test").unwrap();
+            }
+
+            let stats = dispatcher.drain(&mut ring);
+            assert_eq!(stats.events_read, 4);
+            assert_eq!(stats.events_delivered, 4);
+            assert_eq!(stats.events_failed, 2);
+        }
+
+        #[test]
+        fn replay_selects_stream_by_name() {
+            let path = temp_path();
+            let mut names = StreamNames::new();
+            names.register("debug").unwrap();
+            let audit = names.register("audit").unwrap();
+            assert_eq!(names.register("audit"), Some(audit));
+
+            {
+                let mut metadata = Metadata::new("app");
+                names.write_to(&mut metadata);
+                let options = WriterOptions {
+                    metadata: Some(metadata),
+                    ..Default::default()
+                };
+                let mut writer = MmapWriter::create_with_options(&path, 4096, &options).unwrap();
+                for i in 0..6u64 {
+                    let stream = if i % 3 == 0 { audit } else { StreamId::DEFAULT };
+                    let header = EventHeader::new(i, 1, 8).with_stream(stream);
+                    writer.write_event(&header, &i.to_le_bytes());
+                }
+            }
+
+            let reader = MmapReader::open(&path).unwrap();
+            let names = reader.stream_names();
+            let audit = names.id("audit").unwrap();
+            assert_eq!(names.name(StreamId::DEFAULT), Some("debug"));
+
+            let timestamps: Vec<u64> = reader
+                .iter_stream(audit)
+                .map(|e| e.header.timestamp)
+                .collect();
+            assert_eq!(timestamps, vec![0, 3]);
+
+            drop(reader);
+            fs::remove_file(&path).ok();
+        }
+    }
+
+    mod tags {
+        use super::*;
+        use crate::event::{EventSink, EventView, FieldWriter, Tags};
+        use crate::query::Query;
+        use std::fs;
+
+        const STORAGE: Tags = Tags::tag(1);
+        const NETWORK: Tags = Tags::tag(5);
+
+        fn tagged(tags: Tags) -> Vec<u8> {
+            let mut fields = FieldWriter::new();
+            fields.tags(tags).str("msg", "hello");
+            fields.finish().unwrap()
+        }
+
+        #[test]
+        fn tag_sets() {
+            let both = STORAGE | NETWORK;
+            assert_eq!(both.bits(), 0b10_0010);
+            assert!(both.contains(STORAGE) && both.contains(Tags::EMPTY));
+            assert!(!STORAGE.contains(both));
+            assert!(STORAGE.intersects(both) && !STORAGE.intersects(NETWORK));
+            assert!(Tags::EMPTY.is_empty());
+
+            let mut ring = RingBuffer::new(1024).unwrap();
+            ring.event(1).fields(&tagged(both)).write().unwrap();
+            ring.event(1).payload(&tagged(both)).write().unwrap();
+            ring.event(1).payload(b"test").write().unwrap();
+            let tags: Vec<_> = std::iter::from_fn(|| ring.read_event())
+                .map(|(header, payload)| {
+                    Tags::of(&EventView {
+                        header,
+                        payload: &payload,
+                    })
+                })
+                .collect();
+            // Only the event marked as carrying fields is searched.
+            assert_eq!(tags, [both, Tags::EMPTY, Tags::EMPTY]);
+        }
+
+        #[test]
+        fn readers_and_queries_filter_by_tag() {
+            let path = temp_path();
+            let mut writer = MmapWriter::create(&path, 4096).unwrap();
+            for (timestamp, tags) in [(1, STORAGE), (2, NETWORK), (3, STORAGE | NETWORK)] {
+                let payload = tagged(tags);
+                writer
+                    .event(1)
+                    .timestamp(timestamp)
+                    .fields(&payload)
+                    .write()
+                    .unwrap();
+            }
+            writer
+                .event(1)
+                .timestamp(4)
+                .payload(b"untagged")
+                .write()
+                .unwrap();
+            drop(writer);
+
+            let reader = MmapReader::open(&path).unwrap();
+            let timestamps = |tags| -> Vec<u64> {
+                reader
+                    .iter_tagged(tags)
+                    .map(|event| event.header.timestamp)
+                    .collect()
+            };
+            assert_eq!(timestamps(STORAGE), vec![1, 3]);
+            assert_eq!(timestamps(STORAGE | NETWORK), vec![3]);
+            assert_eq!(timestamps(Tags::EMPTY), vec![1, 2, 3, 4]);
+
+            let query = Query::parse("tag == 5 && tag != 1").unwrap();
+            let matched: Vec<_> = query
+                .filter(&reader)
+                .map(|event| event.header.timestamp)
+                .collect();
+            assert_eq!(matched, vec![2]);
+            assert!(Query::parse("tag == 64").is_err());
+            assert!(Query::parse("tag > 1").is_err());
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn dispatcher_routes_by_tag() {
+            let mut ring = RingBuffer::new(1024).unwrap();
+            let mut dispatcher = EventDispatcher::new();
+            dispatcher.add_tagged_consumer(STORAGE, FailingConsumer);
+            dispatcher.add_consumer(CountingConsumer::new());
+
+            for tags in [STORAGE, NETWORK, STORAGE | NETWORK, Tags::EMPTY] {
+                let payload = tagged(tags);
+                ring.event(1).fields(&payload).write().unwrap();
+            }
+
+            let stats = dispatcher.drain(&mut ring);
+            assert_eq!(stats.events_read, 4);
+            assert_eq!(stats.events_delivered, 4);
+            assert_eq!(stats.events_failed, 2);
+        }
+    }
+
+    mod ring_set {
+        use super::*;
+        use crate::ring::RingSet;
+
+        struct OrderConsumer(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+        impl EventConsumer for OrderConsumer {
+            fn consume(&mut self, header: &EventHeader, _payload: &[u8]) -> bool {
+                self.0.lock().unwrap().push(header.event_type);
+                true
+            }
+
+            fn name(&self) -> &str {
+                "order"
+            }
+        }
+
+        #[test]
+        fn drains_high_priority_first() {
+            let mut set = RingSet::new();
+            set.add("bulk", 0, RingBuffer::new(1024).unwrap());
+            set.add("control", 10, RingBuffer::new(1024).unwrap());
+
+            for i in 0..3 {
+                let header = EventHeader::new(i, 2, 4);
+                set.get_mut("bulk")
+                    .unwrap()
+                    .write_event(&header, b"bulk")
+                    .unwrap();
+            }
+            let header = EventHeader::new(0, 1, 4);
+            set.get_mut("control")
+                .unwrap()
+                .write_event(&header, b"ctrl")
+                .unwrap();
+
+            let order = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+            let mut dispatcher = EventDispatcher::new();
+            dispatcher.add_consumer(OrderConsumer(order.clone()));
+
+            let stats = dispatcher.drain_set_batch(&mut set, 2);
+            assert_eq!(*order.lock().unwrap(), vec![1, 2]);
+            assert_eq!(stats.ring("control").unwrap().events_read, 1);
+            assert_eq!(stats.ring("bulk").unwrap().events_read, 1);
+
+            set.get_mut("control")
+                .unwrap()
+                .write_event(&header, b"ctrl")
+                .unwrap();
+            let stats = dispatcher.drain_set(&mut set);
+            assert_eq!(*order.lock().unwrap(), vec![1, 2, 1, 2, 2]);
+            assert_eq!(stats.total().events_delivered, 3);
+            assert!(set.is_empty());
+        }
+    }
+
+    mod clock {
+        use super::*;
+        use crate::clock::{Clock, SystemClock, TscClock};
+        use std::sync::{Arc, Mutex};
+
+        #[test]
+        fn tsc_converts_to_wall_clock() {
+            // Absent on other architectures and CPUs without an invariant TSC.
+            let Some(clock) = TscClock::new() else {
+                return;
+            };
+            assert!(clock.frequency() > 0);
+
+            let a = clock.now();
+            let b = clock.now();
+            assert!(b >= a);
+
+            let wall = SystemClock.now();
+            let converted = clock.to_unix_nanos(clock.now());
+            assert!(converted.abs_diff(wall) < 50_000_000);
+        }
+
+        #[test]
+        fn dispatcher_converts_timestamps() {
+            struct MicroClock;
+
+            impl Clock for MicroClock {
+                fn now(&self) -> u64 {
+                    0
+                }
+
+                fn to_unix_nanos(&self, ticks: u64) -> u64 {
+                    ticks * 1000
+                }
+            }
+
+            struct Recorder(Arc<Mutex<Vec<u64>>>);
+
+            impl EventConsumer for Recorder {
+                fn consume(&mut self, header: &EventHeader, _payload: &[u8]) -> bool {
+                    self.0.lock().unwrap().push(header.timestamp);
+                    true
+                }
+
+                fn name(&self) -> &str {
+                    "recorder"
+                }
+            }
+
+            let mut ring = RingBuffer::new(1024).unwrap();
+            ring.write_event(&EventHeader::new(7, 1, 4), b"test")
+                .unwrap();
+
+            let seen = Arc::new(Mutex::new(Vec::new()));
+            let mut dispatcher = EventDispatcher::new();
+            dispatcher.add_consumer(Recorder(seen.clone()));
+            dispatcher.set_clock(Some(Arc::new(MicroClock)));
+            dispatcher.drain(&mut ring);
+
+            assert_eq!(*seen.lock().unwrap(), vec![7000]);
+        }
+    }
+
+    mod file_stats {
+        use super::*;
+        use crate::storage::FileStats;
+        use std::fs;
+
+        #[test]
+        fn stats_summarize_file() {
+            let path = temp_path();
+
+            {
+                let mut writer = MmapWriter::create(&path, 4096).unwrap();
+                for (ts, ty, len) in [(50, 1, 0), (10, 2, 8), (30, 1, 16), (20, 3, 24)] {
+                    let header = EventHeader::new(ts, ty, len as u16);
+                    writer.write_event(&header, &[0xAB; 24][..len]);
+                }
+            }
+
+            let stats = MmapReader::open(&path).unwrap().stats();
+
+            assert_eq!(stats.event_count, 4);
+            assert_eq!(stats.payload_bytes, 48);
+            assert_eq!(stats.total_bytes, 48 + 4 * EventHeader::SIZE as u64);
+            assert_eq!(stats.min_timestamp, Some(10));
+            assert_eq!(stats.max_timestamp, Some(50));
+            assert_eq!(stats.events_by_type.get(&1), Some(&2));
+            assert_eq!(stats.events_by_type.len(), 3);
+            assert!((stats.avg_payload_size() - 12.0).abs() < 0.001);
+            assert_eq!(&stats.payload_histogram[..6], &[1, 0, 0, 0, 1, 2]);
+
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn histogram_buckets_cover_u16() {
+            assert_eq!(FileStats::histogram_bucket(0), 0);
+            assert_eq!(FileStats::histogram_bucket(1), 1);
+            assert_eq!(FileStats::histogram_bucket(64), 7);
+            assert_eq!(FileStats::histogram_bucket(u16::MAX), 16);
+            assert_eq!(FileStats::histogram_bucket_range(7), (64, 127));
+        }
+
+        #[test]
+        fn empty_file_stats() {
+            let path = temp_path();
+            MmapWriter::create(&path, 4096).unwrap();
+
+            let stats = MmapReader::open(&path).unwrap().stats();
+            assert_eq!(stats.event_count, 0);
+            assert_eq!(stats.min_timestamp, None);
+            assert_eq!(stats.avg_payload_size(), 0.0);
+
+            fs::remove_file(&path).ok();
+        }
+    }
+
+    mod hash_chain {
+        use super::*;
+        use crate::storage::{VerifyIssue, WriterOptions};
+        use std::fs;
+
+        const EVENT_SIZE: usize = EventHeader::SIZE + 8;
+
+        fn write_chained(path: &str, events: std::ops::Range<u64>) {
+            let options = WriterOptions {
+                hash_chain: true,
+                ..Default::default()
+            };
+            let mut writer = match MmapWriter::open(path) {
+                Ok(writer) => writer,
+                Err(_) => MmapWriter::create_with_options(path, 4096, &options).unwrap(),
+            };
+            for ts in events {
+                let payload = ts.to_le_bytes();
+                let header = EventHeader::new(ts, 1, 8).with_checksum(&payload);
+                writer.try_write_event(&header, &payload).unwrap();
+            }
+        }
+
+        #[test]
+        fn chain_survives_reopening_and_catches_edits() {
+            let path = temp_path();
+            write_chained(&path, 0..5);
+            write_chained(&path, 5..7);
+            let reader = MmapReader::open(&path).unwrap();
+            assert_eq!(reader.verify_chain(), Ok(7));
+            assert!(reader.verify().is_ok());
+            drop(reader);
+
+            let original = fs::read(&path).unwrap();
+            let data_start = FileHeader::SIZE;
+            let mut modified = original.clone();
+            modified[data_start + 3 * EVENT_SIZE + EventHeader::SIZE] ^= 1;
+            fs::write(&path, &modified).unwrap();
+            assert_eq!(
+                MmapReader::open(&path).unwrap().verify_chain(),
+                Err(VerifyIssue::ChainBroken {
+                    offset: data_start + 3 * EVENT_SIZE,
+                    index: 3,
+                })
+            );
+
+            // Cut event 2 out and fix up the file header to match.
+            let mut removed = original.clone();
+            let event = data_start + 2 * EVENT_SIZE;
+            removed.drain(event..event + EVENT_SIZE);
+            removed.extend_from_slice(&[0; EVENT_SIZE]);
+            let mut header = FileHeader::decode(removed[..FileHeader::SIZE].try_into().unwrap());
+            header.event_count -= 1;
+            header.write_offset -= EVENT_SIZE as u64;
+            removed[..FileHeader::SIZE].copy_from_slice(&header.encode());
+            fs::write(&path, &removed).unwrap();
+            assert_eq!(
+                MmapReader::open(&path).unwrap().verify_chain(),
+                Err(VerifyIssue::ChainBroken {
+                    offset: event,
+                    index: 2,
+                })
+            );
+
+            // Dropping trailing events leaves a valid prefix of the chain.
+            let mut truncated = original;
+            let mut header = FileHeader::decode(truncated[..FileHeader::SIZE].try_into().unwrap());
+            header.write_offset -= EVENT_SIZE as u64;
+            truncated[..FileHeader::SIZE].copy_from_slice(&header.encode());
+            fs::write(&path, &truncated).unwrap();
+            assert_eq!(
+                MmapReader::open(&path).unwrap().verify_chain(),
+                Err(VerifyIssue::EventCountMismatch {
+                    declared: 7,
+                    actual: 6,
+                })
+            );
+
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn plain_and_compact_files_are_not_chained() {
+            let path = temp_path();
+            MmapWriter::create(&path, 4096).unwrap();
+            assert_eq!(
+                MmapReader::open(&path).unwrap().verify_chain(),
+                Err(VerifyIssue::NotHashChained)
+            );
+            let options = WriterOptions {
+                hash_chain: true,
+                compact: true,
+                ..Default::default()
+            };
+            assert!(MmapWriter::create_with_options(&path, 4096, &options).is_err());
+            fs::remove_file(&path).ok();
+        }
+    }
+
+    mod verify {
+        use super::*;
+        use crate::storage::VerifyIssue;
+        use std::fs;
+
+        fn write_file(path: &str, events: &[(u64, &[u8])]) {
+            let mut writer = MmapWriter::create(path, 4096).unwrap();
+            for (ts, payload) in events {
+                let header = EventHeader::new(*ts, 1, payload.len() as u16).with_checksum(payload);
+                writer.write_event(&header, payload);
+            }
+            writer.sync().unwrap();
+        }
+
+        #[test]
+        fn clean_file_passes() {
+            let path = temp_path();
+            write_file(
+                &path,
+                &[(1, b"aaaaaaaa"), (2, b"bbbbbbbb"), (3, b"cccccccc")],
+            );
+
+            let reader = MmapReader::open(&path).unwrap();
+            let report = reader.verify();
+
+            assert!(report.is_ok());
+            assert_eq!(report.events_scanned, 3);
+            assert_eq!(report.checksums_verified, 3);
+            assert!(MmapReader::open_verified(&path).is_ok());
+
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn detects_checksum_mismatch() {
+            let path = temp_path();
+            write_file(&path, &[(1, b"aaaaaaaa"), (2, b"bbbbbbbb")]);
+
+            let mut bytes = fs::read(&path).unwrap();
+            bytes[FileHeader::SIZE + EventHeader::SIZE] ^= 0xFF;
+            fs::write(&path, &bytes).unwrap();
+
+            let report = MmapReader::open(&path).unwrap().verify();
+            assert!(matches!(
+                report.issues.as_slice(),
+                [VerifyIssue::ChecksumMismatch { offset, .. }] if *offset == FileHeader::SIZE
+            ));
+            assert!(MmapReader::open_verified(&path).is_err());
+
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn detects_timestamp_regression() {
+            let path = temp_path();
+            write_file(&path, &[(5, b"aaaaaaaa"), (3, b"bbbbbbbb")]);
+
+            let report = MmapReader::open(&path).unwrap().verify();
+            assert!(matches!(
+                report.issues.as_slice(),
+                [VerifyIssue::TimestampRegression {
+                    previous: 5,
+                    current: 3,
+                    ..
+                }]
+            ));
+
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn detects_event_count_mismatch() {
+            let path = temp_path();
+            write_file(&path, &[(1, b"aaaaaaaa")]);
+
+            let mut bytes = fs::read(&path).unwrap();
+            bytes[16..24].copy_from_slice(&9u64.to_le_bytes());
+            fs::write(&path, &bytes).unwrap();
+
+            let report = MmapReader::open(&path).unwrap().verify();
+            assert!(matches!(
+                report.issues.as_slice(),
+                [VerifyIssue::EventCountMismatch {
+                    declared: 9,
+                    actual: 1
+                }]
+            ));
+
+            fs::remove_file(&path).ok();
+        }
+    }
+
+    mod seal {
+        use super::*;
+        use crate::storage::{SegmentFooter, VerifyIssue};
+        use std::fs;
+
+        #[test]
+        fn seal_shrinks_file_and_blocks_writes() {
+            let path = temp_path();
+
+            {
+                let mut writer = MmapWriter::create(&path, 4096).unwrap();
+                for i in 0..3u64 {
+                    let header = EventHeader::new(i, 1, 8);
+                    writer.write_event(&header, &i.to_le_bytes());
+                }
+
+                writer.seal().unwrap();
+                assert!(writer.is_sealed());
+                assert_eq!(writer.available(), 0);
+
+                let header = EventHeader::new(3, 1, 8);
+                assert!(!writer.write_event(&header, &3u64.to_le_bytes()));
+                assert_eq!(writer.file_header().event_count, 3);
+            }
+
+            let used = FileHeader::SIZE + 3 * (EventHeader::SIZE + 8);
+            let len = used + SegmentFooter::SIZE;
+            assert_eq!(fs::metadata(&path).unwrap().len(), len as u64);
+
+            let reader = MmapReader::open(&path).unwrap();
+            assert!(reader.is_sealed());
+            assert!(reader.verify().is_ok());
+            assert_eq!(reader.iter().count(), 3);
+
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn reopened_sealed_file_rejects_writes() {
+            let path = temp_path();
+
+            {
+                let mut writer = MmapWriter::create(&path, 4096).unwrap();
+                let header = EventHeader::new(0, 1, 8);
+                writer.write_event(&header, b"testdata");
+                writer.seal().unwrap();
+                writer.seal().unwrap();
+            }
+
+            let mut writer = MmapWriter::open(&path).unwrap();
+            assert!(writer.is_sealed());
+            let header = EventHeader::new(1, 1, 8);
+            assert!(!writer.write_event(&header, b"testdata"));
+
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn verify_detects_tampered_sealed_segment() {
+            let path = temp_path();
+
+            {
+                let mut writer = MmapWriter::create(&path, 4096).unwrap();
+                let header = EventHeader::new(0, 1, 8);
+                writer.write_event(&header, b"testdata");
+                writer.seal().unwrap();
+            }
+
+            let mut bytes = fs::read(&path).unwrap();
+            bytes[FileHeader::SIZE + EventHeader::SIZE] ^= 0xFF;
+            fs::write(&path, &bytes).unwrap();
+
+            let report = MmapReader::open(&path).unwrap().verify();
+            assert!(matches!(
+                report.issues.as_slice(),
+                [VerifyIssue::SegmentChecksumMismatch { .. }]
+            ));
+
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn quick_verify_checks_footer() {
+            let path = temp_path();
+
+            {
+                let mut writer = MmapWriter::create(&path, 4096).unwrap();
+                writer.write_event(&EventHeader::new(0, 1, 8), b"testdata");
+                assert!(matches!(
+                    MmapReader::open(&path).unwrap().quick_verify(),
+                    Err(VerifyIssue::MissingFooter { .. })
+                ));
+                writer.seal().unwrap();
+            }
+
+            let reader = MmapReader::open(&path).unwrap();
+            assert_eq!(reader.quick_verify(), Ok(()));
+            assert_eq!(reader.iter().count(), 1);
+            assert!(reader.verify().is_ok());
+            drop(reader);
+
+            let mut bytes = fs::read(&path).unwrap();
+            bytes[FileHeader::SIZE + EventHeader::SIZE] ^= 0xFF;
+            fs::write(&path, &bytes).unwrap();
+            assert!(matches!(
+                MmapReader::open(&path).unwrap().quick_verify(),
+                Err(VerifyIssue::SegmentHashMismatch { .. })
+            ));
+
+            let footer = bytes.len() - SegmentFooter::SIZE;
+            bytes[footer + 8] ^= 0x01;
+            fs::write(&path, &bytes).unwrap();
+            assert!(matches!(
+                MmapReader::open(&path).unwrap().quick_verify(),
+                Err(VerifyIssue::FooterMismatch {
+                    field: "event_count",
+                    ..
+                })
+            ));
+
+            fs::remove_file(&path).ok();
+        }
+    }
+
+    mod merkle {
+        use super::*;
+        use crate::storage::merkle::{leaf_hash, merkle_root};
+        use crate::storage::{SegmentFooter, VerifyIssue};
+        use std::fs;
+
+        fn sealed_file(events: u64) -> String {
+            let path = temp_path();
+            let mut writer = MmapWriter::create(&path, 4096).unwrap();
+            for i in 0..events {
+                writer.write_event(&EventHeader::new(i, 1, 8), &i.to_le_bytes());
+            }
+            writer.seal().unwrap();
+            path
+        }
+
+        #[test]
+        fn roots_split_like_rfc_6962() {
+            let leaves: Vec<_> = (0u8..3).map(|i| leaf_hash(&[i])).collect();
+            let node = |l: &[u8; 32], r: &[u8; 32]| {
+                use sha2::{Digest, Sha256};
+                let mut hasher = Sha256::new();
+                hasher.update([1]);
+                hasher.update(l);
+                hasher.update(r);
+                <[u8; 32]>::from(hasher.finalize())
+            };
+            assert_eq!(merkle_root(&leaves[..1]), leaves[0]);
+            assert_eq!(
+                merkle_root(&leaves),
+                node(&node(&leaves[0], &leaves[1]), &leaves[2])
+            );
+        }
+
+        #[test]
+        fn seal_records_a_root_that_catches_edits() {
+            let path = sealed_file(5);
+            let reader = MmapReader::open(&path).unwrap();
+            let footer = reader.verify_seal().unwrap();
+            assert_eq!(footer.merkle_root, Some(reader.merkle_root()));
+            assert_eq!(footer.signature, None);
+            drop(reader);
+
+            // Rewrite one payload and the xxh3 footer hash to match, as
+            // someone covering their tracks would.
+            let mut bytes = fs::read(&path).unwrap();
+            bytes[FileHeader::SIZE + EventHeader::SIZE] ^= 1;
+            let data_end = bytes.len() - SegmentFooter::SIZE;
+            let mut footer = SegmentFooter::decode(&bytes[data_end..]).unwrap();
+            footer.hash = SegmentFooter::hash(&bytes[FileHeader::SIZE..data_end]);
+            bytes[data_end..].copy_from_slice(&footer.encode());
+            fs::write(&path, &bytes).unwrap();
+
+            let reader = MmapReader::open(&path).unwrap();
+            assert_eq!(reader.quick_verify(), Ok(()));
+            assert!(matches!(
+                reader.verify_seal(),
+                Err(VerifyIssue::MerkleRootMismatch { .. })
+            ));
+
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn version_1_footers_still_decode() {
+            let mut bytes = SegmentFooter {
+                event_count: 2,
+                data_end: 100,
+                hash: 7,
+                merkle_root: None,
+                signature: None,
+                bloom: None,
+            }
+            .encode();
+            bytes[4..8].copy_from_slice(&1u32.to_le_bytes());
+            let footer = SegmentFooter::decode(&bytes[..32]).unwrap();
+            assert_eq!((footer.event_count, footer.merkle_root), (2, None));
+        }
+
+        #[cfg(feature = "signing")]
+        #[test]
+        fn signed_seals_verify_against_the_trusted_key() {
+            use ed25519_dalek::SigningKey;
+
+            let key = SigningKey::from_bytes(&[7; 32]);
+            let other = SigningKey::from_bytes(&[8; 32]);
+            let path = temp_path();
+            let mut writer = MmapWriter::create(&path, 4096).unwrap();
+            writer.write_event(&EventHeader::new(0, 1, 8), b"testdata");
+            writer.seal_signed(&key).unwrap();
+            drop(writer);
+
+            let reader = MmapReader::open(&path).unwrap();
+            let footer = reader.verify_signed_seal(&key.verifying_key()).unwrap();
+            assert_eq!(
+                footer.signature.unwrap().public_key,
+                key.verifying_key().to_bytes()
+            );
+            assert_eq!(
+                reader.verify_signed_seal(&other.verifying_key()),
+                Err(VerifyIssue::SignatureInvalid)
+            );
+            fs::remove_file(&path).ok();
+
+            let path = sealed_file(1);
+            assert_eq!(
+                MmapReader::open(&path)
+                    .unwrap()
+                    .verify_signed_seal(&key.verifying_key()),
+                Err(VerifyIssue::Unsigned)
+            );
+            fs::remove_file(&path).ok();
+        }
+    }
+
+    mod export {
+        use super::*;
+        use crate::event::{EventSink, FieldWriter};
+        use crate::storage::{CsvColumn, SegmentedReader, segment_path};
+        use std::fs;
+
+        #[test]
+        fn exports_csv_rows() {
+            let path = temp_path();
+            let mut writer = MmapWriter::create(&path, 4096).unwrap();
+            writer
+                .event(1)
+                .timestamp(10)
+                .sequence(7)
+                .payload(b"disk full, retrying")
+                .write()
+                .unwrap();
+            writer
+                .event(2)
+                .timestamp(20)
+                .payload(&[0, 0xFF])
+                .write()
+                .unwrap();
+            let mut fields = FieldWriter::new();
+            fields.str("user", "say \"hi\"");
+            writer
+                .event(3)
+                .timestamp(1_700_000_000_000_000_000)
+                .payload(fields.as_bytes().unwrap())
+                .write()
+                .unwrap();
+            drop(writer);
+
+            let reader = MmapReader::open(&path).unwrap();
+            let mut out = Vec::new();
+            let rows = reader.export_csv(&mut out, &CsvColumn::defaults()).unwrap();
+            assert_eq!(rows, 3);
+            let csv = String::from_utf8(out).unwrap();
+            let lines: Vec<_> = csv.split_terminator("\r\n").collect();
+            assert_eq!(lines[0], "timestamp,type,seq,payload");
+            assert_eq!(lines[1], "10,1,7,\"disk full, retrying\"");
+            assert_eq!(lines[2], "20,2,,0x00ff");
+
+            let columns = CsvColumn::parse_list("time,stream,field.user,field.missing").unwrap();
+            let mut out = Vec::new();
+            reader.export_csv(&mut out, &columns).unwrap();
+            let csv = String::from_utf8(out).unwrap();
+            let last = csv.split_terminator("\r\n").last().unwrap();
+            assert_eq!(last, "2023-11-14T22:13:20.000000000Z,0,\"say \"\"hi\"\"\",");
+            assert!(CsvColumn::parse_list("timestamp,bogus").is_err());
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn exports_segment_directories() {
+            let dir = temp_path();
+            fs::create_dir_all(&dir).unwrap();
+            for seq in 0..2 {
+                let mut writer = MmapWriter::create(segment_path(&dir, seq), 4096).unwrap();
+                writer
+                    .event(1)
+                    .timestamp(seq)
+                    .payload(b"x")
+                    .write()
+                    .unwrap();
+            }
+
+            let reader = SegmentedReader::open(&dir).unwrap();
+            let mut out = Vec::new();
+            assert_eq!(
+                reader
+                    .export_csv(&mut out, &[CsvColumn::Timestamp])
+                    .unwrap(),
+                2
+            );
+            assert_eq!(out, b"timestamp\r\n0\r\n1\r\n");
+            fs::remove_dir_all(&dir).ok();
+        }
+    }
+
+    #[cfg(feature = "postgres")]
+    mod pg_copy {
+        use super::*;
+        use crate::event::{EventSink, TraceContext};
+        use crate::storage::{PgCopyWriter, PgExporter};
+        use std::fs;
+
+        /// Splits a binary COPY row into its fields; `None` for NULL.
+        fn fields(row: &[u8]) -> (Vec<Option<&[u8]>>, &[u8]) {
+            let count = i16::from_be_bytes(row[..2].try_into().unwrap());
+            let mut rest = &row[2..];
+            let mut fields = Vec::new();
+            for _ in 0..count {
+                let len = i32::from_be_bytes(rest[..4].try_into().unwrap());
+                rest = &rest[4..];
+                if len < 0 {
+                    fields.push(None);
+                } else {
+                    let (field, tail) = rest.split_at(len as usize);
+                    fields.push(Some(field));
+                    rest = tail;
+                }
+            }
+            (fields, rest)
+        }
+
+        #[test]
+        fn encodes_binary_copy_rows() {
+            let path = temp_path();
+            let mut writer = MmapWriter::create(&path, 4096).unwrap();
+            writer
+                .event(3)
+                .timestamp(946_684_800_000_001_000)
+                .sequence(7)
+                .trace(TraceContext::new(42, 9))
+                .payload(b"hello")
+                .write()
+                .unwrap();
+            writer.event(4).timestamp(5).write().unwrap();
+            drop(writer);
+
+            let reader = MmapReader::open(&path).unwrap();
+            let mut copy = PgCopyWriter::new(Vec::new()).unwrap();
+            for event in reader.iter() {
+                copy.write_event(&event).unwrap();
+            }
+            let (bytes, rows) = copy.finish().unwrap();
+            assert_eq!(rows, 2);
+            assert_eq!(&bytes[..11], b"PGCOPY\n\xff\r\n\0");
+            assert_eq!(&bytes[11..19], &[0; 8]);
+            assert_eq!(&bytes[bytes.len() - 2..], &[0xFF, 0xFF]);
+
+            let bigint = |value: i64| value.to_be_bytes().to_vec();
+            let (first, rest) = fields(&bytes[19..]);
+            let first: Vec<_> = first.into_iter().map(|f| f.map(<[u8]>::to_vec)).collect();
+            assert_eq!(
+                first,
+                vec![
+                    Some(bigint(946_684_800_000_001_000)),
+                    Some(bigint(1)),
+                    Some(3i16.to_be_bytes().to_vec()),
+                    Some(0i16.to_be_bytes().to_vec()),
+                    Some(
+                        ((EventHeader::FLAG_SEQUENCE | EventHeader::FLAG_TRACE) as i16)
+                            .to_be_bytes()
+                            .to_vec()
+                    ),
+                    Some(bigint(7)),
+                    Some(bigint(42)),
+                    Some(bigint(9)),
+                    Some(b"hello".to_vec()),
+                ]
+            );
+            let (second, rest) = fields(rest);
+            assert_eq!(second[5..8], [None, None, None]);
+            assert_eq!(second[8], Some(&b""[..]));
+            assert_eq!(rest, &[0xFF, 0xFF]);
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn validates_table_names() {
+            let exporter = PgExporter::new("warehouse.ringlog_events");
+            assert!(
+                exporter
+                    .create_table_sql()
+                    .starts_with("CREATE TABLE IF NOT EXISTS warehouse.ringlog_events (")
+            );
+            for bad in ["", "events; DROP TABLE x", "a.b.c", "1events", "a."] {
+                assert!(std::panic::catch_unwind(|| PgExporter::new(bad)).is_err());
+            }
+        }
+    }
+
+    mod markers {
+        use super::*;
+        use crate::event::EventSink;
+        use crate::storage::{SegmentedReader, segment_path};
+        use std::fs;
+
+        #[test]
+        fn finds_the_latest_marker() {
+            let path = temp_path();
+            let mut writer = MmapWriter::create(&path, 4096).unwrap();
+            writer.event(1).timestamp(10).write().unwrap();
+            writer.marker("backup taken").timestamp(20).write().unwrap();
+            writer.event(1).timestamp(30).write().unwrap();
+            writer.marker("deploy v1.2").timestamp(40).write().unwrap();
+            writer.marker("backup taken").timestamp(50).write().unwrap();
+            writer.event(2).timestamp(60).write().unwrap();
+            drop(writer);
+
+            let reader = MmapReader::open(&path).unwrap();
+            let marker = reader.find_marker("backup taken").unwrap();
+            assert_eq!(marker.header.timestamp, 50);
+            assert_eq!(marker.header.marker(marker.payload), Some("backup taken"));
+            assert!(reader.find_marker("restore").is_none());
+
+            let mut replayed = Vec::new();
+            let count = reader.replay_since_marker("deploy v1.2", |event| {
+                replayed.push(event.header.timestamp);
+            });
+            assert_eq!(count, Some(2));
+            assert_eq!(replayed, vec![50, 60]);
+            assert_eq!(reader.replay_since_marker("restore", |_| {}), None);
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn replays_since_a_marker_across_segments() {
+            let dir = temp_path();
+            fs::create_dir_all(&dir).unwrap();
+            let mut writer = MmapWriter::create(segment_path(&dir, 0), 4096).unwrap();
+            writer.event(1).timestamp(1).write().unwrap();
+            writer.marker("deploy v1.2").timestamp(2).write().unwrap();
+            writer.event(1).timestamp(3).write().unwrap();
+            drop(writer);
+            let mut writer = MmapWriter::create(segment_path(&dir, 1), 4096).unwrap();
+            writer.event(1).timestamp(4).write().unwrap();
+            drop(writer);
+
+            let reader = SegmentedReader::open(&dir).unwrap();
+            assert_eq!(
+                reader.find_marker("deploy v1.2").unwrap().header.timestamp,
+                2
+            );
+            let mut replayed = Vec::new();
+            let count = reader.replay_since_marker("deploy v1.2", |event| {
+                replayed.push(event.header.timestamp);
+            });
+            assert_eq!(count, Some(2));
+            assert_eq!(replayed, vec![3, 4]);
+            fs::remove_dir_all(&dir).ok();
+        }
+
+        #[test]
+        fn marker_names_come_from_marker_events_only() {
+            let header = EventHeader::new(0, 1, 4);
+            assert_eq!(header.marker(b"name"), None);
+            let header = EventHeader::new(0, EventHeader::TYPE_MARKER, 2);
+            assert_eq!(header.marker(&[0xFF, 0xFE]), None);
+        }
+    }
+
+    mod cursor {
+        use super::*;
+        use crate::event::EventSink;
+        use crate::storage::{Cursor, SegmentedReader, segment_path};
+        use std::fs;
+
+        fn write(dir: &str, seq: u64, sequences: std::ops::Range<u64>) {
+            let path = segment_path(dir, seq);
+            let mut writer = if path.exists() {
+                MmapWriter::open(&path).unwrap()
+            } else {
+                MmapWriter::create(&path, 4096).unwrap()
+            };
+            for sequence in sequences {
+                writer.event(1).sequence(sequence).write().unwrap();
+            }
+        }
+
+        #[test]
+        fn resumes_after_the_last_event_read() {
+            let dir = temp_path();
+            fs::create_dir_all(&dir).unwrap();
+            let state = format!("{}/export.cursor", dir);
+            write(&dir, 0, 0..3);
+            write(&dir, 1, 3..5);
+
+            let reader = SegmentedReader::open(&dir).unwrap();
+            let mut events = reader.resume(Cursor::load(&state).unwrap());
+            let first: Vec<_> = events
+                .by_ref()
+                .take(4)
+                .map(|event| event.header.split_sequence(event.payload).0.unwrap())
+                .collect();
+            assert_eq!(first, vec![0, 1, 2, 3]);
+            let cursor = events.cursor();
+            assert_eq!((cursor.segment, cursor.sequence), (1, Some(3)));
+            cursor.save(&state).unwrap();
+            drop(reader);
+
+            write(&dir, 1, 5..6);
+            write(&dir, 2, 6..8);
+            let reader = SegmentedReader::open(&dir).unwrap();
+            let mut events = reader.resume(Cursor::load(&state).unwrap());
+            let rest: Vec<_> = events
+                .by_ref()
+                .map(|event| event.header.split_sequence(event.payload).0.unwrap())
+                .collect();
+            assert_eq!(rest, vec![4, 5, 6, 7]);
+            events.cursor().save(&state).unwrap();
+
+            let cursor = Cursor::load(&state).unwrap();
+            assert_eq!((cursor.segment, cursor.sequence), (2, Some(7)));
+            assert_eq!(reader.resume(cursor).count(), 0);
+            fs::remove_dir_all(&dir).ok();
+        }
+
+        #[test]
+        fn skips_to_the_next_segment_after_retention() {
+            let dir = temp_path();
+            fs::create_dir_all(&dir).unwrap();
+            write(&dir, 0, 0..2);
+            write(&dir, 1, 2..4);
+            write(&dir, 2, 4..6);
+
+            let reader = SegmentedReader::open(&dir).unwrap();
+            let mut events = reader.resume(Cursor::default());
+            events.by_ref().take(3).count();
+            let cursor = events.cursor();
+            assert_eq!(cursor.segment, 1);
+            drop(reader);
+
+            fs::remove_file(segment_path(&dir, 1)).unwrap();
+            let reader = SegmentedReader::open(&dir).unwrap();
+            let sequences: Vec<_> = reader
+                .resume(cursor)
+                .map(|event| event.header.split_sequence(event.payload).0.unwrap())
+                .collect();
+            assert_eq!(sequences, vec![4, 5]);
+            fs::remove_dir_all(&dir).ok();
+        }
+
+        #[test]
+        fn saves_and_loads_cursors() {
+            let path = temp_path();
+            assert_eq!(Cursor::load(&path).unwrap(), Cursor::default());
+
+            let cursor = Cursor {
+                segment: 3,
+                offset: 4160,
+                previous: 1_700_000_000,
+                sequence: Some(42),
+            };
+            cursor.save(&path).unwrap();
+            assert_eq!(Cursor::load(&path).unwrap(), cursor);
+
+            let unsequenced = Cursor {
+                sequence: None,
+                ..cursor
+            };
+            unsequenced.save(&path).unwrap();
+            assert_eq!(Cursor::load(&path).unwrap(), unsequenced);
+
+            fs::write(&path, "segment=3 offset=x\n").unwrap();
+            assert_eq!(
+                Cursor::load(&path).unwrap_err().kind(),
+                std::io::ErrorKind::InvalidData
+            );
+            fs::remove_file(&path).ok();
+        }
+    }
+
+    mod merge {
+        use super::*;
+        use crate::storage::{MergedIterator, SegmentedReader, segment_path};
+        use std::fs;
+
+        fn file(timestamps: &[u64], event_type: u8) -> (String, MmapReader) {
+            let path = temp_path();
+            let mut writer = MmapWriter::create(&path, 4096).unwrap();
+            for &timestamp in timestamps {
+                writer.write_event(&EventHeader::new(timestamp, event_type, 1), b"x");
+            }
+            drop(writer);
+            let reader = MmapReader::open(&path).unwrap();
+            (path, reader)
+        }
+
+        #[test]
+        fn merges_files_in_timestamp_order() {
+            let (a, first) = file(&[1, 4, 4, 9], 1);
+            let (b, second) = file(&[2, 4, 10], 2);
+            let (c, empty) = file(&[], 3);
+            let readers = vec![first, second, empty];
+
+            let merged: Vec<_> = MergedIterator::new(&readers)
+                .map(|event| (event.header.timestamp, event.header.event_type))
+                .collect();
+            assert_eq!(
+                merged,
+                vec![(1, 1), (2, 2), (4, 1), (4, 1), (4, 2), (9, 1), (10, 2)]
+            );
+            for path in [a, b, c] {
+                fs::remove_file(path).ok();
+            }
+        }
+
+        #[test]
+        fn merges_segment_directories() {
+            let dirs: Vec<_> = [[3u64, 5], [1, 4]]
+                .iter()
+                .map(|timestamps| {
+                    let dir = temp_path();
+                    fs::create_dir_all(&dir).unwrap();
+                    for (seq, &timestamp) in timestamps.iter().enumerate() {
+                        let mut writer =
+                            MmapWriter::create(segment_path(&dir, seq as u64), 4096).unwrap();
+                        writer.write_event(&EventHeader::new(timestamp, 1, 1), b"x");
+                    }
+                    dir
+                })
+                .collect();
+            let logs: Vec<_> = dirs
+                .iter()
+                .map(|dir| SegmentedReader::open(dir).unwrap())
+                .collect();
+
+            let timestamps: Vec<_> =
+                MergedIterator::from_iters(logs.iter().map(SegmentedReader::iter))
+                    .map(|event| event.header.timestamp)
+                    .collect();
+            assert_eq!(timestamps, vec![1, 3, 4, 5]);
+            for dir in dirs {
+                fs::remove_dir_all(dir).ok();
+            }
+        }
+    }
+
+    mod bloom {
+        use super::*;
+        use crate::event::{EventSink, EventView, TraceContext};
+        use crate::storage::{
+            BloomFilter, SegmentFooter, SegmentedReader, WriterOptions, segment_path,
+        };
+        use std::fs;
+
+        #[test]
+        fn filters_have_no_false_negatives_and_few_false_positives() {
+            let mut bloom = BloomFilter::with_capacity(1000);
+            for key in 0..1000u64 {
+                bloom.insert(key * 7919);
+            }
+            assert!((0..1000u64).all(|key| bloom.may_contain(key * 7919)));
+            let false_positives = (1_000_000..1_010_000u64)
+                .filter(|&key| bloom.may_contain(key))
+                .count();
+            assert!(false_positives < 300, "{} false positives", false_positives);
+            assert_eq!(BloomFilter::decode(&bloom.encode()).unwrap(), bloom);
+        }
+
+        fn first_byte(event: &EventView<'_>) -> Option<u64> {
+            let body = event.header.body(event.payload);
+            body.first().map(|&b| 1000 + b as u64)
+        }
+
+        /// Three segments with trace ids 1 to 3 and keys 1000 to 1002.
+        fn traced_segments() -> String {
+            let dir = temp_path();
+            fs::create_dir_all(&dir).unwrap();
+            let options = WriterOptions {
+                bloom_key: Some(first_byte),
+                ..Default::default()
+            };
+            for seq in 1..=3u64 {
+                let path = segment_path(&dir, seq);
+                let mut writer = MmapWriter::create_with_options(path, 4096, &options).unwrap();
+                let trace = TraceContext {
+                    trace_id: seq,
+                    span_id: 9,
+                };
+                writer
+                    .event(1)
+                    .trace(trace)
+                    .payload(&[seq as u8 - 1])
+                    .write()
+                    .unwrap();
+                writer.write_event(&EventHeader::new(2, 1, 1), &[seq as u8 - 1]);
+                writer.seal().unwrap();
+            }
+            dir
+        }
+
+        #[test]
+        fn sealed_segments_skip_lookups_for_absent_keys() {
+            let dir = traced_segments();
+            let reader = SegmentedReader::open(&dir).unwrap();
+            for key in [2, 1001] {
+                assert_eq!(reader.readers_containing(key).count(), 1);
+            }
+            assert_eq!(reader.readers_containing(77).count(), 0);
+            let found: Vec<_> = reader.find_by_trace(3).collect();
+            assert_eq!(found.len(), 1);
+            assert_eq!(found[0].header.split_trace(found[0].payload).1, &[2]);
+
+            let segment = reader.readers().next().unwrap();
+            assert!(segment.bloom().is_some());
+            assert_eq!(segment.quick_verify(), Ok(()));
+            assert!(segment.verify_seal().is_ok());
+            fs::remove_dir_all(&dir).ok();
+        }
+
+        #[test]
+        fn segments_without_keys_keep_version_2_footers() {
+            // Unsealed segments have no filter yet.
+            let path = temp_path();
+            let mut writer = MmapWriter::create(&path, 4096).unwrap();
+            let trace = TraceContext {
+                trace_id: 5,
+                span_id: 1,
+            };
+            writer.event(1).trace(trace).write().unwrap();
+            assert!(MmapReader::open(&path).unwrap().bloom().is_none());
+            fs::remove_file(&path).ok();
+
+            let path = temp_path();
+            let mut writer = MmapWriter::create(&path, 4096).unwrap();
+            writer.write_event(&EventHeader::new(1, 1, 1), b"x");
+            writer.seal().unwrap();
+            drop(writer);
+
+            let bytes = fs::read(&path).unwrap();
+            let footer = &bytes[bytes.len() - SegmentFooter::SIZE..];
+            assert_eq!(footer[4..8], 2u32.to_le_bytes());
+            let reader = MmapReader::open(&path).unwrap();
+            assert_eq!(reader.bloom(), None);
+            assert!(reader.may_contain(12345));
+            fs::remove_file(&path).ok();
+        }
+    }
+
+    mod compaction {
+        use super::*;
+        use crate::event::EventSink;
+        use crate::storage::{Metadata, WriterOptions, compact_deleted, segment_path};
+        use std::fs;
+        use std::path::Path;
+
+        /// Keys are the payload up to the first `:`.
+        fn key_of(_: &EventHeader, payload: &[u8]) -> Option<Vec<u8>> {
+            let end = payload.iter().position(|&b| b == b':')?;
+            Some(payload[..end].to_vec())
+        }
+
+        fn write_segment(path: &Path, options: &WriterOptions, events: &[&[u8]], seal: bool) {
+            let mut writer = MmapWriter::create_with_options(path, 64 * 1024, options).unwrap();
+            for (i, payload) in events.iter().enumerate() {
+                let event = if payload.contains(&b':') {
+                    writer.event(1).payload(payload)
+                } else {
+                    writer.tombstone(payload)
+                };
+                event.timestamp(i as u64).write().unwrap();
+            }
+            if seal {
+                writer.seal().unwrap();
+            }
+        }
+
+        fn payloads(path: &Path) -> Vec<Vec<u8>> {
+            let reader = MmapReader::open(path).unwrap();
+            reader.iter().map(|event| event.payload.to_vec()).collect()
+        }
+
+        #[test]
+        fn removes_tombstoned_keys_from_sealed_segments() {
+            let dir = temp_path();
+            fs::create_dir_all(&dir).unwrap();
+            let options = WriterOptions {
+                metadata: Some(Metadata::new("billing")),
+                ..Default::default()
+            };
+            let first = segment_path(&dir, 0);
+            let second = segment_path(&dir, 1);
+            let live = segment_path(&dir, 2);
+            write_segment(&first, &options, &[b"alice:1", b"bob:1", b"alice:2"], true);
+            write_segment(&second, &options, &[b"carol:1", b"bob:2"], true);
+            write_segment(&live, &options, &[b"alice:3", b"alice"], false);
+            let created_at = MmapReader::open(&first).unwrap().created_at();
+
+            let report = compact_deleted(&dir, key_of).unwrap();
+            assert_eq!(report.tombstones, 1);
+            assert_eq!(report.segments_rewritten, vec![first.clone()]);
+            assert_eq!(report.events_removed, 2);
+            assert!(report.bytes_reclaimed > 0);
+            assert_eq!(report.removed_by_key.get(b"alice".as_slice()), Some(&2));
+            assert_eq!(report.skipped_unsealed, vec![live.clone()]);
+
+            assert_eq!(payloads(&first), vec![b"bob:1".to_vec()]);
+            assert_eq!(payloads(&second).len(), 2);
+            assert_eq!(
+                payloads(&live),
+                vec![b"alice:3".to_vec(), b"alice".to_vec()]
+            );
+            let reader = MmapReader::open(&first).unwrap();
+            assert!(reader.verify_seal().is_ok());
+            assert_eq!(reader.created_at(), created_at);
+            assert_eq!(reader.metadata(), options.metadata.as_ref());
+            assert!(!Path::new(&format!("{}.compact.tmp", first.display())).exists());
+
+            // Nothing left to remove.
+            let report = compact_deleted(&dir, key_of).unwrap();
+            assert!(report.segments_rewritten.is_empty());
+            fs::remove_dir_all(&dir).ok();
+        }
+
+        #[test]
+        fn rewritten_segments_keep_their_format() {
+            let dir = temp_path();
+            fs::create_dir_all(&dir).unwrap();
+            let chained = WriterOptions {
+                hash_chain: true,
+                ..Default::default()
+            };
+            let path = segment_path(&dir, 0);
+            write_segment(&path, &chained, &[b"a:1", b"b:1", b"a", b"b:2"], true);
+
+            let report = compact_deleted(&dir, key_of).unwrap();
+            assert_eq!(report.events_removed, 1);
+            let reader = MmapReader::open(&path).unwrap();
+            assert_eq!(reader.verify_chain(), Ok(3));
+            assert_eq!(reader.event_count(), 3);
+            fs::remove_dir_all(&dir).ok();
+        }
+    }
+
+    mod index {
+        use super::*;
+        use crate::event::{EventSink, FieldReader, FieldValue, FieldWriter};
+        use crate::storage::{IndexBuilder, WriterOptions, index_path};
+        use std::fs;
+
+        fn request_id(event: &crate::event::EventView<'_>) -> Option<u64> {
+            match FieldReader::new(event.payload).get("request")? {
+                FieldValue::U64(id) => Some(id),
+                _ => None,
+            }
+        }
+
+        fn write_requests(path: &str, options: &WriterOptions, seal: bool) {
+            let mut writer = MmapWriter::create_with_options(path, 64 * 1024, options).unwrap();
+            for i in 0..100u64 {
+                let mut fields = FieldWriter::new();
+                fields.u64("request", i % 10).u64("n", i);
+                writer
+                    .event(1)
+                    .timestamp(1000 + i)
+                    .payload(&fields.finish().unwrap())
+                    .write()
+                    .unwrap();
+            }
+            writer.event(2).payload(b"unkeyed").write().unwrap();
+            if seal {
+                writer.seal().unwrap();
+            }
+        }
+
+        fn ns(reader: &MmapReader, key: u64) -> Vec<(u64, u64)> {
+            let events = reader.lookup(key).unwrap();
+            events
+                .iter()
+                .map(|event| match FieldReader::new(event.payload).get("n") {
+                    Some(FieldValue::U64(n)) => (n, event.header.timestamp),
+                    _ => panic!("event without n"),
+                })
+                .collect()
+        }
+
+        #[test]
+        fn lookup_finds_events_by_key() {
+            for compact in [false, true] {
+                let path = temp_path();
+                let options = WriterOptions {
+                    compact,
+                    ..Default::default()
+                };
+                write_requests(&path, &options, true);
+                assert_eq!(IndexBuilder::by(request_id).build(&path).unwrap(), 100);
+
+                let reader = MmapReader::open(&path).unwrap();
+                let found = ns(&reader, 7);
+                assert_eq!(found.len(), 10);
+                assert!(found.iter().all(|&(n, ts)| n % 10 == 7 && ts == 1000 + n));
+                assert!(reader.lookup(42).unwrap().is_empty());
+                fs::remove_file(index_path(&path)).ok();
+                fs::remove_file(&path).ok();
+            }
+        }
+
+        #[test]
+        fn missing_stale_and_unsealed_indexes_fail() {
+            let path = temp_path();
+            write_requests(&path, &WriterOptions::default(), false);
+            let error = IndexBuilder::by(request_id).build(&path).unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+
+            let reader = MmapReader::open(&path).unwrap();
+            let error = reader.lookup(1).unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+            drop(reader);
+
+            // An index built from another segment is refused.
+            let other = temp_path();
+            write_requests(&other, &WriterOptions::default(), true);
+            IndexBuilder::by(request_id).build(&other).unwrap();
+            let mut writer = MmapWriter::open(&path).unwrap();
+            writer.write_event(&EventHeader::new(1, 1, 1), b"x");
+            writer.seal().unwrap();
+            drop(writer);
+            fs::copy(index_path(&other), index_path(&path)).unwrap();
+            let reader = MmapReader::open(&path).unwrap();
+            let error = reader.lookup(1).unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+
+            for path in [&path, &other] {
+                fs::remove_file(index_path(path)).ok();
+                fs::remove_file(path).ok();
+            }
+        }
+    }
+
+    mod state_replay {
+        use super::*;
+        use crate::event::EventSink;
+        use crate::storage::{ReplayTarget, StateCodec, StateReplayer, segment_path};
+        use std::fs;
+
+        #[derive(Debug, Clone, Default, PartialEq)]
+        struct Sum(u64);
+
+        impl StateCodec for Sum {
+            fn encode(&self) -> Vec<u8> {
+                self.0.to_le_bytes().to_vec()
+            }
+
+            fn decode(bytes: &[u8]) -> Option<Self> {
+                Some(Self(u64::from_le_bytes(bytes.try_into().ok()?)))
+            }
+        }
+
+        /// Two segments of five events, timestamps and sequence numbers
+        /// 1 to 10.
+        fn journal() -> String {
+            let dir = temp_path();
+            fs::create_dir_all(&dir).unwrap();
+            for segment in 0..2u64 {
+                let path = segment_path(&dir, segment + 1);
+                let mut writer = MmapWriter::create(path, 4096).unwrap();
+                for n in segment * 5 + 1..=segment * 5 + 5 {
+                    writer
+                        .event(1)
+                        .timestamp(n)
+                        .sequence(n)
+                        .payload(b"x")
+                        .write()
+                        .unwrap();
+                }
+            }
+            dir
+        }
+
+        fn apply(sum: &mut Sum, event: &crate::event::EventView<'_>) {
+            sum.0 += event.header.timestamp;
+        }
+
+        #[test]
+        fn replays_up_to_a_timestamp_or_sequence() {
+            let dir = journal();
+            let mut replayer = StateReplayer::new(&dir, Sum(0), apply);
+
+            let replayed = replayer.replay_to(ReplayTarget::Timestamp(7)).unwrap();
+            assert_eq!(replayed.state, Sum(28));
+            assert_eq!(replayed.applied, 7);
+            assert_eq!(replayed.resumed, None);
+
+            let replayed = replayer.replay_to(ReplayTarget::Sequence(3)).unwrap();
+            assert_eq!(replayed.state, Sum(6));
+            assert_eq!(
+                replayer.replay_to(ReplayTarget::End).unwrap().state,
+                Sum(55)
+            );
+            fs::remove_dir_all(&dir).ok();
+        }
+
+        #[test]
+        fn snapshots_speed_up_later_replays() {
+            let dir = journal();
+            let mut replayer = StateReplayer::new(&dir, Sum(0), apply).with_snapshots("sum", 3);
+
+            let replayed = replayer.replay_to(ReplayTarget::End).unwrap();
+            assert_eq!(replayed.state, Sum(55));
+            assert_eq!(replayed.snapshots_written, 3);
+
+            let replayed = replayer.replay_to(ReplayTarget::End).unwrap();
+            assert_eq!(replayed.state, Sum(55));
+            assert_eq!((replayed.resumed, replayed.applied), (Some(9), 1));
+            assert_eq!(replayed.snapshots_written, 0);
+
+            // The snapshot after six events covers timestamp 6, past the target.
+            let replayed = replayer.replay_to(ReplayTarget::Timestamp(5)).unwrap();
+            assert_eq!(replayed.state, Sum(15));
+            assert_eq!((replayed.resumed, replayed.applied), (Some(3), 2));
+
+            // A corrupt snapshot is skipped in favour of an older one.
+            let latest = fs::read_dir(&dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .filter(|path| path.extension().is_some_and(|e| e == "snapshot"))
+                .max()
+                .unwrap();
+            let mut bytes = fs::read(&latest).unwrap();
+            bytes[10] ^= 0xFF;
+            fs::write(&latest, bytes).unwrap();
+            let replayed = replayer.replay_to(ReplayTarget::End).unwrap();
+            assert_eq!(replayed.state, Sum(55));
+            assert_eq!(replayed.resumed, Some(6));
+
+            // Snapshots outlive the segments they cover.
+            fs::remove_file(segment_path(&dir, 1)).unwrap();
+            let replayed = replayer.replay_to(ReplayTarget::End).unwrap();
+            assert_eq!(replayed.state, Sum(55));
+
+            assert_eq!(replayer.remove_snapshots().unwrap(), 3);
+            let replayed = replayer.replay_to(ReplayTarget::End).unwrap();
+            assert_eq!(replayed.state, Sum(40));
+            fs::remove_dir_all(&dir).ok();
+        }
+    }
+
+    mod deferred_header {
+        use super::*;
+        use crate::storage::WriterOptions;
+        use std::fs;
+
+        #[test]
+        fn snapshots_exclude_unpublished_events() {
+            let path = temp_path();
+            let mut writer = MmapWriter::create(&path, 64 * 1024).unwrap();
+            writer.write_event(&EventHeader::new(1, 1, 8), b"publishd");
+            writer.set_deferred_header(true);
+            writer.write_event(&EventHeader::new(2, 1, 8), b"deferred");
+
+            let snapshot = MmapReader::open_snapshot(&path).unwrap();
+            assert_eq!(snapshot.iter().count(), 1);
+            assert_eq!(
+                snapshot.mapped_len(),
+                FileHeader::SIZE + EventHeader::SIZE + 8
+            );
+
+            writer.commit();
+            assert_eq!(snapshot.iter().count(), 1);
+            assert_eq!(MmapReader::open_snapshot(&path).unwrap().iter().count(), 2);
+
+            writer.seal().unwrap();
+            let sealed = MmapReader::open_snapshot(&path).unwrap();
+            assert_eq!(sealed.iter().count(), 2);
+            assert_eq!(sealed.quick_verify(), Ok(()));
+            drop(writer);
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn snapshots_reject_files_truncated_below_write_offset() {
+            let path = temp_path();
+            let mut writer = MmapWriter::create(&path, 64 * 1024).unwrap();
+            writer.write_event(&EventHeader::new(1, 1, 8), b"publishd");
+            writer.write_event(&EventHeader::new(2, 1, 8), b"truncate");
+            drop(writer);
+
+            let end = FileHeader::SIZE + 2 * (EventHeader::SIZE + 8);
+            let file = fs::OpenOptions::new().write(true).open(&path).unwrap();
+            file.set_len(end as u64 - 4).unwrap();
+            let err = MmapReader::open_snapshot(&path).err().unwrap();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+            file.set_len(end as u64).unwrap();
+            assert_eq!(MmapReader::open_snapshot(&path).unwrap().iter().count(), 2);
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn snapshots_taken_during_writes_are_consistent() {
+            let path = temp_path();
+            let mut writer = MmapWriter::create(&path, 1024 * 1024).unwrap();
+            writer.write_event(&EventHeader::new(0, 1, 8), &0u64.to_le_bytes());
+
+            let reader_path = path.clone();
+            let reader = std::thread::spawn(move || {
+                for _ in 0..50 {
+                    let snapshot = MmapReader::open_snapshot(&reader_path).unwrap();
+                    let mut count = 0u64;
+                    for event in snapshot.iter() {
+                        assert_eq!(event.payload, &event.header.timestamp.to_le_bytes());
+                        count += 1;
+                    }
+                    assert!(count >= snapshot.event_count());
+                    assert_eq!(snapshot.try_iter().filter(Result::is_err).count(), 0);
+                }
+            });
+            for i in 1..20_000u64 {
+                writer.write_event(&EventHeader::new(i, 1, 8), &i.to_le_bytes());
+            }
+            reader.join().unwrap();
+            drop(writer);
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn write_batch_publishes_once() {
+            let path = temp_path();
+            let mut writer = MmapWriter::create(&path, 4096).unwrap();
+
+            let payloads: Vec<[u8; 8]> = (0..4u64).map(|i| i.to_le_bytes()).collect();
+            let batch: Vec<_> = payloads
+                .iter()
+                .enumerate()
+                .map(|(i, p)| (EventHeader::new(i as u64, 1, 8), &p[..]))
+                .collect();
+
+            assert_eq!(writer.write_batch(&batch), 4);
+            assert_eq!(writer.file_header().event_count, 4);
+            assert_eq!(
+                writer.file_header().write_offset,
+                writer.write_offset() as u64
+            );
+
+            drop(writer);
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn write_batch_stops_when_full() {
+            let path = temp_path();
+            let mut writer = MmapWriter::create(&path, 4096).unwrap();
+
+            let payload = [0u8; 1024];
+            let batch: Vec<_> = (0..5)
+                .map(|i| (EventHeader::new(i, 1, 1024), &payload[..]))
+                .collect();
+
+            assert_eq!(writer.write_batch(&batch), 3);
+            assert_eq!(writer.file_header().event_count, 3);
+
+            drop(writer);
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn header_updates_only_on_commit() {
+            let path = temp_path();
+            let mut writer = MmapWriter::create(&path, 4096).unwrap();
+            writer.set_deferred_header(true);
+
+            for i in 0..3u64 {
+                let header = EventHeader::new(i, 1, 8);
+                writer.write_event(&header, &i.to_le_bytes());
+            }
+
+            assert_eq!(writer.pending_events(), 3);
+            assert_eq!(writer.file_header().event_count, 0);
+            assert_eq!(writer.file_header().write_offset, FileHeader::SIZE as u64);
+
+            writer.sync().unwrap();
+            assert_eq!(writer.pending_events(), 0);
+            assert_eq!(writer.file_header().event_count, 3);
+
+            drop(writer);
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn readers_never_see_unwritten_events() {
+            let path = temp_path();
+            let mut writer = MmapWriter::create(&path, 64 * 1024).unwrap();
+            let done = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+
+            let reader_path = path.clone();
+            let reader_done = done.clone();
+            let reader = std::thread::spawn(move || {
+                let mut checks = 0;
+                while !reader_done.load(std::sync::atomic::Ordering::Acquire) || checks == 0 {
+                    let reader = MmapReader::open(&reader_path).unwrap();
+                    let mut seen = 0;
+                    for event in reader.try_iter() {
+                        let event = event.unwrap();
+                        assert_eq!(event.payload, &(event.header.timestamp + 1).to_le_bytes());
+                        seen += 1;
+                    }
+                    assert!(seen >= reader.event_count());
+                    checks += 1;
+                }
+            });
+
+            for i in 0..1000u64 {
+                let header = EventHeader::new(i, 1, 8);
+                if !writer.write_event(&header, &(i + 1).to_le_bytes()) {
+                    break;
+                }
+            }
+            done.store(true, std::sync::atomic::Ordering::Release);
+            reader.join().unwrap();
+
+            drop(writer);
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn sync_flushes_data_before_header() {
+            let path = temp_path();
+            let mut writer = MmapWriter::create(&path, 4096).unwrap();
+            writer.set_deferred_header(true);
+            writer.write_event(&EventHeader::new(0, 1, 8), &1u64.to_le_bytes());
+
+            let on_disk = fs::read(&path).unwrap();
+            let header = FileHeader::decode(on_disk[..FileHeader::SIZE].try_into().unwrap());
+            assert_eq!(header.write_offset, FileHeader::SIZE as u64);
+            assert_eq!(
+                &on_disk[FileHeader::SIZE + EventHeader::SIZE..][..8],
+                &1u64.to_le_bytes()
+            );
+
+            writer.sync().unwrap();
+            let on_disk = fs::read(&path).unwrap();
+            let header = FileHeader::decode(on_disk[..FileHeader::SIZE].try_into().unwrap());
+            assert_eq!(header.write_offset, writer.write_offset() as u64);
+            assert_eq!(header.event_count, 1);
+
+            drop(writer);
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn open_recovers_uncommitted_events() {
+            let path = temp_path();
+
+            {
+                // Unlocked, so the forgotten writer does not keep a lock a
+                // crash would have released.
+                let options = WriterOptions {
+                    lock: false,
+                    ..Default::default()
+                };
+                let mut writer = MmapWriter::create_with_options(&path, 4096, &options).unwrap();
+                let header = EventHeader::new(0, 1, 8);
+                writer.write_event(&header, &0u64.to_le_bytes());
+
+                writer.set_deferred_header(true);
+                for i in 1..4u64 {
+                    let payload = i.to_le_bytes();
+                    let header = EventHeader::new(i, 1, 8).with_checksum(&payload);
+                    writer.write_event(&header, &payload);
+                }
+
+                // Simulate a crash: the header is never committed.
+                std::mem::forget(writer);
+            }
+
+            assert_eq!(MmapReader::open(&path).unwrap().event_count(), 1);
+
+            {
+                let writer = MmapWriter::open(&path).unwrap();
+                assert_eq!(writer.file_header().event_count, 4);
+            }
+
+            let reader = MmapReader::open(&path).unwrap();
+            assert!(reader.verify().is_ok());
+            assert_eq!(reader.iter().count(), 4);
+
+            fs::remove_file(&path).ok();
+        }
+    }
+
+    mod writer_options {
+        use super::*;
+        use crate::storage::{Advice, Metadata, Prefault, WriterOptions};
+        use std::fs;
+
+        #[test]
+        fn metadata_round_trips() {
+            let path = temp_path();
+            let metadata = Metadata::new("audit").with("region", "eu-west-1");
+
+            {
+                let options = WriterOptions {
+                    metadata: Some(metadata.clone()),
+                    ..Default::default()
+                };
+                let mut writer = MmapWriter::create_with_options(&path, 4096, &options).unwrap();
+                assert_eq!(writer.write_offset() % 8, 0);
+                writer.write_event(&EventHeader::new(1, 1, 8), b"testdata");
+            }
+
+            let reader = MmapReader::open(&path).unwrap();
+            let read = reader.metadata().unwrap();
+            assert_eq!(read, &metadata);
+            assert_eq!(read.pid, Some(std::process::id()));
+            assert_eq!(read.get("region"), Some("eu-west-1"));
+            assert_eq!(reader.iter().count(), 1);
+            assert!(reader.verify().is_ok());
+
+            drop(reader);
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn metadata_is_optional() {
+            let path = temp_path();
+            drop(MmapWriter::create(&path, 4096).unwrap());
+
+            let reader = MmapReader::open(&path).unwrap();
+            assert!(reader.metadata().is_none());
+            assert_eq!(reader.file_header().data_start(), FileHeader::SIZE);
+
+            drop(reader);
+            fs::remove_file(&path).ok();
+        }
+
+        fn check_prefault(prefault: Prefault) {
+            let path = temp_path();
+
+            {
+                let options = WriterOptions {
+                    prefault,
+                    ..Default::default()
+                };
+                let mut writer =
+                    MmapWriter::create_with_options(&path, 64 * 1024, &options).unwrap();
+                writer.advise(Advice::Sequential).unwrap();
+                writer.advise_willneed().unwrap();
+
+                let header = EventHeader::new(1, 1, 8);
+                assert!(writer.write_event(&header, b"testdata"));
+            }
+
+            let reader = MmapReader::open(&path).unwrap();
+            reader.advise(Advice::Random).unwrap();
+            assert!(reader.verify().is_ok());
+            assert_eq!(reader.event_count(), 1);
+
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn populate_prefault() {
+            check_prefault(Prefault::Populate);
+        }
+
+        #[test]
+        fn touch_prefault_preserves_header() {
+            check_prefault(Prefault::Touch);
+        }
+
+        #[test]
+        fn default_mode_is_owner_only() {
+            use std::os::unix::fs::PermissionsExt;
+
+            let path = temp_path();
+            let writer = MmapWriter::create(&path, 4096).unwrap();
+
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+
+            drop(writer);
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn tmpfile_is_linked_after_init() {
+            use std::os::unix::fs::PermissionsExt;
+
+            let path = temp_path();
+            fs::write(&path, b"stale").unwrap();
+
+            let options = WriterOptions {
+                mode: 0o640,
+                tmpfile: true,
+                sync_dir: true,
+                ..Default::default()
+            };
+
+            {
+                let mut writer = MmapWriter::create_with_options(&path, 4096, &options).unwrap();
+                let header = EventHeader::new(1, 1, 8);
+                writer.write_event(&header, b"testdata");
+            }
+
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o640);
+
+            let reader = MmapReader::open(&path).unwrap();
+            assert_eq!(reader.event_count(), 1);
+
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn second_writer_is_rejected() {
+            let path = temp_path();
+            let writer = MmapWriter::create(&path, 4096).unwrap();
+
+            let err = MmapWriter::open(&path).err().unwrap();
+            assert_eq!(err.kind(), std::io::ErrorKind::WouldBlock);
+            assert!(MmapWriter::create(&path, 4096).is_err());
+            assert_eq!(MmapReader::open(&path).unwrap().event_count(), 0);
+
+            // `force` waits for the holder instead of skipping the lock.
+            let release = std::thread::spawn(move || {
+                std::thread::sleep(std::time::Duration::from_millis(50));
+                drop(writer);
+            });
+            let forced = WriterOptions {
+                force: true,
+                ..Default::default()
+            };
+            let writer = MmapWriter::open_with_options(&path, &forced).unwrap();
+            release.join().unwrap();
+            assert!(MmapWriter::open(&path).is_err());
+            drop(writer);
+            assert!(MmapWriter::open(&path).is_ok());
+
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn create_does_not_truncate_locked_file() {
+            let path = temp_path();
+            let mut writer = MmapWriter::create(&path, 4096).unwrap();
+            let header = EventHeader::new(1, 1, 8);
+            writer.write_event(&header, b"testdata");
+
+            assert!(MmapWriter::create(&path, 4096).is_err());
+            assert_eq!(fs::metadata(&path).unwrap().len(), 4096);
+            assert_eq!(writer.file_header().event_count, 1);
+
+            drop(writer);
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn preallocate_allocates_blocks() {
+            use std::os::unix::fs::MetadataExt;
+
+            let path = temp_path();
+            let capacity = 256 * 1024;
+
+            let options = WriterOptions {
+                preallocate: true,
+                ..Default::default()
+            };
+            let writer = MmapWriter::create_with_options(&path, capacity, &options).unwrap();
+
+            let metadata = fs::metadata(&path).unwrap();
+            assert_eq!(metadata.len(), capacity as u64);
+            assert!(metadata.blocks() * 512 >= capacity as u64);
+
+            drop(writer);
+            fs::remove_file(&path).ok();
+        }
+    }
+
+    mod memory_lock {
+        use super::*;
+        use std::fs;
+
+        #[test]
+        fn ring_buffer_lock_round_trip() {
+            let mut ring = RingBuffer::new(4096).unwrap();
+            ring.lock_memory().unwrap();
+            assert!(ring.is_memory_locked());
+
+            let header = EventHeader::new(1, 1, 8);
+            ring.write_event(&header, b"testdata").unwrap();
+            assert!(ring.read_event().is_some());
+
+            ring.unlock_memory().unwrap();
+            assert!(!ring.is_memory_locked());
+        }
+
+        #[test]
+        fn spsc_lock_round_trip() {
+            let ring = SpscRingBuffer::new(4096).unwrap();
+            ring.lock_memory().unwrap();
+            assert!(ring.is_memory_locked());
+            ring.unlock_memory().unwrap();
+            assert!(!ring.is_memory_locked());
+        }
+
+        #[test]
+        fn writer_lock_round_trip() {
+            let path = temp_path();
+            let writer = MmapWriter::create(&path, 4096).unwrap();
+
+            writer.lock_memory().unwrap();
+            writer.unlock_memory().unwrap();
+
+            drop(writer);
+            fs::remove_file(&path).ok();
+        }
+    }
+
+    mod snapshot {
+        use super::*;
+        use std::fs;
+
+        #[test]
+        fn snapshot_copies_committed_events() {
+            let path = temp_path();
+            let snap = temp_path();
+
+            let mut writer = MmapWriter::create(&path, 4096).unwrap();
+            for i in 0..3u64 {
+                let header = EventHeader::new(i, 1, 8);
+                writer.write_event(&header, &i.to_le_bytes());
+            }
+
+            let bytes = writer.snapshot(&snap).unwrap();
+            assert_eq!(bytes, writer.write_offset() as u64);
+
+            let header = EventHeader::new(3, 1, 8);
+            writer.write_event(&header, &3u64.to_le_bytes());
+
+            let reader = MmapReader::open(&snap).unwrap();
+            assert_eq!(reader.event_count(), 3);
+            assert!(reader.verify().is_ok());
+            assert_eq!(fs::metadata(&snap).unwrap().len(), bytes);
+
+            drop(writer);
+            fs::remove_file(&path).ok();
+            fs::remove_file(&snap).ok();
+        }
+
+        #[test]
+        fn snapshot_to_missing_directory_fails() {
+            let path = temp_path();
+            let writer = MmapWriter::create(&path, 4096).unwrap();
+
+            assert!(writer.snapshot("/nonexistent/ringlog/snap.log").is_err());
+
+            drop(writer);
+            fs::remove_file(&path).ok();
+        }
+    }
+
+    mod manifest {
+        use super::*;
+        use crate::storage::{Manifest, list_segments, segment_path};
+        use std::fs;
+
+        fn temp_dir() -> String {
+            let dir = temp_path();
+            fs::create_dir_all(&dir).unwrap();
+            dir
+        }
+
+        #[test]
+        fn list_segments_orders_by_sequence() {
+            let dir = temp_dir();
+            for seq in [3, 1, 2] {
+                fs::write(segment_path(&dir, seq), b"").unwrap();
+            }
+            fs::write(format!("{}/notes.txt", dir), b"").unwrap();
+
+            let seqs: Vec<_> = list_segments(&dir).unwrap().iter().map(|s| s.seq).collect();
+            assert_eq!(seqs, vec![1, 2, 3]);
+
+            fs::remove_dir_all(&dir).ok();
+        }
+
+        #[test]
+        fn manifest_round_trip() {
+            let dir = temp_dir();
+
+            let mut manifest = Manifest::load(&dir).unwrap();
+            assert_eq!(manifest.entries().count(), 0);
+            manifest.entry_mut(1).archived_key = Some("logs/1.log".to_string());
+            manifest.entry_mut(2).cold_path = Some(format!("{}/cold/2.log", dir).into());
+            manifest.save().unwrap();
+
+            let manifest = Manifest::load(&dir).unwrap();
+            assert_eq!(
+                manifest.entry(1).unwrap().archived_key.as_deref(),
+                Some("logs/1.log")
+            );
+            assert_eq!(manifest.entry(2).unwrap().archived_key, None);
+            assert_eq!(
+                manifest.entry(2).unwrap().cold_path,
+                Some(format!("{}/cold/2.log", dir).into())
+            );
+            assert_eq!(manifest.entry(1).unwrap().cold_path, None);
+            assert!(manifest.entry(3).is_none());
+
+            fs::remove_dir_all(&dir).ok();
+        }
+    }
+
+    mod segmented_reader {
+        use super::*;
+        use crate::storage::{SegmentedReader, segment_path};
+        use std::fs;
+
+        fn write_segment(dir: &str, seq: u64, timestamps: std::ops::Range<u64>) {
+            let mut writer = MmapWriter::create(segment_path(dir, seq), 4096).unwrap();
+            for ts in timestamps {
+                let header = EventHeader::new(ts, 1, 8);
+                writer.write_event(&header, &ts.to_le_bytes());
+            }
+            writer.seal().unwrap();
+        }
+
+        #[test]
+        fn iterates_segments_in_sequence_order() {
+            let dir = temp_path();
+            fs::create_dir_all(&dir).unwrap();
+            write_segment(&dir, 10, 6..9);
+            write_segment(&dir, 2, 0..3);
+            write_segment(&dir, 5, 3..6);
+
+            let reader = SegmentedReader::open(&dir).unwrap();
+            assert_eq!(reader.segment_count(), 3);
+            assert_eq!(reader.event_count(), 9);
+
+            let timestamps: Vec<_> = reader.iter().map(|e| e.header.timestamp).collect();
+            assert_eq!(timestamps, (0..9).collect::<Vec<_>>());
+
+            let mut replayed = Vec::new();
+            let count = reader.replay(|e| replayed.push(e.header.timestamp));
+            assert_eq!(count, 9);
+            assert_eq!(replayed, timestamps);
+
+            fs::remove_dir_all(&dir).ok();
+        }
+
+        #[test]
+        fn skips_empty_segments() {
+            let dir = temp_path();
+            fs::create_dir_all(&dir).unwrap();
+            write_segment(&dir, 1, 0..2);
+            write_segment(&dir, 2, 0..0);
+            write_segment(&dir, 3, 2..4);
+
+            let reader = SegmentedReader::open(&dir).unwrap();
+            assert_eq!(reader.iter().count(), 4);
+
+            fs::remove_dir_all(&dir).ok();
+        }
+
+        #[test]
+        fn empty_directory_yields_nothing() {
+            let dir = temp_path();
+            fs::create_dir_all(&dir).unwrap();
+
+            let reader = SegmentedReader::open(&dir).unwrap();
+            assert_eq!(reader.iter().count(), 0);
+
+            fs::remove_dir_all(&dir).ok();
+        }
+    }
+
+    #[cfg(feature = "tiering")]
+    mod tiering {
+        use super::*;
+        use crate::storage::{SegmentedReader, TierPolicy, apply_tiering, segment_path};
+        use std::fs;
+
+        fn write_segment(dir: &str, seq: u64, timestamps: std::ops::Range<u64>, seal: bool) {
+            let mut writer = MmapWriter::create(segment_path(dir, seq), 4096).unwrap();
+            for ts in timestamps {
+                let header = EventHeader::new(ts, 1, 8);
+                writer.write_event(&header, &ts.to_le_bytes());
+            }
+            if seal {
+                writer.seal().unwrap();
+            }
+        }
+
+        fn timestamps(dir: &str) -> Vec<u64> {
+            let reader = SegmentedReader::open(dir).unwrap();
+            reader.iter().map(|e| e.header.timestamp).collect()
+        }
+
+        #[test]
+        fn compresses_old_segments_in_place() {
+            let dir = temp_path();
+            fs::create_dir_all(&dir).unwrap();
+            write_segment(&dir, 1, 0..3, true);
+            write_segment(&dir, 2, 3..6, true);
+            write_segment(&dir, 3, 6..9, true);
+
+            let policy = TierPolicy {
+                hot_segments: 1,
+                ..Default::default()
+            };
+            let report = apply_tiering(&dir, &policy).unwrap();
+            let tiered: Vec<_> = report.tiered.iter().map(|(seq, _)| *seq).collect();
+            assert_eq!(tiered, vec![1, 2]);
+            assert!(!segment_path(&dir, 1).exists());
+            assert!(
+                std::path::Path::new(&format!("{}.zst", segment_path(&dir, 1).display())).exists()
+            );
+            assert!(segment_path(&dir, 3).exists());
+
+            assert_eq!(timestamps(&dir), (0..9).collect::<Vec<_>>());
+
+            // Already-tiered segments are not touched again.
+            let report = apply_tiering(&dir, &policy).unwrap();
+            assert!(report.tiered.is_empty());
+
+            fs::remove_dir_all(&dir).ok();
+        }
+
+        #[test]
+        fn moves_old_segments_to_cold_dir() {
+            let dir = temp_path();
+            let cold = temp_path();
+            fs::create_dir_all(&dir).unwrap();
+            write_segment(&dir, 1, 0..2, true);
+            write_segment(&dir, 2, 2..4, true);
+            write_segment(&dir, 3, 4..6, true);
+            write_segment(&dir, 4, 6..8, false);
+
+            let policy = TierPolicy {
+                hot_segments: 1,
+                compress: false,
+                cold_dir: Some(cold.clone().into()),
+                ..Default::default()
+            };
+            let report = apply_tiering(&dir, &policy).unwrap();
+            assert_eq!(report.tiered.len(), 2);
+            assert_eq!(report.skipped_unsealed, 1);
+            assert!(segment_path(&cold, 1).exists());
+
+            let manifest = crate::storage::Manifest::load(&dir).unwrap();
+            assert_eq!(
+                manifest.entry(2).unwrap().cold_path,
+                Some(segment_path(&cold, 2))
+            );
+
+            assert_eq!(timestamps(&dir), (0..8).collect::<Vec<_>>());
+
+            fs::remove_dir_all(&dir).ok();
+            fs::remove_dir_all(&cold).ok();
+        }
+    }
+
+    mod watcher {
+        use super::*;
+        use crate::storage::{TailReader, WatchEvent, Watcher, segment_path};
+        use std::fs;
+        use std::time::Duration;
+
+        fn check_new_events(mut watcher: Watcher) {
+            let path = temp_path();
+            let mut writer = MmapWriter::create(&path, 4096).unwrap();
+            watcher.watch(&path).unwrap();
+
+            let idle = watcher.wait(Some(Duration::from_millis(20))).unwrap();
+            assert!(idle.is_empty());
+
+            let header = EventHeader::new(0, 1, 8);
+            writer.write_event(&header, b"testdata");
+
+            let events = watcher.wait(Some(Duration::from_secs(5))).unwrap();
+            assert_eq!(
+                events,
+                vec![WatchEvent::NewEvents {
+                    path: path.clone().into(),
+                    write_offset: writer.write_offset() as u64,
+                }]
+            );
+
+            drop(writer);
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn detects_new_events_with_inotify() {
+            check_new_events(Watcher::new());
+        }
+
+        #[test]
+        fn detects_new_events_by_polling() {
+            check_new_events(Watcher::polling(Duration::from_millis(5)));
+        }
+
+        #[test]
+        fn detects_new_segments() {
+            let dir = temp_path();
+            fs::create_dir_all(&dir).unwrap();
+            MmapWriter::create(segment_path(&dir, 1), 4096).unwrap();
+
+            let mut watcher = Watcher::new();
+            watcher.watch(&dir).unwrap();
+
+            let handle = {
+                let dir = dir.clone();
+                std::thread::spawn(move || {
+                    std::thread::sleep(Duration::from_millis(20));
+                    MmapWriter::create(segment_path(&dir, 2), 4096).unwrap();
+                })
+            };
+
+            let events = watcher.wait(Some(Duration::from_secs(5))).unwrap();
+            handle.join().unwrap();
+            assert_eq!(
+                events,
+                vec![WatchEvent::NewSegment {
+                    path: segment_path(&dir, 2)
+                }]
+            );
+
+            fs::remove_dir_all(&dir).ok();
+        }
+
+        #[test]
+        fn tail_reader_hands_out_each_event_once() {
+            let path = temp_path();
+            let mut writer = MmapWriter::create(&path, 4096).unwrap();
+            writer.write_event(&EventHeader::new(1, 1, 0), &[]);
+            writer.write_event(&EventHeader::new(2, 1, 0), &[]);
+
+            let mut from_start = TailReader::open(&path).unwrap();
+            let mut at_end = TailReader::open_at_end(&path).unwrap();
+            assert_eq!(at_end.position(), 2);
+
+            let mut seen = Vec::new();
+            let read = from_start.read_new(|e| {
+                seen.push(e.header.timestamp);
+                Ok(())
+            });
+            assert_eq!(read.unwrap(), 2);
+            assert_eq!(from_start.read_new(|_| Ok(())).unwrap(), 0);
+
+            writer.write_event(&EventHeader::new(3, 1, 0), &[]);
+            assert!(at_end.wait(Duration::from_secs(5)).unwrap());
+
+            // A failed callback leaves the event to be handed out again.
+            let failed = at_end.read_new(|_| Err(std::io::Error::other("busy")));
+            assert!(failed.is_err());
+            at_end
+                .read_new(|e| {
+                    seen.push(e.header.timestamp);
+                    Ok(())
+                })
+                .unwrap();
+            assert_eq!(seen, vec![1, 2, 3]);
+            assert!(!at_end.is_sealed());
+
+            writer.seal().unwrap();
+            assert_eq!(at_end.read_new(|_| Ok(())).unwrap(), 0);
+            assert!(at_end.is_sealed());
+
+            drop(writer);
+            fs::remove_file(&path).ok();
+        }
+    }
+
+    mod replication {
+        use super::*;
+        use crate::replication::{Follower, Leader};
+        use std::fs;
+        use std::net::TcpListener;
+        use std::thread;
+        use std::time::Duration;
+
+        fn write_events(writer: &mut MmapWriter, timestamps: std::ops::Range<u64>) {
+            for ts in timestamps {
+                let header = EventHeader::new(ts, 1, 8);
+                writer.write_event(&header, &ts.to_le_bytes());
+            }
+        }
+
+        /// Serves a single follower connection on a background thread.
+        fn spawn_leader(path: &str) -> (std::net::SocketAddr, thread::JoinHandle<u64>) {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+            let leader = Leader::new(path).with_heartbeat_interval(Duration::from_millis(20));
+            let handle = thread::spawn(move || {
+                let (stream, _) = listener.accept().unwrap();
+                leader.serve_follower(stream).unwrap()
+            });
+            (addr, handle)
+        }
+
+        fn timestamps(path: &str) -> Vec<u64> {
+            let reader = MmapReader::open(path).unwrap();
+            reader.iter().map(|e| e.header.timestamp).collect()
+        }
+
+        #[test]
+        fn follower_tails_live_log_until_sealed() {
+            let leader_path = temp_path();
+            let follower_path = temp_path();
+            let mut writer = MmapWriter::create(&leader_path, 4096).unwrap();
+            write_events(&mut writer, 0..5);
+
+            let (addr, leader) = spawn_leader(&leader_path);
+            let mut follower = Follower::new(MmapWriter::create(&follower_path, 4096).unwrap());
+            let sync = thread::spawn(move || {
+                let received = follower.sync(addr).unwrap();
+                (received, follower.into_writer())
+            });
+
+            thread::sleep(Duration::from_millis(50));
+            write_events(&mut writer, 5..10);
+            writer.seal().unwrap();
+
+            let (received, copy) = sync.join().unwrap();
+            assert_eq!(received, 10);
+            assert_eq!(leader.join().unwrap(), 10);
+            assert!(copy.is_sealed());
+            drop(copy);
+
+            assert_eq!(timestamps(&follower_path), (0..10).collect::<Vec<_>>());
+            let payloads: Vec<_> = MmapReader::open(&follower_path)
+                .unwrap()
+                .iter()
+                .map(|e| e.payload.to_vec())
+                .collect();
+            assert_eq!(payloads[7], 7u64.to_le_bytes());
+
+            drop(writer);
+            fs::remove_file(&leader_path).ok();
+            fs::remove_file(&follower_path).ok();
+        }
+
+        #[test]
+        fn follower_resumes_after_events_it_holds() {
+            let leader_path = temp_path();
+            let follower_path = temp_path();
+            let mut writer = MmapWriter::create(&leader_path, 4096).unwrap();
+            write_events(&mut writer, 0..6);
+            writer.seal().unwrap();
+
+            let mut copy = MmapWriter::create(&follower_path, 4096).unwrap();
+            write_events(&mut copy, 0..3);
+
+            let (addr, leader) = spawn_leader(&leader_path);
+            let mut follower = Follower::new(copy);
+            assert_eq!(follower.position(), 3);
+            assert_eq!(follower.sync(addr).unwrap(), 3);
+            assert_eq!(leader.join().unwrap(), 3);
+            assert_eq!(follower.position(), 6);
+            drop(follower);
+
+            assert_eq!(timestamps(&follower_path), (0..6).collect::<Vec<_>>());
+
+            fs::remove_file(&leader_path).ok();
+            fs::remove_file(&follower_path).ok();
+        }
+
+        #[test]
+        fn follower_ahead_of_leader_is_rejected() {
+            let leader_path = temp_path();
+            let follower_path = temp_path();
+            let mut writer = MmapWriter::create(&leader_path, 4096).unwrap();
+            write_events(&mut writer, 0..2);
+
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+            let leader = Leader::new(&leader_path);
+            let handle = thread::spawn(move || {
+                let (stream, _) = listener.accept().unwrap();
+                leader.serve_follower(stream)
+            });
+
+            let mut copy = MmapWriter::create(&follower_path, 4096).unwrap();
+            write_events(&mut copy, 0..4);
+            let mut follower = Follower::new(copy);
+            let err = follower.sync(addr).unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+            assert!(handle.join().unwrap().is_err());
+
+            drop(writer);
+            fs::remove_file(&leader_path).ok();
+            fs::remove_file(&follower_path).ok();
+        }
+    }
+
+    #[cfg(feature = "grpc")]
+    mod grpc {
+        use super::*;
+        use crate::grpc::{Event, Journal, JournalClient, JournalServer, ReplayRequest};
+        use std::fs;
+        use std::sync::{Arc, Mutex};
+        use tokio_stream::wrappers::TcpListenerStream;
+
+        fn start_server(
+            runtime: &tokio::runtime::Runtime,
+            journal: Journal,
+        ) -> std::net::SocketAddr {
+            runtime.block_on(async {
+                let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+                let addr = listener.local_addr().unwrap();
+                tokio::spawn(
+                    tonic::transport::Server::builder()
+                        .add_service(JournalServer::new(journal))
+                        .serve_with_incoming(TcpListenerStream::new(listener)),
+                );
+                addr
+            })
+        }
+
+        fn event(timestamp: u64, event_type: u32) -> Event {
+            Event {
+                timestamp,
+                event_type,
+                stream: 0,
+                payload: timestamp.to_le_bytes().to_vec(),
+            }
+        }
+
+        #[test]
+        fn append_replay_and_stats() {
+            let path = temp_path();
+            let mut writer = MmapWriter::create(&path, 4096).unwrap();
+            for ts in 10..15 {
+                let header = EventHeader::new(ts, (ts % 2) as u8, 8);
+                writer.write_event(&header, &ts.to_le_bytes());
+            }
+
+            let ring = Arc::new(Mutex::new(RingBuffer::new(4096).unwrap()));
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+            let addr = start_server(&runtime, Journal::new(ring.clone(), &path));
+
+            runtime.block_on(async {
+                let mut client = JournalClient::connect(format!("http://{}", addr))
+                    .await
+                    .unwrap();
+
+                let events = tokio_stream::iter(vec![event(1, 7), event(2, 7), event(3, 8)]);
+                let appended = client.append(events).await.unwrap().into_inner();
+                assert_eq!(appended.appended, 3);
+                assert_eq!(appended.dropped, 0);
+
+                let request = ReplayRequest {
+                    start_timestamp: 11,
+                    end_timestamp: 14,
+                    event_type: None,
+                };
+                let mut stream = client.replay(request).await.unwrap().into_inner();
+                let mut replayed = Vec::new();
+                while let Some(event) = stream.message().await.unwrap() {
+                    replayed.push(event.timestamp);
+                }
+                assert_eq!(replayed, vec![11, 12, 13]);
+
+                let request = ReplayRequest {
+                    event_type: Some(0),
+                    ..Default::default()
+                };
+                let mut stream = client.replay(request).await.unwrap().into_inner();
+                let mut replayed = Vec::new();
+                while let Some(event) = stream.message().await.unwrap() {
+                    assert_eq!(event.payload, event.timestamp.to_le_bytes());
+                    replayed.push(event.timestamp);
+                }
+                assert_eq!(replayed, vec![10, 12, 14]);
+
+                let stats = client.stats().await.unwrap().into_inner();
+                assert_eq!(stats.log_events, 5);
+                assert_eq!(stats.events_attempted, 3);
+                assert!(stats.ring_used > 0);
+                assert!(!stats.log_sealed);
+            });
+
+            let mut ring = ring.lock().unwrap();
+            let types: Vec<_> = std::iter::from_fn(|| ring.read_event())
+                .map(|(h, _)| h.event_type)
+                .collect();
+            assert_eq!(types, vec![7, 7, 8]);
+
+            drop(writer);
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn append_rejects_invalid_events() {
+            let path = temp_path();
+            let writer = MmapWriter::create(&path, 4096).unwrap();
+            let ring = Arc::new(Mutex::new(RingBuffer::new(4096).unwrap()));
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+            let addr = start_server(&runtime, Journal::new(ring.clone(), &path));
+
+            runtime.block_on(async {
+                let mut client = JournalClient::connect(format!("http://{}", addr))
+                    .await
+                    .unwrap();
+                for event_type in [300, EventHeader::TYPE_MARKER as u32] {
+                    let events = tokio_stream::iter(vec![event(1, event_type)]);
+                    let status = client.append(events).await.unwrap_err();
+                    assert_eq!(status.code(), tonic::Code::InvalidArgument);
+                }
+            });
+            assert!(ring.lock().unwrap().is_empty());
+
+            drop(writer);
+            fs::remove_file(&path).ok();
+        }
+    }
+
+    #[cfg(feature = "websocket")]
+    mod websocket {
+        use super::*;
+        use crate::websocket::LiveFeed;
+        use std::fs;
+        use std::net::{TcpListener, TcpStream};
+        use std::thread;
+        use std::time::Duration;
+        use tungstenite::Message;
+
+        #[test]
+        fn streams_new_events_filtered_by_type() {
+            let path = temp_path();
+            let mut writer = MmapWriter::create(&path, 4096).unwrap();
+            writer.write_event(&EventHeader::new(1, 2, 1), &[0xff]);
+
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+            let feed = LiveFeed::new(&path).with_heartbeat_interval(Duration::from_millis(20));
+            let server = thread::spawn(move || {
+                let (stream, _) = listener.accept().unwrap();
+                feed.serve_client(stream).unwrap()
+            });
+
+            let stream = TcpStream::connect(addr).unwrap();
+            let url = format!("ws://{}/?types=2", addr);
+            let (mut socket, _) = tungstenite::client(url, stream).unwrap();
+            thread::sleep(Duration::from_millis(50));
+
+            for ts in 10..16 {
+                let header = EventHeader::new(ts, (ts % 2 + 1) as u8, 2);
+                writer.write_event(&header, &[0x0a, ts as u8]);
+            }
+            writer.seal().unwrap();
+
+            let mut events = Vec::new();
+            loop {
+                match socket.read() {
+                    Ok(Message::Text(text)) => {
+                        events.push(serde_json::from_str::<serde_json::Value>(&text).unwrap())
+                    }
+                    Ok(Message::Close(_)) | Err(_) => break,
+                    Ok(_) => {}
+                }
+            }
+
+            assert_eq!(server.join().unwrap(), 3);
+            let timestamps: Vec<_> = events
+                .iter()
+                .map(|e| e["timestamp"].as_u64().unwrap())
+                .collect();
+            assert_eq!(timestamps, vec![11, 13, 15]);
+            assert_eq!(events[0]["event_type"], 2);
+            assert_eq!(events[0]["payload"], "0a0b");
+
+            drop(writer);
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn busy_feeds_still_see_client_close() {
+            use std::sync::Arc;
+            use std::sync::atomic::{AtomicBool, Ordering};
+
+            let path = temp_path();
+            let mut writer = MmapWriter::create(&path, 1 << 20).unwrap();
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+            // Never idle long enough to ping, so only per-batch reads see
+            // the close.
+            let feed = LiveFeed::new(&path).with_heartbeat_interval(Duration::from_secs(60));
+            let server = thread::spawn(move || {
+                let (stream, _) = listener.accept().unwrap();
+                feed.serve_client(stream).unwrap()
+            });
+
+            let stream = TcpStream::connect(addr).unwrap();
+            let (mut socket, _) = tungstenite::client(format!("ws://{}/", addr), stream).unwrap();
+            thread::sleep(Duration::from_millis(50));
+
+            let stop = Arc::new(AtomicBool::new(false));
+            let producer = {
+                let stop = stop.clone();
+                thread::spawn(move || {
+                    let mut ts = 0;
+                    while !stop.load(Ordering::Relaxed)
+                        && writer.write_event(&EventHeader::new(ts, 1, 1), &[0])
+                    {
+                        ts += 1;
+                        thread::sleep(Duration::from_millis(1));
+                    }
+                })
+            };
+
+            while !matches!(socket.read().unwrap(), Message::Text(_)) {}
+            socket.close(None).unwrap();
+            while socket.read().is_ok() {}
+
+            let sent = server.join().unwrap();
+            assert!(sent > 0);
+            assert!(!producer.is_finished());
+            stop.store(true, Ordering::Relaxed);
+            producer.join().unwrap();
+            fs::remove_file(&path).ok();
+        }
+    }
+
+    #[cfg(feature = "archive")]
+    mod archive {
+        use super::*;
+        use crate::archive::s3::{amz_date, hex, sign};
+        use crate::archive::{ArchiveConfig, Archiver, ObjectStore};
+        use crate::storage::{Manifest, segment_path};
+        use sha2::{Digest, Sha256};
+        use std::collections::HashMap;
+        use std::fs;
+        use std::io;
+        use std::time::Duration;
+
+        #[derive(Default)]
+        struct MemoryStore {
+            objects: HashMap<String, Vec<u8>>,
+            uploads: HashMap<String, Vec<(u32, Vec<u8>)>>,
+            failures_left: u32,
+        }
+
+        impl MemoryStore {
+            fn maybe_fail(&mut self) -> io::Result<()> {
+                if self.failures_left > 0 {
+                    self.failures_left -= 1;
+                    return Err(io::Error::other("injected failure"));
+                }
+                Ok(())
+            }
+        }
+
+        impl ObjectStore for MemoryStore {
+            fn put_object(&mut self, key: &str, data: &[u8]) -> io::Result<()> {
+                self.maybe_fail()?;
+                self.objects.insert(key.to_string(), data.to_vec());
+                Ok(())
+            }
+
+            fn create_multipart_upload(&mut self, key: &str) -> io::Result<String> {
+                self.uploads.insert(key.to_string(), Vec::new());
+                Ok(key.to_string())
+            }
+
+            fn upload_part(
+                &mut self,
+                _key: &str,
+                upload_id: &str,
+                part_number: u32,
+                data: &[u8],
+            ) -> io::Result<String> {
+                self.maybe_fail()?;
+                let parts = self.uploads.get_mut(upload_id).unwrap();
+                parts.push((part_number, data.to_vec()));
+                Ok(format!("etag-{}", part_number))
+            }
+
+            fn complete_multipart_upload(
+                &mut self,
+                key: &str,
+                upload_id: &str,
+                parts: &[(u32, String)],
+            ) -> io::Result<()> {
+                let mut uploaded = self.uploads.remove(upload_id).unwrap();
+                uploaded.sort_by_key(|(n, _)| *n);
+                assert_eq!(uploaded.len(), parts.len());
+                let data = uploaded.into_iter().flat_map(|(_, d)| d).collect();
+                self.objects.insert(key.to_string(), data);
+                Ok(())
+            }
+
+            fn abort_multipart_upload(&mut self, _key: &str, upload_id: &str) -> io::Result<()> {
+                self.uploads.remove(upload_id);
+                Ok(())
+            }
+        }
+
+        fn config(part_size: usize) -> ArchiveConfig {
+            ArchiveConfig {
+                prefix: "ringlog/".to_string(),
+                part_size,
+                max_retries: 2,
+                retry_backoff: Duration::from_millis(1),
+            }
+        }
+
+        fn write_segment(dir: &str, seq: u64, seal: bool) {
+            let mut writer = MmapWriter::create(segment_path(dir, seq), 4096).unwrap();
+            for i in 0..4u64 {
+                let header = EventHeader::new(i, 1, 8);
+                writer.write_event(&header, &i.to_le_bytes());
+            }
+            if seal {
+                writer.seal().unwrap();
+            }
+        }
+
+        #[test]
+        fn archives_sealed_segments_once() {
+            let dir = temp_path();
+            fs::create_dir_all(&dir).unwrap();
+            write_segment(&dir, 1, true);
+            write_segment(&dir, 2, false);
+
+            let mut archiver = Archiver::new(MemoryStore::default(), config(32));
+
+            let report = archiver.archive_dir(&dir).unwrap();
+            assert_eq!(
+                report.archived,
+                vec![(1, "ringlog/00000000000000000001.log".to_string())]
+            );
+            assert_eq!(report.skipped_unsealed, 1);
+
+            let stored = &archiver.store().objects["ringlog/00000000000000000001.log"];
+            assert_eq!(stored, &fs::read(segment_path(&dir, 1)).unwrap());
+
+            let manifest = Manifest::load(&dir).unwrap();
+            assert!(manifest.entry(1).unwrap().archived_key.is_some());
+
+            let report = archiver.archive_dir(&dir).unwrap();
+            assert!(report.archived.is_empty());
+            assert_eq!(report.already_archived, 1);
+
+            fs::remove_dir_all(&dir).ok();
+        }
+
+        #[test]
+        fn upload_retries_transient_failures() {
+            let store = MemoryStore {
+                failures_left: 2,
+                ..Default::default()
+            };
+            let mut archiver = Archiver::new(store, config(1024));
+
+            archiver.upload("key", b"payload").unwrap();
+            assert_eq!(archiver.store().objects["key"], b"payload");
+        }
+
+        #[test]
+        fn upload_gives_up_after_max_retries() {
+            let store = MemoryStore {
+                failures_left: 3,
+                ..Default::default()
+            };
+            let mut archiver = Archiver::new(store, config(4));
+
+            assert!(archiver.upload("key", b"payload").is_err());
+            assert!(archiver.store().uploads.is_empty());
+        }
+
+        #[test]
+        fn backoff_saturates_past_32_retries() {
+            let store = MemoryStore {
+                failures_left: 40,
+                ..Default::default()
+            };
+            let mut archiver = Archiver::new(
+                store,
+                ArchiveConfig {
+                    max_retries: 40,
+                    retry_backoff: Duration::ZERO,
+                    ..config(1024)
+                },
+            );
+
+            archiver.upload("key", b"payload").unwrap();
+            assert_eq!(archiver.store().objects["key"], b"payload");
+        }
+
+        #[test]
+        fn signature_matches_aws_example() {
+            let empty_hash = hex(&Sha256::digest(b""));
+            let headers = [
+                ("host", "examplebucket.s3.amazonaws.com"),
+                ("range", "bytes=0-9"),
+                ("x-amz-content-sha256", empty_hash.as_str()),
+                ("x-amz-date", "20130524T000000Z"),
+            ];
+
+            let signature = sign(
+                "wJalrXUtnFEMI/K7MDENG/bPxRfiCYEXAMPLEKEY",
+                "us-east-1",
+                "20130524T000000Z",
+                "GET",
+                "/test.txt",
+                "",
+                &headers,
+                &empty_hash,
+            );
+
+            assert_eq!(
+                signature,
+                "f0e8bdb87c964420e857bd35b5d6ed310bd44f0170aba48dd91039c6036bdb41"
+            );
+        }
+
+        #[test]
+        fn amz_date_formats_utc() {
+            assert_eq!(amz_date(1_369_353_600), "20130524T000000Z");
+            assert_eq!(amz_date(951_782_400 + 3_723), "20000229T010203Z");
+        }
+    }
+
+    mod query {
+        use super::*;
+        use crate::event::{EventSink, FieldWriter, StreamId};
+        use crate::query::Query;
+
+        fn log() -> (String, MmapReader) {
+            let path = temp_path();
+            let mut writer = MmapWriter::create(&path, 4096).unwrap();
+            writer.write_event(&EventHeader::new(100, 3, 4), b"tiny");
+            writer.write_event(&EventHeader::new(200, 3, 200), &[0u8; 200]);
+            let stream = StreamId::new(2).unwrap();
+            writer.write_event(&EventHeader::new(300, 7, 4).with_stream(stream), b"data");
+            writer
+                .event(5)
+                .sequence(42)
+                .payload(b"seq")
+                .write()
+                .unwrap();
+            for user in ["alice", "bob"] {
+                let mut fields = FieldWriter::new();
+                fields.str("user", user).u64("status", 500);
+                writer
+                    .event(9)
+                    .payload(&fields.finish().unwrap())
+                    .write()
+                    .unwrap();
+            }
+            drop(writer);
+            let reader = MmapReader::open(&path).unwrap();
+            (path, reader)
+        }
+
+        fn types(query: &str, reader: &MmapReader) -> Vec<u8> {
+            let query = Query::parse(query).unwrap();
+            query
+                .filter(reader)
+                .map(|event| event.header.event_type)
+                .collect()
+        }
+
+        #[test]
+        fn header_fields_and_operators() {
+            let (path, reader) = log();
+            assert_eq!(types("type == 3 && payload.len > 100", &reader), vec![3]);
+            assert_eq!(types("type == 3 && ts >= 100", &reader), vec![3, 3]);
+            assert_eq!(types("stream == 2 || type == 0x05", &reader), vec![7, 5]);
+            assert_eq!(types("seq == 42", &reader), vec![5]);
+            assert_eq!(types("!(type == 3 || type == 9)", &reader), vec![7, 5]);
+            assert_eq!(types("type != 9 && !(ts < 300)", &reader).len(), 2);
+            std::fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn payload_fields() {
+            let (path, reader) = log();
+            let query = Query::parse(r#"payload.user == "alice""#).unwrap();
+            let matched: Vec<_> = query.filter(&reader).collect();
+            assert_eq!(matched.len(), 1);
+            assert_eq!(matched[0].header.event_type, 9);
+
+            assert_eq!(types("payload.status >= 500", &reader), vec![9, 9]);
+            // Missing fields and mismatched kinds never match, negated or not.
+            assert!(types(r#"payload.status == "500""#, &reader).is_empty());
+            assert!(types("payload.user != 1", &reader).is_empty());
+            std::fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn errors_point_at_the_offending_token() {
+            let error = "type == 3 &&".parse::<Query>().unwrap_err();
+            assert_eq!(error.offset, 12);
+            assert!(error.message.contains("expected a field"));
+
+            let error = Query::parse("size > 3").unwrap_err();
+            assert_eq!(error.offset, 0);
+            assert!(error.message.contains("unknown field"));
+
+            assert_eq!(Query::parse(r#"ts == "now""#).unwrap_err().offset, 0);
+            assert_eq!(Query::parse("(type == 3").unwrap_err().offset, 10);
+            assert_eq!(Query::parse("type == 3 )").unwrap_err().offset, 10);
+            assert_eq!(Query::parse("type = 3").unwrap_err().offset, 5);
+            assert_eq!(
+                Query::parse(r#"payload.user == "ali"#).unwrap_err().offset,
+                16
+            );
+            assert_eq!(Query::parse("type == 0xZZ").unwrap_err().offset, 8);
+        }
+    }
+
+    mod analysis {
+        use super::*;
+        use crate::analysis::{Counts, sliding_windows, windows};
+        use std::time::Duration;
+
+        fn log(events: &[(u64, u8)]) -> (String, MmapReader) {
+            let path = temp_path();
+            let mut writer = MmapWriter::create(&path, 4096).unwrap();
+            for &(timestamp, event_type) in events {
+                writer.write_event(&EventHeader::new(timestamp, event_type, 2), b"hi");
+            }
+            drop(writer);
+            let reader = MmapReader::open(&path).unwrap();
+            (path, reader)
+        }
+
+        #[test]
+        fn tumbling_windows_skip_gaps() {
+            let (path, reader) = log(&[(3, 1), (7, 2), (9, 1), (12, 1), (45, 3)]);
+            let summary: Vec<_> = windows(&reader, Duration::from_nanos(10))
+                .map(|w| (w.start, w.end, w.total.events))
+                .collect();
+            assert_eq!(summary, vec![(0, 10, 3), (10, 20, 1), (40, 50, 1)]);
+
+            let first = windows(&reader, Duration::from_nanos(10)).next().unwrap();
+            let size = EventHeader::SIZE as u64 + 2;
+            assert_eq!(first.total.bytes, 3 * size);
+            assert_eq!(
+                first.by_type[&1],
+                Counts {
+                    events: 2,
+                    bytes: 2 * size
+                }
+            );
+            assert_eq!(first.by_type[&2].events, 1);
+            std::fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn sliding_windows_overlap() {
+            let (path, reader) = log(&[(1, 1), (6, 1), (11, 1), (16, 1)]);
+            let summary: Vec<_> =
+                sliding_windows(&reader, Duration::from_nanos(10), Duration::from_nanos(5))
+                    .map(|w| (w.start, w.total.events))
+                    .collect();
+            assert_eq!(summary, vec![(0, 2), (5, 2), (10, 2), (15, 1)]);
+            std::fs::remove_file(&path).ok();
+        }
+    }
+
+    mod repair {
+        use super::*;
+        use crate::storage::repair;
+        use std::fs;
+
+        fn write_file(path: &str, count: u64) {
+            let mut writer = MmapWriter::create(path, 4096).unwrap();
+            for i in 1..=count {
+                let payload = i.to_le_bytes();
+                let header = EventHeader::new(i, 1, 8).with_checksum(&payload);
+                writer.write_event(&header, &payload);
+            }
+            writer.sync().unwrap();
+        }
+
+        #[test]
+        fn truncates_corrupt_tail() {
+            let path = temp_path();
+            write_file(&path, 3);
+
+            let mut bytes = fs::read(&path).unwrap();
+            let last = FileHeader::SIZE + 2 * (EventHeader::SIZE + 8);
+            bytes[last + EventHeader::SIZE] ^= 0xFF;
+            fs::write(&path, &bytes).unwrap();
+
+            let report = repair(&path, true).unwrap();
+            assert!(report.changed());
+            assert_eq!(report.previous_event_count, 3);
+            assert_eq!(report.event_count, 2);
+            assert_eq!(report.write_offset, last as u64);
+
+            let backup = report.backup_path.unwrap();
+            assert_eq!(fs::read(&backup).unwrap(), bytes);
+
+            let reader = MmapReader::open(&path).unwrap();
+            assert!(reader.verify().is_ok());
+            assert_eq!(reader.iter().count(), 2);
+
+            fs::remove_file(&path).ok();
+            fs::remove_file(&backup).ok();
+        }
+
+        #[test]
+        fn recovers_stale_header() {
+            let path = temp_path();
+            write_file(&path, 4);
+
+            let mut bytes = fs::read(&path).unwrap();
+            bytes[16..24].copy_from_slice(&1u64.to_le_bytes());
+            bytes[24..32].copy_from_slice(&(FileHeader::SIZE as u64).to_le_bytes());
+            fs::write(&path, &bytes).unwrap();
+
+            let report = repair(&path, false).unwrap();
+            assert_eq!(report.event_count, 4);
+            assert!(report.backup_path.is_none());
+
+            {
+                let mut writer = MmapWriter::open(&path).unwrap();
+                let header = EventHeader::new(5, 1, 8);
+                writer.write_event(&header, &5u64.to_le_bytes());
+            }
+
+            let reader = MmapReader::open(&path).unwrap();
+            assert!(reader.verify().is_ok());
+            assert_eq!(reader.event_count(), 5);
+
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn healthy_file_is_unchanged() {
+            let path = temp_path();
+            write_file(&path, 2);
+
+            let report = repair(&path, false).unwrap();
+            assert!(!report.changed());
+
+            fs::remove_file(&path).ok();
+        }
+    }
+
+    mod mmap_storage {
+        use super::*;
+        use crate::event::{EventSink, StreamId};
+        use crate::storage::{ReaderOptions, ReplayIssue};
+        use std::fs;
+
+        #[test]
+        fn reads_unaligned_events() {
+            let path = temp_path();
+            let payloads: [&[u8]; 3] = [b"abc", b"hello", b"x"];
+
+            {
+                let mut writer = MmapWriter::create(&path, 4096).unwrap();
+                for (i, payload) in payloads.iter().enumerate() {
+                    let header = EventHeader::new(i as u64, 1, payload.len() as u16);
+                    assert!(writer.write_event(&header, payload));
+                }
+            }
+
+            let reader = MmapReader::open(&path).unwrap();
+            let read: Vec<_> = reader.iter().map(|e| e.payload.to_vec()).collect();
+            assert_eq!(read, payloads.map(|p| p.to_vec()));
+            assert_eq!(reader.iter_rev().next().unwrap().header.timestamp, 2);
+
+            drop(reader);
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn create_and_write() {
+            let path = temp_path();
+
+            {
+                let mut writer = MmapWriter::create(&path, 4096).unwrap();
+
+                for i in 0..5 {
+                    let header = EventHeader::new(i * 1000, 1, 8);
+                    let payload = i.to_le_bytes();
+                    assert!(writer.write_event(&header, &payload));
+                }
+
+                let fh = writer.file_header();
+                assert_eq!(fh.event_count, 5);
+            }
+
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn failed_writes_say_why() {
+            use crate::storage::StorageError;
+
+            let path = temp_path();
+            let mut writer = MmapWriter::create(&path, 4096).unwrap();
+
+            let mismatched = writer.try_write_event(&EventHeader::new(0, 1, 8), b"abc");
+            assert!(matches!(
+                mismatched,
+                Err(StorageError::InvalidHeader { .. })
+            ));
+
+            let header = EventHeader::new(1, 1, 1000);
+            let payload = [0u8; 1000];
+            let full = loop {
+                let available = writer.available();
+                if let Err(e) = writer.try_write_event(&header, &payload) {
+                    assert!(available < header.total_size());
+                    break e;
+                }
+            };
+            let available = writer.available();
+            assert!(matches!(
+                full,
+                StorageError::Full { needed, available: a }
+                    if needed == header.total_size() && a == available
+            ));
+            assert!(!writer.write_event(&header, &payload));
+            assert!(matches!(
+                writer.event(1).payload(&payload).write(),
+                Err(crate::Error::Storage(StorageError::Full { .. }))
+            ));
+
+            writer.seal().unwrap();
+            let sealed = writer.try_write_event(&EventHeader::new(2, 1, 0), b"");
+            assert!(matches!(sealed, Err(StorageError::Sealed)));
+            assert!(matches!(
+                writer.event(1).write(),
+                Err(crate::Error::Storage(StorageError::Sealed))
+            ));
+
+            let error: crate::Error = full.into();
+            assert!(matches!(
+                error,
+                crate::Error::Storage(StorageError::Full { .. })
+            ));
+            assert!(
+                error
+                    .to_string()
+                    .starts_with("Not enough space in log file")
+            );
+            let error: crate::Error = RingError::PayloadTooLarge {
+                payload_len: 70_000,
+                max_len: 65_535,
+            }
+            .into();
+            assert!(matches!(error, crate::Error::Ring(_)));
+
+            drop(writer);
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn write_and_read_back() {
+            let path = temp_path();
+
+            {
+                let mut writer = MmapWriter::create(&path, 4096).unwrap();
+
+                for i in 0..10u64 {
+                    let header = EventHeader::new(i * 1000, 1, 8);
+                    let payload = i.to_le_bytes();
+                    writer.write_event(&header, &payload);
+                }
+
+                writer.sync().unwrap();
+            }
+
+            {
+                let reader = MmapReader::open(&path).unwrap();
+                assert_eq!(reader.event_count(), 10);
+
+                let mut sum = 0u64;
+                let count = reader.replay(|event| {
+                    let val = u64::from_le_bytes(event.payload.try_into().unwrap());
+                    sum += val;
+                });
+
+                assert_eq!(count, 10);
+                assert_eq!(sum, 45);
+            }
+
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn iterator_works() {
+            let path = temp_path();
+
+            {
+                let mut writer = MmapWriter::create(&path, 4096).unwrap();
+
+                for i in 0..5u64 {
+                    let header = EventHeader::new(i, 1, 8);
+                    writer.write_event(&header, &i.to_le_bytes());
+                }
+
+                writer.sync().unwrap();
+            }
+
+            {
+                let reader = MmapReader::open(&path).unwrap();
+                let events: Vec<_> = reader.iter().collect();
+
+                assert_eq!(events.len(), 5);
+                for (i, event) in events.iter().enumerate() {
+                    assert_eq!(event.header.timestamp, i as u64);
+                }
+            }
+
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn replay_to_channel_streams_all_events() {
+            let path = temp_path();
+            let mut writer = MmapWriter::create(&path, 4096).unwrap();
+            for i in 0..20u64 {
+                writer.write_event(&EventHeader::new(i, 1, 8), &i.to_le_bytes());
+            }
+            drop(writer);
+
+            let rx = MmapReader::open(&path).unwrap().replay_to_channel(2);
+            let received: Vec<u64> = rx
+                .iter()
+                .map(|(header, payload)| {
+                    assert_eq!(
+                        u64::from_le_bytes(payload.try_into().unwrap()),
+                        header.timestamp
+                    );
+                    header.timestamp
+                })
+                .collect();
+            assert_eq!(received, (0..20).collect::<Vec<_>>());
+
+            let rx = MmapReader::open(&path).unwrap().replay_to_channel(1);
+            assert_eq!(rx.recv().unwrap().0.timestamp, 0);
+            drop(rx);
+
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn replay_checked_reports_regressions_and_gaps() {
+            let path = temp_path();
+            let mut writer = MmapWriter::create(&path, 4096).unwrap();
+            let other = StreamId::new(1).unwrap();
+            for (ts, seq, stream) in [
+                (10, 1, StreamId::DEFAULT),
+                (20, 1, other),
+                (15, 2, StreamId::DEFAULT),
+                (30, 5, StreamId::DEFAULT),
+                (40, 2, other),
+                (50, 5, StreamId::DEFAULT),
+            ] {
+                writer
+                    .event(1)
+                    .timestamp(ts)
+                    .stream(stream)
+                    .sequence(seq)
+                    .write()
+                    .unwrap();
+            }
+            drop(writer);
+
+            let reader = MmapReader::open(&path).unwrap();
+            let unchecked = reader.replay_checked(&ReaderOptions::default(), |_| {});
+            assert_eq!(unchecked.events, 6);
+            assert!(unchecked.is_ok());
+
+            let options = ReaderOptions {
+                check_monotonic_timestamps: true,
+                check_sequence_gaps: true,
+                ..Default::default()
+            };
+            let mut seen = 0;
+            let report = reader.replay_checked(&options, |_| seen += 1);
+            assert_eq!(seen, 6);
+            assert_eq!(report.issues.len(), 3);
+            assert!(matches!(
+                report.issues[0],
+                ReplayIssue::TimestampRegression {
+                    previous: 20,
+                    current: 15,
+                    ..
+                }
+            ));
+            assert!(matches!(
+                report.issues[1],
+                ReplayIssue::SequenceGap {
+                    expected: 3,
+                    found: 5,
+                    ..
+                }
+            ));
+            assert!(matches!(
+                report.issues[2],
+                ReplayIssue::SequenceRegression {
+                    previous: 5,
+                    found: 5,
+                    ..
+                }
+            ));
+
+            drop(reader);
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn replay_dedups_overlapping_segments() {
+            use crate::storage::{SegmentedReader, segment_path};
+
+            let dir = temp_path();
+            fs::create_dir_all(&dir).unwrap();
+            let other = StreamId::new(1).unwrap();
+            let segments: [&[(u64, StreamId)]; 2] = [
+                &[
+                    (1, StreamId::DEFAULT),
+                    (2, StreamId::DEFAULT),
+                    (3, StreamId::DEFAULT),
+                ],
+                &[
+                    (2, StreamId::DEFAULT),
+                    (3, StreamId::DEFAULT),
+                    (2, other),
+                    (4, StreamId::DEFAULT),
+                ],
+            ];
+            for (seq, events) in segments.iter().enumerate() {
+                let mut writer = MmapWriter::create(segment_path(&dir, seq as u64), 4096).unwrap();
+                for &(sequence, stream) in *events {
+                    writer
+                        .event(1)
+                        .stream(stream)
+                        .sequence(sequence)
+                        .write()
+                        .unwrap();
+                }
+                writer.event(2).payload(b"unsequenced").write().unwrap();
+            }
+
+            let reader = SegmentedReader::open(&dir).unwrap();
+            let options = ReaderOptions {
+                check_sequence_gaps: true,
+                dedup_sequences: true,
+                ..Default::default()
+            };
+            let mut replayed = Vec::new();
+            let report = reader.replay_checked(&options, |event| {
+                replayed.push((
+                    event.header.split_sequence(event.payload).0,
+                    event.header.stream().get(),
+                ));
+            });
+            assert_eq!(
+                replayed,
+                vec![
+                    (Some(1), 0),
+                    (Some(2), 0),
+                    (Some(3), 0),
+                    (None, 0),
+                    (Some(2), 1),
+                    (Some(4), 0),
+                    (None, 0),
+                ]
+            );
+            assert_eq!((report.events, report.duplicates), (7, 2));
+            assert!(report.is_ok());
+
+            let report = reader.replay_checked(&ReaderOptions::default(), |_| {});
+            assert_eq!((report.events, report.duplicates), (9, 0));
+            fs::remove_dir_all(&dir).ok();
+        }
+
+        #[test]
+        fn filtered_iteration_and_replay() {
+            let path = temp_path();
+            let mut writer = MmapWriter::create(&path, 4096).unwrap();
+            for i in 0..6u64 {
+                let header = EventHeader::new(i, (i % 3) as u8, 8);
+                writer.write_event(&header, &i.to_le_bytes());
+            }
+            drop(writer);
+
+            let reader = MmapReader::open(&path).unwrap();
+            let timestamps: Vec<u64> = reader
+                .iter_filtered(|h| h.event_type == 1)
+                .map(|e| e.header.timestamp)
+                .collect();
+            assert_eq!(timestamps, vec![1, 4]);
+
+            let mut payloads = Vec::new();
+            let count = reader.replay_filtered(
+                |h| h.timestamp >= 4,
+                |e| payloads.push(u64::from_le_bytes(e.payload.try_into().unwrap())),
+            );
+            assert_eq!(count, 2);
+            assert_eq!(payloads, vec![4, 5]);
+
+            drop(reader);
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn write_vectored_persists_parts() {
+            let path = temp_path();
+
+            {
+                let mut writer = MmapWriter::create(&path, 4096).unwrap();
+                let header = EventHeader::new(1, 1, 8);
+                assert!(writer.write_event_vectored(&header, &[b"test", b"data"]));
+                assert!(!writer.write_event_vectored(&header, &[b"short"]));
+                writer.sync().unwrap();
+            }
+
+            {
+                let reader = MmapReader::open(&path).unwrap();
+                let events: Vec<_> = reader.iter().collect();
+                assert_eq!(events.len(), 1);
+                assert_eq!(events[0].payload, b"testdata");
+            }
+
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn reverse_iterator_yields_newest_first() {
+            let path = temp_path();
+
+            {
+                let mut writer = MmapWriter::create(&path, 4096).unwrap();
+
+                for i in 0..5u64 {
+                    let header = EventHeader::new(i, 1, 8);
+                    writer.write_event(&header, &i.to_le_bytes());
+                }
+            }
+
+            {
+                let reader = MmapReader::open(&path).unwrap();
+                let rev = reader.iter_rev();
+                assert_eq!(rev.len(), 5);
+
+                let timestamps: Vec<_> = rev.map(|e| e.header.timestamp).collect();
+                assert_eq!(timestamps, vec![4, 3, 2, 1, 0]);
+
+                let last_two: Vec<_> = reader.iter_rev().take(2).map(|e| e.payload[0]).collect();
+                assert_eq!(last_two, vec![4, 3]);
+            }
+
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn reopen_existing_file() {
+            let path = temp_path();
+
+            {
+                let mut writer = MmapWriter::create(&path, 4096).unwrap();
+                let header = EventHeader::new(0, 1, 4);
+                writer.write_event(&header, b"test");
+                writer.sync().unwrap();
+            }
+
+            {
+                let mut writer = MmapWriter::open(&path).unwrap();
+                let header = EventHeader::new(1, 1, 4);
+                writer.write_event(&header, b"more");
+                writer.sync().unwrap();
+            }
+
+            {
+                let reader = MmapReader::open(&path).unwrap();
+                assert_eq!(reader.event_count(), 2);
+            }
+
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn buffer_full_returns_false() {
+            let path = temp_path();
+
+            {
+                let mut writer = MmapWriter::create(&path, 4096).unwrap();
+                let header = EventHeader::new(0, 1, 2048);
+                let payload = [0u8; 2048];
+
+                let first = writer.write_event(&header, &payload);
+                assert!(first);
+
+                let second = writer.write_event(&header, &payload);
+                assert!(!second);
+            }
+
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn corrupt_payload_len_stops_iteration() {
+            let path = temp_path();
+
+            {
+                let mut writer = MmapWriter::create(&path, 4096).unwrap();
+
+                for i in 0..3u64 {
+                    let header = EventHeader::new(i, 1, 8);
+                    writer.write_event(&header, &i.to_le_bytes());
+                }
+
+                writer.sync().unwrap();
+            }
+
+            let mut bytes = fs::read(&path).unwrap();
+            let last = FileHeader::SIZE + 2 * (EventHeader::SIZE + 8);
+            bytes[last + 10..last + 12].copy_from_slice(&u16::MAX.to_le_bytes());
+            fs::write(&path, &bytes).unwrap();
+
+            {
+                let reader = MmapReader::open(&path).unwrap();
+                assert_eq!(reader.iter().count(), 2);
+                assert_eq!(reader.replay(|_| {}), 2);
+
+                let results: Vec<_> = reader.try_iter().collect();
+                assert_eq!(results.len(), 3);
+                assert!(results[0].is_ok());
+                assert!(results[1].is_ok());
+                assert!(results[2].is_err());
+            }
+
+            fs::remove_file(&path).ok();
+        }
+
+        #[test]
+        fn write_offset_past_mapping_is_clamped() {
+            let path = temp_path();
+
+            {
+                let mut writer = MmapWriter::create(&path, 4096).unwrap();
+                let header = EventHeader::new(7, 1, 8);
+                writer.write_event(&header, b"testdata");
+                writer.sync().unwrap();
+            }
+
+            let mut bytes = fs::read(&path).unwrap();
+            bytes[24..32].copy_from_slice(&u64::MAX.to_le_bytes());
+            fs::write(&path, &bytes).unwrap();
+
+            {
+                let reader = MmapReader::open(&path).unwrap();
+                let results: Vec<_> = reader.try_iter().collect();
+                assert_eq!(results[0].as_ref().unwrap().header.timestamp, 7);
+                assert!(results.last().unwrap().is_err());
+                assert_eq!(reader.iter().count(), results.len() - 1);
             }
 
             fs::remove_file(&path).ok();
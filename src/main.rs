@@ -1,99 +1,249 @@
-use ringlog::consumer::EventConsumer;
-use ringlog::consumer::dispatcher::EventDispatcher;
-use ringlog::event::EventHeader;
-use ringlog::ring::RingBuffer;
-use ringlog::storage::MmapWriter;
-use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, Ordering};
+use ringlog::admin::{ControlSocket, DEFAULT_CONTROL_PATH, LogLevel, admin_channel};
+use ringlog::config::RinglogConfig;
+use ringlog::service::RinglogService;
+use ringlog::signal::{self, Signal};
+use ringlog::storage::MmapReader;
+use std::path::PathBuf;
 use std::time::{Duration, Instant};
 
-struct MmapConsumer {
-    writer: MmapWriter,
-    events_written: u64,
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let result = match args.get(1).map(String::as_str) {
+        Some("dump") => match args.get(2) {
+            Some(path) => dump(path),
+            None => Err("usage: ringlog dump <file>".into()),
+        },
+        _ => run(&args[1..]),
+    };
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
 }
 
-impl MmapConsumer {
-    fn new(path: &str, capacity: usize) -> std::io::Result<Self> {
-        Ok(Self {
-            writer: MmapWriter::create(path, capacity)?,
-            events_written: 0,
-        })
+/// Prints every event in a log file, decoding payloads written with
+/// `FieldWriter`.
+fn dump(path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let reader = MmapReader::open(path)?;
+    let (types, strings) = (reader.type_names(), reader.string_table());
+    for event in reader.iter() {
+        println!(
+            "{}",
+            event.display().with_types(&types).with_strings(&strings)
+        );
     }
+    Ok(())
 }
 
-impl EventConsumer for MmapConsumer {
-    fn consume(&mut self, header: &EventHeader, payload: &[u8]) -> bool {
-        let ok = self.writer.write_event(header, payload);
-        if ok {
-            self.events_written += 1;
-        }
-        ok
-    }
+/// Settings given on the command line, which win over the config file.
+#[derive(Debug, Default)]
+struct Overrides {
+    admin: Option<String>,
+    control: Option<PathBuf>,
+    ephemeral: Option<bool>,
+}
 
-    fn flush(&mut self) {
-        let _ = self.writer.sync_async();
+impl Overrides {
+    fn apply(&self, config: &mut RinglogConfig) {
+        if let Some(addr) = &self.admin {
+            config.admin.http = Some(addr.clone());
+        }
+        if let Some(path) = &self.control {
+            config.admin.control = Some(path.clone());
+        }
+        if let Some(ephemeral) = self.ephemeral {
+            config.storage.ephemeral = ephemeral;
+        }
     }
+}
 
-    fn name(&self) -> &str {
-        "mmap"
-    }
+/// Reads the config file if one was given, otherwise the defaults, with
+/// `RINGLOG_*` environment overrides applied either way.
+fn load_config(
+    path: Option<&PathBuf>,
+    overrides: &Overrides,
+) -> Result<RinglogConfig, Box<dyn std::error::Error>> {
+    let mut config = match path {
+        Some(path) => RinglogConfig::load(path)?,
+        None => {
+            let mut config = RinglogConfig::default();
+            config.apply_env(std::env::vars())?;
+            config
+        }
+    };
+    overrides.apply(&mut config);
+    Ok(config)
 }
 
-fn main() {
-    if let Err(e) = run() {
-        eprintln!("Error: {}", e);
-        std::process::exit(1);
+fn run(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut config_path = None;
+    let mut overrides = Overrides::default();
+    let mut args = args.iter().peekable();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => {
+                let path = args.next().ok_or("usage: ringlog --config <path>")?;
+                config_path = Some(PathBuf::from(path));
+            }
+            "--admin" => {
+                overrides.admin = Some(args.next().ok_or("usage: ringlog --admin <addr>")?.clone())
+            }
+            "--control" => {
+                let path = args.next_if(|a| !a.starts_with("--"));
+                overrides.control = Some(PathBuf::from(
+                    path.map_or(DEFAULT_CONTROL_PATH, |p| p.as_str()),
+                ));
+            }
+            "--persist" => overrides.ephemeral = Some(false),
+            "--ephemeral" => overrides.ephemeral = Some(true),
+            other => return Err(format!("unknown argument: {}", other).into()),
+        }
     }
-}
+    let config = load_config(config_path.as_ref(), &overrides)?;
 
-fn run() -> Result<(), Box<dyn std::error::Error>> {
     println!("ringlog v0.1.0");
     println!("Press Ctrl+C to stop\n");
 
-    let running = Arc::new(AtomicBool::new(true));
-    let r = running.clone();
-
-    ctrlc::set_handler(move || {
-        println!("\nShutting down...");
-        r.store(false, Ordering::SeqCst);
-    })
-    .map_err(|e| format!("Failed to set Ctrl+C handler: {}", e))?;
-
-    let mut ring = RingBuffer::new(64 * 1024)
-        .map_err(|e| format!("Failed to create ring buffer: {}", e))?;
-    let mut dispatcher = EventDispatcher::new();
-
-    let mmap_consumer = MmapConsumer::new("/tmp/ringlog.log", 64 * 1024 * 1024)
-        .map_err(|e| format!("Failed to create mmap consumer: {}", e))?;
-    dispatcher.add_consumer(mmap_consumer);
+    signal::install(&[
+        Signal::Interrupt,
+        Signal::Terminate,
+        Signal::Hangup,
+        Signal::User1,
+    ])?;
+
+    let (admin, admin_queue) = admin_channel();
+    #[cfg(feature = "http")]
+    let _http_admin = match &config.admin.http {
+        Some(addr) => {
+            let server = ringlog::admin::HttpAdmin::start(addr.as_str(), admin.clone())
+                .map_err(|e| format!("Failed to start admin server on {}: {}", addr, e))?;
+            println!("Admin server listening on http://{}", addr);
+            Some(server)
+        }
+        None => None,
+    };
+    #[cfg(not(feature = "http"))]
+    if config.admin.http.is_some() {
+        return Err(
+            "the HTTP admin server requires ringlog to be built with the `http` feature".into(),
+        );
+    }
+    // A socket passed by systemd socket activation takes the place of
+    // `--control`.
+    #[cfg(feature = "systemd")]
+    let activated = ringlog::systemd::activated_unix_listener()
+        .map_err(|e| format!("Failed to take socket from systemd: {}", e))?;
+    #[cfg(not(feature = "systemd"))]
+    let activated: Option<std::os::unix::net::UnixListener> = None;
+    let _control = match (activated, &config.admin.control) {
+        (Some(listener), _) => {
+            let socket = ControlSocket::from_listener(listener, admin)
+                .map_err(|e| format!("Failed to serve socket from systemd: {}", e))?;
+            println!("Control socket activated on {}", socket.path().display());
+            Some(socket)
+        }
+        (None, Some(path)) => {
+            let socket = ControlSocket::bind(path, admin)
+                .map_err(|e| format!("Failed to bind control socket {}: {}", path.display(), e))?;
+            println!("Control socket listening on {}", path.display());
+            Some(socket)
+        }
+        (None, None) => None,
+    };
 
-    let mut total_events = 0u64;
+    let mut service = RinglogService::new(config)?;
     let mut last_report = Instant::now();
 
     println!("Service running. Waiting for events...");
+    #[cfg(feature = "systemd")]
+    let mut watchdog = ringlog::systemd::Watchdog::from_env();
+    #[cfg(feature = "systemd")]
+    ringlog::systemd::notify_ready()?;
+
+    loop {
+        if signal::take(Signal::Interrupt) || signal::take(Signal::Terminate) {
+            println!("\nShutting down...");
+            #[cfg(feature = "systemd")]
+            let _ = ringlog::systemd::notify_stopping();
+            break;
+        }
+        if signal::take(Signal::Hangup) {
+            // Without the segment consumer there is nothing to rotate.
+            if service.segment().is_some() {
+                match service.rotate() {
+                    Ok(seq) => println!("[INFO] SIGHUP: rotated to segment {}", seq),
+                    Err(e) => eprintln!("[ERROR] SIGHUP: rotation failed: {}", e),
+                }
+            }
+            if let Some(path) = &config_path {
+                match load_config(Some(path), &overrides) {
+                    Ok(config) => {
+                        for section in service.reload(config) {
+                            println!("[WARN] SIGHUP: {} changes need a restart", section);
+                        }
+                        println!("[INFO] SIGHUP: reloaded {}", path.display());
+                    }
+                    Err(e) => eprintln!("[ERROR] SIGHUP: keeping the current config: {}", e),
+                }
+            }
+        }
 
-    while running.load(Ordering::SeqCst) {
-        let stats = dispatcher.drain(&mut ring);
-        total_events += stats.events_read;
+        let stats = match service.poll() {
+            Ok(stats) => stats,
+            Err(e) => {
+                eprintln!("[ERROR] {}", e);
+                Default::default()
+            }
+        };
+
+        let log_level = service.log_level();
+        if log_level >= LogLevel::Warn && stats.events_failed > 0 {
+            println!("[WARN] {} deliveries failed", stats.events_failed);
+        }
+        if log_level >= LogLevel::Debug && stats.events_read > 0 {
+            println!(
+                "[DEBUG] drained {} events in {:?}",
+                stats.events_read, stats.elapsed
+            );
+        }
 
-        if last_report.elapsed() >= Duration::from_secs(5) {
+        service.handle_admin_commands(&admin_queue);
+        if signal::take(Signal::User1) {
+            println!("[STATS] {}", service.diagnostics().to_json());
+        }
+        admin_queue.publish(service.status());
+
+        if service.log_level() >= LogLevel::Info && last_report.elapsed() >= Duration::from_secs(5)
+        {
+            let ring = service.ring();
             println!(
                 "[STATUS] total_events={} ring_used={} ring_available={}",
-                total_events,
+                service.totals().events_read,
                 ring.used(),
                 ring.available()
             );
             last_report = Instant::now();
         }
 
+        #[cfg(feature = "systemd")]
+        if let Some(watchdog) = watchdog.as_mut()
+            && let Err(e) = watchdog.tick()
+        {
+            eprintln!("[ERROR] Failed to ping the systemd watchdog: {}", e);
+        }
+
         std::thread::sleep(Duration::from_millis(10));
     }
 
-    println!("Total events processed: {}", total_events);
-    
-    if let Err(e) = std::fs::remove_file("/tmp/ringlog.log") {
-        eprintln!("Warning: Failed to remove temporary file: {}", e);
+    let report = service.shutdown()?;
+    println!("Total events processed: {}", report.totals.events_read);
+    if report.kept {
+        println!("Log kept in {}", report.dir.display());
+        println!(
+            "  segments={} events={} bytes={} failed_deliveries={}",
+            report.segments, report.events, report.totals.bytes_read, report.totals.events_failed
+        );
     }
-    
+
     Ok(())
 }
@@ -0,0 +1,389 @@
+//! A small filter language over log files, compiled to a predicate on
+//! `EventView`s:
+//!
+//! ```text
+//! type == 3 && ts >= 1700000000000000000 && payload.len > 100
+//! (stream == 2 || type != 7) && payload.user == "alice"
+//! ```
+//!
+//! Comparisons take a field, one of `==`, `!=`, `<`, `<=`, `>` and `>=`,
+//! and a number (decimal or `0x` hex) or a double-quoted string. They
+//! combine with `&&`, `||`, `!` and parentheses. Fields:
+//!
+//! - `type`, `ts`, `stream`: the header's event type, timestamp and stream
+//! - `seq`: the sequence number; events without one never match
+//! - `payload.len`: the payload length, without sequence or trace prefixes
+//! - `payload.<key>`: a `FieldWriter` field of the payload; events without
+//!   it, with a value of another kind, or with a compressed payload never
+//!   match
+//! - `tag`: `tag == 3` matches events carrying tag 3 (see `Tags`), `tag != 3`
+//!   those without it; other comparisons are rejected
+
+use crate::event::{EventHeader, EventView, FieldReader, FieldValue, Tags};
+use std::fmt;
+use std::str::FromStr;
+
+type Predicate = Box<dyn Fn(&EventView<'_>) -> bool + Send + Sync>;
+
+/// Why a query failed to parse.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryError {
+    /// Byte offset in the query text.
+    pub offset: usize,
+    pub message: String,
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Invalid query at offset {}: {}",
+            self.offset, self.message
+        )
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+/// A compiled query.
+pub struct Query {
+    text: String,
+    predicate: Predicate,
+}
+
+impl Query {
+    pub fn parse(text: &str) -> Result<Self, QueryError> {
+        let mut parser = Parser {
+            tokens: tokenize(text)?,
+            pos: 0,
+            end: text.len(),
+        };
+        let predicate = parser.or()?;
+        if let Some((offset, token)) = parser.tokens.get(parser.pos) {
+            return Err(error(*offset, format!("unexpected {}", token)));
+        }
+        Ok(Self {
+            text: text.to_string(),
+            predicate,
+        })
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    #[inline]
+    pub fn matches(&self, event: &EventView<'_>) -> bool {
+        (self.predicate)(event)
+    }
+
+    /// The events of `events` that match, e.g. `query.filter(&reader)` for
+    /// an `MmapReader` or `SegmentedReader`.
+    pub fn filter<'q, 'a, I>(&'q self, events: I) -> impl Iterator<Item = EventView<'a>> + 'q
+    where
+        I: IntoIterator<Item = EventView<'a>>,
+        I::IntoIter: 'q,
+    {
+        events.into_iter().filter(move |event| self.matches(event))
+    }
+}
+
+impl FromStr for Query {
+    type Err = QueryError;
+
+    fn from_str(text: &str) -> Result<Self, QueryError> {
+        Self::parse(text)
+    }
+}
+
+impl fmt::Debug for Query {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Query").field(&self.text).finish()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(u64),
+    Str(String),
+    Op(&'static str),
+    Open,
+    Close,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ident(name) => write!(f, "`{}`", name),
+            Self::Number(n) => write!(f, "`{}`", n),
+            Self::Str(s) => write!(f, "{:?}", s),
+            Self::Op(op) => write!(f, "`{}`", op),
+            Self::Open => write!(f, "`(`"),
+            Self::Close => write!(f, "`)`"),
+        }
+    }
+}
+
+const OPS: [&str; 9] = ["&&", "||", "==", "!=", "<=", ">=", "<", ">", "!"];
+
+fn error(offset: usize, message: impl Into<String>) -> QueryError {
+    QueryError {
+        offset,
+        message: message.into(),
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<(usize, Token)>, QueryError> {
+    let bytes = text.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let start = i;
+        let c = bytes[i];
+        if c.is_ascii_whitespace() {
+            i += 1;
+            continue;
+        }
+        let token = if c == b'(' {
+            i += 1;
+            Token::Open
+        } else if c == b')' {
+            i += 1;
+            Token::Close
+        } else if c == b'"' {
+            let mut value = String::new();
+            let mut chars = text[i + 1..].char_indices();
+            loop {
+                match chars.next() {
+                    Some((n, '"')) => {
+                        i += n + 2;
+                        break;
+                    }
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, escaped)) => value.push(escaped),
+                        None => return Err(error(start, "unterminated string")),
+                    },
+                    Some((_, c)) => value.push(c),
+                    None => return Err(error(start, "unterminated string")),
+                }
+            }
+            Token::Str(value)
+        } else if c.is_ascii_digit() {
+            while i < bytes.len() && bytes[i].is_ascii_alphanumeric() {
+                i += 1;
+            }
+            let literal = &text[start..i];
+            let parsed = match literal.strip_prefix("0x") {
+                Some(hex) => u64::from_str_radix(hex, 16),
+                None => literal.parse(),
+            };
+            Token::Number(parsed.map_err(|_| error(start, format!("invalid number {}", literal)))?)
+        } else if c.is_ascii_alphabetic() || c == b'_' {
+            while i < bytes.len()
+                && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_' || bytes[i] == b'.')
+            {
+                i += 1;
+            }
+            Token::Ident(text[start..i].to_string())
+        } else if let Some(op) = OPS.iter().find(|op| text[i..].starts_with(*op)) {
+            i += op.len();
+            Token::Op(op)
+        } else {
+            let c = text[i..].chars().next().unwrap_or_default();
+            return Err(error(start, format!("unexpected character {:?}", c)));
+        };
+        tokens.push((start, token));
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    /// Length of the text, for errors at its end.
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, token)| token)
+    }
+
+    fn next(&mut self, expected: &str) -> Result<(usize, Token), QueryError> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or_else(|| error(self.end, format!("expected {}", expected)))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn eat(&mut self, op: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Op(found)) if *found == op) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn or(&mut self) -> Result<Predicate, QueryError> {
+        let mut left = self.and()?;
+        while self.eat("||") {
+            let right = self.and()?;
+            left = Box::new(move |event| left(event) || right(event));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Predicate, QueryError> {
+        let mut left = self.not()?;
+        while self.eat("&&") {
+            let right = self.not()?;
+            left = Box::new(move |event| left(event) && right(event));
+        }
+        Ok(left)
+    }
+
+    fn not(&mut self) -> Result<Predicate, QueryError> {
+        if self.eat("!") {
+            let inner = self.not()?;
+            return Ok(Box::new(move |event| !inner(event)));
+        }
+        if self.peek() == Some(&Token::Open) {
+            self.pos += 1;
+            let inner = self.or()?;
+            return match self.next("`)`")? {
+                (_, Token::Close) => Ok(inner),
+                (offset, token) => Err(error(offset, format!("expected `)`, found {}", token))),
+            };
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Predicate, QueryError> {
+        let (offset, field) = match self.next("a field")? {
+            (offset, Token::Ident(field)) => (offset, field),
+            (offset, token) => {
+                return Err(error(offset, format!("expected a field, found {}", token)));
+            }
+        };
+        let op = match self.next("a comparison")? {
+            (_, Token::Op(op)) if !matches!(op, "&&" | "||" | "!") => Cmp::from_op(op),
+            (offset, token) => {
+                return Err(error(
+                    offset,
+                    format!("expected a comparison, found {}", token),
+                ));
+            }
+        };
+        let value = match self.next("a value")? {
+            (_, Token::Number(n)) => Value::Number(n),
+            (_, Token::Str(s)) => Value::Str(s),
+            (offset, token) => {
+                return Err(error(offset, format!("expected a value, found {}", token)));
+            }
+        };
+
+        let number = |get: fn(&EventView<'_>) -> Option<u64>| -> Result<Predicate, QueryError> {
+            match value {
+                Value::Number(n) => Ok(Box::new(move |event| {
+                    get(event).is_some_and(|actual| op.holds(actual.cmp(&n)))
+                })),
+                Value::Str(_) => Err(error(offset, format!("`{}` is a number", field))),
+            }
+        };
+        match field.as_str() {
+            "type" => number(|event| Some(event.header.event_type as u64)),
+            "ts" => number(|event| Some(event.header.timestamp)),
+            "stream" => number(|event| Some(event.header.stream().get() as u64)),
+            "seq" => number(|event| event.header.split_sequence(event.payload).0),
+            "payload.len" => number(|event| Some(event.header.body(event.payload).len() as u64)),
+            "tag" => match (op, value) {
+                (Cmp::Eq | Cmp::Ne, Value::Number(tag)) if tag < 64 => {
+                    let tag = Tags::tag(tag as u32);
+                    Ok(Box::new(move |event| {
+                        Tags::of(event).contains(tag) == (op == Cmp::Eq)
+                    }))
+                }
+                (Cmp::Eq | Cmp::Ne, _) => Err(error(offset, "`tag` is a tag number from 0 to 63")),
+                _ => Err(error(offset, "`tag` only supports `==` and `!=`")),
+            },
+            _ => match field.strip_prefix("payload.") {
+                Some(key) if !key.is_empty() => {
+                    let key = key.to_string();
+                    Ok(Box::new(move |event| {
+                        payload_field(event, &key).is_some_and(|actual| value.compare(op, &actual))
+                    }))
+                }
+                _ => Err(error(offset, format!("unknown field `{}`", field))),
+            },
+        }
+    }
+}
+
+fn payload_field<'a>(event: &EventView<'a>, key: &str) -> Option<FieldValue<'a>> {
+    if event.header.flags & EventHeader::FLAG_COMPRESSED != 0 {
+        return None;
+    }
+    FieldReader::new(event.header.body(event.payload)).get(key)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Cmp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Cmp {
+    fn from_op(op: &str) -> Self {
+        match op {
+            "==" => Self::Eq,
+            "!=" => Self::Ne,
+            "<" => Self::Lt,
+            "<=" => Self::Le,
+            ">" => Self::Gt,
+            _ => Self::Ge,
+        }
+    }
+
+    fn holds(self, ordering: std::cmp::Ordering) -> bool {
+        use std::cmp::Ordering::*;
+        match self {
+            Self::Eq => ordering == Equal,
+            Self::Ne => ordering != Equal,
+            Self::Lt => ordering == Less,
+            Self::Le => ordering != Greater,
+            Self::Gt => ordering == Greater,
+            Self::Ge => ordering != Less,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Number(u64),
+    Str(String),
+}
+
+impl Value {
+    fn compare(&self, op: Cmp, actual: &FieldValue<'_>) -> bool {
+        let ordering = match (self, actual) {
+            (Self::Number(n), FieldValue::U64(v)) => v.cmp(n),
+            (Self::Number(n), FieldValue::F64(v)) => match v.partial_cmp(&(*n as f64)) {
+                Some(ordering) => ordering,
+                None => return false,
+            },
+            (Self::Str(s), FieldValue::Str(v)) => (*v).cmp(s.as_str()),
+            (Self::Str(s), FieldValue::Bytes(v)) => (*v).cmp(s.as_bytes()),
+            _ => return false,
+        };
+        op.holds(ordering)
+    }
+}
@@ -0,0 +1,138 @@
+use super::{FRAME_EVENT, FRAME_HEARTBEAT, FRAME_SEALED, read_reply, write_hello};
+use crate::event::EventHeader;
+use crate::storage::MmapWriter;
+use std::io::{self, BufReader, Read};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::thread;
+use std::time::Duration;
+
+/// Keeps a local copy of a leader's log, resuming after the events it already
+/// holds whenever it reconnects.
+pub struct Follower {
+    writer: MmapWriter,
+    read_timeout: Duration,
+}
+
+impl Follower {
+    pub const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Replicates into `writer`. Its existing events are assumed to be the
+    /// leader's first events, so reopen the same file after a restart.
+    pub fn new(writer: MmapWriter) -> Self {
+        Self {
+            writer,
+            read_timeout: Self::DEFAULT_READ_TIMEOUT,
+        }
+    }
+
+    /// How long to wait for an event or heartbeat before treating the leader
+    /// as gone. Keep it well above the leader's heartbeat interval.
+    pub fn with_read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = timeout;
+        self
+    }
+
+    /// Events held locally, which is where the next session resumes.
+    pub fn position(&self) -> u64 {
+        self.writer.file_header().event_count + self.writer.pending_events()
+    }
+
+    pub fn writer(&self) -> &MmapWriter {
+        &self.writer
+    }
+
+    pub fn into_writer(self) -> MmapWriter {
+        self.writer
+    }
+
+    /// Runs one replication session. Returns once the leader's log is sealed,
+    /// after sealing the local copy too; any error means the session ended
+    /// early and can be resumed with another call.
+    pub fn sync<A: ToSocketAddrs>(&mut self, addr: A) -> io::Result<u64> {
+        let mut stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(self.read_timeout))?;
+
+        let position = self.position();
+        write_hello(&mut stream, position)?;
+        let (accepted, leader_events) = read_reply(&mut stream)?;
+        if !accepted {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Leader holds {} events but the local copy already has {}",
+                    leader_events, position
+                ),
+            ));
+        }
+
+        let mut input = BufReader::new(stream);
+        let mut received = 0;
+        let mut payload = Vec::new();
+
+        loop {
+            let mut tag = [0u8; 1];
+            input.read_exact(&mut tag)?;
+
+            match tag[0] {
+                FRAME_EVENT => {
+                    let mut buf = [0u8; EventHeader::SIZE];
+                    input.read_exact(&mut buf)?;
+                    let header = EventHeader::decode(&buf);
+                    payload.resize(header.payload_len as usize, 0);
+                    input.read_exact(&mut payload)?;
+
+                    if self
+                        .writer
+                        .write_event_unchecked(&header, &[&payload])
+                        .is_err()
+                    {
+                        return Err(io::Error::new(
+                            io::ErrorKind::StorageFull,
+                            format!("Local copy is full after {} events", self.position()),
+                        ));
+                    }
+                    received += 1;
+                }
+                FRAME_HEARTBEAT => {}
+                FRAME_SEALED => {
+                    self.writer.seal()?;
+                    return Ok(received);
+                }
+                tag => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Unknown replication frame tag {}", tag),
+                    ));
+                }
+            }
+        }
+    }
+
+    /// Calls `sync` until the leader's log is sealed, waiting `retry_interval`
+    /// after each session lost to a connection error. Errors that reconnecting
+    /// cannot fix, such as a full or diverged local copy, are returned.
+    /// Returns the events received across all sessions.
+    pub fn run<A: ToSocketAddrs + Copy>(
+        &mut self,
+        addr: A,
+        retry_interval: Duration,
+    ) -> io::Result<u64> {
+        let start = self.position();
+        while !self.writer.is_sealed() {
+            match self.sync(addr) {
+                Ok(_) => {}
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::InvalidData | io::ErrorKind::StorageFull
+                    ) =>
+                {
+                    return Err(e);
+                }
+                Err(_) => thread::sleep(retry_interval),
+            }
+        }
+        Ok(self.position() - start)
+    }
+}
@@ -0,0 +1,95 @@
+use super::{FRAME_EVENT, FRAME_HEARTBEAT, FRAME_SEALED, read_hello, write_reply};
+use crate::storage::{MmapReader, TailReader};
+use std::io::{self, BufWriter, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// Tails a local log file and streams its events to followers.
+#[derive(Debug, Clone)]
+pub struct Leader {
+    path: PathBuf,
+    heartbeat_interval: Duration,
+}
+
+impl Leader {
+    pub const DEFAULT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            heartbeat_interval: Self::DEFAULT_HEARTBEAT_INTERVAL,
+        }
+    }
+
+    /// How long the log may stay idle before a heartbeat is sent. Heartbeats
+    /// let followers tell an idle leader from a dead one, and let the leader
+    /// notice followers that went away.
+    pub fn with_heartbeat_interval(mut self, interval: Duration) -> Self {
+        self.heartbeat_interval = interval;
+        self
+    }
+
+    /// Accepts followers until the listener fails, serving each on its own
+    /// thread.
+    pub fn serve(&self, listener: TcpListener) -> io::Result<()> {
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let leader = self.clone();
+            thread::spawn(move || leader.serve_follower(stream));
+        }
+        Ok(())
+    }
+
+    /// Streams events to one connected follower, starting after the events
+    /// it reports already holding. Returns the number of events sent once the
+    /// log is sealed and fully sent, or an error when the follower goes away.
+    pub fn serve_follower(&self, stream: TcpStream) -> io::Result<u64> {
+        stream.set_nodelay(true)?;
+        let mut reader_half = stream.try_clone()?;
+        let mut out = BufWriter::new(stream);
+
+        let position = read_hello(&mut reader_half)?;
+        let mut tail = TailReader::open(&self.path)?;
+        let skipped = tail.skip(position)?;
+        if skipped < position {
+            write_reply(&mut out, false, skipped)?;
+            out.flush()?;
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Follower is at event {} but {} holds only {}",
+                    position,
+                    self.path.display(),
+                    skipped
+                ),
+            ));
+        }
+        write_reply(&mut out, true, MmapReader::open(&self.path)?.event_count())?;
+        out.flush()?;
+
+        let mut sent = 0;
+        loop {
+            let batch = tail.read_new(|event| {
+                out.write_all(&[FRAME_EVENT])?;
+                out.write_all(&event.header.encode())?;
+                out.write_all(event.payload)
+            })?;
+            sent += batch;
+
+            if tail.is_sealed() {
+                out.write_all(&[FRAME_SEALED])?;
+                out.flush()?;
+                return Ok(sent);
+            }
+
+            if batch > 0 {
+                out.flush()?;
+            } else if !tail.wait(self.heartbeat_interval)? {
+                out.write_all(&[FRAME_HEARTBEAT])?;
+                out.flush()?;
+            }
+        }
+    }
+}
@@ -0,0 +1,83 @@
+//! Streams a log file to standby copies over TCP.
+//!
+//! A follower connects and sends a hello carrying how many events its copy
+//! already holds. The leader answers with its own count, then streams every
+//! later event as it is appended, sending heartbeats while the log is idle
+//! and a final sealed marker once the log is sealed:
+//!
+//! ```text
+//! hello:  [MAGIC 4][VERSION u8][position u64]
+//! reply:  [MAGIC 4][VERSION u8][accepted u8][leader events u64]
+//! frame:  [FRAME_EVENT][EventHeader][payload] | [FRAME_HEARTBEAT] | [FRAME_SEALED]
+//! ```
+//!
+//! Integers are little-endian. Events carry their header and raw payload, so
+//! sequence numbers, trace contexts and checksums are copied unchanged.
+
+pub mod follower;
+pub mod leader;
+
+pub use follower::Follower;
+pub use leader::Leader;
+
+use std::io::{self, Read, Write};
+
+pub const MAGIC: [u8; 4] = *b"RLRP";
+pub const VERSION: u8 = 1;
+
+const FRAME_EVENT: u8 = 1;
+const FRAME_HEARTBEAT: u8 = 2;
+const FRAME_SEALED: u8 = 3;
+
+fn write_hello<W: Write>(w: &mut W, position: u64) -> io::Result<()> {
+    let mut buf = [0u8; 13];
+    buf[..4].copy_from_slice(&MAGIC);
+    buf[4] = VERSION;
+    buf[5..].copy_from_slice(&position.to_le_bytes());
+    w.write_all(&buf)
+}
+
+fn read_hello<R: Read>(r: &mut R) -> io::Result<u64> {
+    let mut buf = [0u8; 13];
+    r.read_exact(&mut buf)?;
+    check_preamble(&buf)?;
+    Ok(u64::from_le_bytes(buf[5..].try_into().unwrap()))
+}
+
+fn write_reply<W: Write>(w: &mut W, accepted: bool, leader_events: u64) -> io::Result<()> {
+    let mut buf = [0u8; 14];
+    buf[..4].copy_from_slice(&MAGIC);
+    buf[4] = VERSION;
+    buf[5] = accepted as u8;
+    buf[6..].copy_from_slice(&leader_events.to_le_bytes());
+    w.write_all(&buf)
+}
+
+fn read_reply<R: Read>(r: &mut R) -> io::Result<(bool, u64)> {
+    let mut buf = [0u8; 14];
+    r.read_exact(&mut buf)?;
+    check_preamble(&buf)?;
+    Ok((
+        buf[5] != 0,
+        u64::from_le_bytes(buf[6..].try_into().unwrap()),
+    ))
+}
+
+fn check_preamble(buf: &[u8]) -> io::Result<()> {
+    if buf[..4] != MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Peer is not speaking the ringlog replication protocol",
+        ));
+    }
+    if buf[4] != VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Unsupported replication protocol version {}, expected {}",
+                buf[4], VERSION
+            ),
+        ));
+    }
+    Ok(())
+}
@@ -1,6 +1,19 @@
+use super::quota::TenantLedger;
+use super::stats::ProducerCounters;
+use super::{FullPolicy, Watermarks};
+
+/// Single-threaded ring of events. Construct it with `new` (or its alias
+/// `try_new`), which validates the capacity, or with the panicking
+/// `new_unchecked` and `with_capacity_pow2` when the capacity is a known
+/// constant.
 pub struct RingBuffer {
-    pub buf: Vec<u8>,
-    pub capacity: usize,
-    pub head: usize,
-    pub tail: usize,
+    pub(super) buf: Vec<u8>,
+    pub(super) capacity: usize,
+    pub(super) head: usize,
+    pub(super) tail: usize,
+    pub(super) locked: bool,
+    pub(super) stats: ProducerCounters,
+    pub(super) policy: FullPolicy,
+    pub(super) watermarks: Option<Watermarks>,
+    pub(super) tenants: Option<Box<TenantLedger>>,
 }
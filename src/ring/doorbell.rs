@@ -0,0 +1,110 @@
+//! A doorbell a producer rings after writing so a consumer, possibly in
+//! another process, can sleep while its ring is empty instead of polling.
+//!
+//! ```ignore
+//! // consumer
+//! let epoch = bell.epoch();
+//! if ring_is_empty() {
+//!     bell.wait(epoch, Some(Duration::from_millis(100)));
+//! }
+//!
+//! // producer
+//! write_event();
+//! bell.ring();
+//! ```
+//!
+//! Reading the epoch before checking the ring means a ring that lands in
+//! between makes `wait` return at once, so no wake-up is lost.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+/// Two words that can be placed in shared memory next to a ring. On Linux
+/// waiting is a futex; elsewhere it polls the epoch every millisecond.
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct Doorbell {
+    epoch: AtomicU32,
+    sleepers: AtomicU32,
+}
+
+impl Doorbell {
+    /// Bytes a doorbell occupies in a shared mapping.
+    pub const SIZE: usize = std::mem::size_of::<Self>();
+
+    pub const fn new() -> Self {
+        Self {
+            epoch: AtomicU32::new(0),
+            sleepers: AtomicU32::new(0),
+        }
+    }
+
+    /// Views `SIZE` bytes of a shared mapping as a doorbell. Zeroed memory,
+    /// as a fresh mapping is, is a valid doorbell.
+    ///
+    /// # Safety
+    /// `ptr` must be 4-byte aligned and point to `SIZE` bytes that stay mapped
+    /// for `'a` and are only ever accessed through `Doorbell`.
+    pub unsafe fn from_ptr<'a>(ptr: *mut u8) -> &'a Self {
+        unsafe { &*(ptr as *const Self) }
+    }
+
+    /// Changes every time the doorbell rings.
+    #[inline]
+    pub fn epoch(&self) -> u32 {
+        self.epoch.load(Ordering::SeqCst)
+    }
+
+    /// Wakes every consumer waiting on the doorbell. Costs no system call
+    /// when none is asleep.
+    #[inline]
+    pub fn ring(&self) {
+        self.epoch.fetch_add(1, Ordering::SeqCst);
+        if self.sleepers.load(Ordering::SeqCst) > 0 {
+            self.wake();
+        }
+    }
+
+    /// Sleeps until the doorbell rings after `epoch` was read, or for at most
+    /// `timeout`. Returns whether it rang; spurious wake-ups are retried.
+    pub fn wait(&self, epoch: u32, timeout: Option<Duration>) -> bool {
+        let deadline = timeout.map(|timeout| std::time::Instant::now() + timeout);
+        self.sleepers.fetch_add(1, Ordering::SeqCst);
+        let rang = loop {
+            if self.epoch() != epoch {
+                break true;
+            }
+            let remaining = match deadline {
+                Some(deadline) => {
+                    match deadline.checked_duration_since(std::time::Instant::now()) {
+                        Some(remaining) if !remaining.is_zero() => Some(remaining),
+                        _ => break false,
+                    }
+                }
+                None => None,
+            };
+            self.sleep(epoch, remaining);
+        };
+        self.sleepers.fetch_sub(1, Ordering::SeqCst);
+        rang
+    }
+
+    #[cfg(target_os = "linux")]
+    fn sleep(&self, epoch: u32, timeout: Option<Duration>) {
+        crate::sys::futex_wait(&self.epoch, epoch, timeout);
+    }
+
+    #[cfg(target_os = "linux")]
+    fn wake(&self) {
+        crate::sys::futex_wake(&self.epoch);
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn sleep(&self, _epoch: u32, timeout: Option<Duration>) {
+        let poll = Duration::from_millis(1);
+        std::thread::sleep(timeout.map_or(poll, |timeout| timeout.min(poll)));
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn wake(&self) {}
+}
@@ -0,0 +1,67 @@
+use super::RingBuffer;
+use super::spsc::Consumer;
+use crate::event::EventHeader;
+use crate::storage::{FileHeader, MmapWriter};
+use std::io;
+use std::path::Path;
+
+impl RingBuffer {
+    /// Writes every pending event to a new log file at `path`, readable with
+    /// `MmapReader`, without consuming them. An existing file is replaced.
+    /// Returns how many events were written.
+    pub fn dump_to_file<P: AsRef<Path>>(&self, path: P) -> io::Result<usize> {
+        let mut dump = Dump::create(path.as_ref(), self.used())?;
+        for event in self.iter() {
+            dump.write(&event.header, &event.payload)?;
+        }
+        dump.finish()
+    }
+}
+
+impl Consumer<'_> {
+    /// Reads every pending event into a new log file at `path`, readable with
+    /// `MmapReader`. An existing file is replaced. Returns how many events
+    /// were written.
+    pub fn dump_to_file<P: AsRef<Path>>(&mut self, path: P) -> io::Result<usize> {
+        let mut events = Vec::new();
+        let mut bytes = 0;
+        while let Some((header, payload)) = self.read_event() {
+            bytes += header.total_size();
+            events.push((header, payload));
+        }
+
+        let mut dump = Dump::create(path.as_ref(), bytes)?;
+        for (header, payload) in &events {
+            dump.write(header, payload)?;
+        }
+        dump.finish()
+    }
+}
+
+struct Dump {
+    writer: MmapWriter,
+    events: usize,
+}
+
+impl Dump {
+    fn create(path: &Path, bytes: usize) -> io::Result<Self> {
+        let writer = MmapWriter::create(path, FileHeader::SIZE + bytes).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Failed to create dump {}: {}", path.display(), e),
+            )
+        })?;
+        Ok(Self { writer, events: 0 })
+    }
+
+    fn write(&mut self, header: &EventHeader, payload: &[u8]) -> io::Result<()> {
+        self.writer.write_event_unchecked(header, &[payload])?;
+        self.events += 1;
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<usize> {
+        self.writer.seal()?;
+        Ok(self.events)
+    }
+}
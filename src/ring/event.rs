@@ -1,6 +1,9 @@
-use super::RingError;
-use crate::event::EventHeader;
+use super::quota::{TenantLedger, TenantQuotas, TenantStats};
+use super::{FullPolicy, RingError, Watermarks};
+use super::stats::{ProducerCounters, ProducerStats};
+use crate::event::{EventHeader, StreamId};
 use crate::ring::RingBuffer;
+use crate::sys;
 use std::ptr;
 
 impl RingBuffer {
@@ -25,9 +28,149 @@ impl RingBuffer {
             capacity,
             head: 0,
             tail: 0,
+            locked: false,
+            stats: ProducerCounters::default(),
+            policy: FullPolicy::Error,
+            watermarks: None,
+            tenants: None,
         })
     }
 
+    /// Same as `new`.
+    #[inline]
+    pub fn try_new(capacity: usize) -> Result<Self, RingError> {
+        Self::new(capacity)
+    }
+
+    /// Like `new`, but panics if `capacity` is not a power of two of at least
+    /// twice `EventHeader::SIZE`.
+    pub fn new_unchecked(capacity: usize) -> Self {
+        match Self::new(capacity) {
+            Ok(ring) => ring,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// A ring of `2^log2` bytes; panics if that is below the minimum capacity.
+    pub fn with_capacity_pow2(log2: u32) -> Self {
+        let capacity = 1usize
+            .checked_shl(log2)
+            .unwrap_or_else(|| panic!("2^{} bytes does not fit in usize", log2));
+        Self::new_unchecked(capacity)
+    }
+
+    /// A ring of at least `bytes`, rounded up to the next power of two and to
+    /// the minimum capacity. Panics only if that overflows `usize`.
+    pub fn with_at_least(bytes: usize) -> Self {
+        let capacity = bytes
+            .max(EventHeader::SIZE * 2)
+            .checked_next_power_of_two()
+            .unwrap_or_else(|| panic!("no power of two capacity holds {} bytes", bytes));
+        Self::new_unchecked(capacity)
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn with_full_policy(mut self, policy: FullPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    #[inline]
+    pub fn full_policy(&self) -> FullPolicy {
+        self.policy
+    }
+
+    pub fn with_watermarks(mut self, watermarks: Watermarks) -> Self {
+        self.watermarks = Some(watermarks.bind(self.capacity));
+        self
+    }
+
+    /// Limits how much of the ring each stream may hold; a write that would
+    /// put its stream over quota fails with `RingError::QuotaExceeded`,
+    /// whatever the full policy.
+    pub fn with_quotas(mut self, quotas: TenantQuotas) -> Self {
+        self.set_quotas(quotas);
+        self
+    }
+
+    /// Replaces the quotas, keeping the stats; events already pending stay
+    /// even if they exceed the new quotas.
+    pub fn set_quotas(&mut self, quotas: TenantQuotas) {
+        match &mut self.tenants {
+            Some(tenants) => tenants.set_quotas(quotas),
+            None => self.tenants = Some(Box::new(TenantLedger::new(quotas))),
+        }
+    }
+
+    pub fn quotas(&self) -> Option<&TenantQuotas> {
+        self.tenants.as_deref().map(TenantLedger::quotas)
+    }
+
+    /// Stats of one stream; all zero without quotas.
+    pub fn tenant_stats(&self, stream: StreamId) -> TenantStats {
+        self.tenants
+            .as_deref()
+            .map_or_else(TenantStats::default, |tenants| tenants.stats(stream))
+    }
+
+    /// Stats of every stream that wrote or was refused anything; empty
+    /// without quotas.
+    pub fn active_tenants(&self) -> Vec<(StreamId, TenantStats)> {
+        self.tenants
+            .as_deref()
+            .map_or_else(Vec::new, TenantLedger::active)
+    }
+
+    /// Number of watermark thresholds the occupancy has reached; 0 without
+    /// watermarks.
+    #[inline]
+    pub fn pressure(&self) -> usize {
+        self.watermarks
+            .as_ref()
+            .map_or(0, |w| w.level_for(self.used()))
+    }
+
+    #[inline]
+    pub fn occupancy(&self) -> f64 {
+        self.used() as f64 / self.capacity as f64
+    }
+
+    #[inline]
+    fn update_watermarks(&self) {
+        if let Some(watermarks) = &self.watermarks {
+            watermarks.update(self.used(), self.capacity);
+        }
+    }
+
+    /// Pins the buffer in RAM so page-outs cannot stall the hot path. The lock
+    /// is released by `unlock_memory` or when the buffer is dropped.
+    pub fn lock_memory(&mut self) -> std::io::Result<()> {
+        sys::mlock(self.buf.as_ptr(), self.buf.len())?;
+        self.locked = true;
+        Ok(())
+    }
+
+    pub fn unlock_memory(&mut self) -> std::io::Result<()> {
+        if self.locked {
+            sys::munlock(self.buf.as_ptr(), self.buf.len())?;
+            self.locked = false;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    pub fn is_memory_locked(&self) -> bool {
+        self.locked
+    }
+
+    pub fn producer_stats(&self) -> ProducerStats {
+        self.stats.snapshot()
+    }
+
     #[inline(always)]
     pub fn used(&self) -> usize {
         self.head.wrapping_sub(self.tail) & (self.capacity - 1)
@@ -43,16 +186,24 @@ impl RingBuffer {
         self.head == self.tail
     }
 
+    /// Writes one event. Reserved event types are refused.
     #[inline]
     pub fn write_event(&mut self, header: &EventHeader, payload: &[u8]) -> Result<(), RingError> {
+        self.check_type(header)?;
+        self.write_event_unchecked(header, payload)
+    }
+
+    /// `write_event` without the reserved type check.
+    #[inline]
+    pub(crate) fn write_event_unchecked(
+        &mut self,
+        header: &EventHeader,
+        payload: &[u8],
+    ) -> Result<(), RingError> {
+        self.check_quota(header)?;
         let total_size = header.total_size();
-        let available = self.available();
-        
-        if total_size > available {
-            return Err(RingError::NotEnoughSpace {
-                required: total_size,
-                available,
-            });
+        if total_size > self.available() && !self.make_room(total_size)? {
+            return Ok(());
         }
 
         let mask = self.capacity - 1;
@@ -104,23 +255,169 @@ impl RingBuffer {
         }
 
         self.head = (start + total_size) & mask;
+        self.stats.record_write(total_size, payload.len());
+        self.hold(header);
+        self.update_watermarks();
+        Ok(())
+    }
+
+    /// Writes one event whose payload is the concatenation of `parts`.
+    /// Reserved event types are refused.
+    pub fn write_event_vectored(
+        &mut self,
+        header: &EventHeader,
+        parts: &[&[u8]],
+    ) -> Result<(), RingError> {
+        self.check_type(header)?;
+        self.write_event_vectored_unchecked(header, parts)
+    }
+
+    /// `write_event_vectored` without the reserved type check.
+    pub(crate) fn write_event_vectored_unchecked(
+        &mut self,
+        header: &EventHeader,
+        parts: &[&[u8]],
+    ) -> Result<(), RingError> {
+        let payload_len: usize = parts.iter().map(|p| p.len()).sum();
+        if payload_len != header.payload_len as usize {
+            self.stats.record_rejected();
+            return Err(RingError::PayloadLengthMismatch {
+                header_len: header.payload_len as usize,
+                payload_len,
+            });
+        }
+
+        self.check_quota(header)?;
+        let total_size = header.total_size();
+        if total_size > self.available() && !self.make_room(total_size)? {
+            return Ok(());
+        }
+
+        let header_bytes =
+            unsafe { &*(header as *const EventHeader as *const [u8; EventHeader::SIZE]) };
+        let mut pos = self.copy_in(self.head, header_bytes);
+        for part in parts {
+            pos = self.copy_in(pos, part);
+        }
+
+        self.head = pos;
+        self.stats.record_write(total_size, payload_len);
+        self.hold(header);
+        self.update_watermarks();
         Ok(())
     }
 
     #[inline]
-    pub fn read_event(&mut self) -> Option<(EventHeader, Vec<u8>)> {
-        if self.is_empty() {
-            return None;
+    fn check_type(&mut self, header: &EventHeader) -> Result<(), RingError> {
+        if EventHeader::is_reserved_type(header.event_type) {
+            self.stats.record_rejected();
+            return Err(RingError::ReservedType {
+                event_type: header.event_type,
+            });
         }
+        Ok(())
+    }
 
-        let mask = self.capacity - 1;
-        let start = self.tail;
-        let contiguous = self.capacity - start;
+    #[inline]
+    fn check_quota(&mut self, header: &EventHeader) -> Result<(), RingError> {
+        if let Some(tenants) = &mut self.tenants
+            && !tenants.check(header)
+        {
+            self.stats.record_rejected();
+            return Err(RingError::QuotaExceeded {
+                stream: header.stream(),
+            });
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn hold(&mut self, header: &EventHeader) {
+        if let Some(tenants) = &mut self.tenants {
+            tenants.hold(header);
+        }
+    }
 
+    #[inline]
+    fn release(&mut self, header: &EventHeader) {
+        if let Some(tenants) = &mut self.tenants {
+            tenants.release(header);
+        }
+    }
+
+    /// Applies the full policy when `total_size` does not fit. Returns whether
+    /// the write should go ahead; `false` means the event is dropped.
+    fn make_room(&mut self, total_size: usize) -> Result<bool, RingError> {
+        let available = self.available();
+        if total_size < self.capacity {
+            match self.policy {
+                FullPolicy::DropOldest => {
+                    while self.available() < total_size {
+                        self.skip_event();
+                        self.stats.record_evict();
+                    }
+                    return Ok(true);
+                }
+                FullPolicy::DropNewest => {
+                    self.stats.record_drop();
+                    return Ok(false);
+                }
+                FullPolicy::Error | FullPolicy::Block(_) => {}
+            }
+        }
+
+        self.stats.record_drop();
+        Err(RingError::NotEnoughSpace {
+            required: total_size,
+            available,
+        })
+    }
+
+    /// Drops every pending event.
+    pub fn clear(&mut self) {
+        self.tail = self.head;
+        if let Some(tenants) = &mut self.tenants {
+            tenants.release_all();
+        }
+        self.update_watermarks();
+    }
+
+    /// Drops up to `n` of the oldest pending events and returns how many
+    /// were dropped.
+    pub fn truncate_oldest(&mut self, n: usize) -> usize {
+        let mut dropped = 0;
+        while dropped < n && !self.is_empty() {
+            self.skip_event();
+            dropped += 1;
+        }
+        self.update_watermarks();
+        dropped
+    }
+
+    /// Drops the oldest pending events until at most `bytes` remain, and
+    /// returns how many were dropped.
+    pub fn truncate_to_bytes(&mut self, bytes: usize) -> usize {
+        let mut dropped = 0;
+        while self.used() > bytes {
+            self.skip_event();
+            dropped += 1;
+        }
+        self.update_watermarks();
+        dropped
+    }
+
+    fn skip_event(&mut self) {
+        let header = self.header_at(self.tail);
+        self.tail = (self.tail + header.total_size()) & (self.capacity - 1);
+        self.release(&header);
+    }
+
+    #[inline]
+    pub(super) fn header_at(&self, start: usize) -> EventHeader {
+        let contiguous = self.capacity - start;
         unsafe {
             let buf_ptr = self.buf.as_ptr();
-
-            let header = if contiguous >= EventHeader::SIZE {
+            if contiguous >= EventHeader::SIZE {
                 ptr::read_unaligned(buf_ptr.add(start) as *const EventHeader)
             } else {
                 let mut header_bytes = [0u8; EventHeader::SIZE];
@@ -131,7 +428,68 @@ impl RingBuffer {
                     EventHeader::SIZE - contiguous,
                 );
                 ptr::read_unaligned(header_bytes.as_ptr() as *const EventHeader)
-            };
+            }
+        }
+    }
+
+    /// Moves up to `max_events` of the oldest events into `other`, stopping at
+    /// the first one that does not fit or that `other`'s tenant quotas
+    /// reject; `other`'s full policy is not applied. The events are copied as
+    /// one byte range rather than one by one. Returns how many were moved.
+    pub fn transfer_to(&mut self, other: &mut RingBuffer, max_events: usize) -> usize {
+        let room = other.available();
+        let mut pos = self.tail;
+        let mut bytes = 0;
+        let mut moved = 0;
+        while moved < max_events && pos != self.head {
+            let header = self.header_at(pos);
+            let total_size = header.total_size();
+            if bytes + total_size > room || other.check_quota(&header).is_err() {
+                break;
+            }
+            other.stats.record_write(total_size, header.payload_len as usize);
+            other.hold(&header);
+            self.release(&header);
+            bytes += total_size;
+            pos = (pos + total_size) & (self.capacity - 1);
+            moved += 1;
+        }
+        if moved == 0 {
+            return 0;
+        }
+
+        let first = bytes.min(self.capacity - self.tail);
+        let head = other.copy_in(other.head, &self.buf[self.tail..self.tail + first]);
+        other.head = other.copy_in(head, &self.buf[..bytes - first]);
+        self.tail = pos;
+
+        self.update_watermarks();
+        other.update_watermarks();
+        moved
+    }
+
+    /// Copies `src` into the buffer at `pos`, wrapping at the end, and returns
+    /// the position just past it.
+    #[inline]
+    pub(super) fn copy_in(&mut self, pos: usize, src: &[u8]) -> usize {
+        let first = src.len().min(self.capacity - pos);
+        self.buf[pos..pos + first].copy_from_slice(&src[..first]);
+        self.buf[..src.len() - first].copy_from_slice(&src[first..]);
+        (pos + src.len()) & (self.capacity - 1)
+    }
+
+    #[inline]
+    pub fn read_event(&mut self) -> Option<(EventHeader, Vec<u8>)> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let mask = self.capacity - 1;
+        let start = self.tail;
+        let header = self.header_at(start);
+
+        unsafe {
+            let buf_ptr = self.buf.as_ptr();
 
             let payload_len = header.payload_len as usize;
             let mut payload = vec![0u8; payload_len];
@@ -159,8 +517,16 @@ impl RingBuffer {
             }
 
             self.tail = (start + header.total_size()) & mask;
+            self.release(&header);
+            self.update_watermarks();
 
             Some((header, payload))
         }
     }
 }
+
+impl Drop for RingBuffer {
+    fn drop(&mut self) {
+        let _ = self.unlock_memory();
+    }
+}
@@ -0,0 +1,80 @@
+use super::RingBuffer;
+use crate::event::{EventHeader, EventView};
+use std::borrow::Cow;
+
+/// A pending event seen by `RingBuffer::iter`. The payload is borrowed from
+/// the ring unless it wraps around the end of the buffer, in which case it
+/// is copied.
+#[derive(Debug, Clone)]
+pub struct PendingEvent<'a> {
+    pub header: EventHeader,
+    pub payload: Cow<'a, [u8]>,
+}
+
+impl PendingEvent<'_> {
+    pub fn view(&self) -> EventView<'_> {
+        EventView {
+            header: self.header,
+            payload: &self.payload,
+        }
+    }
+}
+
+/// Walks the pending events of a ring, oldest first, without consuming them.
+pub struct RingIter<'a> {
+    ring: &'a RingBuffer,
+    pos: usize,
+}
+
+impl<'a> Iterator for RingIter<'a> {
+    type Item = PendingEvent<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos == self.ring.head {
+            return None;
+        }
+
+        let ring = self.ring;
+        let mask = ring.capacity - 1;
+        let header = ring.header_at(self.pos);
+        let start = (self.pos + EventHeader::SIZE) & mask;
+        let end = start + header.payload_len as usize;
+        let payload = if end <= ring.capacity {
+            Cow::Borrowed(&ring.buf[start..end])
+        } else {
+            let mut payload = ring.buf[start..].to_vec();
+            payload.extend_from_slice(&ring.buf[..end - ring.capacity]);
+            Cow::Owned(payload)
+        };
+
+        self.pos = (self.pos + header.total_size()) & mask;
+        Some(PendingEvent { header, payload })
+    }
+}
+
+impl RingBuffer {
+    /// Iterates over pending events without reading them, so the next
+    /// `read_event` still returns the first one.
+    pub fn iter(&self) -> RingIter<'_> {
+        RingIter {
+            ring: self,
+            pos: self.tail,
+        }
+    }
+
+    /// Copies every pending event, in the form `read_event` returns them.
+    pub fn to_vec(&self) -> Vec<(EventHeader, Vec<u8>)> {
+        self.iter()
+            .map(|event| (event.header, event.payload.into_owned()))
+            .collect()
+    }
+}
+
+impl<'a> IntoIterator for &'a RingBuffer {
+    type Item = PendingEvent<'a>;
+    type IntoIter = RingIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
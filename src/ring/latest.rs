@@ -0,0 +1,154 @@
+//! Seqlock slots holding the last value written, for gauge-style telemetry
+//! where readers want the current reading rather than every event. Readers
+//! never block the writer and never consume anything; a read that overlaps
+//! a write retries.
+
+use super::RingError;
+use crate::event::EventHeader;
+use std::hint;
+use std::sync::atomic::{AtomicU64, Ordering, fence};
+
+const HEADER_WORDS: usize = EventHeader::SIZE / 8;
+
+/// The most recent event stored, with payloads of up to `max_payload` bytes.
+///
+/// The sequence number is odd while a store is in progress. Data lives in
+/// atomic words so overlapping reads are torn but never undefined, and are
+/// discarded when the sequence number moved. Concurrent stores take turns.
+pub struct LatestValueSlot {
+    seq: AtomicU64,
+    max_payload: usize,
+    words: Box<[AtomicU64]>,
+}
+
+impl LatestValueSlot {
+    pub fn new(max_payload: usize) -> Self {
+        let words = HEADER_WORDS + max_payload.div_ceil(8);
+        Self {
+            seq: AtomicU64::new(0),
+            max_payload,
+            words: (0..words).map(|_| AtomicU64::new(0)).collect(),
+        }
+    }
+
+    #[inline]
+    pub fn max_payload(&self) -> usize {
+        self.max_payload
+    }
+
+    /// Number of stores so far.
+    #[inline]
+    pub fn version(&self) -> u64 {
+        self.seq.load(Ordering::Acquire) / 2
+    }
+
+    /// Replaces the stored value.
+    pub fn store(&self, header: &EventHeader, payload: &[u8]) -> Result<(), RingError> {
+        if payload.len() != header.payload_len as usize {
+            return Err(RingError::PayloadLengthMismatch {
+                header_len: header.payload_len as usize,
+                payload_len: payload.len(),
+            });
+        }
+        if payload.len() > self.max_payload {
+            return Err(RingError::PayloadTooLarge {
+                payload_len: payload.len(),
+                max_len: self.max_payload,
+            });
+        }
+
+        let mut seq = self.seq.load(Ordering::Relaxed);
+        loop {
+            if seq & 1 == 0 {
+                match self.seq.compare_exchange_weak(
+                    seq,
+                    seq + 1,
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => break,
+                    Err(current) => seq = current,
+                }
+            } else {
+                hint::spin_loop();
+                seq = self.seq.load(Ordering::Relaxed);
+            }
+        }
+        fence(Ordering::Release);
+
+        let header = header.encode();
+        for (word, bytes) in self.words.iter().zip(header.chunks(8)) {
+            word.store(
+                u64::from_le_bytes(bytes.try_into().unwrap()),
+                Ordering::Relaxed,
+            );
+        }
+        for (word, bytes) in self.words[HEADER_WORDS..].iter().zip(payload.chunks(8)) {
+            let mut padded = [0u8; 8];
+            padded[..bytes.len()].copy_from_slice(bytes);
+            word.store(u64::from_le_bytes(padded), Ordering::Relaxed);
+        }
+
+        self.seq.store(seq + 2, Ordering::Release);
+        Ok(())
+    }
+
+    /// The stored value, or `None` before the first store.
+    pub fn load(&self) -> Option<(EventHeader, Vec<u8>)> {
+        loop {
+            let before = self.seq.load(Ordering::Acquire);
+            if before == 0 {
+                return None;
+            }
+            if before & 1 == 1 {
+                hint::spin_loop();
+                continue;
+            }
+
+            let mut header = [0u8; EventHeader::SIZE];
+            for (bytes, word) in header.chunks_mut(8).zip(self.words.iter()) {
+                bytes.copy_from_slice(&word.load(Ordering::Relaxed).to_le_bytes());
+            }
+            let header = EventHeader::decode(&header);
+            let payload_len = (header.payload_len as usize).min(self.max_payload);
+            let mut payload = Vec::with_capacity(payload_len.next_multiple_of(8));
+            for word in &self.words[HEADER_WORDS..HEADER_WORDS + payload_len.div_ceil(8)] {
+                payload.extend_from_slice(&word.load(Ordering::Relaxed).to_le_bytes());
+            }
+            payload.truncate(payload_len);
+
+            fence(Ordering::Acquire);
+            if self.seq.load(Ordering::Relaxed) == before {
+                return Some((header, payload));
+            }
+        }
+    }
+}
+
+/// One `LatestValueSlot` per event type.
+pub struct LatestValues {
+    slots: Box<[LatestValueSlot]>,
+}
+
+impl LatestValues {
+    pub fn new(max_payload: usize) -> Self {
+        Self {
+            slots: (0..=u8::MAX as usize)
+                .map(|_| LatestValueSlot::new(max_payload))
+                .collect(),
+        }
+    }
+
+    /// Stores the event as the latest value of its type.
+    pub fn record(&self, header: &EventHeader, payload: &[u8]) -> Result<(), RingError> {
+        self.slots[header.event_type as usize].store(header, payload)
+    }
+
+    pub fn get(&self, event_type: u8) -> Option<(EventHeader, Vec<u8>)> {
+        self.slots[event_type as usize].load()
+    }
+
+    pub fn slot(&self, event_type: u8) -> &LatestValueSlot {
+        &self.slots[event_type as usize]
+    }
+}
@@ -1,8 +1,28 @@
 pub mod buffer;
+pub mod doorbell;
+mod dump;
 pub mod event;
+pub mod iter;
+pub mod latest;
+pub mod policy;
+pub mod quota;
 pub mod ring_error;
+mod save;
+pub mod set;
+pub mod sharded;
 pub mod spsc;
+pub mod stats;
+pub mod watermark;
 
 pub use buffer::RingBuffer;
+pub use doorbell::Doorbell;
+pub use iter::{PendingEvent, RingIter};
+pub use latest::{LatestValueSlot, LatestValues};
+pub use policy::FullPolicy;
+pub use quota::{Quota, TenantQuotas, TenantStats};
 pub use ring_error::*;
+pub use set::RingSet;
+pub use sharded::{DrainOrder, ShardProducer, ShardedConsumer, ShardedRing};
 pub use spsc::*;
+pub use stats::ProducerStats;
+pub use watermark::Watermarks;
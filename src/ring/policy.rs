@@ -0,0 +1,19 @@
+use std::time::Duration;
+
+/// What a write does when the ring has no room for the event.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FullPolicy {
+    /// Rejects the write: `RingError::NotEnoughSpace`, or `false` from an SPSC
+    /// producer.
+    #[default]
+    Error,
+    /// Discards the new event. `RingBuffer` reports success; an SPSC producer
+    /// still returns `false` since nothing was written.
+    DropNewest,
+    /// Evicts the oldest events until the new one fits.
+    DropOldest,
+    /// Waits up to the timeout for the consumer to make room, then drops the
+    /// new event. A `RingBuffer` has no concurrent consumer, so it fails
+    /// immediately as with `Error`.
+    Block(Duration),
+}
@@ -0,0 +1,174 @@
+use crate::event::{EventHeader, StreamId};
+
+/// How much of a shared ring or segment one tenant, i.e. one stream id, may
+/// hold at a time. In a ring it bounds the tenant's pending events; in the
+/// service's segment log it bounds what the tenant writes to each segment,
+/// and resets when the segment rotates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quota {
+    pub events: u64,
+    /// Header plus payload bytes.
+    pub bytes: u64,
+}
+
+impl Quota {
+    pub const UNLIMITED: Self = Self {
+        events: u64::MAX,
+        bytes: u64::MAX,
+    };
+
+    pub const fn events(events: u64) -> Self {
+        Self {
+            events,
+            bytes: u64::MAX,
+        }
+    }
+
+    pub const fn bytes(bytes: u64) -> Self {
+        Self {
+            events: u64::MAX,
+            bytes,
+        }
+    }
+
+    /// The tighter of both limits.
+    pub fn min(self, other: Self) -> Self {
+        Self {
+            events: self.events.min(other.events),
+            bytes: self.bytes.min(other.bytes),
+        }
+    }
+}
+
+impl Default for Quota {
+    fn default() -> Self {
+        Self::UNLIMITED
+    }
+}
+
+/// A quota for every tenant, with overrides for some.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TenantQuotas {
+    default: Quota,
+    streams: [Option<Quota>; StreamId::MAX as usize + 1],
+}
+
+impl TenantQuotas {
+    /// `default` applies to every stream without its own quota.
+    pub fn new(default: Quota) -> Self {
+        Self {
+            default,
+            streams: [None; StreamId::MAX as usize + 1],
+        }
+    }
+
+    pub fn with_stream(mut self, stream: StreamId, quota: Quota) -> Self {
+        self.streams[stream.get() as usize] = Some(quota);
+        self
+    }
+
+    pub fn quota(&self, stream: StreamId) -> Quota {
+        self.streams[stream.get() as usize].unwrap_or(self.default)
+    }
+}
+
+/// Counters for one tenant.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TenantStats {
+    pub events_written: u64,
+    /// Header plus payload bytes of every accepted event.
+    pub bytes_written: u64,
+    /// Events refused because they would exceed the tenant's quota.
+    pub events_rejected: u64,
+    /// What currently counts against the quota: events still pending in
+    /// the ring, or written to the current segment.
+    pub events_held: u64,
+    pub bytes_held: u64,
+}
+
+impl TenantStats {
+    fn is_idle(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Enforces `TenantQuotas` and keeps per-tenant stats.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TenantLedger {
+    quotas: TenantQuotas,
+    stats: [TenantStats; StreamId::MAX as usize + 1],
+}
+
+impl TenantLedger {
+    pub(crate) fn new(quotas: TenantQuotas) -> Self {
+        Self {
+            quotas,
+            stats: Default::default(),
+        }
+    }
+
+    pub(crate) fn quotas(&self) -> &TenantQuotas {
+        &self.quotas
+    }
+
+    pub(crate) fn set_quotas(&mut self, quotas: TenantQuotas) {
+        self.quotas = quotas;
+    }
+
+    /// Whether the event fits its tenant's quota; counts a rejection if not.
+    #[inline]
+    pub(crate) fn check(&mut self, header: &EventHeader) -> bool {
+        let quota = self.quotas.quota(header.stream());
+        let stats = &mut self.stats[header.stream().get() as usize];
+        if stats.events_held >= quota.events
+            || stats.bytes_held + header.total_size() as u64 > quota.bytes
+        {
+            stats.events_rejected += 1;
+            return false;
+        }
+        true
+    }
+
+    /// Counts a written event against its tenant.
+    #[inline]
+    pub(crate) fn hold(&mut self, header: &EventHeader) {
+        let stats = &mut self.stats[header.stream().get() as usize];
+        let total_size = header.total_size() as u64;
+        stats.events_written += 1;
+        stats.bytes_written += total_size;
+        stats.events_held += 1;
+        stats.bytes_held += total_size;
+    }
+
+    /// The event no longer counts against its tenant's quota.
+    #[inline]
+    pub(crate) fn release(&mut self, header: &EventHeader) {
+        let stats = &mut self.stats[header.stream().get() as usize];
+        stats.events_held = stats.events_held.saturating_sub(1);
+        stats.bytes_held = stats.bytes_held.saturating_sub(header.total_size() as u64);
+    }
+
+    /// Releases everything held, e.g. when the ring is cleared or a segment
+    /// rotates.
+    pub(crate) fn release_all(&mut self) {
+        for stats in &mut self.stats {
+            stats.events_held = 0;
+            stats.bytes_held = 0;
+        }
+    }
+
+    pub(crate) fn stats(&self, stream: StreamId) -> TenantStats {
+        self.stats[stream.get() as usize]
+    }
+
+    /// Stats of every tenant that wrote or was refused anything.
+    pub(crate) fn active(&self) -> Vec<(StreamId, TenantStats)> {
+        self.stats
+            .iter()
+            .enumerate()
+            .filter(|(_, stats)| !stats.is_idle())
+            .filter_map(|(id, stats)| Some((StreamId::new(id as u8)?, *stats)))
+            .collect()
+    }
+}
@@ -1,3 +1,4 @@
+use crate::event::StreamId;
 use std::fmt;
 
 #[derive(Debug)]
@@ -14,6 +15,18 @@ pub enum RingError {
         payload_len: usize,
         max_len: usize,
     },
+    PayloadLengthMismatch {
+        header_len: usize,
+        payload_len: usize,
+    },
+    /// The event's tenant already holds its `Quota` of the ring.
+    QuotaExceeded {
+        stream: StreamId,
+    },
+    /// The event type is reserved; see `EventHeader::FIRST_RESERVED_TYPE`.
+    ReservedType {
+        event_type: u8,
+    },
 }
 
 impl fmt::Display for RingError {
@@ -36,6 +49,22 @@ impl fmt::Display for RingError {
                     payload_len, max_len
                 )
             }
+            Self::PayloadLengthMismatch {
+                header_len,
+                payload_len,
+            } => {
+                write!(
+                    f,
+                    "Payload length mismatch: header declares {} bytes, got {} bytes",
+                    header_len, payload_len
+                )
+            }
+            Self::QuotaExceeded { stream } => {
+                write!(f, "Stream {} is over its ring quota", stream.get())
+            }
+            Self::ReservedType { event_type } => {
+                write!(f, "Event type {:#04x} is reserved", event_type)
+            }
         }
     }
 }
@@ -0,0 +1,111 @@
+use super::RingBuffer;
+use crate::event::{crc32, crc32_vectored};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+const MAGIC: [u8; 4] = *b"RLRS";
+const VERSION: u32 = 1;
+/// Magic, version, capacity, head, tail and the crc32 of the pending bytes.
+const HEADER_SIZE: usize = 4 + 4 + 8 + 8 + 8 + 4;
+
+impl RingBuffer {
+    /// Writes the capacity, head, tail and pending bytes to `path`, so
+    /// `restore` can rebuild the ring with the same events at the same
+    /// positions. The file is written to a temporary sibling, fsynced and
+    /// renamed into place. Returns the number of pending bytes saved.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<usize> {
+        let path = path.as_ref();
+        let mut tmp_name = path.as_os_str().to_owned();
+        tmp_name.push(".tmp");
+        let tmp_path = Path::new(&tmp_name);
+
+        let used = self.used();
+        let first = used.min(self.capacity - self.tail);
+        let pending = [
+            &self.buf[self.tail..self.tail + first],
+            &self.buf[..used - first],
+        ];
+        let mut header = Vec::with_capacity(HEADER_SIZE);
+        header.extend_from_slice(&MAGIC);
+        header.extend_from_slice(&VERSION.to_le_bytes());
+        header.extend_from_slice(&(self.capacity as u64).to_le_bytes());
+        header.extend_from_slice(&(self.head as u64).to_le_bytes());
+        header.extend_from_slice(&(self.tail as u64).to_le_bytes());
+        header.extend_from_slice(&crc32_vectored(&pending).to_le_bytes());
+
+        let result = (|| {
+            let mut file = OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(tmp_path)?;
+            file.write_all(&header)?;
+            for part in pending {
+                file.write_all(part)?;
+            }
+            file.sync_all()?;
+            fs::rename(tmp_path, path)
+        })();
+
+        if let Err(e) = result {
+            let _ = fs::remove_file(tmp_path);
+            return Err(io::Error::new(
+                e.kind(),
+                format!("Saving ring to {} failed: {}", path.display(), e),
+            ));
+        }
+        Ok(used)
+    }
+
+    /// Rebuilds a ring saved by `save`. The full policy, watermarks and
+    /// producer stats are not saved; they start from their defaults.
+    pub fn restore<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        let bytes = fs::read(path).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Failed to read saved ring {}: {}", path.display(), e),
+            )
+        })?;
+        let invalid = |reason: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} is not a saved ring: {}", path.display(), reason),
+            )
+        };
+
+        if bytes.len() < HEADER_SIZE || bytes[..4] != MAGIC {
+            return Err(invalid("bad magic".to_string()));
+        }
+        let u32_at = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        let u64_at = |at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+        let version = u32_at(4);
+        if version != VERSION {
+            return Err(invalid(format!("unsupported version {}", version)));
+        }
+        let capacity = u64_at(8) as usize;
+        let head = u64_at(16) as usize;
+        let tail = u64_at(24) as usize;
+        let checksum = u32_at(32);
+
+        let mut ring = Self::new(capacity).map_err(|e| invalid(e.to_string()))?;
+        if head >= capacity || tail >= capacity {
+            return Err(invalid("head or tail out of range".to_string()));
+        }
+        let pending = &bytes[HEADER_SIZE..];
+        if pending.len() != head.wrapping_sub(tail) & (capacity - 1) {
+            return Err(invalid(
+                "pending bytes do not match head and tail".to_string(),
+            ));
+        }
+        if crc32(pending) != checksum {
+            return Err(invalid("checksum mismatch".to_string()));
+        }
+
+        ring.copy_in(tail, pending);
+        ring.head = head;
+        ring.tail = tail;
+        Ok(ring)
+    }
+}
@@ -0,0 +1,66 @@
+use super::RingBuffer;
+use crate::event::EventHeader;
+
+/// Several named rings drained in priority order: every read takes from the
+/// highest-priority ring that has an event, so control traffic never waits
+/// behind bulk telemetry.
+#[derive(Default)]
+pub struct RingSet {
+    rings: Vec<Entry>,
+}
+
+struct Entry {
+    name: String,
+    priority: u8,
+    ring: RingBuffer,
+}
+
+impl RingSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a ring. Higher `priority` drains first; rings of equal priority
+    /// drain in the order they were added.
+    pub fn add(&mut self, name: impl Into<String>, priority: u8, ring: RingBuffer) {
+        let pos = self.rings.partition_point(|e| e.priority >= priority);
+        self.rings.insert(
+            pos,
+            Entry {
+                name: name.into(),
+                priority,
+                ring,
+            },
+        );
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut RingBuffer> {
+        self.rings
+            .iter_mut()
+            .find(|e| e.name == name)
+            .map(|e| &mut e.ring)
+    }
+
+    pub fn ring_count(&self) -> usize {
+        self.rings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rings.iter().all(|e| e.ring.is_empty())
+    }
+
+    /// Names of the rings, highest priority first. Indices match those
+    /// returned by `read_event`.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.rings.iter().map(|e| e.name.as_str())
+    }
+
+    /// Reads from the highest-priority non-empty ring, returning its index.
+    #[inline]
+    pub fn read_event(&mut self) -> Option<(usize, EventHeader, Vec<u8>)> {
+        self.rings
+            .iter_mut()
+            .enumerate()
+            .find_map(|(i, e)| e.ring.read_event().map(|(h, p)| (i, h, p)))
+    }
+}
@@ -0,0 +1,176 @@
+use super::{Consumer, Producer, RingError, SpscRingBuffer};
+use crate::consumer::EventSource;
+use crate::event::EventHeader;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// One SPSC ring per producer thread, drained together by a single consumer.
+/// Writers never contend with each other; each claims its own shard.
+pub struct ShardedRing {
+    shards: Vec<Shard>,
+    consumer_claimed: AtomicBool,
+}
+
+struct Shard {
+    ring: SpscRingBuffer,
+    claimed: AtomicBool,
+}
+
+/// How `ShardedConsumer` interleaves events from different shards.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DrainOrder {
+    /// One event from each non-empty shard in turn.
+    #[default]
+    RoundRobin,
+    /// The lowest timestamp among the events currently at the front of each
+    /// shard. Events written later with an older timestamp can still come out
+    /// after newer ones.
+    Timestamp,
+}
+
+impl ShardedRing {
+    pub fn new(shard_count: usize, capacity_per_shard: usize) -> Result<Self, RingError> {
+        let shards = (0..shard_count)
+            .map(|_| {
+                Ok(Shard {
+                    ring: SpscRingBuffer::new(capacity_per_shard)?,
+                    claimed: AtomicBool::new(false),
+                })
+            })
+            .collect::<Result<_, RingError>>()?;
+        Ok(Self {
+            shards,
+            consumer_claimed: AtomicBool::new(false),
+        })
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Claims a free shard for the calling thread. `None` when every shard
+    /// already has a producer; the shard is released when the handle drops.
+    pub fn producer(&self) -> Option<ShardProducer<'_>> {
+        self.shards.iter().enumerate().find_map(|(index, shard)| {
+            shard
+                .claimed
+                .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .ok()?;
+            Some(ShardProducer {
+                producer: shard.ring.split().0,
+                claimed: &shard.claimed,
+                index,
+            })
+        })
+    }
+
+    /// The single consumer. `None` if one is already live.
+    pub fn consumer(&self, order: DrainOrder) -> Option<ShardedConsumer<'_>> {
+        self.consumer_claimed
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .ok()?;
+        Some(ShardedConsumer {
+            consumers: self.shards.iter().map(|s| s.ring.split().1).collect(),
+            pending: (0..self.shards.len()).map(|_| None).collect(),
+            claimed: &self.consumer_claimed,
+            order,
+            next: 0,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(|s| s.ring.is_empty())
+    }
+}
+
+pub struct ShardProducer<'a> {
+    producer: Producer<'a>,
+    claimed: &'a AtomicBool,
+    index: usize,
+}
+
+impl ShardProducer<'_> {
+    pub fn shard_index(&self) -> usize {
+        self.index
+    }
+}
+
+impl<'a> Deref for ShardProducer<'a> {
+    type Target = Producer<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.producer
+    }
+}
+
+impl DerefMut for ShardProducer<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.producer
+    }
+}
+
+impl Drop for ShardProducer<'_> {
+    fn drop(&mut self) {
+        self.claimed.store(false, Ordering::Release);
+    }
+}
+
+pub struct ShardedConsumer<'a> {
+    consumers: Vec<Consumer<'a>>,
+    pending: Vec<Option<(EventHeader, Vec<u8>)>>,
+    claimed: &'a AtomicBool,
+    order: DrainOrder,
+    next: usize,
+}
+
+impl ShardedConsumer<'_> {
+    pub fn read_event(&mut self) -> Option<(EventHeader, Vec<u8>)> {
+        match self.order {
+            DrainOrder::RoundRobin => self.read_round_robin(),
+            DrainOrder::Timestamp => self.read_oldest(),
+        }
+    }
+
+    fn read_round_robin(&mut self) -> Option<(EventHeader, Vec<u8>)> {
+        let count = self.consumers.len();
+        for i in 0..count {
+            let index = (self.next + i) % count;
+            if let Some(event) = self.consumers[index].read_event() {
+                self.next = (index + 1) % count;
+                return Some(event);
+            }
+        }
+        None
+    }
+
+    fn read_oldest(&mut self) -> Option<(EventHeader, Vec<u8>)> {
+        for (slot, consumer) in self.pending.iter_mut().zip(&mut self.consumers) {
+            if slot.is_none() {
+                *slot = consumer.read_event();
+            }
+        }
+
+        let index = self
+            .pending
+            .iter()
+            .enumerate()
+            .filter_map(|(i, slot)| slot.as_ref().map(|(h, _)| (h.timestamp, i)))
+            .min()?
+            .1;
+        self.pending[index].take()
+    }
+}
+
+impl EventSource for ShardedConsumer<'_> {
+    #[inline]
+    fn next_event(&mut self) -> Option<(EventHeader, Vec<u8>)> {
+        self.read_event()
+    }
+}
+
+impl Drop for ShardedConsumer<'_> {
+    fn drop(&mut self) {
+        // Events buffered for timestamp ordering are lost with the consumer.
+        self.claimed.store(false, Ordering::Release);
+    }
+}
@@ -1,13 +1,20 @@
 use crate::event::EventHeader;
-use crate::ring::RingError;
+use crate::ring::{FullPolicy, RingError, Watermarks};
+use crate::ring::stats::{ProducerCounters, ProducerStats};
+use crate::sys;
 use std::cell::UnsafeCell;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Instant;
 pub struct SpscRingBuffer {
     buf: UnsafeCell<Box<[u8]>>,
     capacity: usize,
     mask: usize,
     head: AtomicUsize,
     tail: AtomicUsize,
+    locked: AtomicBool,
+    stats: ProducerCounters,
+    policy: FullPolicy,
+    watermarks: Option<Watermarks>,
 }
 unsafe impl Send for SpscRingBuffer {}
 unsafe impl Sync for SpscRingBuffer {}
@@ -33,8 +40,63 @@ impl SpscRingBuffer {
             mask: capacity - 1,
             head: AtomicUsize::new(0),
             tail: AtomicUsize::new(0),
+            locked: AtomicBool::new(false),
+            stats: ProducerCounters::default(),
+            policy: FullPolicy::Error,
+            watermarks: None,
         })
     }
+    /// A ring of at least `bytes`, rounded up to the next power of two and to
+    /// the 64-byte minimum. Panics only if that overflows `usize`.
+    pub fn with_at_least(bytes: usize) -> Self {
+        let capacity = bytes
+            .max(64)
+            .checked_next_power_of_two()
+            .unwrap_or_else(|| panic!("no power of two capacity holds {} bytes", bytes));
+        match Self::new(capacity) {
+            Ok(ring) => ring,
+            Err(e) => panic!("{}", e),
+        }
+    }
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+    pub fn with_full_policy(mut self, policy: FullPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+    #[inline]
+    pub fn full_policy(&self) -> FullPolicy {
+        self.policy
+    }
+    pub fn with_watermarks(mut self, watermarks: Watermarks) -> Self {
+        self.watermarks = Some(watermarks.bind(self.capacity));
+        self
+    }
+    /// Number of watermark thresholds the occupancy has reached; 0 without
+    /// watermarks.
+    #[inline]
+    pub fn pressure(&self) -> usize {
+        self.watermarks
+            .as_ref()
+            .map_or(0, |w| w.level_for(self.used()))
+    }
+    #[inline]
+    pub fn occupancy(&self) -> f64 {
+        self.used() as f64 / self.capacity as f64
+    }
+    #[inline]
+    fn used(&self) -> usize {
+        let tail = self.tail.load(Ordering::Acquire);
+        self.head.load(Ordering::Acquire).wrapping_sub(tail)
+    }
+    #[inline]
+    fn update_watermarks(&self) {
+        if let Some(watermarks) = &self.watermarks {
+            watermarks.update(self.used(), self.capacity);
+        }
+    }
     pub fn split(&self) -> (Producer<'_>, Consumer<'_>) {
         (Producer { ring: self }, Consumer { ring: self })
     }
@@ -42,6 +104,54 @@ impl SpscRingBuffer {
     pub fn is_empty(&self) -> bool {
         self.head.load(Ordering::Relaxed) == self.tail.load(Ordering::Relaxed)
     }
+    pub fn lock_memory(&self) -> std::io::Result<()> {
+        let buf = unsafe { &*self.buf.get() };
+        sys::mlock(buf.as_ptr(), buf.len())?;
+        self.locked.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+    pub fn unlock_memory(&self) -> std::io::Result<()> {
+        if self.locked.swap(false, Ordering::Relaxed) {
+            let buf = unsafe { &*self.buf.get() };
+            sys::munlock(buf.as_ptr(), buf.len())?;
+        }
+        Ok(())
+    }
+    #[inline]
+    pub fn is_memory_locked(&self) -> bool {
+        self.locked.load(Ordering::Relaxed)
+    }
+    pub fn producer_stats(&self) -> ProducerStats {
+        self.stats.snapshot()
+    }
+    #[inline]
+    fn header_at(&self, start: usize) -> EventHeader {
+        let contiguous = self.capacity - start;
+        unsafe {
+            let buf_ptr = (*self.buf.get()).as_ptr();
+            if contiguous >= EventHeader::SIZE {
+                std::ptr::read_unaligned(buf_ptr.add(start) as *const EventHeader)
+            } else {
+                let mut header_bytes = [0u8; EventHeader::SIZE];
+                std::ptr::copy_nonoverlapping(
+                    buf_ptr.add(start),
+                    header_bytes.as_mut_ptr(),
+                    contiguous,
+                );
+                std::ptr::copy_nonoverlapping(
+                    buf_ptr,
+                    header_bytes.as_mut_ptr().add(contiguous),
+                    EventHeader::SIZE - contiguous,
+                );
+                std::ptr::read_unaligned(header_bytes.as_ptr() as *const EventHeader)
+            }
+        }
+    }
+}
+impl Drop for SpscRingBuffer {
+    fn drop(&mut self) {
+        let _ = self.unlock_memory();
+    }
 }
 pub struct Producer<'a> {
     ring: &'a SpscRingBuffer,
@@ -50,13 +160,22 @@ pub struct Consumer<'a> {
     ring: &'a SpscRingBuffer,
 }
 impl Producer<'_> {
+    pub fn stats(&self) -> ProducerStats {
+        self.ring.stats.snapshot()
+    }
+    #[inline]
+    pub fn pressure(&self) -> usize {
+        self.ring.pressure()
+    }
+    #[inline]
+    pub fn available(&self) -> usize {
+        self.ring.capacity - self.ring.used() - 1
+    }
     #[inline]
     pub fn write_event(&mut self, header: &EventHeader, payload: &[u8]) -> bool {
         let total_size = header.total_size();
         let head = self.ring.head.load(Ordering::Relaxed);
-        let tail = self.ring.tail.load(Ordering::Acquire);
-        let available = self.ring.capacity - head.wrapping_sub(tail) - 1;
-        if total_size > available {
+        if !self.reserve(head, total_size) {
             return false;
         }
         let mask = self.ring.mask;
@@ -110,66 +229,156 @@ impl Producer<'_> {
         self.ring
             .head
             .store(head.wrapping_add(total_size), Ordering::Release);
+        self.ring.stats.record_write(total_size, payload.len());
+        self.ring.update_watermarks();
         true
     }
+    #[inline]
+    pub fn write_event_vectored(&mut self, header: &EventHeader, parts: &[&[u8]]) -> bool {
+        let payload_len: usize = parts.iter().map(|p| p.len()).sum();
+        if payload_len != header.payload_len as usize {
+            self.ring.stats.record_rejected();
+            return false;
+        }
+        let total_size = header.total_size();
+        let head = self.ring.head.load(Ordering::Relaxed);
+        if !self.reserve(head, total_size) {
+            return false;
+        }
+        let header_bytes =
+            unsafe { &*(header as *const EventHeader as *const [u8; EventHeader::SIZE]) };
+        let mut pos = head & self.ring.mask;
+        pos = self.copy_in(pos, header_bytes);
+        for part in parts {
+            pos = self.copy_in(pos, part);
+        }
+        self.ring
+            .head
+            .store(head.wrapping_add(total_size), Ordering::Release);
+        self.ring.stats.record_write(total_size, payload_len);
+        self.ring.update_watermarks();
+        true
+    }
+    /// Makes `total_size` bytes free past `head`, applying the ring's full
+    /// policy. Returns `false` if the event has to be dropped.
+    #[inline]
+    fn reserve(&mut self, head: usize, total_size: usize) -> bool {
+        let ring = self.ring;
+        let fits = |tail: usize| total_size < ring.capacity - head.wrapping_sub(tail);
+        if fits(ring.tail.load(Ordering::Acquire)) {
+            return true;
+        }
+        if total_size < ring.capacity {
+            match ring.policy {
+                FullPolicy::DropOldest => loop {
+                    // The consumer may advance `tail` concurrently; whoever
+                    // wins the exchange owns the event.
+                    let tail = ring.tail.load(Ordering::Acquire);
+                    if fits(tail) {
+                        return true;
+                    }
+                    let size = ring.header_at(tail & ring.mask).total_size();
+                    if ring
+                        .tail
+                        .compare_exchange(
+                            tail,
+                            tail.wrapping_add(size),
+                            Ordering::AcqRel,
+                            Ordering::Acquire,
+                        )
+                        .is_ok()
+                    {
+                        ring.stats.record_evict();
+                    }
+                },
+                FullPolicy::Block(timeout) => {
+                    let deadline = Instant::now() + timeout;
+                    while Instant::now() < deadline {
+                        std::thread::yield_now();
+                        if fits(ring.tail.load(Ordering::Acquire)) {
+                            return true;
+                        }
+                    }
+                }
+                FullPolicy::Error | FullPolicy::DropNewest => {}
+            }
+        }
+        ring.stats.record_drop();
+        false
+    }
+    #[inline]
+    fn copy_in(&mut self, pos: usize, src: &[u8]) -> usize {
+        let capacity = self.ring.capacity;
+        let first = src.len().min(capacity - pos);
+        unsafe {
+            let buf_ptr = (*self.ring.buf.get()).as_mut_ptr();
+            std::ptr::copy_nonoverlapping(src.as_ptr(), buf_ptr.add(pos), first);
+            std::ptr::copy_nonoverlapping(src.as_ptr().add(first), buf_ptr, src.len() - first);
+        }
+        (pos + src.len()) & self.ring.mask
+    }
 }
 impl Consumer<'_> {
     #[inline]
     pub fn read_event(&mut self) -> Option<(EventHeader, Vec<u8>)> {
-        let tail = self.ring.tail.load(Ordering::Relaxed);
-        let head = self.ring.head.load(Ordering::Acquire);
-        if head == tail {
-            return None;
-        }
-        let mask = self.ring.mask;
-        let start = tail & mask;
-        let contiguous = self.ring.capacity - start;
-        unsafe {
-            let buf = &*self.ring.buf.get();
-            let buf_ptr = buf.as_ptr();
-            let header = if contiguous >= EventHeader::SIZE {
-                std::ptr::read_unaligned(buf_ptr.add(start) as *const EventHeader)
-            } else {
-                let mut header_bytes = [0u8; EventHeader::SIZE];
-                std::ptr::copy_nonoverlapping(
-                    buf_ptr.add(start),
-                    header_bytes.as_mut_ptr(),
-                    contiguous,
-                );
-                std::ptr::copy_nonoverlapping(
-                    buf_ptr,
-                    header_bytes.as_mut_ptr().add(contiguous),
-                    EventHeader::SIZE - contiguous,
-                );
-                std::ptr::read_unaligned(header_bytes.as_ptr() as *const EventHeader)
-            };
+        loop {
+            let tail = self.ring.tail.load(Ordering::Acquire);
+            let head = self.ring.head.load(Ordering::Acquire);
+            if head == tail {
+                return None;
+            }
+            let mask = self.ring.mask;
+            let start = tail & mask;
+            let header = self.ring.header_at(start);
+            let total_size = header.total_size();
+            if total_size > head.wrapping_sub(tail) {
+                // Torn read of an event the producer evicted under us.
+                if self.ring.tail.load(Ordering::Acquire) != tail {
+                    continue;
+                }
+                return None;
+            }
             let payload_len = header.payload_len as usize;
             let mut payload = vec![0u8; payload_len];
             let payload_start = (start + EventHeader::SIZE) & mask;
             let payload_contiguous = self.ring.capacity - payload_start;
-            if payload_len <= payload_contiguous {
-                std::ptr::copy_nonoverlapping(
-                    buf_ptr.add(payload_start),
-                    payload.as_mut_ptr(),
-                    payload_len,
-                );
-            } else {
-                std::ptr::copy_nonoverlapping(
-                    buf_ptr.add(payload_start),
-                    payload.as_mut_ptr(),
-                    payload_contiguous,
-                );
-                std::ptr::copy_nonoverlapping(
-                    buf_ptr,
-                    payload.as_mut_ptr().add(payload_contiguous),
-                    payload_len - payload_contiguous,
-                );
+            unsafe {
+                let buf_ptr = (*self.ring.buf.get()).as_ptr();
+                if payload_len <= payload_contiguous {
+                    std::ptr::copy_nonoverlapping(
+                        buf_ptr.add(payload_start),
+                        payload.as_mut_ptr(),
+                        payload_len,
+                    );
+                } else {
+                    std::ptr::copy_nonoverlapping(
+                        buf_ptr.add(payload_start),
+                        payload.as_mut_ptr(),
+                        payload_contiguous,
+                    );
+                    std::ptr::copy_nonoverlapping(
+                        buf_ptr,
+                        payload.as_mut_ptr().add(payload_contiguous),
+                        payload_len - payload_contiguous,
+                    );
+                }
             }
-            let total_size = header.total_size();
-            self.ring
+            // Fails only if a `DropOldest` producer evicted this event while
+            // it was being copied.
+            if self
+                .ring
                 .tail
-                .store(tail.wrapping_add(total_size), Ordering::Release);
-            Some((header, payload))
+                .compare_exchange(
+                    tail,
+                    tail.wrapping_add(total_size),
+                    Ordering::AcqRel,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
+            {
+                self.ring.update_watermarks();
+                return Some((header, payload));
+            }
         }
     }
     #[inline]
@@ -0,0 +1,69 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Counters for the writing side of a ring.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ProducerStats {
+    pub events_attempted: u64,
+    /// New events lost because the ring was full.
+    pub events_dropped: u64,
+    /// Old events discarded to make room under `FullPolicy::DropOldest`.
+    pub events_evicted: u64,
+    /// Header plus payload bytes of every accepted event.
+    pub bytes_written: u64,
+    pub largest_payload: u64,
+}
+
+/// Shared so a ring's stats can be read while its single producer writes.
+/// Only the producer updates them, so plain relaxed load/store suffices.
+#[derive(Debug, Default)]
+pub(crate) struct ProducerCounters {
+    events_attempted: AtomicU64,
+    events_dropped: AtomicU64,
+    events_evicted: AtomicU64,
+    bytes_written: AtomicU64,
+    largest_payload: AtomicU64,
+}
+
+impl ProducerCounters {
+    #[inline]
+    pub(crate) fn record_write(&self, total_size: usize, payload_len: usize) {
+        bump(&self.events_attempted, 1);
+        bump(&self.bytes_written, total_size as u64);
+        if payload_len as u64 > self.largest_payload.load(Ordering::Relaxed) {
+            self.largest_payload
+                .store(payload_len as u64, Ordering::Relaxed);
+        }
+    }
+
+    #[inline]
+    pub(crate) fn record_drop(&self) {
+        bump(&self.events_attempted, 1);
+        bump(&self.events_dropped, 1);
+    }
+
+    #[inline]
+    pub(crate) fn record_evict(&self) {
+        bump(&self.events_evicted, 1);
+    }
+
+    #[inline]
+    pub(crate) fn record_rejected(&self) {
+        bump(&self.events_attempted, 1);
+    }
+
+    pub(crate) fn snapshot(&self) -> ProducerStats {
+        ProducerStats {
+            events_attempted: self.events_attempted.load(Ordering::Relaxed),
+            events_dropped: self.events_dropped.load(Ordering::Relaxed),
+            events_evicted: self.events_evicted.load(Ordering::Relaxed),
+            bytes_written: self.bytes_written.load(Ordering::Relaxed),
+            largest_payload: self.largest_payload.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[inline]
+fn bump(counter: &AtomicU64, n: u64) {
+    counter.store(counter.load(Ordering::Relaxed) + n, Ordering::Relaxed);
+}
@@ -0,0 +1,82 @@
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+type Callback = Box<dyn Fn(usize, f64) + Send + Sync>;
+
+/// Occupancy thresholds for a ring. The pressure level is the number of
+/// thresholds the current occupancy has reached, so with the defaults
+/// (50%, 80%, 95%) it runs from 0 to 3.
+pub struct Watermarks {
+    thresholds: Vec<f64>,
+    bytes: Vec<usize>,
+    level: AtomicUsize,
+    callback: Option<Callback>,
+}
+
+impl Watermarks {
+    /// `thresholds` are fractions of capacity in `(0, 1]`; they are sorted.
+    pub fn new(thresholds: &[f64]) -> Self {
+        let mut thresholds = thresholds.to_vec();
+        thresholds.sort_by(f64::total_cmp);
+        Self {
+            thresholds,
+            bytes: Vec::new(),
+            level: AtomicUsize::new(0),
+            callback: None,
+        }
+    }
+
+    /// Called with `(new_level, occupancy)` whenever the level changes. Runs on
+    /// whichever side (producer or consumer) observed the change.
+    pub fn on_change<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(usize, f64) + Send + Sync + 'static,
+    {
+        self.callback = Some(Box::new(callback));
+        self
+    }
+
+    pub fn thresholds(&self) -> &[f64] {
+        &self.thresholds
+    }
+
+    pub(crate) fn bind(mut self, capacity: usize) -> Self {
+        self.bytes = self
+            .thresholds
+            .iter()
+            .map(|t| (t * capacity as f64).ceil() as usize)
+            .collect();
+        self
+    }
+
+    #[inline]
+    pub(crate) fn level_for(&self, used: usize) -> usize {
+        self.bytes.iter().take_while(|&&b| used >= b).count()
+    }
+
+    #[inline]
+    pub(crate) fn update(&self, used: usize, capacity: usize) {
+        let level = self.level_for(used);
+        if self.level.load(Ordering::Relaxed) != level
+            && self.level.swap(level, Ordering::Relaxed) != level
+            && let Some(callback) = &self.callback
+        {
+            callback(level, used as f64 / capacity as f64);
+        }
+    }
+}
+
+impl Default for Watermarks {
+    fn default() -> Self {
+        Self::new(&[0.5, 0.8, 0.95])
+    }
+}
+
+impl fmt::Debug for Watermarks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Watermarks")
+            .field("thresholds", &self.thresholds)
+            .field("level", &self.level.load(Ordering::Relaxed))
+            .finish()
+    }
+}
@@ -0,0 +1,96 @@
+//! The panic hook behind `ring.crash_dump`. It runs before unwinding starts,
+//! and also when panics abort, and writes the undrained events of each
+//! registered ring to its dump file before handing over to the hook that was
+//! installed before it.
+
+use crate::ring::RingBuffer;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, Once};
+use std::thread::{self, ThreadId};
+
+struct Entry {
+    id: u64,
+    ring: *const RingBuffer,
+    path: PathBuf,
+    /// The thread driving the ring; panics elsewhere leave it alone, as it
+    /// may be mid-write.
+    owner: ThreadId,
+}
+
+// The ring is only read from its owner thread, see `dump_owned`.
+unsafe impl Send for Entry {}
+
+static ENTRIES: Mutex<Vec<Entry>> = Mutex::new(Vec::new());
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+static INSTALL: Once = Once::new();
+
+/// Registers a ring until the guard is dropped. The ring must stay at its
+/// address meanwhile, and whichever thread goes on to drive it must `claim`
+/// it first.
+pub(super) struct CrashDump {
+    id: u64,
+    owner: ThreadId,
+}
+
+impl CrashDump {
+    pub(super) fn register(ring: &RingBuffer, path: PathBuf) -> Self {
+        INSTALL.call_once(|| {
+            let previous = std::panic::take_hook();
+            std::panic::set_hook(Box::new(move |info| {
+                dump_owned();
+                previous(info);
+            }));
+        });
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let owner = thread::current().id();
+        ENTRIES
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(Entry {
+                id,
+                ring,
+                path,
+                owner,
+            });
+        Self { id, owner }
+    }
+
+    /// Makes the calling thread the ring's owner, if it is not already.
+    pub(super) fn claim(&mut self) {
+        let current = thread::current().id();
+        if current == self.owner {
+            return;
+        }
+        self.owner = current;
+        let mut entries = ENTRIES.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(entry) = entries.iter_mut().find(|entry| entry.id == self.id) {
+            entry.owner = current;
+        }
+    }
+}
+
+impl Drop for CrashDump {
+    fn drop(&mut self) {
+        let mut entries = ENTRIES.lock().unwrap_or_else(|e| e.into_inner());
+        entries.retain(|entry| entry.id != self.id);
+    }
+}
+
+/// Dumps the non-empty rings owned by the panicking thread. Skipped if the
+/// registry is locked, which only happens when the panic came from inside it.
+fn dump_owned() {
+    let Ok(entries) = ENTRIES.try_lock() else {
+        return;
+    };
+    let current = thread::current().id();
+    for entry in entries.iter().filter(|entry| entry.owner == current) {
+        // SAFETY: the entry is removed before its ring is freed or moved,
+        // and no other thread writes to it, so the only writer is stopped in
+        // this hook.
+        let ring = unsafe { &*entry.ring };
+        if !ring.is_empty() {
+            let _ = ring.dump_to_file(&entry.path);
+        }
+    }
+}
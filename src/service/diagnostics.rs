@@ -0,0 +1,194 @@
+use crate::admin::escape_json;
+use crate::ring::{ProducerStats, TenantStats};
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Point-in-time view of a `RinglogService` for health endpoints and crash
+/// reports. Serializable with the `serde` feature, or as JSON with `to_json`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Diagnostics {
+    pub uptime: Duration,
+    pub ring: RingDiagnostics,
+    pub drain: DrainDiagnostics,
+    pub consumers: Vec<ConsumerDiagnostics>,
+    /// `None` when the segment consumer is disabled.
+    pub storage: Option<StorageDiagnostics>,
+    /// Tenants (stream ids) that wrote or were refused anything.
+    pub tenants: Vec<TenantDiagnostics>,
+    pub errors: ErrorCounts,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct RingDiagnostics {
+    pub capacity: usize,
+    pub used: usize,
+    pub producer: ProducerStats,
+}
+
+/// Totals across every drain since startup.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DrainDiagnostics {
+    pub events_read: u64,
+    pub events_delivered: u64,
+    pub events_failed: u64,
+    pub bytes_read: u64,
+    pub heartbeats: u64,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ConsumerDiagnostics {
+    pub name: String,
+    pub healthy: bool,
+    pub quarantined: bool,
+    pub delivered: u64,
+    pub failed: u64,
+    pub consecutive_failures: u64,
+    /// Time since the last failed delivery.
+    pub since_last_failure: Option<Duration>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct StorageDiagnostics {
+    pub dir: PathBuf,
+    /// Sequence number of the segment being written.
+    pub segment: u64,
+    pub segment_used: usize,
+    pub segment_capacity: usize,
+    /// Segment files in `dir`, including the one being written.
+    pub segments: usize,
+    pub bytes_on_disk: u64,
+    /// When the last `MS_SYNC` of the segment being written completed.
+    pub last_sync: Option<SystemTime>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TenantDiagnostics {
+    pub stream: u8,
+    pub ring: TenantStats,
+    /// `None` when the segment consumer is disabled.
+    pub segment: Option<TenantStats>,
+}
+
+/// Failures the service recovered from or reported without stopping.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ErrorCounts {
+    /// Events a consumer refused.
+    pub delivery: u64,
+    pub sync: u64,
+    pub rotation: u64,
+    pub retention: u64,
+}
+
+impl Diagnostics {
+    /// Durations are in seconds, `last_sync` in milliseconds since the Unix
+    /// epoch.
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        let producer = &self.ring.producer;
+        let _ = write!(
+            json,
+            "{{\"uptime_secs\":{:.3},\"ring\":{{\"capacity\":{},\"used\":{},\"events_attempted\":{},\"events_dropped\":{},\"events_evicted\":{},\"bytes_written\":{}}},",
+            self.uptime.as_secs_f64(),
+            self.ring.capacity,
+            self.ring.used,
+            producer.events_attempted,
+            producer.events_dropped,
+            producer.events_evicted,
+            producer.bytes_written
+        );
+        let _ = write!(
+            json,
+            "\"drain\":{{\"events_read\":{},\"events_delivered\":{},\"events_failed\":{},\"bytes_read\":{},\"heartbeats\":{}}},",
+            self.drain.events_read,
+            self.drain.events_delivered,
+            self.drain.events_failed,
+            self.drain.bytes_read,
+            self.drain.heartbeats
+        );
+        json.push_str("\"consumers\":[");
+        for (i, consumer) in self.consumers.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            let _ = write!(
+                json,
+                "{{\"name\":\"{}\",\"healthy\":{},\"quarantined\":{},\"delivered\":{},\"failed\":{},\"consecutive_failures\":{},\"since_last_failure_secs\":{}}}",
+                escape_json(&consumer.name),
+                consumer.healthy,
+                consumer.quarantined,
+                consumer.delivered,
+                consumer.failed,
+                consumer.consecutive_failures,
+                optional(
+                    consumer
+                        .since_last_failure
+                        .map(|d| format!("{:.3}", d.as_secs_f64()))
+                )
+            );
+        }
+        json.push_str("],\"storage\":");
+        match &self.storage {
+            Some(storage) => {
+                let last_sync = storage.last_sync.map(|t| {
+                    let millis = t.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis());
+                    millis.to_string()
+                });
+                let _ = write!(
+                    json,
+                    "{{\"dir\":\"{}\",\"segment\":{},\"segment_used\":{},\"segment_capacity\":{},\"segments\":{},\"bytes_on_disk\":{},\"last_sync_unix_ms\":{}}}",
+                    escape_json(&storage.dir.display().to_string()),
+                    storage.segment,
+                    storage.segment_used,
+                    storage.segment_capacity,
+                    storage.segments,
+                    storage.bytes_on_disk,
+                    optional(last_sync)
+                );
+            }
+            None => json.push_str("null"),
+        }
+        json.push_str(",\"tenants\":[");
+        for (i, tenant) in self.tenants.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            let _ = write!(
+                json,
+                "{{\"stream\":{},\"ring\":{},\"segment\":{}}}",
+                tenant.stream,
+                tenant_json(&tenant.ring),
+                optional(tenant.segment.as_ref().map(tenant_json))
+            );
+        }
+        json.push(']');
+        let _ = write!(
+            json,
+            ",\"errors\":{{\"delivery\":{},\"sync\":{},\"rotation\":{},\"retention\":{}}}}}",
+            self.errors.delivery, self.errors.sync, self.errors.rotation, self.errors.retention
+        );
+        json
+    }
+}
+
+fn tenant_json(stats: &TenantStats) -> String {
+    format!(
+        "{{\"events_written\":{},\"bytes_written\":{},\"events_rejected\":{},\"events_held\":{},\"bytes_held\":{}}}",
+        stats.events_written,
+        stats.bytes_written,
+        stats.events_rejected,
+        stats.events_held,
+        stats.bytes_held
+    )
+}
+
+fn optional(value: Option<String>) -> String {
+    value.unwrap_or_else(|| "null".to_string())
+}
@@ -0,0 +1,641 @@
+//! The collector service: a ring drained into segment files and the other
+//! consumers enabled in a `RinglogConfig`. The `ringlog` binary wraps it
+//! with signal handling and admin front-ends; embedders drive `poll` from
+//! their own loop.
+
+mod crash;
+mod diagnostics;
+
+pub use diagnostics::{
+    ConsumerDiagnostics, Diagnostics, DrainDiagnostics, ErrorCounts, RingDiagnostics,
+    StorageDiagnostics, TenantDiagnostics,
+};
+
+use crate::admin::{AdminCommand, AdminQueue, LogLevel, Status};
+use crate::config::{
+    PrintConsumerConfig, RetentionConfig, RingConfig, RinglogConfig, SyncConfig, SyncPolicy,
+};
+use crate::consumer::EventConsumer;
+use crate::consumer::dispatcher::{DrainStats, EventDispatcher};
+use crate::event::{EventHeader, EventView};
+use crate::instrument::{instrument_event, instrument_span};
+use crate::ring::quota::TenantLedger;
+use crate::ring::{RingBuffer, TenantQuotas};
+use crate::service::crash::CrashDump;
+use crate::storage::{
+    MmapWriter, SegmentedReader, WriterOptions, index_path, list_segments, segment_path,
+};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+pub struct RinglogService {
+    /// Declared first so it is unregistered before the ring is freed.
+    crash_dump: Option<CrashDump>,
+    config: RinglogConfig,
+    /// Boxed so the crash dump hook can find it after the service moves.
+    ring: Box<RingBuffer>,
+    dispatcher: EventDispatcher,
+    log: Option<Arc<Mutex<SegmentLog>>>,
+    totals: DrainStats,
+    log_level: LogLevel,
+    /// Segment retention was last applied after.
+    retained_seq: u64,
+    started: Instant,
+}
+
+/// What `RinglogService::shutdown` did with the log directory.
+#[derive(Debug, Clone)]
+pub struct ShutdownReport {
+    pub dir: PathBuf,
+    /// False when this run's segments were removed (`storage.ephemeral`).
+    pub kept: bool,
+    pub segments: usize,
+    pub events: u64,
+    /// Totals across every drain since startup.
+    pub totals: DrainStats,
+}
+
+impl RinglogService {
+    pub fn new(config: RinglogConfig) -> io::Result<Self> {
+        let ring = RingBuffer::new(config.ring.capacity)
+            .map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Failed to create ring buffer: {}", e),
+                )
+            })?
+            .with_quotas(TenantQuotas::new(config.quotas.ring));
+        let mut dispatcher = EventDispatcher::new();
+        dispatcher.set_heartbeat(config.ring.heartbeat);
+
+        let log = if config.consumers.segment {
+            let log = SegmentLog::create(&config)?;
+            let log = Arc::new(Mutex::new(log));
+            dispatcher.add_consumer(SegmentConsumer { log: log.clone() });
+            Some(log)
+        } else {
+            None
+        };
+        if config.consumers.print.enabled {
+            dispatcher.add_consumer(PrintConsumer {
+                config: config.consumers.print.clone(),
+            });
+        }
+
+        let ring = Box::new(ring);
+        let crash_dump = config
+            .ring
+            .crash_dump
+            .clone()
+            .map(|path| CrashDump::register(&ring, path));
+        let retained_seq = log.as_ref().map_or(0, |log| log.lock().unwrap().seq);
+        Ok(Self {
+            crash_dump,
+            log_level: config.log_level,
+            config,
+            ring,
+            dispatcher,
+            log,
+            totals: DrainStats::default(),
+            retained_seq,
+            started: Instant::now(),
+        })
+    }
+
+    pub fn config(&self) -> &RinglogConfig {
+        &self.config
+    }
+
+    pub fn ring(&self) -> &RingBuffer {
+        &self.ring
+    }
+
+    /// For producers running in the service's thread.
+    pub fn ring_mut(&mut self) -> &mut RingBuffer {
+        self.claim_ring();
+        &mut self.ring
+    }
+
+    /// For adding consumers beyond those in the config.
+    pub fn dispatcher_mut(&mut self) -> &mut EventDispatcher {
+        &mut self.dispatcher
+    }
+
+    pub fn totals(&self) -> &DrainStats {
+        &self.totals
+    }
+
+    pub fn log_level(&self) -> LogLevel {
+        self.log_level
+    }
+
+    pub fn set_log_level(&mut self, level: LogLevel) {
+        self.log_level = level;
+    }
+
+    /// Sequence number of the segment being written, if segments are
+    /// enabled.
+    pub fn segment(&self) -> Option<u64> {
+        self.log.as_ref().map(|log| log.lock().unwrap().seq)
+    }
+
+    /// Drains the ring, then rotates by age and applies retention as the
+    /// config asks. Errors from rotation or retention leave the drained
+    /// events delivered and counted in `totals`.
+    pub fn poll(&mut self) -> io::Result<DrainStats> {
+        self.claim_ring();
+        let stats = self.dispatcher.drain(&mut *self.ring);
+        self.totals.merge(&stats);
+
+        if let Some(max_age) = self.config.rotation.max_age
+            && self
+                .log
+                .as_ref()
+                .is_some_and(|log| log.lock().unwrap().opened.elapsed() >= max_age)
+        {
+            self.rotate()?;
+        }
+        if self.segment().is_some_and(|seq| seq != self.retained_seq) {
+            self.apply_retention()?;
+        }
+        Ok(stats)
+    }
+
+    /// Seals the current segment, starts the next one and applies
+    /// retention.
+    pub fn rotate(&mut self) -> io::Result<u64> {
+        let seq = self.segment_log()?.lock().unwrap().rotate_counted()?;
+        self.apply_retention()?;
+        Ok(seq)
+    }
+
+    /// Waits for the current segment to reach disk.
+    pub fn flush(&self) -> io::Result<()> {
+        match &self.log {
+            Some(log) => log.lock().unwrap().sync(),
+            None => Ok(()),
+        }
+    }
+
+    /// Deletes sealed segments that fall outside `retention`, returning
+    /// their paths.
+    pub fn apply_retention(&mut self) -> io::Result<Vec<PathBuf>> {
+        let (seq, removed) = {
+            let mut log = self.segment_log()?.lock().unwrap();
+            let result = log.apply_retention(&self.config.retention);
+            if result.is_err() {
+                log.errors.retention += 1;
+            }
+            (log.seq, result?)
+        };
+        self.retained_seq = seq;
+        Ok(removed)
+    }
+
+    /// Applies the settings of `config` that can change while running: log
+    /// level, heartbeat, crash dump path, rotation, retention, sync and
+    /// quotas. Returns the sections whose changes need a restart and were
+    /// ignored.
+    pub fn reload(&mut self, config: RinglogConfig) -> Vec<&'static str> {
+        let mut ignored = Vec::new();
+        if config.ring.capacity != self.config.ring.capacity {
+            ignored.push("ring.capacity");
+        }
+        if config.storage != self.config.storage {
+            ignored.push("storage");
+        }
+        if config.consumers != self.config.consumers {
+            ignored.push("consumers");
+        }
+        if config.admin != self.config.admin {
+            ignored.push("admin");
+        }
+
+        self.log_level = config.log_level;
+        self.dispatcher.set_heartbeat(config.ring.heartbeat);
+        self.claim_ring();
+        self.ring.set_quotas(TenantQuotas::new(config.quotas.ring));
+        if let Some(log) = &self.log {
+            let mut log = log.lock().unwrap();
+            log.sync = config.sync.clone();
+            log.tenants
+                .set_quotas(TenantQuotas::new(config.quotas.segment));
+        }
+        self.config = RinglogConfig {
+            ring: RingConfig {
+                capacity: self.config.ring.capacity,
+                ..config.ring
+            },
+            storage: self.config.storage.clone(),
+            consumers: self.config.consumers.clone(),
+            admin: self.config.admin.clone(),
+            ..config
+        };
+        ignored
+    }
+
+    pub fn status(&self) -> Status {
+        Status {
+            ring_capacity: self.ring.capacity(),
+            ring_used: self.ring.used(),
+            drain: self.totals.clone(),
+            consumers: self.dispatcher.consumer_health().cloned().collect(),
+            segment: self.segment(),
+            log_level: self.log_level,
+        }
+    }
+
+    /// Ring, consumer, storage and error state, for health endpoints and
+    /// crash reports. Lists the log directory to size it.
+    pub fn diagnostics(&self) -> Diagnostics {
+        let drain = &self.totals;
+        let mut errors = ErrorCounts {
+            delivery: drain.events_failed,
+            ..Default::default()
+        };
+        let mut segment_tenants = None;
+        let storage = self.log.as_ref().map(|log| {
+            let log = log.lock().unwrap();
+            segment_tenants = Some(log.tenants.clone());
+            errors.sync = log.errors.sync;
+            errors.rotation = log.errors.rotation;
+            errors.retention = log.errors.retention;
+            let segments = list_segments(&log.dir).unwrap_or_default();
+            StorageDiagnostics {
+                dir: log.dir.clone(),
+                segment: log.seq,
+                segment_used: log.writer.write_offset(),
+                segment_capacity: log.capacity,
+                segments: segments.len(),
+                bytes_on_disk: segments
+                    .iter()
+                    .filter_map(|s| fs::metadata(&s.path).ok())
+                    .map(|m| m.len())
+                    .sum(),
+                last_sync: log.last_synced,
+            }
+        });
+
+        let streams: BTreeSet<_> = self
+            .ring
+            .active_tenants()
+            .into_iter()
+            .chain(segment_tenants.iter().flat_map(TenantLedger::active))
+            .map(|(stream, _)| stream)
+            .collect();
+        let tenants = streams
+            .into_iter()
+            .map(|stream| TenantDiagnostics {
+                stream: stream.get(),
+                ring: self.ring.tenant_stats(stream),
+                segment: segment_tenants.as_ref().map(|t| t.stats(stream)),
+            })
+            .collect();
+
+        Diagnostics {
+            uptime: self.started.elapsed(),
+            ring: RingDiagnostics {
+                capacity: self.ring.capacity(),
+                used: self.ring.used(),
+                producer: self.ring.producer_stats(),
+            },
+            drain: DrainDiagnostics {
+                events_read: drain.events_read,
+                events_delivered: drain.events_delivered,
+                events_failed: drain.events_failed,
+                bytes_read: drain.bytes_read,
+                heartbeats: drain.heartbeats,
+            },
+            consumers: self
+                .dispatcher
+                .consumer_health()
+                .map(|health| ConsumerDiagnostics {
+                    name: health.name.clone(),
+                    healthy: health.is_healthy(),
+                    quarantined: health.quarantined,
+                    delivered: health.delivered,
+                    failed: health.failed,
+                    consecutive_failures: health.consecutive_failures,
+                    since_last_failure: health.last_failure.map(|t| t.elapsed()),
+                })
+                .collect(),
+            storage,
+            tenants,
+            errors,
+        }
+    }
+
+    /// Runs commands queued by admin front-ends.
+    pub fn handle_admin_commands(&mut self, queue: &AdminQueue) {
+        while let Some(request) = queue.try_next() {
+            let result = match request.command() {
+                AdminCommand::Flush => self
+                    .flush()
+                    .map(|()| "flushed".to_string())
+                    .map_err(|e| e.to_string()),
+                AdminCommand::Rotate => self
+                    .rotate()
+                    .map(|seq| format!("rotated to segment {}", seq))
+                    .map_err(|e| e.to_string()),
+                AdminCommand::SetLogLevel(level) => {
+                    self.log_level = *level;
+                    Ok(format!("log level set to {}", level))
+                }
+                AdminCommand::QuarantineConsumer { name, quarantined } => {
+                    if self.dispatcher.set_quarantined(name, *quarantined) {
+                        let state = if *quarantined {
+                            "quarantined"
+                        } else {
+                            "released"
+                        };
+                        Ok(format!("{} {}", state, name))
+                    } else {
+                        Err(format!("no consumer named {}", name))
+                    }
+                }
+            };
+            request.reply(result);
+        }
+    }
+
+    /// Delivers events still in the ring, then seals the last segment and
+    /// keeps the log directory. When `storage.ephemeral` is set, removes the
+    /// segments this run wrote instead, and the directory if that leaves it
+    /// empty; segments from earlier runs are kept.
+    pub fn shutdown(mut self) -> io::Result<ShutdownReport> {
+        self.claim_ring();
+        let stats = self.dispatcher.drain(&mut *self.ring);
+        self.totals.merge(&stats);
+        self.dispatcher = EventDispatcher::new();
+
+        let dir = self.config.storage.dir.clone();
+        let mut report = ShutdownReport {
+            dir: dir.clone(),
+            kept: !self.config.storage.ephemeral,
+            segments: 0,
+            events: 0,
+            totals: self.totals.clone(),
+        };
+        let Some(log) = self.log.take() else {
+            return Ok(report);
+        };
+        let mut log = Arc::into_inner(log)
+            .expect("the dispatcher held the only other handle")
+            .into_inner()
+            .unwrap();
+
+        if self.config.storage.ephemeral {
+            let (first, last) = (log.first_seq, log.seq);
+            drop(log);
+            for seq in first..=last {
+                let path = segment_path(&dir, seq);
+                match fs::remove_file(&path) {
+                    // Retention may already have removed it.
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                        return Err(io::Error::new(
+                            e.kind(),
+                            format!("Failed to remove segment {}: {}", path.display(), e),
+                        ));
+                    }
+                    _ => {}
+                }
+                let _ = fs::remove_file(index_path(&path));
+            }
+            // Fails, leaving the directory, while other files remain.
+            let _ = fs::remove_dir(&dir);
+            return Ok(report);
+        }
+
+        log.writer.seal().map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Failed to seal segment {}: {}", log.seq, e),
+            )
+        })?;
+        let reader = SegmentedReader::open(&dir)?;
+        report.segments = reader.segment_count();
+        report.events = reader.event_count();
+        Ok(report)
+    }
+
+    /// Called before the ring is written, so a crash dump hook only reads
+    /// it on the thread driving it.
+    fn claim_ring(&mut self) {
+        if let Some(crash_dump) = &mut self.crash_dump {
+            crash_dump.claim();
+        }
+    }
+
+    fn segment_log(&self) -> io::Result<&Arc<Mutex<SegmentLog>>> {
+        self.log.as_ref().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Unsupported,
+                "the segment consumer is disabled",
+            )
+        })
+    }
+}
+
+/// The segment currently being written in the service's log directory.
+struct SegmentLog {
+    dir: PathBuf,
+    capacity: usize,
+    options: WriterOptions,
+    sync: SyncConfig,
+    /// The first segment of this run, so ephemeral shutdown leaves earlier
+    /// ones alone.
+    first_seq: u64,
+    seq: u64,
+    writer: MmapWriter,
+    opened: Instant,
+    last_sync: Instant,
+    /// Wall-clock time of the last completed `MS_SYNC`.
+    last_synced: Option<SystemTime>,
+    errors: ErrorCounts,
+    /// Per-tenant usage of the segment being written.
+    tenants: TenantLedger,
+}
+
+impl SegmentLog {
+    /// Starts a new segment after any already in the configured directory.
+    fn create(config: &RinglogConfig) -> io::Result<Self> {
+        let dir = config.storage.dir.clone();
+        fs::create_dir_all(&dir).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Failed to create log directory {}: {}", dir.display(), e),
+            )
+        })?;
+        let options = WriterOptions {
+            mode: config.storage.mode,
+            preallocate: config.storage.preallocate,
+            ..Default::default()
+        };
+        let seq = list_segments(&dir)?.last().map_or(1, |s| s.seq + 1);
+        let capacity = config.storage.segment_size;
+        let writer = MmapWriter::create_with_options(segment_path(&dir, seq), capacity, &options)?;
+        Ok(Self {
+            dir,
+            capacity,
+            options,
+            sync: config.sync.clone(),
+            first_seq: seq,
+            seq,
+            writer,
+            opened: Instant::now(),
+            last_sync: Instant::now(),
+            last_synced: None,
+            errors: ErrorCounts::default(),
+            tenants: TenantLedger::new(TenantQuotas::new(config.quotas.segment)),
+        })
+    }
+
+    /// Seals the current segment and starts the next one.
+    fn rotate(&mut self) -> io::Result<u64> {
+        instrument_span!(INFO, "ringlog.rotate", from = self.seq);
+        self.writer.seal()?;
+        let path = segment_path(&self.dir, self.seq + 1);
+        self.writer = MmapWriter::create_with_options(path, self.capacity, &self.options)?;
+        self.seq += 1;
+        self.opened = Instant::now();
+        self.tenants.release_all();
+        instrument_event!(
+            INFO,
+            monotonic_counter.ringlog_rotations = 1u64,
+            seq = self.seq
+        );
+        Ok(self.seq)
+    }
+
+    /// `rotate`, counting failures.
+    fn rotate_counted(&mut self) -> io::Result<u64> {
+        let result = self.rotate();
+        if result.is_err() {
+            self.errors.rotation += 1;
+        }
+        result
+    }
+
+    /// Waits for the current segment to reach disk, counting failures.
+    fn sync(&mut self) -> io::Result<()> {
+        match self.writer.sync() {
+            Ok(()) => {
+                self.last_synced = Some(SystemTime::now());
+                Ok(())
+            }
+            Err(e) => {
+                self.errors.sync += 1;
+                Err(e)
+            }
+        }
+    }
+
+    fn sync_by_policy(&mut self) -> io::Result<()> {
+        match self.sync.policy {
+            SyncPolicy::Async => {
+                let result = self.writer.sync_async();
+                if result.is_err() {
+                    self.errors.sync += 1;
+                }
+                result
+            }
+            SyncPolicy::Always => self.sync(),
+            SyncPolicy::Interval if self.last_sync.elapsed() >= self.sync.interval => {
+                self.last_sync = Instant::now();
+                self.sync()
+            }
+            SyncPolicy::Interval | SyncPolicy::Never => Ok(()),
+        }
+    }
+
+    fn apply_retention(&self, retention: &RetentionConfig) -> io::Result<Vec<PathBuf>> {
+        let sealed: Vec<_> = list_segments(&self.dir)?
+            .into_iter()
+            .filter(|s| s.seq < self.seq)
+            .collect();
+        // The segment being written counts towards `max_segments`.
+        let excess = retention
+            .max_segments
+            .map_or(0, |max| (sealed.len() + 1).saturating_sub(max));
+
+        let mut removed = Vec::new();
+        for (index, segment) in sealed.into_iter().enumerate() {
+            if index < excess
+                || retention
+                    .max_age
+                    .is_some_and(|age| is_older(&segment.path, age))
+            {
+                fs::remove_file(&segment.path)?;
+                let _ = fs::remove_file(index_path(&segment.path));
+                instrument_event!(INFO, seq = segment.seq, "segment removed by retention");
+                removed.push(segment.path);
+            }
+        }
+        Ok(removed)
+    }
+}
+
+fn is_older(path: &Path, age: Duration) -> bool {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .is_ok_and(|modified| modified.elapsed().is_ok_and(|elapsed| elapsed > age))
+}
+
+/// Writes events to the shared `SegmentLog`, rotating when a segment fills.
+/// Events over their tenant's segment quota are dropped and reported as
+/// failed deliveries.
+struct SegmentConsumer {
+    log: Arc<Mutex<SegmentLog>>,
+}
+
+impl EventConsumer for SegmentConsumer {
+    fn consume(&mut self, header: &EventHeader, payload: &[u8]) -> bool {
+        let mut log = self.log.lock().unwrap();
+        if !log.tenants.check(header) {
+            return false;
+        }
+        // Reserved types, such as markers and tombstones, are stored too.
+        let written = log.writer.write_event_unchecked(header, &[payload]).is_ok()
+            || (log.rotate_counted().is_ok()
+                && log.writer.write_event_unchecked(header, &[payload]).is_ok());
+        if written {
+            log.tenants.hold(header);
+        }
+        written
+    }
+
+    fn flush(&mut self) {
+        let _ = self.log.lock().unwrap().sync_by_policy();
+    }
+
+    fn name(&self) -> &str {
+        "mmap"
+    }
+}
+
+/// Prints events to stdout in the same form as `ringlog dump`.
+struct PrintConsumer {
+    config: PrintConsumerConfig,
+}
+
+impl EventConsumer for PrintConsumer {
+    fn consume(&mut self, header: &EventHeader, payload: &[u8]) -> bool {
+        let types = &self.config.event_types;
+        if types.is_empty() || types.contains(&header.event_type) {
+            let event = EventView {
+                header: *header,
+                payload,
+            };
+            println!("{}", event.display());
+        }
+        true
+    }
+
+    fn name(&self) -> &str {
+        "print"
+    }
+}
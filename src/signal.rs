@@ -0,0 +1,70 @@
+//! Process signals turned into flags a service loop can poll, since almost
+//! nothing is safe to do inside a signal handler itself.
+
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    /// SIGHUP
+    Hangup,
+    /// SIGINT, e.g. Ctrl+C.
+    Interrupt,
+    /// SIGTERM
+    Terminate,
+    /// SIGUSR1
+    User1,
+}
+
+impl Signal {
+    const ALL: [Signal; 4] = [Self::Hangup, Self::Interrupt, Self::Terminate, Self::User1];
+
+    fn number(self) -> libc::c_int {
+        match self {
+            Self::Hangup => libc::SIGHUP,
+            Self::Interrupt => libc::SIGINT,
+            Self::Terminate => libc::SIGTERM,
+            Self::User1 => libc::SIGUSR1,
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+static PENDING: [AtomicBool; 4] = [const { AtomicBool::new(false) }; 4];
+
+extern "C" fn on_signal(number: libc::c_int) {
+    if let Some(signal) = Signal::ALL.iter().find(|s| s.number() == number) {
+        PENDING[signal.index()].store(true, Ordering::SeqCst);
+    }
+}
+
+/// Replaces the default action of each of `signals` with recording it for
+/// `take`. Interrupted system calls are restarted.
+pub fn install(signals: &[Signal]) -> io::Result<()> {
+    for signal in signals {
+        let result = unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = on_signal as *const () as libc::sighandler_t;
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(signal.number(), &action, std::ptr::null_mut())
+        };
+        if result != 0 {
+            let err = io::Error::last_os_error();
+            return Err(io::Error::new(
+                err.kind(),
+                format!("Failed to install handler for {:?}: {}", signal, err),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Whether `signal` arrived since the last call. Several deliveries between
+/// calls count once.
+pub fn take(signal: Signal) -> bool {
+    PENDING[signal.index()].swap(false, Ordering::SeqCst)
+}
@@ -0,0 +1,200 @@
+use super::{MmapReader, ReaderOptions, SegmentedReader};
+use crate::event::{EventView, StreamId};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplayIssue {
+    TimestampRegression {
+        offset: usize,
+        previous: u64,
+        current: u64,
+    },
+    /// Sequence numbers `expected..found` never appeared.
+    SequenceGap {
+        offset: usize,
+        stream: StreamId,
+        expected: u64,
+        found: u64,
+    },
+    /// A sequence number at or below one already seen.
+    SequenceRegression {
+        offset: usize,
+        stream: StreamId,
+        previous: u64,
+        found: u64,
+    },
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ReplayReport {
+    /// Events passed to the callback.
+    pub events: u64,
+    /// Events skipped by `ReaderOptions::dedup_sequences`.
+    pub duplicates: u64,
+    pub issues: Vec<ReplayIssue>,
+}
+
+impl ReplayReport {
+    #[inline]
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl MmapReader {
+    /// Replays every event like `replay`, running the checks enabled in
+    /// `options` along the way. Issues are collected; replay never stops
+    /// early because of them.
+    pub fn replay_checked<F>(&self, options: &ReaderOptions, mut callback: F) -> ReplayReport
+    where
+        F: FnMut(EventView),
+    {
+        let mut checker = ReplayChecker::new(*options);
+        self.replay_with(&mut checker, &mut callback);
+        checker.report
+    }
+
+    fn replay_with<F>(&self, checker: &mut ReplayChecker, callback: &mut F)
+    where
+        F: FnMut(EventView),
+    {
+        for frame in self.frames().map_while(Result::ok) {
+            let event = EventView {
+                header: frame.header,
+                payload: &self.mapped_bytes()[frame.payload.clone()],
+            };
+            if checker.check(frame.offset, &event) {
+                callback(event);
+            }
+        }
+    }
+}
+
+impl SegmentedReader {
+    /// `MmapReader::replay_checked` across every segment, with the checks
+    /// and `dedup_sequences` carrying over from one segment to the next.
+    /// Issue offsets are within the segment the event is in.
+    pub fn replay_checked<F>(&self, options: &ReaderOptions, mut callback: F) -> ReplayReport
+    where
+        F: FnMut(EventView),
+    {
+        let mut checker = ReplayChecker::new(*options);
+        for reader in self.readers() {
+            reader.replay_with(&mut checker, &mut callback);
+        }
+        checker.report
+    }
+}
+
+struct ReplayChecker {
+    options: ReaderOptions,
+    report: ReplayReport,
+    last_timestamp: Option<u64>,
+    last_sequence: [Option<u64>; StreamId::MAX as usize + 1],
+    seen: [SequenceRanges; StreamId::MAX as usize + 1],
+}
+
+impl ReplayChecker {
+    fn new(options: ReaderOptions) -> Self {
+        Self {
+            options,
+            report: ReplayReport::default(),
+            last_timestamp: None,
+            last_sequence: [None; StreamId::MAX as usize + 1],
+            seen: Default::default(),
+        }
+    }
+
+    /// Runs the checks on the event at `offset`; `false` if it is a
+    /// duplicate to skip.
+    fn check(&mut self, offset: usize, event: &EventView) -> bool {
+        let header = event.header;
+        let stream = header.stream();
+        let sequence = header.split_sequence(event.payload).0;
+
+        if self.options.dedup_sequences
+            && let Some(found) = sequence
+            && !self.seen[stream.get() as usize].insert(found)
+        {
+            self.report.duplicates += 1;
+            return false;
+        }
+
+        if self.options.check_monotonic_timestamps {
+            if let Some(previous) = self.last_timestamp
+                && header.timestamp < previous
+            {
+                self.report.issues.push(ReplayIssue::TimestampRegression {
+                    offset,
+                    previous,
+                    current: header.timestamp,
+                });
+            }
+            self.last_timestamp = Some(header.timestamp);
+        }
+
+        if self.options.check_sequence_gaps
+            && let Some(found) = sequence
+        {
+            let last = &mut self.last_sequence[stream.get() as usize];
+            match *last {
+                Some(previous) if found <= previous => {
+                    self.report.issues.push(ReplayIssue::SequenceRegression {
+                        offset,
+                        stream,
+                        previous,
+                        found,
+                    });
+                }
+                Some(previous) if found > previous + 1 => {
+                    self.report.issues.push(ReplayIssue::SequenceGap {
+                        offset,
+                        stream,
+                        expected: previous + 1,
+                        found,
+                    });
+                }
+                _ => {}
+            }
+            *last = Some(last.map_or(found, |previous| previous.max(found)));
+        }
+
+        self.report.events += 1;
+        true
+    }
+}
+
+/// The sequence numbers seen on one stream, as disjoint inclusive ranges
+/// keyed by their start. Sequences are mostly contiguous, so this stays
+/// small however many events are replayed.
+#[derive(Debug, Default)]
+struct SequenceRanges {
+    ranges: BTreeMap<u64, u64>,
+}
+
+impl SequenceRanges {
+    /// Adds `seq`; `false` if it was already there.
+    fn insert(&mut self, seq: u64) -> bool {
+        let before = self.ranges.range(..=seq).next_back().map(|(&s, &e)| (s, e));
+        if before.is_some_and(|(_, end)| end >= seq) {
+            return false;
+        }
+        let after = seq
+            .checked_add(1)
+            .and_then(|next| self.ranges.get(&next).map(|&end| (next, end)));
+
+        let start = match before {
+            Some((start, end)) if end + 1 == seq => start,
+            _ => seq,
+        };
+        let end = match after {
+            Some((next, end)) => {
+                self.ranges.remove(&next);
+                end
+            }
+            None => seq,
+        };
+        self.ranges.insert(start, end);
+        true
+    }
+}
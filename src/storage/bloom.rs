@@ -0,0 +1,90 @@
+//! Bloom filters over the keys of a sealed segment, stored in its footer so
+//! lookups across many segments skip those that cannot hold a key.
+
+use std::io;
+use xxhash_rust::xxh3::xxh3_128;
+
+/// Bits per key; with `HASHES` probes this gives about 1% false positives.
+const BITS_PER_KEY: usize = 10;
+const HASHES: u32 = 7;
+
+/// A set of `u64` keys that answers "maybe" or "definitely not".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BloomFilter {
+    hashes: u32,
+    words: Vec<u64>,
+}
+
+impl BloomFilter {
+    /// An empty filter sized for `keys` keys.
+    pub fn with_capacity(keys: usize) -> Self {
+        let bits = (keys.max(1) * BITS_PER_KEY).next_multiple_of(64);
+        Self {
+            hashes: HASHES,
+            words: vec![0; bits / 64],
+        }
+    }
+
+    /// Bit positions of `key`, by double hashing the two halves of its
+    /// xxh3-128.
+    fn probes(&self, key: u64) -> impl Iterator<Item = usize> + use<> {
+        let hash = xxh3_128(&key.to_le_bytes());
+        let (h1, h2) = (hash as u64, (hash >> 64) as u64 | 1);
+        let bits = self.words.len() as u64 * 64;
+        (0..self.hashes as u64).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % bits) as usize)
+    }
+
+    pub fn insert(&mut self, key: u64) {
+        for bit in self.probes(key) {
+            self.words[bit / 64] |= 1 << (bit % 64);
+        }
+    }
+
+    /// `false` only if `key` was never inserted.
+    pub fn may_contain(&self, key: u64) -> bool {
+        self.probes(key)
+            .all(|bit| self.words[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    /// Size of the encoded filter.
+    pub fn encoded_len(&self) -> usize {
+        8 + self.words.len() * 8
+    }
+
+    /// Layout: the number of hashes and of 64-bit words as little-endian
+    /// u32s, then the words.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.encoded_len());
+        bytes.extend_from_slice(&self.hashes.to_le_bytes());
+        bytes.extend_from_slice(&(self.words.len() as u32).to_le_bytes());
+        for word in &self.words {
+            bytes.extend_from_slice(&word.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> io::Result<Self> {
+        let invalid = |reason: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("bloom filter {}", reason),
+            )
+        };
+        let head = bytes.get(..8).ok_or_else(|| invalid("truncated"))?;
+        let hashes = u32::from_le_bytes(head[0..4].try_into().unwrap());
+        let words = u32::from_le_bytes(head[4..8].try_into().unwrap()) as usize;
+        if hashes == 0 || words == 0 {
+            return Err(invalid("is empty"));
+        }
+        let body = bytes
+            .get(8..8 + words * 8)
+            .ok_or_else(|| invalid("truncated"))?;
+        Ok(Self {
+            hashes,
+            words: body
+                .chunks_exact(8)
+                .map(|word| u64::from_le_bytes(word.try_into().unwrap()))
+                .collect(),
+        })
+    }
+}
@@ -0,0 +1,78 @@
+//! Hash-chained files for audit trails. In a file created with
+//! `WriterOptions::hash_chain`, each event's `_reserved` header field holds
+//! the low 32 bits of xxh3-64 over the previous event's chain value (0 for
+//! the first event), the event's header with `_reserved` zeroed, and its
+//! payload. Inserting, removing or changing an event breaks the chain from
+//! that event on; removing events from the end is caught by the header's
+//! event count.
+//!
+//! xxh3 is not a cryptographic hash and 32 bits are few: the chain detects
+//! accidental and naive tampering, not a forger who recomputes it.
+
+use super::{FileHeader, MmapReader, VerifyIssue};
+use crate::event::EventHeader;
+use xxhash_rust::xxh3::Xxh3;
+
+/// The chain value of an event whose header (with `_reserved` ignored) and
+/// payload parts are given, following an event with chain value `previous`.
+pub(crate) fn chain_hash(previous: u32, header: &EventHeader, parts: &[&[u8]]) -> u32 {
+    let header = EventHeader {
+        _reserved: 0,
+        ..*header
+    };
+    let mut hasher = Xxh3::new();
+    hasher.update(&previous.to_le_bytes());
+    hasher.update(&header.encode());
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.digest() as u32
+}
+
+impl MmapReader {
+    /// Walks the hash chain of a file written with
+    /// `WriterOptions::hash_chain`, returning the number of events checked.
+    /// Fails at the first event whose chain value does not match, or if the
+    /// file holds fewer or more events than its header declares.
+    pub fn verify_chain(&self) -> Result<u64, VerifyIssue> {
+        let file_header = self.file_header();
+        if file_header.flags & FileHeader::FLAG_HASH_CHAIN == 0 {
+            return Err(VerifyIssue::NotHashChained);
+        }
+
+        let mut previous = 0;
+        let mut events = 0;
+        let mut frames = self.frames();
+        loop {
+            let offset = frames.offset();
+            let frame = match frames.next() {
+                Some(Ok(frame)) => frame,
+                Some(Err(e)) => {
+                    return Err(VerifyIssue::Framing {
+                        offset,
+                        reason: e.to_string(),
+                    });
+                }
+                None => break,
+            };
+            let payload = &self.mapped_bytes()[frame.payload.clone()];
+            let expected = chain_hash(previous, &frame.header, &[payload]);
+            if frame.header._reserved != expected {
+                return Err(VerifyIssue::ChainBroken {
+                    offset: frame.offset,
+                    index: events,
+                });
+            }
+            previous = expected;
+            events += 1;
+        }
+
+        if events != file_header.event_count {
+            return Err(VerifyIssue::EventCountMismatch {
+                declared: file_header.event_count,
+                actual: events,
+            });
+        }
+        Ok(events)
+    }
+}
@@ -0,0 +1,141 @@
+//! Keyed deletion for retained logs. A producer asks for a key's events to
+//! be erased by writing a `TYPE_TOMBSTONE` event whose payload is the key;
+//! `compact_deleted` then rewrites every sealed segment holding events for a
+//! tombstoned key without them. Tombstones themselves are kept, so the
+//! deletion stays on record and later compactions still apply it.
+
+use super::{FileHeader, MmapReader, MmapWriter, WriterOptions, index_path, list_segments};
+use crate::event::{EventHeader, EventView};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompactionReport {
+    /// Distinct keys found in tombstones across all segments.
+    pub tombstones: usize,
+    pub segments_rewritten: Vec<PathBuf>,
+    pub events_removed: u64,
+    /// How much smaller the rewritten files are.
+    pub bytes_reclaimed: u64,
+    /// Events removed per key.
+    pub removed_by_key: BTreeMap<Vec<u8>, u64>,
+    /// Unsealed segments, still being written, left untouched; they may
+    /// hold events for tombstoned keys until sealed and compacted.
+    pub skipped_unsealed: Vec<PathBuf>,
+}
+
+/// Removes the events of tombstoned keys from the sealed segments in `dir`.
+/// `key_of` extracts an event's key, e.g. a `FieldReader` field; events it
+/// returns `None` for are kept. Each affected segment is rewritten to a
+/// temporary sibling, sealed with a fresh footer and renamed over the
+/// original, keeping its metadata, format and creation time. The new footer
+/// is unsigned, since no signing key is at hand.
+pub fn compact_deleted<P, F>(dir: P, key_of: F) -> io::Result<CompactionReport>
+where
+    P: AsRef<Path>,
+    F: Fn(&EventHeader, &[u8]) -> Option<Vec<u8>>,
+{
+    let segments = list_segments(&dir)?;
+    let mut deleted = BTreeSet::new();
+    for segment in &segments {
+        let reader = MmapReader::open(&segment.path)?;
+        for event in reader.iter() {
+            if event.header.event_type == EventHeader::TYPE_TOMBSTONE {
+                deleted.insert(event.payload.to_vec());
+            }
+        }
+    }
+
+    let mut report = CompactionReport {
+        tombstones: deleted.len(),
+        ..Default::default()
+    };
+    if deleted.is_empty() {
+        return Ok(report);
+    }
+    for segment in &segments {
+        let reader = MmapReader::open(&segment.path)?;
+        if !reader.is_sealed() {
+            report.skipped_unsealed.push(segment.path.clone());
+            continue;
+        }
+        let doomed = |event: &EventView<'_>| {
+            if event.header.event_type == EventHeader::TYPE_TOMBSTONE {
+                return None;
+            }
+            key_of(&event.header, event.payload).filter(|key| deleted.contains(key))
+        };
+        if !reader.iter().any(|event| doomed(&event).is_some()) {
+            continue;
+        }
+
+        let old_len = fs::metadata(&segment.path)?.len();
+        let mut removed = 0;
+        let mut by_key = BTreeMap::new();
+        let result = rewrite(&segment.path, &reader, |event| match doomed(event) {
+            Some(key) => {
+                removed += 1;
+                *by_key.entry(key).or_insert(0) += 1;
+                false
+            }
+            None => true,
+        });
+        drop(reader);
+        let new_len = result.map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Compacting {} failed: {}", segment.path.display(), e),
+            )
+        })?;
+
+        report.segments_rewritten.push(segment.path.clone());
+        report.events_removed += removed;
+        report.bytes_reclaimed += old_len.saturating_sub(new_len);
+        for (key, count) in by_key {
+            *report.removed_by_key.entry(key).or_insert(0) += count;
+        }
+    }
+    Ok(report)
+}
+
+/// Copies the events of `reader` that `keep` accepts into a sealed temporary
+/// sibling of `path`, then renames it over `path`, returning its new size.
+fn rewrite<F>(path: &Path, reader: &MmapReader, mut keep: F) -> io::Result<u64>
+where
+    F: FnMut(&EventView<'_>) -> bool,
+{
+    let mut tmp_name = path.as_os_str().to_owned();
+    tmp_name.push(".compact.tmp");
+    let tmp_path = Path::new(&tmp_name);
+
+    let file_header = reader.file_header();
+    let options = WriterOptions {
+        metadata: reader.metadata().cloned(),
+        compact: file_header.is_compact(),
+        hash_chain: file_header.flags & FileHeader::FLAG_HASH_CHAIN != 0,
+        ..Default::default()
+    };
+    let result = (|| {
+        let mut writer = MmapWriter::create_with_options(tmp_path, reader.mapped_len(), &options)?;
+        writer.set_created_at(file_header.created_at);
+        for event in reader.iter() {
+            if keep(&event) {
+                writer.write_event_unchecked(&event.header, &[event.payload])?;
+            }
+        }
+        writer.seal()?;
+        writer.sync()?;
+        drop(writer);
+        let len = fs::metadata(tmp_path)?.len();
+        fs::rename(tmp_path, path)?;
+        // Its sidecar index, if any, points at the old offsets.
+        let _ = fs::remove_file(index_path(path));
+        Ok(len)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(tmp_path);
+    }
+    result
+}
@@ -0,0 +1,166 @@
+use super::frame::Frames;
+use super::segment::SegmentInfo;
+use super::{MmapReader, SegmentedReader};
+use crate::event::EventView;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// A position in a log directory: the next event to read is at `offset` in
+/// the segment with sequence number `segment`. Batch jobs save it after
+/// each run and resume from it with `SegmentedReader::resume`, so every
+/// event is processed once.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Cursor {
+    pub segment: u64,
+    /// File offset of the next event; 0 for the segment's first.
+    pub offset: u64,
+    /// Timestamp of the event before `offset`, which compact frames are
+    /// relative to.
+    pub previous: u64,
+    /// Sequence number of the last event read, if it had one.
+    pub sequence: Option<u64>,
+}
+
+impl Cursor {
+    /// Loads a cursor saved by `save`, or the start of the log if `path`
+    /// does not exist yet.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => {
+                return Err(io::Error::new(
+                    e.kind(),
+                    format!("Failed to read cursor {}: {}", path.display(), e),
+                ));
+            }
+        };
+
+        let invalid = |field: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid {} in cursor {}", field, path.display()),
+            )
+        };
+        let mut cursor = Self::default();
+        for field in text.split_whitespace() {
+            let (key, value) = field.split_once('=').ok_or_else(|| invalid(field))?;
+            let value = value.parse::<u64>().map_err(|_| invalid(key))?;
+            match key {
+                "segment" => cursor.segment = value,
+                "offset" => cursor.offset = value,
+                "previous" => cursor.previous = value,
+                "sequence" => cursor.sequence = Some(value),
+                _ => return Err(invalid(key)),
+            }
+        }
+        Ok(cursor)
+    }
+
+    /// Writes the cursor as one `key=value` line, atomically.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut line = format!(
+            "segment={} offset={} previous={}",
+            self.segment, self.offset, self.previous
+        );
+        if let Some(sequence) = self.sequence {
+            line.push_str(&format!(" sequence={}", sequence));
+        }
+        line.push('\n');
+
+        let mut tmp_name = path.as_os_str().to_owned();
+        tmp_name.push(".tmp");
+        let tmp_path = Path::new(&tmp_name);
+        let result = (|| {
+            let mut file = fs::File::create(tmp_path)?;
+            file.write_all(line.as_bytes())?;
+            file.sync_all()?;
+            fs::rename(tmp_path, path)
+        })();
+
+        if let Err(e) = result {
+            let _ = fs::remove_file(tmp_path);
+            return Err(io::Error::new(
+                e.kind(),
+                format!("Saving cursor to {} failed: {}", path.display(), e),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl SegmentedReader {
+    /// Events after `cursor`. If its segment was removed since, reading
+    /// starts at the next segment. The cursor must come from this
+    /// directory: one pointing into a segment that was rewritten, e.g. by
+    /// compaction, stops at the first event it cannot frame.
+    pub fn resume(&self, cursor: Cursor) -> Resumed<'_> {
+        let start = self
+            .segments
+            .partition_point(|(info, _)| info.seq < cursor.segment);
+        Resumed {
+            segments: &self.segments[start..],
+            current: None,
+            cursor,
+        }
+    }
+}
+
+/// Iterator returned by `SegmentedReader::resume`.
+pub struct Resumed<'a> {
+    segments: &'a [(SegmentInfo, MmapReader)],
+    current: Option<(&'a MmapReader, Frames<'a>)>,
+    cursor: Cursor,
+}
+
+impl Resumed<'_> {
+    /// The position after the last event yielded, to save for the next run.
+    pub fn cursor(&self) -> Cursor {
+        self.cursor
+    }
+}
+
+impl<'a> Iterator for Resumed<'a> {
+    type Item = EventView<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((reader, frames)) = &mut self.current {
+                match frames.next() {
+                    Some(Ok(frame)) => {
+                        let event = reader.view(&frame);
+                        self.cursor.offset = frames.offset() as u64;
+                        self.cursor.previous = frames.previous();
+                        if let Some(sequence) = event.header.split_sequence(event.payload).0 {
+                            self.cursor.sequence = Some(sequence);
+                        }
+                        return Some(event);
+                    }
+                    // Stop at a framing error rather than skip to the next
+                    // segment, so the cursor never moves past unread events.
+                    Some(Err(_)) => {
+                        self.segments = &[];
+                        self.current = None;
+                        return None;
+                    }
+                    None => {}
+                }
+            }
+
+            let ((info, reader), rest) = self.segments.split_first()?;
+            self.segments = rest;
+            let mut frames = reader.frames();
+            if info.seq == self.cursor.segment && self.cursor.offset != 0 {
+                frames.seek(self.cursor.offset as usize, self.cursor.previous);
+            } else {
+                self.cursor.segment = info.seq;
+                self.cursor.offset = frames.offset() as u64;
+                self.cursor.previous = 0;
+            }
+            self.current = Some((reader, frames));
+        }
+    }
+}
@@ -0,0 +1,230 @@
+//! CSV export of log files for spreadsheets and other tools that do not
+//! read the binary format. `CsvWriter` writes a header row and one row per
+//! event, quoting fields as RFC 4180 asks.
+
+use super::{MmapReader, SegmentedReader};
+use crate::event::{EventHeader, EventView, FieldReader, FieldValue, StringTable, Timestamp};
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+/// A column of a CSV export.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CsvColumn {
+    /// Unix nanoseconds.
+    Timestamp,
+    /// RFC 3339 UTC time, which spreadsheets parse as a date.
+    Time,
+    Type,
+    Stream,
+    /// Empty for events without a sequence number.
+    Sequence,
+    /// The application payload, without sequence or trace prefixes, as
+    /// UTF-8 if it is valid UTF-8 and as `0x`-prefixed hex otherwise.
+    Payload,
+    /// A `FieldWriter` field of the payload, bytes as hex; empty for events
+    /// without it.
+    Field(String),
+}
+
+impl CsvColumn {
+    /// `timestamp,type,seq,payload`.
+    pub fn defaults() -> Vec<Self> {
+        vec![Self::Timestamp, Self::Type, Self::Sequence, Self::Payload]
+    }
+
+    /// Parses a comma-separated list such as `time,type,field.user`.
+    pub fn parse_list(list: &str) -> Result<Vec<Self>, String> {
+        list.split(',').map(|name| name.trim().parse()).collect()
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            Self::Timestamp => "timestamp",
+            Self::Time => "time",
+            Self::Type => "type",
+            Self::Stream => "stream",
+            Self::Sequence => "seq",
+            Self::Payload => "payload",
+            Self::Field(key) => key,
+        }
+    }
+}
+
+impl FromStr for CsvColumn {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "timestamp" | "ts" => Ok(Self::Timestamp),
+            "time" => Ok(Self::Time),
+            "type" => Ok(Self::Type),
+            "stream" => Ok(Self::Stream),
+            "seq" => Ok(Self::Sequence),
+            "payload" => Ok(Self::Payload),
+            _ => match s.strip_prefix("field.") {
+                Some(key) if !key.is_empty() => Ok(Self::Field(key.to_string())),
+                _ => Err(format!("unknown CSV column: {}", s)),
+            },
+        }
+    }
+}
+
+/// Writes events as CSV rows, after a header row of column names.
+pub struct CsvWriter<'a, W: Write> {
+    out: W,
+    columns: &'a [CsvColumn],
+    strings: Option<&'a StringTable>,
+    header_written: bool,
+    row: String,
+}
+
+impl<'a, W: Write> CsvWriter<'a, W> {
+    pub fn new(out: W, columns: &'a [CsvColumn]) -> Self {
+        Self {
+            out,
+            columns,
+            strings: None,
+            header_written: false,
+            row: String::new(),
+        }
+    }
+
+    /// Resolves interned strings of `CsvColumn::Field` columns.
+    pub fn with_strings(mut self, strings: &'a StringTable) -> Self {
+        self.strings = Some(strings);
+        self
+    }
+
+    /// Writes the header row if no row was written yet. Called by
+    /// `write_event`, so only needed for exports that may be empty.
+    pub fn write_header(&mut self) -> io::Result<()> {
+        if self.header_written {
+            return Ok(());
+        }
+        self.row.clear();
+        for (i, column) in self.columns.iter().enumerate() {
+            if i > 0 {
+                self.row.push(',');
+            }
+            push_quoted(&mut self.row, column.name());
+        }
+        self.row.push_str("\r\n");
+        self.out.write_all(self.row.as_bytes())?;
+        self.header_written = true;
+        Ok(())
+    }
+
+    pub fn write_event(&mut self, event: &EventView<'_>) -> io::Result<()> {
+        self.write_header()?;
+        self.row.clear();
+        let header = &event.header;
+        let body = header.body(event.payload);
+        for (i, column) in self.columns.iter().enumerate() {
+            if i > 0 {
+                self.row.push(',');
+            }
+            let row = &mut self.row;
+            match column {
+                CsvColumn::Timestamp => {
+                    let _ = write!(row, "{}", header.timestamp);
+                }
+                CsvColumn::Time => {
+                    let _ = write!(row, "{}", Timestamp(header.timestamp));
+                }
+                CsvColumn::Type => {
+                    let _ = write!(row, "{}", header.event_type);
+                }
+                CsvColumn::Stream => {
+                    let _ = write!(row, "{}", header.stream().get());
+                }
+                CsvColumn::Sequence => {
+                    if let Some(seq) = header.split_sequence(event.payload).0 {
+                        let _ = write!(row, "{}", seq);
+                    }
+                }
+                CsvColumn::Payload => match std::str::from_utf8(body) {
+                    Ok(text) if header.flags & EventHeader::FLAG_COMPRESSED == 0 => {
+                        push_quoted(row, text)
+                    }
+                    _ => push_hex(row, body),
+                },
+                CsvColumn::Field(key) => {
+                    if header.flags & EventHeader::FLAG_COMPRESSED != 0 {
+                        continue;
+                    }
+                    let mut fields = FieldReader::new(body);
+                    if let Some(strings) = self.strings {
+                        fields = fields.with_strings(strings);
+                    }
+                    match fields.get(key) {
+                        Some(FieldValue::Str(text)) => push_quoted(row, text),
+                        Some(FieldValue::Bytes(bytes)) => push_hex(row, bytes),
+                        Some(value) => {
+                            let _ = write!(row, "{}", value);
+                        }
+                        None => {}
+                    }
+                }
+            }
+        }
+        self.row.push_str("\r\n");
+        self.out.write_all(self.row.as_bytes())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Appends `value`, quoted if it holds a comma, quote or line break.
+fn push_quoted(row: &mut String, value: &str) {
+    if value.contains([',', '"', '\r', '\n']) {
+        row.push('"');
+        row.push_str(&value.replace('"', "\"\""));
+        row.push('"');
+    } else {
+        row.push_str(value);
+    }
+}
+
+fn push_hex(row: &mut String, bytes: &[u8]) {
+    row.push_str("0x");
+    for byte in bytes {
+        let _ = write!(row, "{:02x}", byte);
+    }
+}
+
+impl MmapReader {
+    /// Writes the file's events to `out` as CSV with `columns`, resolving
+    /// interned strings through the file's string table. Returns the number
+    /// of rows, not counting the header.
+    pub fn export_csv<W: Write>(&self, out: W, columns: &[CsvColumn]) -> io::Result<u64> {
+        let strings = self.string_table();
+        let mut csv = CsvWriter::new(out, columns).with_strings(&strings);
+        csv.write_header()?;
+        let mut rows = 0;
+        for event in self.iter() {
+            csv.write_event(&event)?;
+            rows += 1;
+        }
+        csv.flush()?;
+        Ok(rows)
+    }
+}
+
+impl SegmentedReader {
+    /// Writes the events of every segment to `out` as CSV with `columns`.
+    /// Returns the number of rows, not counting the header.
+    pub fn export_csv<W: Write>(&self, out: W, columns: &[CsvColumn]) -> io::Result<u64> {
+        let mut csv = CsvWriter::new(out, columns);
+        csv.write_header()?;
+        let mut rows = 0;
+        for event in self.iter() {
+            csv.write_event(&event)?;
+            rows += 1;
+        }
+        csv.flush()?;
+        Ok(rows)
+    }
+}
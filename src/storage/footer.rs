@@ -0,0 +1,118 @@
+use super::bloom::BloomFilter;
+use super::merkle::Hash;
+use std::io;
+use xxhash_rust::xxh3::xxh3_64;
+
+/// Trailer appended after the events of a sealed segment, past
+/// `write_offset`. Lets `MmapReader::quick_verify` check a segment with one
+/// xxh3 pass instead of framing every event and running CRC-32 over it, and
+/// `verify_seal` check its Merkle root and signature.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SegmentFooter {
+    pub event_count: u64,
+    /// `write_offset` of the sealed segment; the footer starts here.
+    pub data_end: u64,
+    /// xxh3-64 of the bytes from the end of the file header to `data_end`.
+    pub hash: u64,
+    /// Root over the events; see `storage::merkle`. `None` in footers
+    /// written before it was recorded.
+    pub merkle_root: Option<Hash>,
+    pub signature: Option<SealSignature>,
+    /// Filter over the segment's trace ids and `WriterOptions::bloom_key`
+    /// keys; `None` if it has neither.
+    pub bloom: Option<BloomFilter>,
+}
+
+/// An ed25519 signature over `merkle::seal_message`, with the public key of
+/// the signer. Verifiers should check against a key they trust, not this
+/// one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SealSignature {
+    pub public_key: [u8; 32],
+    pub signature: [u8; 64],
+}
+
+impl SegmentFooter {
+    /// Size of footers without a bloom filter, which are written as version
+    /// 2; version 3 footers append the filter.
+    pub const SIZE: usize = 160;
+    pub const MAGIC: [u8; 4] = *b"EVFT";
+    pub const VERSION: u32 = 3;
+    /// Size of version 1 footers, which end after `hash`.
+    const V1_SIZE: usize = 32;
+
+    pub fn hash(region: &[u8]) -> u64 {
+        xxh3_64(region)
+    }
+
+    /// Layout: magic (4), version (4), then event_count, data_end and hash as
+    /// little-endian u64s, the Merkle root (32), the signer's public key (32)
+    /// and the signature (64). An unsigned footer has zeros for the last two.
+    /// A bloom filter follows in its own encoding.
+    pub fn encode(&self) -> Vec<u8> {
+        let version: u32 = if self.bloom.is_some() { 3 } else { 2 };
+        let mut bytes = vec![0u8; Self::SIZE];
+        bytes[0..4].copy_from_slice(&Self::MAGIC);
+        bytes[4..8].copy_from_slice(&version.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.event_count.to_le_bytes());
+        bytes[16..24].copy_from_slice(&self.data_end.to_le_bytes());
+        bytes[24..32].copy_from_slice(&self.hash.to_le_bytes());
+        bytes[32..64].copy_from_slice(&self.merkle_root.unwrap_or_default());
+        if let Some(signature) = &self.signature {
+            bytes[64..96].copy_from_slice(&signature.public_key);
+            bytes[96..160].copy_from_slice(&signature.signature);
+        }
+        if let Some(bloom) = &self.bloom {
+            bytes.extend_from_slice(&bloom.encode());
+        }
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> io::Result<Self> {
+        let truncated = || invalid("segment footer truncated".to_string());
+        let head = bytes.get(..Self::V1_SIZE).ok_or_else(truncated)?;
+        if head[0..4] != Self::MAGIC {
+            return Err(invalid("segment footer magic mismatch".to_string()));
+        }
+        let version = u32::from_le_bytes(head[4..8].try_into().unwrap());
+        let mut footer = Self {
+            event_count: u64::from_le_bytes(head[8..16].try_into().unwrap()),
+            data_end: u64::from_le_bytes(head[16..24].try_into().unwrap()),
+            hash: u64::from_le_bytes(head[24..32].try_into().unwrap()),
+            merkle_root: None,
+            signature: None,
+            bloom: None,
+        };
+        match version {
+            1 => {}
+            2 | 3 => {
+                let fixed = bytes.get(..Self::SIZE).ok_or_else(truncated)?;
+                footer.merkle_root = Some(fixed[32..64].try_into().unwrap());
+                let public_key: [u8; 32] = fixed[64..96].try_into().unwrap();
+                if public_key != [0; 32] {
+                    footer.signature = Some(SealSignature {
+                        public_key,
+                        signature: fixed[96..160].try_into().unwrap(),
+                    });
+                }
+                if version == 3 {
+                    footer.bloom = Some(
+                        BloomFilter::decode(&bytes[Self::SIZE..])
+                            .map_err(|e| invalid(format!("segment footer {}", e)))?,
+                    );
+                }
+            }
+            _ => {
+                return Err(invalid(format!(
+                    "unsupported segment footer version {}",
+                    version
+                )));
+            }
+        }
+        Ok(footer)
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,166 @@
+use super::FileHeader;
+use crate::event::{CompactHeader, EventHeader};
+use std::io;
+use std::ops::Range;
+
+/// One event located in a mapped file.
+pub(crate) struct Frame {
+    pub header: EventHeader,
+    /// Start of the event's own header.
+    pub offset: usize,
+    pub payload: Range<usize>,
+    pub end: usize,
+    /// The on-disk header, for files in the compact format.
+    pub compact: Option<CompactHeader>,
+}
+
+impl Frame {
+    /// Whether the event's checksum, if it has one, matches its payload.
+    pub fn checksum_ok(&self, data: &[u8]) -> bool {
+        let payload = &data[self.payload.clone()];
+        match (&self.compact, self.header.checksum()) {
+            (Some(compact), _) => compact.verify(payload),
+            (None, Some(expected)) => crate::event::crc32(payload) == expected,
+            (None, None) => true,
+        }
+    }
+}
+
+/// Walks the events of a mapped file in either header format, tracking the
+/// timestamp compact deltas are relative to. Stops after the first framing
+/// error.
+#[derive(Clone)]
+pub(crate) struct Frames<'a> {
+    data: &'a [u8],
+    offset: usize,
+    end: usize,
+    compact: bool,
+    previous: u64,
+}
+
+impl<'a> Frames<'a> {
+    pub fn new(data: &'a [u8], header: &FileHeader, start: usize, end: usize) -> Self {
+        Self {
+            data,
+            offset: start,
+            end,
+            compact: header.is_compact(),
+            previous: 0,
+        }
+    }
+
+    /// Moves to the frame at `offset`, whose predecessor had timestamp
+    /// `previous`.
+    pub fn seek(&mut self, offset: usize, previous: u64) {
+        self.offset = offset;
+        self.previous = previous;
+    }
+
+    #[inline]
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    #[inline]
+    pub fn previous(&self) -> u64 {
+        self.previous
+    }
+
+    #[inline]
+    pub fn remaining(&self) -> usize {
+        self.end.saturating_sub(self.offset)
+    }
+
+    #[inline]
+    pub fn header_size(&self) -> usize {
+        if self.compact {
+            CompactHeader::SIZE
+        } else {
+            EventHeader::SIZE
+        }
+    }
+
+    fn decode(&mut self) -> io::Result<Frame> {
+        let mut offset = self.offset;
+        loop {
+            if offset + self.header_size() > self.end {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "Truncated event header at offset {}: data ends at {}",
+                        offset, self.end
+                    ),
+                ));
+            }
+
+            let (header, compact, header_size) = if self.compact {
+                let compact = CompactHeader::decode_from(&self.data[offset..]).unwrap();
+                (
+                    compact.to_header(self.previous),
+                    Some(compact),
+                    CompactHeader::SIZE,
+                )
+            } else {
+                let header = EventHeader::decode_from(&self.data[offset..]).unwrap();
+                (header, None, EventHeader::SIZE)
+            };
+
+            let payload = offset + header_size..offset + header_size + header.payload_len as usize;
+            if payload.end > self.end {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "Event at offset {} with payload_len {} overruns data end {}",
+                        offset, header.payload_len, self.end
+                    ),
+                ));
+            }
+
+            if let Some(compact) = compact
+                && compact.event_type == CompactHeader::TYPE_TIME_BASE
+            {
+                let bytes = &self.data[payload.clone()];
+                if bytes.len() != 8 || !compact.verify(bytes) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Corrupt time-base record at offset {}", offset),
+                    ));
+                }
+                self.previous = u64::from_le_bytes(bytes.try_into().unwrap());
+                offset = payload.end;
+                continue;
+            }
+
+            if self.compact {
+                self.previous = header.timestamp;
+            }
+            return Ok(Frame {
+                header,
+                offset,
+                end: payload.end,
+                payload,
+                compact,
+            });
+        }
+    }
+}
+
+impl Iterator for Frames<'_> {
+    type Item = io::Result<Frame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset >= self.end {
+            return None;
+        }
+        match self.decode() {
+            Ok(frame) => {
+                self.offset = frame.end;
+                Some(Ok(frame))
+            }
+            Err(e) => {
+                self.offset = self.end;
+                Some(Err(e))
+            }
+        }
+    }
+}
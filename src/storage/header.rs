@@ -1,3 +1,6 @@
+use std::mem::offset_of;
+use std::sync::atomic::{AtomicU64, Ordering};
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
 pub struct FileHeader {
@@ -6,7 +9,11 @@ pub struct FileHeader {
     pub created_at: i64,
     pub event_count: u64,
     pub write_offset: u64,
-    pub _reserved: [u8; 32],
+    pub flags: u32,
+    pub checksum: u32,
+    /// Length of the `Metadata` block following the header, 0 for none.
+    pub metadata_len: u32,
+    pub _reserved: [u8; 20],
 }
 
 impl FileHeader {
@@ -14,6 +21,17 @@ impl FileHeader {
     pub const MAGIC: [u8; 4] = *b"EVIL";
     pub const VERSION: u32 = 1;
 
+    /// The segment is finalized: `checksum` covers the event region and the
+    /// file has been truncated to `write_offset`.
+    pub const FLAG_SEALED: u32 = 1 << 0;
+
+    /// Events use the 8-byte `CompactHeader` instead of `EventHeader`.
+    pub const FLAG_COMPACT: u32 = 1 << 1;
+
+    /// Event headers carry a hash chain in `_reserved` instead of a CRC-32;
+    /// see `storage::chain`.
+    pub const FLAG_HASH_CHAIN: u32 = 1 << 2;
+
     pub fn new(created_at: i64) -> Self {
         Self {
             magic: Self::MAGIC,
@@ -21,12 +39,110 @@ impl FileHeader {
             created_at,
             event_count: 0,
             write_offset: Self::SIZE as u64,
-            _reserved: [0; 32],
+            flags: 0,
+            checksum: 0,
+            metadata_len: 0,
+            _reserved: [0; 20],
         }
     }
 
+    #[inline]
+    pub fn is_sealed(&self) -> bool {
+        self.flags & Self::FLAG_SEALED != 0
+    }
+
+    #[inline]
+    pub fn is_compact(&self) -> bool {
+        self.flags & Self::FLAG_COMPACT != 0
+    }
+
+    /// Offset of the first event: after the header and metadata block, rounded
+    /// up to 8 bytes.
+    #[inline]
+    pub fn data_start(&self) -> usize {
+        Self::data_start_for(self.metadata_len as usize)
+    }
+
+    #[inline]
+    pub(crate) fn data_start_for(metadata_len: usize) -> usize {
+        (Self::SIZE + metadata_len).next_multiple_of(8)
+    }
+
     #[inline]
     pub fn validate(&self) -> bool {
         self.magic == Self::MAGIC && self.version == Self::VERSION
     }
+
+    /// Encodes the header in the little-endian on-disk layout, field by field
+    /// in declaration order with no padding.
+    pub fn encode(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0u8; Self::SIZE];
+        bytes[0..4].copy_from_slice(&self.magic);
+        bytes[4..8].copy_from_slice(&self.version.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.created_at.to_le_bytes());
+        bytes[16..24].copy_from_slice(&self.event_count.to_le_bytes());
+        bytes[24..32].copy_from_slice(&self.write_offset.to_le_bytes());
+        bytes[32..36].copy_from_slice(&self.flags.to_le_bytes());
+        bytes[36..40].copy_from_slice(&self.checksum.to_le_bytes());
+        bytes[40..44].copy_from_slice(&self.metadata_len.to_le_bytes());
+        bytes[44..64].copy_from_slice(&self._reserved);
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8; Self::SIZE]) -> Self {
+        Self {
+            magic: bytes[0..4].try_into().unwrap(),
+            version: u32::from_le_bytes(bytes[4..8].try_into().unwrap()),
+            created_at: i64::from_le_bytes(bytes[8..16].try_into().unwrap()),
+            event_count: u64::from_le_bytes(bytes[16..24].try_into().unwrap()),
+            write_offset: u64::from_le_bytes(bytes[24..32].try_into().unwrap()),
+            flags: u32::from_le_bytes(bytes[32..36].try_into().unwrap()),
+            checksum: u32::from_le_bytes(bytes[36..40].try_into().unwrap()),
+            metadata_len: u32::from_le_bytes(bytes[40..44].try_into().unwrap()),
+            _reserved: bytes[44..64].try_into().unwrap(),
+        }
+    }
+
+    /// Publishes the mutable header fields of a mapped file. `write_offset` is
+    /// stored before `event_count`, both with release ordering, so a reader
+    /// using `load` never sees an offset covering bytes it cannot see, nor a
+    /// count larger than the events below that offset.
+    ///
+    /// # Safety
+    /// `ptr` must be the 8-aligned start of a writable mapped header.
+    #[inline]
+    pub(crate) unsafe fn publish(ptr: *mut u8, write_offset: u64, event_count: u64) {
+        unsafe {
+            Self::field(ptr, offset_of!(Self, write_offset))
+                .store(write_offset.to_le(), Ordering::Release);
+            Self::field(ptr, offset_of!(Self, event_count))
+                .store(event_count.to_le(), Ordering::Release);
+        }
+    }
+
+    /// Reads a mapped header, loading `event_count` and then `write_offset`
+    /// with acquire ordering to pair with `publish`.
+    ///
+    /// # Safety
+    /// `ptr` must be the 8-aligned start of a mapped header.
+    #[inline]
+    pub(crate) unsafe fn load(ptr: *const u8) -> Self {
+        unsafe {
+            let event_count = u64::from_le(
+                Self::field(ptr as *mut u8, offset_of!(Self, event_count)).load(Ordering::Acquire),
+            );
+            let write_offset = u64::from_le(
+                Self::field(ptr as *mut u8, offset_of!(Self, write_offset)).load(Ordering::Acquire),
+            );
+            let mut header = Self::decode(&*(ptr as *const [u8; Self::SIZE]));
+            header.event_count = event_count;
+            header.write_offset = write_offset;
+            header
+        }
+    }
+
+    #[inline]
+    unsafe fn field<'a>(ptr: *mut u8, offset: usize) -> &'a AtomicU64 {
+        unsafe { AtomicU64::from_ptr(ptr.add(offset) as *mut u64) }
+    }
 }
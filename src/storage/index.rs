@@ -0,0 +1,185 @@
+//! Sidecar indexes over sealed segments. `IndexBuilder` extracts a key from
+//! each event and writes `<segment>.idx`, mapping keys to event offsets, so
+//! `MmapReader::lookup` finds the events for a key, e.g. a request id,
+//! without scanning the segment.
+
+use super::MmapReader;
+use crate::event::{EventView, crc32};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+pub const INDEX_EXTENSION: &str = "idx";
+
+const MAGIC: [u8; 4] = *b"RLIX";
+const VERSION: u32 = 1;
+/// Magic, version, the segment's event count and data end, and the entry
+/// count.
+const HEADER_SIZE: usize = 4 + 4 + 8 + 8 + 8;
+const ENTRY_SIZE: usize = 24;
+
+/// An indexed event: its key, the file offset of its frame and the
+/// timestamp of the event before it, which compact frames are relative to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct IndexEntry {
+    pub key: u64,
+    pub offset: u64,
+    pub previous: u64,
+}
+
+/// The sidecar index path of the segment at `segment`.
+pub fn index_path<P: AsRef<Path>>(segment: P) -> PathBuf {
+    let mut name = segment.as_ref().as_os_str().to_owned();
+    name.push(".");
+    name.push(INDEX_EXTENSION);
+    PathBuf::from(name)
+}
+
+/// Builds the sidecar index of a segment from a key extractor. Events for
+/// which it returns `None` are left out of the index.
+pub struct IndexBuilder<F> {
+    extract: F,
+}
+
+impl<F> IndexBuilder<F>
+where
+    F: Fn(&EventView<'_>) -> Option<u64>,
+{
+    pub fn by(extract: F) -> Self {
+        Self { extract }
+    }
+
+    /// Indexes the sealed segment at `segment`, replacing any index it had.
+    /// Returns the number of indexed events.
+    pub fn build<P: AsRef<Path>>(&self, segment: P) -> io::Result<usize> {
+        let segment = segment.as_ref();
+        let reader = MmapReader::open(segment)?;
+        if !reader.is_sealed() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Cannot index unsealed segment {}: it may still grow",
+                    segment.display()
+                ),
+            ));
+        }
+
+        let mut entries = Vec::new();
+        let mut frames = reader.frames();
+        loop {
+            let (offset, previous) = (frames.offset(), frames.previous());
+            let Some(frame) = frames.next() else {
+                break;
+            };
+            let frame = frame.map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("Failed to index {}: {}", segment.display(), e),
+                )
+            })?;
+            if let Some(key) = (self.extract)(&reader.view(&frame)) {
+                entries.push(IndexEntry {
+                    key,
+                    offset: offset as u64,
+                    previous,
+                });
+            }
+        }
+        entries.sort_unstable();
+
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + entries.len() * ENTRY_SIZE + 4);
+        bytes.extend_from_slice(&MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&reader.event_count().to_le_bytes());
+        bytes.extend_from_slice(&(reader.data_end() as u64).to_le_bytes());
+        bytes.extend_from_slice(&(entries.len() as u64).to_le_bytes());
+        for entry in &entries {
+            bytes.extend_from_slice(&entry.key.to_le_bytes());
+            bytes.extend_from_slice(&entry.offset.to_le_bytes());
+            bytes.extend_from_slice(&entry.previous.to_le_bytes());
+        }
+        let crc = crc32(&bytes);
+        bytes.extend_from_slice(&crc.to_le_bytes());
+
+        let path = index_path(segment);
+        write_atomic(&path, &bytes).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Failed to write index {}: {}", path.display(), e),
+            )
+        })?;
+        Ok(entries.len())
+    }
+}
+
+fn write_atomic(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let mut tmp_name = path.as_os_str().to_owned();
+    tmp_name.push(".tmp");
+    let tmp_path = Path::new(&tmp_name);
+
+    let result = (|| {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(tmp_path)?;
+        file.write_all(bytes)?;
+        file.sync_all()?;
+        fs::rename(tmp_path, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(tmp_path);
+    }
+    result
+}
+
+/// Reads the index at `path`, checking it was built from a segment with
+/// `event_count` events ending at `data_end`.
+pub(crate) fn load_index(
+    path: &Path,
+    event_count: u64,
+    data_end: u64,
+) -> io::Result<Vec<IndexEntry>> {
+    let bytes = fs::read(path).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("Failed to read index {}: {}", path.display(), e),
+        )
+    })?;
+    let invalid = |reason: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid index {}: {}", path.display(), reason),
+        )
+    };
+
+    let Some((body, crc)) = bytes.split_last_chunk::<4>() else {
+        return Err(invalid("truncated"));
+    };
+    if body.len() < HEADER_SIZE || body[..4] != MAGIC {
+        return Err(invalid("bad magic"));
+    }
+    if crc32(body) != u32::from_le_bytes(*crc) {
+        return Err(invalid("checksum mismatch"));
+    }
+    let u64_at = |at: usize| u64::from_le_bytes(body[at..at + 8].try_into().unwrap());
+    if u32::from_le_bytes(body[4..8].try_into().unwrap()) != VERSION {
+        return Err(invalid("unsupported version"));
+    }
+    if u64_at(8) != event_count || u64_at(16) != data_end {
+        return Err(invalid("built from a different version of the segment"));
+    }
+    let count = u64_at(24) as usize;
+    if body.len() != HEADER_SIZE + count * ENTRY_SIZE {
+        return Err(invalid("truncated"));
+    }
+
+    Ok(body[HEADER_SIZE..]
+        .chunks_exact(ENTRY_SIZE)
+        .map(|entry| IndexEntry {
+            key: u64::from_le_bytes(entry[..8].try_into().unwrap()),
+            offset: u64::from_le_bytes(entry[8..16].try_into().unwrap()),
+            previous: u64::from_le_bytes(entry[16..].try_into().unwrap()),
+        })
+        .collect())
+}
@@ -0,0 +1,123 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ManifestEntry {
+    pub archived_key: Option<String>,
+    /// Where tiering moved the segment, if it no longer lives in the log
+    /// directory.
+    pub cold_path: Option<PathBuf>,
+}
+
+/// Per-directory record of segment state, stored as one line per segment:
+/// `<seq> [key=value ...]`.
+#[derive(Debug)]
+pub struct Manifest {
+    path: PathBuf,
+    entries: BTreeMap<u64, ManifestEntry>,
+}
+
+impl Manifest {
+    pub const FILE_NAME: &'static str = "MANIFEST";
+
+    pub fn load<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        let path = dir.as_ref().join(Self::FILE_NAME);
+        let mut entries = BTreeMap::new();
+
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+
+        for (lineno, line) in contents.lines().enumerate() {
+            let mut fields = line.split_whitespace();
+            let Some(seq) = fields.next() else {
+                continue;
+            };
+            let seq = seq.parse::<u64>().map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Invalid segment sequence on manifest line {}", lineno + 1),
+                )
+            })?;
+
+            let mut entry = ManifestEntry::default();
+            for field in fields {
+                if let Some(key) = field.strip_prefix("archived=") {
+                    entry.archived_key = Some(key.to_string());
+                } else if let Some(path) = field.strip_prefix("cold=") {
+                    entry.cold_path = Some(PathBuf::from(path));
+                }
+            }
+            entries.insert(seq, entry);
+        }
+
+        Ok(Self { path, entries })
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let mut contents = String::new();
+        for (seq, entry) in &self.entries {
+            contents.push_str(&seq.to_string());
+            if let Some(key) = &entry.archived_key {
+                if key.contains(char::is_whitespace) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("Archive key {:?} contains whitespace", key),
+                    ));
+                }
+                contents.push_str(" archived=");
+                contents.push_str(key);
+            }
+            if let Some(cold_path) = &entry.cold_path {
+                let Some(path) = cold_path
+                    .to_str()
+                    .filter(|p| !p.contains(char::is_whitespace))
+                else {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("Cold path {:?} is not UTF-8 without whitespace", cold_path),
+                    ));
+                };
+                contents.push_str(" cold=");
+                contents.push_str(path);
+            }
+            contents.push('\n');
+        }
+
+        let mut tmp_name = self.path.as_os_str().to_owned();
+        tmp_name.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_name);
+
+        let mut file = fs::File::create(&tmp_path)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, &self.path)
+    }
+
+    #[inline]
+    pub fn entry(&self, seq: u64) -> Option<&ManifestEntry> {
+        self.entries.get(&seq)
+    }
+
+    #[inline]
+    pub fn entry_mut(&mut self, seq: u64) -> &mut ManifestEntry {
+        self.entries.entry(seq).or_default()
+    }
+
+    pub fn remove(&mut self, seq: u64) -> Option<ManifestEntry> {
+        self.entries.remove(&seq)
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (u64, &ManifestEntry)> {
+        self.entries.iter().map(|(seq, entry)| (*seq, entry))
+    }
+
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
@@ -0,0 +1,99 @@
+use super::{EventIterator, MmapReader};
+use crate::event::EventView;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+/// The next event of one source.
+struct Head<'a> {
+    event: EventView<'a>,
+    source: usize,
+}
+
+impl Head<'_> {
+    fn key(&self) -> (u64, usize) {
+        (self.event.header.timestamp, self.source)
+    }
+}
+
+impl PartialEq for Head<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Head<'_> {}
+
+impl PartialOrd for Head<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Head<'_> {
+    /// Reversed, so the max-heap pops the earliest event.
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.key().cmp(&self.key())
+    }
+}
+
+/// Yields the events of several sources, e.g. per-core or per-host files,
+/// in global timestamp order. Each source is expected in timestamp order
+/// itself; events with equal timestamps come in source order.
+pub struct MergedIterator<'a, I = EventIterator<'a>> {
+    sources: Vec<I>,
+    heads: BinaryHeap<Head<'a>>,
+}
+
+impl<'a> MergedIterator<'a> {
+    /// Merges the events of `readers`, e.g. `&readers` for a
+    /// `Vec<MmapReader>`.
+    pub fn new<R>(readers: R) -> Self
+    where
+        R: IntoIterator<Item = &'a MmapReader>,
+    {
+        Self::from_iters(readers.into_iter().map(MmapReader::iter))
+    }
+}
+
+impl<'a, I> MergedIterator<'a, I>
+where
+    I: Iterator<Item = EventView<'a>>,
+{
+    /// Merges arbitrary event iterators, such as `SegmentedReader::iter`s
+    /// of several log directories.
+    pub fn from_iters<S>(sources: S) -> Self
+    where
+        S: IntoIterator<Item = I>,
+    {
+        let mut sources: Vec<I> = sources.into_iter().collect();
+        let heads = sources
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(source, events)| {
+                Some(Head {
+                    event: events.next()?,
+                    source,
+                })
+            })
+            .collect();
+        Self { sources, heads }
+    }
+}
+
+impl<'a, I> Iterator for MergedIterator<'a, I>
+where
+    I: Iterator<Item = EventView<'a>>,
+{
+    type Item = EventView<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let head = self.heads.pop()?;
+        if let Some(event) = self.sources[head.source].next() {
+            self.heads.push(Head {
+                event,
+                source: head.source,
+            });
+        }
+        Some(head.event)
+    }
+}
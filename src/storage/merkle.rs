@@ -0,0 +1,107 @@
+//! Merkle roots over the events of a segment, recorded in the
+//! `SegmentFooter` at seal time and optionally signed with ed25519 (feature
+//! `signing`), so a third party holding only the segment and a public key
+//! can check that it is complete and unmodified.
+//!
+//! Leaves are SHA-256 of `0x00` and an event's stored bytes, header
+//! included; inner nodes are SHA-256 of `0x01` and their two children, and
+//! the tree is split as in RFC 6962, so odd leaves are never duplicated.
+
+use super::frame::Frames;
+use super::{FileHeader, MmapReader, SegmentFooter, VerifyIssue};
+use sha2::{Digest, Sha256};
+
+pub type Hash = [u8; 32];
+
+/// Domain separator of the message a seal signature covers.
+const SEAL_CONTEXT: &[u8] = b"ringlog segment seal v2";
+
+pub fn leaf_hash(event: &[u8]) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([0x00]);
+    hasher.update(event);
+    hasher.finalize().into()
+}
+
+fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([0x01]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// The root over `leaves`; SHA-256 of nothing for an empty segment.
+pub fn merkle_root(leaves: &[Hash]) -> Hash {
+    match leaves {
+        [] => Sha256::digest([]).into(),
+        [leaf] => *leaf,
+        _ => {
+            // The largest power of two below the leaf count.
+            let split = 1 << (usize::BITS - 1 - (leaves.len() - 1).leading_zeros());
+            node_hash(
+                &merkle_root(&leaves[..split]),
+                &merkle_root(&leaves[split..]),
+            )
+        }
+    }
+}
+
+/// Leaf hashes of the events in `data[header.data_start()..end]`.
+pub(crate) fn leaves(data: &[u8], header: &FileHeader, end: usize) -> Vec<Hash> {
+    Frames::new(data, header, header.data_start(), end)
+        .map_while(Result::ok)
+        .map(|frame| leaf_hash(&data[frame.offset..frame.end]))
+        .collect()
+}
+
+/// What a seal signature covers: the segment's event count, data end and
+/// Merkle root.
+pub fn seal_message(footer: &SegmentFooter) -> Vec<u8> {
+    let mut message = SEAL_CONTEXT.to_vec();
+    message.extend_from_slice(&footer.event_count.to_le_bytes());
+    message.extend_from_slice(&footer.data_end.to_le_bytes());
+    message.extend_from_slice(&footer.merkle_root.unwrap_or_default());
+    message
+}
+
+impl MmapReader {
+    /// Recomputes the Merkle root over the file's events.
+    pub fn merkle_root(&self) -> Hash {
+        merkle_root(&leaves(
+            self.mapped_bytes(),
+            &self.file_header(),
+            self.data_end(),
+        ))
+    }
+
+    /// Checks a sealed segment's footer like `quick_verify`, then that its
+    /// Merkle root matches the events. Returns the footer, whose signature
+    /// `verify_signed_seal` checks.
+    pub fn verify_seal(&self) -> Result<SegmentFooter, VerifyIssue> {
+        let footer = self.quick_verify_footer()?;
+        let expected = footer.merkle_root.ok_or(VerifyIssue::MissingMerkleRoot)?;
+        let actual = self.merkle_root();
+        if actual != expected {
+            return Err(VerifyIssue::MerkleRootMismatch { expected, actual });
+        }
+        Ok(footer)
+    }
+
+    /// `verify_seal`, then checks the footer's signature against
+    /// `public_key`, which should come from a trusted source rather than
+    /// from the footer itself.
+    #[cfg(feature = "signing")]
+    pub fn verify_signed_seal(
+        &self,
+        public_key: &ed25519_dalek::VerifyingKey,
+    ) -> Result<SegmentFooter, VerifyIssue> {
+        let footer = self.verify_seal()?;
+        let signature = footer.signature.ok_or(VerifyIssue::Unsigned)?;
+        let signature = ed25519_dalek::Signature::from_bytes(&signature.signature);
+        public_key
+            .verify_strict(&seal_message(&footer), &signature)
+            .map_err(|_| VerifyIssue::SignatureInvalid)?;
+        Ok(footer)
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::BTreeMap;
+use std::io;
+
+/// Self-describing information stored once, at create time, in a block right
+/// after the `FileHeader`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metadata {
+    pub stream_id: Option<String>,
+    pub hostname: Option<String>,
+    pub pid: Option<u32>,
+    pub entries: BTreeMap<String, String>,
+}
+
+impl Metadata {
+    /// Metadata for `stream_id` with the hostname and pid of this process.
+    pub fn new(stream_id: impl Into<String>) -> Self {
+        Self {
+            stream_id: Some(stream_id.into()),
+            hostname: hostname(),
+            pid: Some(std::process::id()),
+            entries: BTreeMap::new(),
+        }
+    }
+
+    pub fn with(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.entries.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries.get(key).map(String::as_str)
+    }
+
+    /// Encodes as little-endian, length-prefixed fields: stream id, hostname
+    /// (u16 length, 0xFFFF for none), pid (u32, with a presence byte), then a
+    /// u16 entry count followed by the key/value strings.
+    pub fn encode(&self) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        encode_opt_str(&mut out, self.stream_id.as_deref())?;
+        encode_opt_str(&mut out, self.hostname.as_deref())?;
+        out.push(self.pid.is_some() as u8);
+        out.extend_from_slice(&self.pid.unwrap_or(0).to_le_bytes());
+
+        let count = u16::try_from(self.entries.len())
+            .map_err(|_| invalid("too many metadata entries".to_string()))?;
+        out.extend_from_slice(&count.to_le_bytes());
+        for (key, value) in &self.entries {
+            encode_str(&mut out, key)?;
+            encode_str(&mut out, value)?;
+        }
+
+        Ok(out)
+    }
+
+    pub fn decode(bytes: &[u8]) -> io::Result<Self> {
+        let mut cursor = Cursor { bytes, pos: 0 };
+        let stream_id = cursor.opt_str()?;
+        let hostname = cursor.opt_str()?;
+        let has_pid = cursor.take(1)?[0] != 0;
+        let pid = u32::from_le_bytes(cursor.take(4)?.try_into().unwrap());
+
+        let count = cursor.u16()?;
+        let mut entries = BTreeMap::new();
+        for _ in 0..count {
+            let key = cursor.str()?;
+            let value = cursor.str()?;
+            entries.insert(key, value);
+        }
+
+        Ok(Self {
+            stream_id,
+            hostname,
+            pid: has_pid.then_some(pid),
+            entries,
+        })
+    }
+}
+
+const NONE_LEN: u16 = u16::MAX;
+
+fn encode_opt_str(out: &mut Vec<u8>, value: Option<&str>) -> io::Result<()> {
+    match value {
+        Some(value) => encode_str(out, value),
+        None => {
+            out.extend_from_slice(&NONE_LEN.to_le_bytes());
+            Ok(())
+        }
+    }
+}
+
+fn encode_str(out: &mut Vec<u8>, value: &str) -> io::Result<()> {
+    let len = u16::try_from(value.len())
+        .ok()
+        .filter(|&len| len != NONE_LEN)
+        .ok_or_else(|| {
+            invalid(format!(
+                "metadata string of {} bytes is too long",
+                value.len()
+            ))
+        })?;
+    out.extend_from_slice(&len.to_le_bytes());
+    out.extend_from_slice(value.as_bytes());
+    Ok(())
+}
+
+struct Cursor<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        let slice = self
+            .bytes
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| invalid(format!("metadata truncated at byte {}", self.pos)))?;
+        self.pos += len;
+        Ok(slice)
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn opt_str(&mut self) -> io::Result<Option<String>> {
+        match self.u16()? {
+            NONE_LEN => Ok(None),
+            len => self.string(len as usize).map(Some),
+        }
+    }
+
+    fn str(&mut self) -> io::Result<String> {
+        let len = self.u16()? as usize;
+        self.string(len)
+    }
+
+    fn string(&mut self, len: usize) -> io::Result<String> {
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec())
+            .map_err(|_| invalid("metadata string is not UTF-8".to_string()))
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+pub(crate) fn hostname() -> Option<String> {
+    let mut buf = [0u8; 256];
+    let result = unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) };
+    if result != 0 {
+        return None;
+    }
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    String::from_utf8(buf[..len].to_vec()).ok()
+}
@@ -1,24 +1,123 @@
-use super::FileHeader;
-use crate::event::{EventHeader, EventView};
+use super::bloom::BloomFilter;
+use super::frame::{Frame, Frames};
+use super::index::{IndexEntry, index_path, load_index};
+use super::options::{Advice, madvise};
+use super::{FileHeader, Metadata, SegmentFooter};
+use crate::event::{EventHeader, EventView, StreamId, StreamNames, StringTable, Tags, TypeNames};
 use std::fs::File;
 use std::io;
 use std::os::unix::io::AsRawFd;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::ptr;
+use std::sync::OnceLock;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
 
 pub struct MmapReader {
     _file: File,
     mmap_ptr: *const u8,
     mmap_len: usize,
     file_header: FileHeader,
+    metadata: Option<Metadata>,
+    /// Offset of each event and the timestamp of the one before it.
+    offsets: OnceLock<Vec<(usize, u64)>>,
+    /// Where the file was opened from, for its sidecar index.
+    path: Option<PathBuf>,
+    index: OnceLock<Vec<IndexEntry>>,
+    bloom: OnceLock<Option<BloomFilter>>,
 }
 
 impl MmapReader {
     pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
-        let file = File::open(path)?;
-        let metadata = file.metadata()?;
-        let len = metadata.len() as usize;
+        let mut reader = Self::from_file(File::open(&path)?)?;
+        reader.path = Some(path.as_ref().to_path_buf());
+        Ok(reader)
+    }
+
+    /// Opens a consistent view of a file another thread or process may be
+    /// appending to.
+    ///
+    /// The writer copies each event into its shared mapping and only then
+    /// publishes `write_offset` and `event_count` to the file header with
+    /// release stores. This loads both with acquire ordering from a mapping
+    /// of the header alone, then maps the file up to that `write_offset`:
+    /// every byte below it was written before it was published, and no
+    /// byte above it is mapped, so iteration never sees a partially written
+    /// event, however far the writer has got since. `event_count` may trail
+    /// the events below `write_offset` by those of the writer's last
+    /// publish. Sealed files are mapped whole, footer included. An unsealed
+    /// file that ends before its `write_offset` is `InvalidData`.
+    ///
+    /// `open` loads the header from a mapping of the whole file instead,
+    /// and bounds iteration by the smaller of `write_offset` and the file
+    /// size when it was opened.
+    pub fn open_snapshot<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::open(&path)?;
+        let file_len = file.metadata()?.len() as usize;
+        if file_len < FileHeader::SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "File too small for header",
+            ));
+        }
 
+        let header_ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                FileHeader::SIZE,
+                libc::PROT_READ,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if header_ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        let header = unsafe { FileHeader::load(header_ptr as *const u8) };
+        unsafe {
+            libc::munmap(header_ptr, FileHeader::SIZE);
+        }
+        if !header.validate() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Invalid file header",
+            ));
+        }
+
+        // The writer extends the file before publishing an offset past its
+        // old end, so only a truncated or corrupt file ends before
+        // `write_offset`. Mapping past the end would fault on first read.
+        let len = if header.is_sealed() {
+            file_len
+        } else {
+            let write_offset = header.write_offset as usize;
+            if write_offset > file_len || header.data_start() > file_len {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "Write offset {} is past the end of the {} byte file",
+                        write_offset, file_len
+                    ),
+                ));
+            }
+            write_offset.min(file_len).max(header.data_start())
+        };
+        let mut reader = Self::map(file, len, Some(header))?;
+        reader.path = Some(path.as_ref().to_path_buf());
+        Ok(reader)
+    }
+
+    /// Maps an already open log file, such as an anonymous file holding a
+    /// decompressed segment.
+    pub(crate) fn from_file(file: File) -> io::Result<Self> {
+        let len = file.metadata()?.len() as usize;
+        Self::map(file, len, None)
+    }
+
+    /// Maps the first `len` bytes of `file`, taking the file header from
+    /// the mapping unless `header` was already loaded.
+    fn map(file: File, len: usize, header: Option<FileHeader>) -> io::Result<Self> {
         if len < FileHeader::SIZE {
             return Err(io::Error::new(
                 io::ErrorKind::InvalidData,
@@ -45,7 +144,8 @@ impl MmapReader {
             ));
         }
 
-        let file_header = unsafe { ptr::read_unaligned(mmap_ptr as *const FileHeader) };
+        let file_header =
+            header.unwrap_or_else(|| unsafe { FileHeader::load(mmap_ptr as *const u8) });
 
         if !file_header.validate() {
             unsafe {
@@ -57,11 +157,26 @@ impl MmapReader {
             ));
         }
 
+        let metadata = match read_metadata(mmap_ptr as *const u8, len, &file_header) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                unsafe {
+                    libc::munmap(mmap_ptr, len);
+                }
+                return Err(e);
+            }
+        };
+
         Ok(Self {
             _file: file,
             mmap_ptr: mmap_ptr as *const u8,
             mmap_len: len,
             file_header,
+            metadata,
+            offsets: OnceLock::new(),
+            path: None,
+            index: OnceLock::new(),
+            bloom: OnceLock::new(),
         })
     }
 
@@ -75,47 +190,298 @@ impl MmapReader {
         self.file_header.created_at
     }
 
+    /// The metadata block written at create time, if any.
+    #[inline]
+    pub fn metadata(&self) -> Option<&Metadata> {
+        self.metadata.as_ref()
+    }
+
+    pub fn file_header(&self) -> FileHeader {
+        self.file_header
+    }
+
+    #[inline]
+    pub fn is_sealed(&self) -> bool {
+        self.file_header.is_sealed()
+    }
+
+    #[inline]
+    pub fn mapped_len(&self) -> usize {
+        self.mmap_len
+    }
+
+    pub(crate) fn mapped_bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.mmap_ptr, self.mmap_len) }
+    }
+
     #[inline]
     pub fn replay<F>(&self, mut callback: F) -> u64
     where
         F: FnMut(EventView),
     {
-        let mut offset = FileHeader::SIZE;
-        let end = self.file_header.write_offset as usize;
         let mut count = 0;
-
-        while offset < end {
-            let event = self.event_at(offset);
-            let size = event.total_size();
+        for event in self.iter() {
             callback(event);
-            offset += size;
             count += 1;
         }
-
         count
     }
 
     #[inline]
-    fn event_at(&self, offset: usize) -> EventView<'_> {
-        unsafe {
-            let header_ptr = self.mmap_ptr.add(offset) as *const EventHeader;
-            let header = &*header_ptr;
+    pub(crate) fn data_end(&self) -> usize {
+        (self.file_header.write_offset as usize).min(self.mmap_len)
+    }
 
-            let payload_ptr = self.mmap_ptr.add(offset + EventHeader::SIZE);
-            let payload = std::slice::from_raw_parts(payload_ptr, header.payload_len as usize);
+    #[inline]
+    pub(crate) fn frames(&self) -> Frames<'_> {
+        Frames::new(
+            self.mapped_bytes(),
+            &self.file_header,
+            self.file_header.data_start(),
+            self.data_end(),
+        )
+    }
 
-            EventView { header, payload }
+    #[inline]
+    pub(crate) fn view(&self, frame: &Frame) -> EventView<'_> {
+        EventView {
+            header: frame.header,
+            payload: &self.mapped_bytes()[frame.payload.clone()],
         }
     }
 
     pub fn iter(&self) -> EventIterator<'_> {
         EventIterator {
+            inner: self.try_iter(),
+        }
+    }
+
+    /// Events whose header matches `filter`. Skipped events are stepped over
+    /// by their header alone; no payload view is built for them.
+    pub fn iter_filtered<F>(&self, filter: F) -> FilteredIterator<'_, F>
+    where
+        F: FnMut(&EventHeader) -> bool,
+    {
+        FilteredIterator {
+            reader: self,
+            frames: self.frames(),
+            filter,
+        }
+    }
+
+    /// Like `replay`, for events whose header matches `filter`. Returns how
+    /// many were passed to `callback`.
+    pub fn replay_filtered<F, C>(&self, filter: F, mut callback: C) -> u64
+    where
+        F: FnMut(&EventHeader) -> bool,
+        C: FnMut(EventView),
+    {
+        let mut count = 0;
+        for event in self.iter_filtered(filter) {
+            callback(event);
+            count += 1;
+        }
+        count
+    }
+
+    /// Streams owned copies of every event through a channel holding at most
+    /// `capacity` events, from a background thread that owns the reader. The
+    /// scan blocks while the channel is full and stops once the receiver is
+    /// dropped.
+    pub fn replay_to_channel(self, capacity: usize) -> Receiver<(EventHeader, Vec<u8>)> {
+        let (tx, rx) = mpsc::sync_channel(capacity);
+        thread::spawn(move || {
+            for event in self.iter() {
+                if tx.send((event.header, event.payload.to_vec())).is_err() {
+                    break;
+                }
+            }
+        });
+        rx
+    }
+
+    /// Events of one stream, in file order.
+    pub fn iter_stream(&self, stream: StreamId) -> impl Iterator<Item = EventView<'_>> {
+        self.iter_filtered(move |h| h.stream() == stream)
+    }
+
+    /// Events carrying every tag of `tags`.
+    pub fn iter_tagged(&self, tags: Tags) -> impl Iterator<Item = EventView<'_>> {
+        self.iter().filter(move |e| Tags::of(e).contains(tags))
+    }
+
+    /// Events carrying `trace_id`, in file order.
+    pub fn find_by_trace(&self, trace_id: u64) -> impl Iterator<Item = EventView<'_>> {
+        self.iter().filter(move |e| {
+            e.header
+                .split_trace(e.payload)
+                .0
+                .is_some_and(|trace| trace.trace_id == trace_id)
+        })
+    }
+
+    /// The latest marker named `name`.
+    pub fn find_marker(&self, name: &str) -> Option<EventView<'_>> {
+        self.marker_position(name).map(|(event, _, _)| event)
+    }
+
+    /// The latest marker named `name`, with the offset of the frame after it
+    /// and the timestamp compact frames there are relative to.
+    pub(crate) fn marker_position(&self, name: &str) -> Option<(EventView<'_>, usize, u64)> {
+        let mut found = None;
+        let mut frames = self.frames();
+        while let Some(Ok(frame)) = frames.next() {
+            let event = self.view(&frame);
+            if event.header.marker(event.payload) == Some(name) {
+                found = Some((event, frames.offset(), frames.previous()));
+            }
+        }
+        found
+    }
+
+    /// Replays the events after the latest marker named `name`. Returns how
+    /// many there were, or `None` if there is no such marker.
+    pub fn replay_since_marker<F>(&self, name: &str, mut callback: F) -> Option<u64>
+    where
+        F: FnMut(EventView),
+    {
+        let (_, offset, previous) = self.marker_position(name)?;
+        let mut frames = self.frames();
+        frames.seek(offset, previous);
+        let mut count = 0;
+        while let Some(Ok(frame)) = frames.next() {
+            callback(self.view(&frame));
+            count += 1;
+        }
+        Some(count)
+    }
+
+    /// The bloom filter in a sealed segment's footer, read on first use.
+    /// `None` for unsealed segments and those sealed without one.
+    pub fn bloom(&self) -> Option<&BloomFilter> {
+        self.bloom
+            .get_or_init(|| {
+                if !self.is_sealed() {
+                    return None;
+                }
+                let footer = self.mapped_bytes().get(self.data_end()..)?;
+                SegmentFooter::decode(footer).ok()?.bloom
+            })
+            .as_ref()
+    }
+
+    /// Whether the segment may hold events with `key`, a trace id or a
+    /// `WriterOptions::bloom_key` key. `false` means it definitely does
+    /// not; segments without a bloom filter always may.
+    pub fn may_contain(&self, key: u64) -> bool {
+        self.bloom().is_none_or(|bloom| bloom.may_contain(key))
+    }
+
+    /// Stream names recorded in the file's metadata.
+    pub fn stream_names(&self) -> StreamNames {
+        self.metadata
+            .as_ref()
+            .map(StreamNames::from_metadata)
+            .unwrap_or_default()
+    }
+
+    /// Event type names recorded in the file's metadata.
+    pub fn type_names(&self) -> TypeNames {
+        self.metadata
+            .as_ref()
+            .map(TypeNames::from_metadata)
+            .unwrap_or_default()
+    }
+
+    /// Interned strings recorded in the file's metadata.
+    pub fn string_table(&self) -> StringTable {
+        self.metadata
+            .as_ref()
+            .map(StringTable::from_metadata)
+            .unwrap_or_default()
+    }
+
+    pub fn try_iter(&self) -> TryEventIterator<'_> {
+        TryEventIterator {
             reader: self,
-            offset: FileHeader::SIZE,
-            end: self.file_header.write_offset as usize,
+            frames: self.frames(),
         }
     }
 
+    /// Yields events newest first. The first call scans the file once to
+    /// build an offset index, which is kept for later calls.
+    pub fn iter_rev(&self) -> RevEventIterator<'_> {
+        RevEventIterator {
+            reader: self,
+            offsets: self.event_offsets().iter(),
+        }
+    }
+
+    fn event_offsets(&self) -> &[(usize, u64)] {
+        self.offsets.get_or_init(|| {
+            let mut frames = self.frames();
+            let max_events = frames.remaining() / frames.header_size();
+            let mut offsets =
+                Vec::with_capacity((self.file_header.event_count as usize).min(max_events));
+
+            loop {
+                let entry = (frames.offset(), frames.previous());
+                match frames.next() {
+                    Some(Ok(_)) => offsets.push(entry),
+                    _ => break,
+                }
+            }
+
+            offsets
+        })
+    }
+
+    /// Events whose key is `key` in the segment's sidecar index, written
+    /// by `IndexBuilder`. The first call loads the index, which is kept for
+    /// later calls. Fails if the reader was not opened from a path, or the
+    /// index is missing or was built before the segment was rewritten.
+    pub fn lookup(&self, key: u64) -> io::Result<Vec<EventView<'_>>> {
+        let index = match self.index.get() {
+            Some(index) => index,
+            None => {
+                let Some(path) = &self.path else {
+                    return Err(io::Error::new(
+                        io::ErrorKind::Unsupported,
+                        "Reader was not opened from a path; it has no index",
+                    ));
+                };
+                let loaded = load_index(
+                    &index_path(path),
+                    self.file_header.event_count,
+                    self.data_end() as u64,
+                )?;
+                self.index.get_or_init(|| loaded)
+            }
+        };
+
+        let start = index.partition_point(|entry| entry.key < key);
+        index[start..]
+            .iter()
+            .take_while(|entry| entry.key == key)
+            .map(|entry| {
+                let mut frames = self.frames();
+                frames.seek(entry.offset as usize, entry.previous);
+                match frames.next() {
+                    Some(Ok(frame)) => Ok(self.view(&frame)),
+                    Some(Err(e)) => Err(e),
+                    None => Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Index points past the data at offset {}", entry.offset),
+                    )),
+                }
+            })
+            .collect()
+    }
+
+    pub fn advise(&self, advice: Advice) -> io::Result<()> {
+        madvise(self.mmap_ptr, self.mmap_len, advice)
+    }
+
     pub fn advise_sequential(&self) -> io::Result<()> {
         let result = unsafe {
             libc::madvise(
@@ -124,7 +490,7 @@ impl MmapReader {
                 libc::MADV_SEQUENTIAL,
             )
         };
-        
+
         if result != 0 {
             Err(io::Error::last_os_error())
         } else {
@@ -140,7 +506,7 @@ impl MmapReader {
                 libc::MADV_WILLNEED,
             )
         };
-        
+
         if result != 0 {
             Err(io::Error::last_os_error())
         } else {
@@ -160,27 +526,132 @@ impl Drop for MmapReader {
 unsafe impl Send for MmapReader {}
 unsafe impl Sync for MmapReader {}
 
+impl<'a> IntoIterator for &'a MmapReader {
+    type Item = EventView<'a>;
+    type IntoIter = EventIterator<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
 pub struct EventIterator<'a> {
-    reader: &'a MmapReader,
-    offset: usize,
-    end: usize,
+    inner: TryEventIterator<'a>,
 }
 
 impl<'a> Iterator for EventIterator<'a> {
     type Item = EventView<'a>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.offset >= self.end {
-            return None;
+        self.inner.next()?.ok()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+pub struct RevEventIterator<'a> {
+    reader: &'a MmapReader,
+    offsets: std::slice::Iter<'a, (usize, u64)>,
+}
+
+impl<'a> Iterator for RevEventIterator<'a> {
+    type Item = EventView<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let &(offset, previous) = self.offsets.next_back()?;
+        let mut frames = self.reader.frames();
+        frames.seek(offset, previous);
+        let frame = frames.next()?.ok()?;
+        Some(self.reader.view(&frame))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.offsets.size_hint()
+    }
+}
+
+impl ExactSizeIterator for RevEventIterator<'_> {}
+
+pub struct FilteredIterator<'a, F> {
+    reader: &'a MmapReader,
+    frames: Frames<'a>,
+    filter: F,
+}
+
+impl<'a, F> Iterator for FilteredIterator<'a, F>
+where
+    F: FnMut(&EventHeader) -> bool,
+{
+    type Item = EventView<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let frame = self.frames.next()?.ok()?;
+            if (self.filter)(&frame.header) {
+                return Some(self.reader.view(&frame));
+            }
         }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (
+            0,
+            Some(self.frames.remaining().div_ceil(self.frames.header_size())),
+        )
+    }
+}
+
+pub struct TryEventIterator<'a> {
+    reader: &'a MmapReader,
+    frames: Frames<'a>,
+}
+
+impl TryEventIterator<'_> {
+    /// File offset of the next event, or of the time-base record before it.
+    #[inline]
+    pub fn offset(&self) -> usize {
+        self.frames.offset()
+    }
+}
+
+impl<'a> Iterator for TryEventIterator<'a> {
+    type Item = io::Result<EventView<'a>>;
 
-        let event = self.reader.event_at(self.offset);
-        self.offset += event.total_size();
-        Some(event)
+    fn next(&mut self) -> Option<Self::Item> {
+        let frame = self.frames.next()?;
+        Some(frame.map(|frame| self.reader.view(&frame)))
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        let max_events = (self.end - self.offset) / EventHeader::SIZE;
+        let max_events = self.frames.remaining().div_ceil(self.frames.header_size());
         (0, Some(max_events))
     }
 }
+
+fn read_metadata(
+    mmap_ptr: *const u8,
+    len: usize,
+    header: &FileHeader,
+) -> io::Result<Option<Metadata>> {
+    if header.metadata_len == 0 {
+        return Ok(None);
+    }
+
+    let end = FileHeader::SIZE + header.metadata_len as usize;
+    if end > len {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Metadata block of {} bytes overruns file of {} bytes",
+                header.metadata_len, len
+            ),
+        ));
+    }
+
+    let bytes = unsafe {
+        std::slice::from_raw_parts(mmap_ptr.add(FileHeader::SIZE), end - FileHeader::SIZE)
+    };
+    Metadata::decode(bytes).map(Some)
+}
@@ -1,27 +1,110 @@
-use super::FileHeader;
-use crate::event::EventHeader;
-use std::fs::{File, OpenOptions};
-use std::io;
+use super::bloom::BloomFilter;
+use super::chain::chain_hash;
+use super::footer::SealSignature;
+use super::frame::Frames;
+use super::merkle;
+use super::options::{Advice, BloomKey, Prefault, WriterOptions, madvise};
+use super::repair::scan_valid_events;
+use super::{FileHeader, SegmentFooter, StorageError};
+use crate::event::{CompactHeader, EventHeader, EventView, crc32};
+use crate::instrument::instrument_span;
+use crate::sys;
+use std::cell::Cell;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::{FileExt, OpenOptionsExt, PermissionsExt};
+use std::os::unix::io::AsRawFd;
 use std::path::Path;
 use std::ptr;
+use std::time::{Duration, Instant};
 
 pub struct MmapWriter {
-    _file: File,
+    file: File,
     mmap_ptr: *mut u8,
     mmap_len: usize,
     write_offset: usize,
+    pending_events: Cell<u64>,
+    defer_header: bool,
+    durable_offset: Cell<usize>,
+    compact: bool,
+    /// Timestamp the next compact delta is taken from.
+    last_timestamp: u64,
+    /// Chain value of the last event, in a hash-chained file.
+    chain: Option<u32>,
+    bloom_key: Option<BloomKey>,
 }
 
 impl MmapWriter {
     pub fn create<P: AsRef<Path>>(path: P, capacity: usize) -> io::Result<Self> {
+        Self::create_with_options(path, capacity, &WriterOptions::default())
+    }
+
+    pub fn create_with_options<P: AsRef<Path>>(
+        path: P,
+        capacity: usize,
+        options: &WriterOptions,
+    ) -> io::Result<Self> {
+        let path = path.as_ref();
         let capacity = capacity.max(4096);
+        let metadata = match &options.metadata {
+            Some(metadata) => metadata.encode()?,
+            None => Vec::new(),
+        };
+        if options.compact && options.hash_chain {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "compact files cannot be hash-chained",
+            ));
+        }
+        let data_start = FileHeader::data_start_for(metadata.len());
+        if data_start > capacity {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "metadata of {} bytes does not fit capacity {}",
+                    metadata.len(),
+                    capacity
+                ),
+            ));
+        }
+
+        let file = if options.tmpfile {
+            if options.lock {
+                match File::open(path) {
+                    Ok(existing) => lock_file(&existing, path, options.force)?,
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            open_tmpfile(path, options.mode)?
+        } else {
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .mode(options.mode)
+                .open(path)?;
+            if options.lock {
+                lock_file(&file, path, options.force)?;
+            }
+            file.set_len(0)?;
+            file
+        };
 
-        let file = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(path)?;
+        if options.tmpfile && options.lock {
+            lock_file(&file, path, options.force)?;
+        }
+
+        file.set_permissions(fs::Permissions::from_mode(options.mode))?;
+        if let Some((uid, gid)) = options.owner {
+            std::os::unix::fs::fchown(&file, Some(uid), Some(gid)).map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("Failed to chown to {}:{}: {}", uid, gid, e),
+                )
+            })?;
+        }
 
         file.set_len(capacity as u64).map_err(|e| {
             io::Error::new(
@@ -30,12 +113,27 @@ impl MmapWriter {
             )
         })?;
 
+        if options.preallocate {
+            preallocate(&file, capacity).map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("Failed to preallocate {} bytes: {}", capacity, e),
+                )
+            })?;
+        }
+
+        let mut flags = libc::MAP_SHARED;
+        #[cfg(target_os = "linux")]
+        if options.prefault == Prefault::Populate {
+            flags |= libc::MAP_POPULATE;
+        }
+
         let mmap_ptr = unsafe {
             libc::mmap(
                 ptr::null_mut(),
                 capacity,
                 libc::PROT_READ | libc::PROT_WRITE,
-                libc::MAP_SHARED,
+                flags,
                 std::os::unix::io::AsRawFd::as_raw_fd(&file),
                 0,
             )
@@ -50,10 +148,17 @@ impl MmapWriter {
         }
 
         let mut mmap_writer = Self {
-            _file: file,
+            file,
             mmap_ptr: mmap_ptr as *mut u8,
             mmap_len: capacity,
-            write_offset: FileHeader::SIZE,
+            write_offset: data_start,
+            pending_events: Cell::new(0),
+            defer_header: false,
+            durable_offset: Cell::new(0),
+            compact: options.compact,
+            last_timestamp: 0,
+            chain: options.hash_chain.then_some(0),
+            bloom_key: options.bloom_key,
         };
 
         let now = std::time::SystemTime::now()
@@ -61,14 +166,99 @@ impl MmapWriter {
             .unwrap()
             .as_secs() as i64;
 
-        let header = FileHeader::new(now);
+        let mut header = FileHeader::new(now);
+        header.metadata_len = metadata.len() as u32;
+        header.write_offset = data_start as u64;
+        if options.compact {
+            header.flags |= FileHeader::FLAG_COMPACT;
+        }
+        if options.hash_chain {
+            header.flags |= FileHeader::FLAG_HASH_CHAIN;
+        }
+        unsafe {
+            ptr::copy_nonoverlapping(
+                metadata.as_ptr(),
+                mmap_writer.mmap_ptr.add(FileHeader::SIZE),
+                metadata.len(),
+            );
+        }
         mmap_writer.write_file_header(&header);
 
+        #[cfg(not(target_os = "linux"))]
+        if options.prefault == Prefault::Populate {
+            mmap_writer.touch_pages();
+        }
+        if options.prefault == Prefault::Touch {
+            mmap_writer.touch_pages();
+        }
+
+        if options.tmpfile {
+            mmap_writer.sync()?;
+            link_tmpfile(&mmap_writer.file, path)?;
+        }
+        if options.sync_dir {
+            sync_parent_dir(path)?;
+        }
+
         Ok(mmap_writer)
     }
 
+    /// Opens an existing file for appending. Complete events found past the
+    /// recorded `write_offset` (left by a crash before a deferred header update
+    /// was committed) are recovered and published.
     pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::open_with_options(path, &WriterOptions::default())
+    }
+
+    /// Like `open`; only the locking fields of `options` and `bloom_key`
+    /// apply.
+    pub fn open_with_options<P: AsRef<Path>>(path: P, options: &WriterOptions) -> io::Result<Self> {
+        let mut writer = Self::open_unchecked(path, options)?;
+
+        let header = writer.file_header();
+        if header.write_offset < header.data_start() as u64
+            || header.write_offset > writer.mmap_len as u64
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "write_offset {} out of bounds for file of {} bytes",
+                    header.write_offset, writer.mmap_len
+                ),
+            ));
+        }
+
+        let (end, recovered) =
+            scan_valid_events(writer.mapped_bytes(), &header, writer.write_offset);
+        if recovered > 0 {
+            writer.set_committed(end, header.event_count + recovered);
+        }
+        if writer.compact || writer.chain.is_some() {
+            let last = Frames::new(writer.mapped_bytes(), &header, header.data_start(), end)
+                .map_while(Result::ok)
+                .last()
+                .map(|frame| frame.header);
+            if writer.compact {
+                writer.last_timestamp = last.map_or(0, |header| header.timestamp);
+            } else {
+                writer.chain = Some(last.map_or(0, |header| header._reserved));
+            }
+        }
+
+        Ok(writer)
+    }
+
+    /// Maps an existing file without checking or recovering its committed
+    /// region. Used by `repair`, which rebuilds that region itself.
+    pub(crate) fn open_unchecked<P: AsRef<Path>>(
+        path: P,
+        options: &WriterOptions,
+    ) -> io::Result<Self> {
+        let path = path.as_ref();
         let file = OpenOptions::new().read(true).write(true).open(path)?;
+        if options.lock {
+            lock_file(&file, path, options.force)?;
+        }
 
         let metadata = file.metadata()?;
         let capacity = metadata.len() as usize;
@@ -91,67 +281,285 @@ impl MmapWriter {
             ));
         }
 
-        let header = unsafe { ptr::read_unaligned(mmap_ptr as *const FileHeader) };
+        let header = unsafe { FileHeader::load(mmap_ptr as *const u8) };
 
         if !header.validate() {
+            unsafe {
+                libc::munmap(mmap_ptr, capacity);
+            }
             return Err(io::Error::new(
                 io::ErrorKind::InvalidData,
                 "Invalid file header",
             ));
         }
 
+        // A sealed segment's footer lies past `write_offset`; map only the
+        // events so the writer sees no free space.
+        let (mmap_ptr, capacity) =
+            if header.is_sealed() && (header.write_offset as usize) < capacity {
+                let used = header.write_offset as usize;
+                unsafe {
+                    libc::munmap(mmap_ptr, capacity);
+                    let ptr = libc::mmap(
+                        ptr::null_mut(),
+                        used,
+                        libc::PROT_READ | libc::PROT_WRITE,
+                        libc::MAP_SHARED,
+                        file.as_raw_fd(),
+                        0,
+                    );
+                    if ptr == libc::MAP_FAILED {
+                        return Err(io::Error::last_os_error());
+                    }
+                    (ptr, used)
+                }
+            } else {
+                (mmap_ptr, capacity)
+            };
+
         Ok(Self {
-            _file: file,
+            file,
             mmap_ptr: mmap_ptr as *mut u8,
             mmap_len: capacity,
             write_offset: header.write_offset as usize,
+            pending_events: Cell::new(0),
+            defer_header: false,
+            durable_offset: Cell::new(0),
+            compact: header.is_compact(),
+            last_timestamp: 0,
+            chain: (header.flags & FileHeader::FLAG_HASH_CHAIN != 0).then_some(0),
+            bloom_key: options.bloom_key,
         })
     }
 
     #[inline]
     pub fn available(&self) -> usize {
-        self.mmap_len - self.write_offset
+        self.mmap_len.saturating_sub(self.write_offset)
+    }
+
+    /// When enabled, appends no longer touch the file header; `event_count` and
+    /// `write_offset` are published by `commit()`, `sync()` or drop. Events
+    /// written but not yet committed are recovered by the next `open()`.
+    ///
+    /// Combined with `sync()` this also makes the file consistent across power
+    /// loss: the header only reaches disk after the events it describes.
+    /// Without it the kernel may write back the header page first.
+    pub fn set_deferred_header(&mut self, deferred: bool) {
+        self.defer_header = deferred;
+        if !deferred {
+            self.commit();
+        }
     }
 
     #[inline]
-    pub fn write_event(&mut self, header: &EventHeader, payload: &[u8]) -> bool {
-        let total_size = header.total_size();
+    pub fn pending_events(&self) -> u64 {
+        self.pending_events.get()
+    }
 
-        if total_size > self.available() {
-            return false;
+    /// Publishes every event written so far to the file header.
+    #[inline]
+    pub fn commit(&self) {
+        let pending = self.pending_events.replace(0);
+        unsafe {
+            let header = FileHeader::load(self.mmap_ptr);
+            FileHeader::publish(
+                self.mmap_ptr,
+                self.write_offset as u64,
+                header.event_count + pending,
+            );
         }
+    }
 
-        unsafe {
-            let dst = self.mmap_ptr.add(self.write_offset);
+    /// Appends events in order until one does not fit or has a reserved type,
+    /// updating the file header once for the whole batch. Returns how many
+    /// events were written.
+    pub fn write_batch(&mut self, events: &[(EventHeader, &[u8])]) -> usize {
+        let mut written = 0;
+        for (header, payload) in events {
+            if check_type(header).is_err() || self.append(header, &[payload]).is_err() {
+                break;
+            }
+            written += 1;
+        }
+
+        self.pending_events
+            .set(self.pending_events.get() + written as u64);
+        if !self.defer_header {
+            self.commit();
+        }
+
+        written
+    }
+
+    /// Like `try_write_event`, without saying why a write failed.
+    #[inline]
+    pub fn write_event(&mut self, header: &EventHeader, payload: &[u8]) -> bool {
+        self.try_write_event(header, payload).is_ok()
+    }
 
-            ptr::write_unaligned(dst as *mut EventHeader, *header);
+    /// Writes one event. Reserved event types are refused as an invalid
+    /// header.
+    #[inline]
+    pub fn try_write_event(
+        &mut self,
+        header: &EventHeader,
+        payload: &[u8],
+    ) -> Result<(), StorageError> {
+        check_type(header)?;
+        self.write_event_unchecked(header, &[payload])
+    }
+
+    pub fn write_event_vectored(&mut self, header: &EventHeader, parts: &[&[u8]]) -> bool {
+        self.try_write_event_vectored(header, parts).is_ok()
+    }
+
+    /// Writes one event whose payload is the concatenation of `parts`.
+    /// Reserved event types are refused as an invalid header.
+    pub fn try_write_event_vectored(
+        &mut self,
+        header: &EventHeader,
+        parts: &[&[u8]],
+    ) -> Result<(), StorageError> {
+        check_type(header)?;
+        self.write_event_unchecked(header, parts)
+    }
+
+    /// `try_write_event_vectored` without the reserved type check, for
+    /// copying events that were already accepted.
+    pub(crate) fn write_event_unchecked(
+        &mut self,
+        header: &EventHeader,
+        parts: &[&[u8]],
+    ) -> Result<(), StorageError> {
+        self.append(header, parts)?;
+        self.record_write();
+        Ok(())
+    }
 
-            ptr::copy_nonoverlapping(payload.as_ptr(), dst.add(EventHeader::SIZE), payload.len());
+    #[inline]
+    fn append(&mut self, header: &EventHeader, parts: &[&[u8]]) -> Result<(), StorageError> {
+        let payload_len: usize = parts.iter().map(|p| p.len()).sum();
+        if payload_len != header.payload_len as usize {
+            return Err(StorageError::InvalidHeader {
+                reason: "payload length does not match the header",
+            });
         }
 
-        self.write_offset += total_size;
-        self.update_file_header();
+        if self.compact {
+            return self.append_compact(header, parts);
+        }
 
-        true
+        let total_size = header.total_size();
+        self.reserve(total_size)?;
+        match self.chain {
+            Some(previous) => {
+                let mut header = EventHeader {
+                    flags: header.flags & !EventHeader::FLAG_CHECKSUM,
+                    ..*header
+                };
+                header._reserved = chain_hash(previous, &header, parts);
+                self.chain = Some(header._reserved);
+                self.copy_frame(&header.encode(), parts);
+            }
+            None => self.copy_frame(&header.encode(), parts),
+        }
+        Ok(())
     }
 
-    pub fn sync(&self) -> io::Result<()> {
-        let result = unsafe {
-            libc::msync(
-                self.mmap_ptr as *mut libc::c_void,
-                self.mmap_len,
-                libc::MS_SYNC,
-            )
+    /// Checks that `needed` more bytes fit in the file.
+    #[inline]
+    fn reserve(&self, needed: usize) -> Result<(), StorageError> {
+        let available = self.available();
+        if needed <= available {
+            Ok(())
+        } else if self.is_sealed() {
+            Err(StorageError::Sealed)
+        } else {
+            Err(StorageError::Full { needed, available })
+        }
+    }
+
+    #[inline]
+    pub fn is_compact(&self) -> bool {
+        self.compact
+    }
+
+    /// Appends in the compact format, preceded by a time-base record when the
+    /// timestamp is not within a u32 delta after the previous event's.
+    fn append_compact(
+        &mut self,
+        header: &EventHeader,
+        parts: &[&[u8]],
+    ) -> Result<(), StorageError> {
+        if header.event_type == CompactHeader::TYPE_TIME_BASE {
+            return Err(StorageError::InvalidHeader {
+                reason: "event type is reserved by the compact format",
+            });
+        }
+        let delta = header
+            .timestamp
+            .checked_sub(self.last_timestamp)
+            .and_then(|delta| u32::try_from(delta).ok());
+        let flags = header.flags & !EventHeader::FLAG_CHECKSUM;
+        let Some(compact) = CompactHeader::new(delta.unwrap_or(0), header.event_type, flags, parts)
+        else {
+            return Err(StorageError::InvalidHeader {
+                reason: "payload is too long for the compact format",
+            });
         };
 
-        if result == 0 {
-            Ok(())
+        let base_size = if delta.is_none() {
+            CompactHeader::SIZE + 8
         } else {
-            Err(io::Error::last_os_error())
+            0
+        };
+        self.reserve(base_size + compact.total_size())?;
+
+        if delta.is_none() {
+            let (base, payload) = CompactHeader::time_base(header.timestamp);
+            self.copy_frame(&base.encode(), &[&payload]);
         }
+        self.copy_frame(&compact.encode(), parts);
+        self.last_timestamp = header.timestamp;
+        Ok(())
     }
 
+    #[inline]
+    fn copy_frame(&mut self, header: &[u8], parts: &[&[u8]]) {
+        unsafe {
+            let dst = self.mmap_ptr.add(self.write_offset);
+            ptr::copy_nonoverlapping(header.as_ptr(), dst, header.len());
+            let mut pos = header.len();
+            for part in parts {
+                ptr::copy_nonoverlapping(part.as_ptr(), dst.add(pos), part.len());
+                pos += part.len();
+            }
+            self.write_offset += pos;
+        }
+    }
+
+    /// Flushes the events written since the last sync, then publishes and
+    /// flushes the header, so the durable header never covers unflushed data.
+    pub fn sync(&self) -> io::Result<()> {
+        instrument_span!(
+            DEBUG,
+            "ringlog.sync",
+            bytes = self.write_offset.saturating_sub(self.durable_offset.get())
+        );
+        let end = self.write_offset;
+        self.msync_range(self.durable_offset.get(), end, libc::MS_SYNC)?;
+        self.commit();
+        self.msync_range(0, FileHeader::SIZE, libc::MS_SYNC)?;
+        self.durable_offset.set(end);
+        Ok(())
+    }
+
+    /// Schedules write-back without waiting for it. Unlike `sync`, this gives
+    /// no ordering between the header and the data.
     pub fn sync_async(&self) -> io::Result<()> {
+        instrument_span!(TRACE, "ringlog.sync_async");
+        self.commit();
+
         let result = unsafe {
             libc::msync(
                 self.mmap_ptr as *mut libc::c_void,
@@ -167,30 +575,405 @@ impl MmapWriter {
         }
     }
 
+    /// Finalizes the segment: records a checksum of the event region, marks the
+    /// header sealed, shrinks the file to its used size and appends a
+    /// `SegmentFooter` with the events' Merkle root. A sealed writer has no
+    /// space left, so every later `write_event` fails.
+    pub fn seal(&mut self) -> io::Result<()> {
+        self.seal_with(|_| None)
+    }
+
+    /// Like `seal`, also signing the footer with `key`; see
+    /// `MmapReader::verify_signed_seal`. A segment that is already sealed is
+    /// left as it is, signed or not.
+    #[cfg(feature = "signing")]
+    pub fn seal_signed(&mut self, key: &ed25519_dalek::SigningKey) -> io::Result<()> {
+        use ed25519_dalek::Signer;
+        self.seal_with(|footer| {
+            Some(SealSignature {
+                public_key: key.verifying_key().to_bytes(),
+                signature: key.sign(&merkle::seal_message(footer)).to_bytes(),
+            })
+        })
+    }
+
+    fn seal_with<F>(&mut self, sign: F) -> io::Result<()>
+    where
+        F: FnOnce(&SegmentFooter) -> Option<SealSignature>,
+    {
+        instrument_span!(DEBUG, "ringlog.seal");
+        self.commit();
+        if self.is_sealed() {
+            return Ok(());
+        }
+
+        let used = self.write_offset;
+
+        let mmap_ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                used,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                std::os::unix::io::AsRawFd::as_raw_fd(&self.file),
+                0,
+            )
+        };
+        if mmap_ptr == libc::MAP_FAILED {
+            let err = io::Error::last_os_error();
+            return Err(io::Error::new(
+                err.kind(),
+                format!("mmap failed for sealed size {}: {}", used, err),
+            ));
+        }
+
+        let checksum = crc32(&self.mapped_bytes()[FileHeader::SIZE..used]);
+        let mut header = self.file_header();
+        header.flags |= FileHeader::FLAG_SEALED;
+        header.checksum = checksum;
+        self.write_file_header(&header);
+        let synced = self.sync();
+
+        unsafe {
+            libc::munmap(self.mmap_ptr as *mut libc::c_void, self.mmap_len);
+        }
+        self.mmap_ptr = mmap_ptr as *mut u8;
+        self.mmap_len = used;
+        synced?;
+
+        self.file.set_len(used as u64).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Failed to truncate sealed file to {} bytes: {}", used, e),
+            )
+        })?;
+
+        let leaves = merkle::leaves(self.mapped_bytes(), &header, used);
+        let mut footer = SegmentFooter {
+            event_count: header.event_count,
+            data_end: used as u64,
+            hash: SegmentFooter::hash(&self.mapped_bytes()[FileHeader::SIZE..used]),
+            merkle_root: Some(merkle::merkle_root(&leaves)),
+            signature: None,
+            bloom: self.bloom_filter(&header, used),
+        };
+        footer.signature = sign(&footer);
+        self.file
+            .write_all_at(&footer.encode(), used as u64)
+            .map_err(|e| {
+                io::Error::new(e.kind(), format!("Failed to write segment footer: {}", e))
+            })?;
+
+        self.file.sync_all()
+    }
+
+    /// A filter over the trace ids and `bloom_key` keys of the events up to
+    /// `end`, or `None` if there are none.
+    fn bloom_filter(&self, header: &FileHeader, end: usize) -> Option<BloomFilter> {
+        let data = self.mapped_bytes();
+        let mut keys = Vec::new();
+        for frame in Frames::new(data, header, header.data_start(), end).map_while(Result::ok) {
+            let event = EventView {
+                header: frame.header,
+                payload: &data[frame.payload],
+            };
+            if let Some(trace) = event.header.split_trace(event.payload).0 {
+                keys.push(trace.trace_id);
+            }
+            if let Some(key) = self.bloom_key.and_then(|bloom_key| bloom_key(&event)) {
+                keys.push(key);
+            }
+        }
+        if keys.is_empty() {
+            return None;
+        }
+        let mut bloom = BloomFilter::with_capacity(keys.len());
+        for key in keys {
+            bloom.insert(key);
+        }
+        Some(bloom)
+    }
+
+    /// Copies the header and every committed event into `dest_path`. The copy is
+    /// written to a temporary sibling, fsynced and renamed into place, so a
+    /// reader never observes a partially written snapshot.
+    pub fn snapshot<P: AsRef<Path>>(&self, dest_path: P) -> io::Result<u64> {
+        let dest_path = dest_path.as_ref();
+        let mut tmp_name = dest_path.as_os_str().to_owned();
+        tmp_name.push(".tmp");
+        let tmp_path = Path::new(&tmp_name);
+
+        self.commit();
+        let used = self.write_offset;
+        let result = (|| {
+            let mut file = OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(tmp_path)?;
+            file.write_all(&self.mapped_bytes()[..used])?;
+            file.sync_all()?;
+            fs::rename(tmp_path, dest_path)
+        })();
+
+        if let Err(e) = result {
+            let _ = fs::remove_file(tmp_path);
+            return Err(io::Error::new(
+                e.kind(),
+                format!("Snapshot to {} failed: {}", dest_path.display(), e),
+            ));
+        }
+
+        Ok(used as u64)
+    }
+
+    #[inline]
+    pub fn is_sealed(&self) -> bool {
+        self.file_header().is_sealed()
+    }
+
     #[inline]
     pub fn write_offset(&self) -> usize {
         self.write_offset
     }
 
     pub fn file_header(&self) -> FileHeader {
-        unsafe { ptr::read_unaligned(self.mmap_ptr as *const FileHeader) }
+        unsafe { FileHeader::load(self.mmap_ptr) }
+    }
+
+    pub(crate) fn mapped_bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.mmap_ptr, self.mmap_len) }
+    }
+
+    pub(crate) fn set_committed(&mut self, write_offset: usize, event_count: u64) {
+        self.write_offset = write_offset;
+        self.pending_events.set(0);
+        unsafe {
+            FileHeader::publish(self.mmap_ptr, write_offset as u64, event_count);
+        }
+    }
+
+    pub fn advise(&self, advice: Advice) -> io::Result<()> {
+        madvise(self.mmap_ptr, self.mmap_len, advice)
+    }
+
+    pub fn advise_sequential(&self) -> io::Result<()> {
+        self.advise(Advice::Sequential)
+    }
+
+    pub fn advise_willneed(&self) -> io::Result<()> {
+        self.advise(Advice::WillNeed)
+    }
+
+    /// Pins the whole mapping in RAM. Locks are released on `unlock_memory`,
+    /// `seal` (which remaps the file) or drop.
+    pub fn lock_memory(&self) -> io::Result<()> {
+        sys::mlock(self.mmap_ptr, self.mmap_len)
+    }
+
+    pub fn unlock_memory(&self) -> io::Result<()> {
+        sys::munlock(self.mmap_ptr, self.mmap_len)
+    }
+
+    fn touch_pages(&mut self) {
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        let mut offset = 0;
+        while offset < self.mmap_len {
+            unsafe {
+                let p = self.mmap_ptr.add(offset);
+                ptr::write_volatile(p, ptr::read_volatile(p));
+            }
+            offset += page_size;
+        }
+    }
+
+    fn msync_range(&self, start: usize, end: usize, flags: libc::c_int) -> io::Result<()> {
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        let start = start - start % page_size;
+        let end = end.min(self.mmap_len);
+        if start >= end {
+            return Ok(());
+        }
+
+        let result = unsafe {
+            libc::msync(
+                self.mmap_ptr.add(start) as *mut libc::c_void,
+                end - start,
+                flags,
+            )
+        };
+
+        if result == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    /// Backdates the file to `created_at`, for rewrites of an older file.
+    pub(crate) fn set_created_at(&mut self, created_at: i64) {
+        let mut header = self.file_header();
+        header.created_at = created_at;
+        self.write_file_header(&header);
     }
 
     #[inline]
     fn write_file_header(&mut self, header: &FileHeader) {
         unsafe {
-            ptr::write_unaligned(self.mmap_ptr as *mut FileHeader, *header);
+            ptr::copy_nonoverlapping(header.encode().as_ptr(), self.mmap_ptr, FileHeader::SIZE);
         }
     }
 
     #[inline]
-    fn update_file_header(&mut self) {
-        unsafe {
-            let header = &mut *(self.mmap_ptr as *mut FileHeader);
-            header.event_count += 1;
-            header.write_offset = self.write_offset as u64;
+    fn record_write(&mut self) {
+        self.pending_events.set(self.pending_events.get() + 1);
+        if !self.defer_header {
+            self.commit();
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn open_tmpfile(path: &Path, mode: u32) -> io::Result<File> {
+    let dir = parent_dir(path);
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_TMPFILE)
+        .mode(mode)
+        .open(dir)
+        .map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("O_TMPFILE open in {} failed: {}", dir.display(), e),
+            )
+        })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open_tmpfile(_path: &Path, _mode: u32) -> io::Result<File> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "O_TMPFILE is only available on Linux",
+    ))
+}
+
+/// Gives an `O_TMPFILE` file its name. `linkat` refuses to replace an existing
+/// path, so the file is linked under a temporary name and renamed over it.
+#[cfg(target_os = "linux")]
+fn link_tmpfile(file: &File, path: &Path) -> io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut tmp_name = path.as_os_str().to_owned();
+    tmp_name.push(format!(".{}.tmp", std::process::id()));
+    let tmp_path = std::path::PathBuf::from(tmp_name);
+
+    let proc_path = std::ffi::CString::new(format!("/proc/self/fd/{}", file.as_raw_fd()))
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let c_tmp = std::ffi::CString::new(tmp_path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+    let result = unsafe {
+        libc::linkat(
+            libc::AT_FDCWD,
+            proc_path.as_ptr(),
+            libc::AT_FDCWD,
+            c_tmp.as_ptr(),
+            libc::AT_SYMLINK_FOLLOW,
+        )
+    };
+    if result != 0 {
+        let err = io::Error::last_os_error();
+        return Err(io::Error::new(
+            err.kind(),
+            format!("Failed to link tmpfile to {}: {}", path.display(), err),
+        ));
+    }
+
+    fs::rename(&tmp_path, path).inspect_err(|_| {
+        let _ = fs::remove_file(&tmp_path);
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn link_tmpfile(_file: &File, _path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+fn check_type(header: &EventHeader) -> Result<(), StorageError> {
+    if EventHeader::is_reserved_type(header.event_type) {
+        return Err(StorageError::InvalidHeader {
+            reason: "event type is reserved",
+        });
+    }
+    Ok(())
+}
+
+fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    }
+}
+
+fn sync_parent_dir(path: &Path) -> io::Result<()> {
+    File::open(parent_dir(path))?.sync_all()
+}
+
+/// How long `WriterOptions::force` waits for another writer's lock.
+const FORCE_LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Takes the writer lock, waiting up to `FORCE_LOCK_TIMEOUT` for it when
+/// `force` is set. The lock is never skipped: callers truncate or remap the
+/// file next, which would fault a writer that still has it mapped.
+fn lock_file(file: &File, path: &Path, force: bool) -> io::Result<()> {
+    let deadline = Instant::now() + FORCE_LOCK_TIMEOUT;
+    let err = loop {
+        let result = unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) };
+        if result == 0 {
+            return Ok(());
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::WouldBlock || !force || Instant::now() >= deadline {
+            break err;
         }
+        std::thread::sleep(Duration::from_millis(10));
+    };
+
+    if err.kind() == io::ErrorKind::WouldBlock {
+        return Err(io::Error::new(
+            io::ErrorKind::WouldBlock,
+            format!("{} is locked by another writer", path.display()),
+        ));
+    }
+    Err(io::Error::new(
+        err.kind(),
+        format!("Failed to lock {}: {}", path.display(), err),
+    ))
+}
+
+fn preallocate(file: &File, len: usize) -> io::Result<()> {
+    let fd = std::os::unix::io::AsRawFd::as_raw_fd(file);
+    let result = unsafe { libc::posix_fallocate(fd, 0, len as libc::off_t) };
+
+    match result {
+        0 => Ok(()),
+        libc::EOPNOTSUPP | libc::EINVAL => zero_fill(file, len),
+        errno => Err(io::Error::from_raw_os_error(errno)),
+    }
+}
+
+fn zero_fill(file: &File, len: usize) -> io::Result<()> {
+    use std::os::unix::fs::FileExt;
+
+    let zeros = [0u8; 64 * 1024];
+    let mut offset = 0;
+    while offset < len {
+        let chunk = zeros.len().min(len - offset);
+        file.write_all_at(&zeros[..chunk], offset as u64)?;
+        offset += chunk;
     }
+    file.sync_data()
 }
 
 impl Drop for MmapWriter {
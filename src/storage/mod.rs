@@ -1,7 +1,60 @@
+pub mod audit;
+pub mod bloom;
+pub mod chain;
+pub mod compaction;
+pub mod cursor;
+pub mod export;
+pub mod footer;
+mod frame;
 pub mod header;
+pub mod index;
+pub mod manifest;
+pub mod merge;
+pub mod merkle;
+pub mod metadata;
 pub mod mmap_reader;
 pub mod mmap_writer;
+pub mod options;
+#[cfg(feature = "postgres")]
+pub mod pg_copy;
+pub mod repair;
+pub mod segment;
+pub mod segmented_reader;
+pub mod state;
+pub mod stats;
+pub mod storage_error;
+pub mod tail;
+#[cfg(feature = "tiering")]
+pub mod tier;
+pub mod verify;
+pub mod watcher;
 
+pub use audit::{ReplayIssue, ReplayReport};
+pub use bloom::BloomFilter;
+pub use compaction::{CompactionReport, compact_deleted};
+pub use cursor::{Cursor, Resumed};
+pub use export::{CsvColumn, CsvWriter};
+pub use footer::{SealSignature, SegmentFooter};
 pub use header::FileHeader;
-pub use mmap_reader::{EventIterator, MmapReader};
+pub use index::{IndexBuilder, index_path};
+pub use manifest::{Manifest, ManifestEntry};
+pub use merge::MergedIterator;
+pub use metadata::Metadata;
+pub use mmap_reader::{
+    EventIterator, FilteredIterator, MmapReader, RevEventIterator, TryEventIterator,
+};
 pub use mmap_writer::MmapWriter;
+pub use options::{Advice, BloomKey, Prefault, ReaderOptions, WriterOptions};
+#[cfg(feature = "postgres")]
+pub use pg_copy::{PgCopyWriter, PgExporter};
+pub use repair::{RepairReport, repair};
+pub use segment::{SegmentInfo, list_segments, segment_path};
+pub use segmented_reader::{SegmentedIterator, SegmentedReader};
+pub use state::{ReplayTarget, Replayed, StateCodec, StateReplayer};
+pub use stats::FileStats;
+pub use storage_error::StorageError;
+pub use tail::TailReader;
+#[cfg(feature = "tiering")]
+pub use tier::{TierPolicy, TierReport, apply_tiering, list_tiered_segments, open_tiered_segment};
+pub use verify::{VerifyIssue, VerifyReport};
+pub use watcher::{WatchEvent, Watcher};
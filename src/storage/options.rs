@@ -0,0 +1,125 @@
+use super::Metadata;
+use crate::event::EventView;
+use std::io;
+
+/// Extracts the key of an event for a segment's bloom filter.
+pub type BloomKey = fn(&EventView<'_>) -> Option<u64>;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Prefault {
+    #[default]
+    None,
+    /// Maps with `MAP_POPULATE` so page tables are filled at create time.
+    Populate,
+    /// Touches every page with a write, so neither the page fault nor the
+    /// first-write fault happens on the append path.
+    Touch,
+}
+
+#[derive(Debug, Clone)]
+pub struct WriterOptions {
+    pub prefault: Prefault,
+    /// Allocates real disk blocks at create time (`posix_fallocate`, falling
+    /// back to writing zeros) so running out of space fails `create` instead
+    /// of raising SIGBUS on a later append.
+    pub preallocate: bool,
+    /// Permission bits applied to the file, regardless of umask.
+    pub mode: u32,
+    /// `(uid, gid)` to `fchown` the file to.
+    pub owner: Option<(u32, u32)>,
+    /// Builds the file as an unnamed `O_TMPFILE` and links it into place only
+    /// once its header is written, so the path never names a half-initialized
+    /// log. Linux only.
+    pub tmpfile: bool,
+    /// Fsyncs the parent directory after creating the file so the directory
+    /// entry itself survives a crash.
+    pub sync_dir: bool,
+    /// Takes an exclusive `flock` on the file so a second writer, in this or
+    /// another process, fails instead of corrupting `write_offset`.
+    pub lock: bool,
+    /// Waits up to 5 seconds for another writer to release the lock instead
+    /// of failing at once, e.g. while the previous process is still exiting.
+    /// The lock is never skipped: it is released when its holder dies, so a
+    /// held lock means a live writer that still has the file mapped.
+    pub force: bool,
+    /// Written once after the header; read back with `MmapReader::metadata`.
+    pub metadata: Option<Metadata>,
+    /// Frames events with the 8-byte `CompactHeader`. Payloads are limited to
+    /// 255 bytes and per-event CRC-32s are replaced by the compact CRC byte.
+    pub compact: bool,
+    /// Chains every event to the one before it by a hash stored in its
+    /// header, replacing per-event CRC-32s; check it with
+    /// `MmapReader::verify_chain`. Not available with `compact`.
+    pub hash_chain: bool,
+    /// Adds the key it extracts from each event to the bloom filter built
+    /// when the segment is sealed, which always covers trace ids; see
+    /// `MmapReader::may_contain`. Payloads are passed as stored, so use
+    /// `EventHeader::body` to skip sequence and trace prefixes.
+    pub bloom_key: Option<BloomKey>,
+}
+
+impl Default for WriterOptions {
+    fn default() -> Self {
+        Self {
+            prefault: Prefault::None,
+            preallocate: false,
+            mode: 0o600,
+            owner: None,
+            tmpfile: false,
+            sync_dir: false,
+            lock: true,
+            force: false,
+            metadata: None,
+            compact: false,
+            hash_chain: false,
+            bloom_key: None,
+        }
+    }
+}
+
+/// Opt-in checks run by `MmapReader::replay_checked` and
+/// `SegmentedReader::replay_checked`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReaderOptions {
+    /// Report events whose timestamp is older than the one before.
+    pub check_monotonic_timestamps: bool,
+    /// Report sequence numbers (see `EventBuilder::sequence`) that skip or
+    /// repeat values, tracked separately for each stream.
+    pub check_sequence_gaps: bool,
+    /// Skip events whose stream and sequence number were already replayed,
+    /// e.g. where segments overlap after a replication failover. Skipped
+    /// events are counted, not reported as regressions. Events without a
+    /// sequence number are always replayed.
+    pub dedup_sequences: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Advice {
+    Normal,
+    Sequential,
+    Random,
+    WillNeed,
+    DontNeed,
+}
+
+impl Advice {
+    fn as_raw(self) -> libc::c_int {
+        match self {
+            Self::Normal => libc::MADV_NORMAL,
+            Self::Sequential => libc::MADV_SEQUENTIAL,
+            Self::Random => libc::MADV_RANDOM,
+            Self::WillNeed => libc::MADV_WILLNEED,
+            Self::DontNeed => libc::MADV_DONTNEED,
+        }
+    }
+}
+
+pub(crate) fn madvise(ptr: *const u8, len: usize, advice: Advice) -> io::Result<()> {
+    let result = unsafe { libc::madvise(ptr as *mut libc::c_void, len, advice.as_raw()) };
+
+    if result != 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,193 @@
+//! Bulk loading of log files into PostgreSQL with `COPY ... FROM STDIN
+//! (FORMAT binary)`, which skips SQL parsing and text conversion per row.
+//! `PgCopyWriter` encodes events in the binary COPY format to any writer;
+//! `PgExporter` streams them over a connection.
+
+use super::{MmapReader, SegmentedReader};
+use crate::event::EventView;
+use postgres::Client;
+use std::io::{self, Write};
+
+/// Columns written per event, in order.
+pub const COLUMNS: &str = "timestamp, time, type, stream, flags, seq, trace_id, span_id, payload";
+
+const SIGNATURE: &[u8; 11] = b"PGCOPY\n\xff\r\n\0";
+const FIELDS: i16 = 9;
+/// Microseconds from the Unix epoch to PostgreSQL's, 2000-01-01.
+const PG_EPOCH_MICROS: i64 = 946_684_800_000_000;
+
+/// Encodes events as rows of the binary COPY format:
+///
+/// | column      | type          | value                                  |
+/// |-------------|---------------|----------------------------------------|
+/// | `timestamp` | `bigint`      | Unix nanoseconds                       |
+/// | `time`      | `timestamptz` | the same, to the microsecond           |
+/// | `type`      | `smallint`    | event type                             |
+/// | `stream`    | `smallint`    | stream id                              |
+/// | `flags`     | `smallint`    | header flags                           |
+/// | `seq`       | `bigint`      | sequence number, or NULL               |
+/// | `trace_id`  | `bigint`      | trace id, or NULL                      |
+/// | `span_id`   | `bigint`      | span id, or NULL                       |
+/// | `payload`   | `bytea`       | payload without sequence or trace      |
+///
+/// Unsigned values past `i64::MAX` wrap negative.
+pub struct PgCopyWriter<W: Write> {
+    out: W,
+    rows: u64,
+    row: Vec<u8>,
+}
+
+impl<W: Write> PgCopyWriter<W> {
+    /// Writes the COPY header.
+    pub fn new(mut out: W) -> io::Result<Self> {
+        out.write_all(SIGNATURE)?;
+        // Flags, then the length of the header extension area.
+        out.write_all(&0i32.to_be_bytes())?;
+        out.write_all(&0i32.to_be_bytes())?;
+        Ok(Self {
+            out,
+            rows: 0,
+            row: Vec::new(),
+        })
+    }
+
+    pub fn write_event(&mut self, event: &EventView<'_>) -> io::Result<()> {
+        let header = &event.header;
+        let seq = header.split_sequence(event.payload).0;
+        let (trace, body) = header.split_trace(event.payload);
+
+        let row = &mut self.row;
+        row.clear();
+        row.extend_from_slice(&FIELDS.to_be_bytes());
+        bigint(row, Some(header.timestamp));
+        let micros = (header.timestamp / 1000) as i64 - PG_EPOCH_MICROS;
+        bigint(row, Some(micros as u64));
+        for smallint in [
+            header.event_type as i16,
+            header.stream().get() as i16,
+            header.flags as i16,
+        ] {
+            row.extend_from_slice(&2i32.to_be_bytes());
+            row.extend_from_slice(&smallint.to_be_bytes());
+        }
+        bigint(row, seq);
+        bigint(row, trace.map(|t| t.trace_id));
+        bigint(row, trace.map(|t| t.span_id));
+        row.extend_from_slice(&(body.len() as i32).to_be_bytes());
+        row.extend_from_slice(body);
+
+        self.out.write_all(row)?;
+        self.rows += 1;
+        Ok(())
+    }
+
+    /// Writes the trailer and returns the writer and the number of rows.
+    pub fn finish(mut self) -> io::Result<(W, u64)> {
+        self.out.write_all(&(-1i16).to_be_bytes())?;
+        self.out.flush()?;
+        Ok((self.out, self.rows))
+    }
+}
+
+/// Appends a `bigint` field, or NULL for `None`.
+fn bigint(row: &mut Vec<u8>, value: Option<u64>) {
+    match value {
+        Some(value) => {
+            row.extend_from_slice(&8i32.to_be_bytes());
+            row.extend_from_slice(&(value as i64).to_be_bytes());
+        }
+        None => row.extend_from_slice(&(-1i32).to_be_bytes()),
+    }
+}
+
+/// Streams events into a table over a PostgreSQL connection.
+pub struct PgExporter {
+    table: String,
+}
+
+impl PgExporter {
+    /// Exports into `table`, optionally schema-qualified. Panics unless
+    /// `table` is made of ASCII letters, digits, `_` and at most one `.`,
+    /// as it is interpolated into SQL.
+    pub fn new(table: &str) -> Self {
+        let valid_part = |part: &str| {
+            !part.is_empty()
+                && !part.starts_with(|c: char| c.is_ascii_digit())
+                && part.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')
+        };
+        assert!(
+            table.split('.').count() <= 2 && table.split('.').all(valid_part),
+            "invalid table name {:?}",
+            table
+        );
+        Self {
+            table: table.to_string(),
+        }
+    }
+
+    /// `CREATE TABLE IF NOT EXISTS` for the table, with the columns
+    /// `PgCopyWriter` writes.
+    pub fn create_table_sql(&self) -> String {
+        format!(
+            "CREATE TABLE IF NOT EXISTS {} (
+                timestamp bigint NOT NULL,
+                time      timestamptz NOT NULL,
+                type      smallint NOT NULL,
+                stream    smallint NOT NULL,
+                flags     smallint NOT NULL,
+                seq       bigint,
+                trace_id  bigint,
+                span_id   bigint,
+                payload   bytea NOT NULL
+            )",
+            self.table
+        )
+    }
+
+    /// Creates the table if it does not exist.
+    pub fn create_table(&self, client: &mut Client) -> io::Result<()> {
+        client
+            .batch_execute(&self.create_table_sql())
+            .map_err(|e| self.error("create", e))
+    }
+
+    /// Copies `events` into the table in one COPY, which commits all rows
+    /// or none. Returns the number of rows.
+    pub fn export<'a, I>(&self, client: &mut Client, events: I) -> io::Result<u64>
+    where
+        I: IntoIterator<Item = EventView<'a>>,
+    {
+        let sql = format!(
+            "COPY {} ({}) FROM STDIN (FORMAT binary)",
+            self.table, COLUMNS
+        );
+        let copy = client
+            .copy_in(&sql)
+            .map_err(|e| self.error("copy into", e))?;
+        let mut writer = PgCopyWriter::new(copy)?;
+        for event in events {
+            writer.write_event(&event)?;
+        }
+        let (copy, _) = writer.finish()?;
+        copy.finish().map_err(|e| self.error("copy into", e))
+    }
+
+    fn error(&self, action: &str, e: postgres::Error) -> io::Error {
+        io::Error::other(format!("Failed to {} {}: {}", action, self.table, e))
+    }
+}
+
+impl MmapReader {
+    /// Bulk-loads the file's events into `table`; see `PgExporter`.
+    pub fn export_postgres(&self, client: &mut Client, table: &str) -> io::Result<u64> {
+        PgExporter::new(table).export(client, self)
+    }
+}
+
+impl SegmentedReader {
+    /// Bulk-loads the events of every segment into `table` in one COPY; see
+    /// `PgExporter`.
+    pub fn export_postgres(&self, client: &mut Client, table: &str) -> io::Result<u64> {
+        PgExporter::new(table).export(client, self)
+    }
+}
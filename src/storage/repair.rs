@@ -0,0 +1,88 @@
+use super::frame::Frames;
+use super::{FileHeader, MmapWriter, WriterOptions};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepairReport {
+    pub previous_event_count: u64,
+    pub previous_write_offset: u64,
+    pub event_count: u64,
+    pub write_offset: u64,
+    pub backup_path: Option<PathBuf>,
+}
+
+impl RepairReport {
+    #[inline]
+    pub fn changed(&self) -> bool {
+        self.previous_event_count != self.event_count
+            || self.previous_write_offset != self.write_offset
+    }
+}
+
+/// Scans `data` (a whole file mapping, header included) from `start` for the
+/// longest run of well-framed events. Returns `(end_offset, event_count)` of
+/// that run.
+///
+/// Scanning stops at the first truncated event, checksum mismatch, or
+/// all-zero header (the unwritten, zero-filled tail of a preallocated file).
+pub(crate) fn scan_valid_events(data: &[u8], header: &FileHeader, start: usize) -> (usize, u64) {
+    let mut frames = Frames::new(data, header, start, data.len());
+    let mut end = start;
+    let mut count = 0;
+
+    while let Some(Ok(frame)) = frames.next() {
+        let blank = match frame.compact {
+            Some(_) => data[frame.offset..frame.payload.start]
+                .iter()
+                .all(|&b| b == 0),
+            None => {
+                let h = &frame.header;
+                h.timestamp == 0 && h.event_type == 0 && h.payload_len == 0
+            }
+        };
+        if blank || !frame.checksum_ok(data) {
+            break;
+        }
+        end = frame.end;
+        count += 1;
+    }
+
+    (end, count)
+}
+
+pub fn repair<P: AsRef<Path>>(path: P, backup: bool) -> io::Result<RepairReport> {
+    let path = path.as_ref();
+
+    let backup_path = if backup {
+        let mut name = path.as_os_str().to_owned();
+        name.push(".bak");
+        let backup_path = PathBuf::from(name);
+        fs::copy(path, &backup_path).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Failed to back up to {}: {}", backup_path.display(), e),
+            )
+        })?;
+        Some(backup_path)
+    } else {
+        None
+    };
+
+    let mut writer = MmapWriter::open_unchecked(path, &WriterOptions::default())?;
+    let previous = writer.file_header();
+    let (write_offset, event_count) =
+        scan_valid_events(writer.mapped_bytes(), &previous, previous.data_start());
+
+    writer.set_committed(write_offset, event_count);
+    writer.sync()?;
+
+    Ok(RepairReport {
+        previous_event_count: previous.event_count,
+        previous_write_offset: previous.write_offset,
+        event_count,
+        write_offset: write_offset as u64,
+        backup_path,
+    })
+}
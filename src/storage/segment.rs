@@ -0,0 +1,43 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub const SEGMENT_EXTENSION: &str = "log";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SegmentInfo {
+    pub seq: u64,
+    pub path: PathBuf,
+}
+
+pub fn segment_file_name(seq: u64) -> String {
+    format!("{:020}.{}", seq, SEGMENT_EXTENSION)
+}
+
+pub fn segment_path<P: AsRef<Path>>(dir: P, seq: u64) -> PathBuf {
+    dir.as_ref().join(segment_file_name(seq))
+}
+
+/// Lists `<seq>.log` files in `dir`, ordered by sequence number. Files that do
+/// not follow the segment naming scheme are ignored.
+pub fn list_segments<P: AsRef<Path>>(dir: P) -> io::Result<Vec<SegmentInfo>> {
+    let mut segments = Vec::new();
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some(SEGMENT_EXTENSION) {
+            continue;
+        }
+        let Some(seq) = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .and_then(|s| s.parse::<u64>().ok())
+        else {
+            continue;
+        };
+        segments.push(SegmentInfo { seq, path });
+    }
+
+    segments.sort_by_key(|s| s.seq);
+    Ok(segments)
+}
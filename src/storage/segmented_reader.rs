@@ -0,0 +1,152 @@
+use super::mmap_reader::EventIterator;
+use super::segment::SegmentInfo;
+#[cfg(not(feature = "tiering"))]
+use super::segment::list_segments;
+#[cfg(feature = "tiering")]
+use super::tier::{list_tiered_segments as list_segments, open_tiered_segment};
+use super::{Cursor, MmapReader};
+use crate::event::{EventView, Tags};
+use std::io;
+use std::path::Path;
+
+/// Reads every segment in a log directory as one stream, in segment sequence
+/// order. With the `tiering` feature, compressed segments and segments moved
+/// to a cold directory are included too.
+pub struct SegmentedReader {
+    pub(super) segments: Vec<(SegmentInfo, MmapReader)>,
+}
+
+impl SegmentedReader {
+    pub fn open<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        let mut segments = Vec::new();
+
+        for info in list_segments(dir)? {
+            #[cfg(not(feature = "tiering"))]
+            let reader = MmapReader::open(&info.path);
+            #[cfg(feature = "tiering")]
+            let reader = open_tiered_segment(&info.path);
+            let reader = reader.map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("Failed to open segment {}: {}", info.path.display(), e),
+                )
+            })?;
+            segments.push((info, reader));
+        }
+
+        Ok(Self { segments })
+    }
+
+    pub fn segments(&self) -> impl Iterator<Item = &SegmentInfo> {
+        self.segments.iter().map(|(info, _)| info)
+    }
+
+    pub fn readers(&self) -> impl Iterator<Item = &MmapReader> {
+        self.segments.iter().map(|(_, reader)| reader)
+    }
+
+    #[inline]
+    pub fn segment_count(&self) -> usize {
+        self.segments.len()
+    }
+
+    pub fn event_count(&self) -> u64 {
+        self.readers().map(MmapReader::event_count).sum()
+    }
+
+    pub fn replay<F>(&self, mut callback: F) -> u64
+    where
+        F: FnMut(EventView),
+    {
+        self.readers()
+            .map(|reader| reader.replay(&mut callback))
+            .sum()
+    }
+
+    /// Events carrying every tag of `tags`, in segment order.
+    pub fn iter_tagged(&self, tags: Tags) -> impl Iterator<Item = EventView<'_>> {
+        self.iter().filter(move |e| Tags::of(e).contains(tags))
+    }
+
+    /// Segments that may hold events with `key`, skipping those whose bloom
+    /// filter rules it out.
+    pub fn readers_containing(&self, key: u64) -> impl Iterator<Item = &MmapReader> {
+        self.readers().filter(move |reader| reader.may_contain(key))
+    }
+
+    /// Events carrying `trace_id`, in segment order. Only segments whose
+    /// bloom filter may hold the trace id are scanned.
+    pub fn find_by_trace(&self, trace_id: u64) -> impl Iterator<Item = EventView<'_>> {
+        self.readers_containing(trace_id)
+            .flat_map(move |reader| reader.find_by_trace(trace_id))
+    }
+
+    /// The latest marker named `name` in any segment.
+    pub fn find_marker(&self, name: &str) -> Option<EventView<'_>> {
+        self.segments
+            .iter()
+            .rev()
+            .find_map(|(_, reader)| reader.find_marker(name))
+    }
+
+    /// Replays the events after the latest marker named `name`, across
+    /// segments. Returns how many there were, or `None` if there is no such
+    /// marker.
+    pub fn replay_since_marker<F>(&self, name: &str, mut callback: F) -> Option<u64>
+    where
+        F: FnMut(EventView),
+    {
+        let cursor = self.segments.iter().rev().find_map(|(info, reader)| {
+            let (_, offset, previous) = reader.marker_position(name)?;
+            Some(Cursor {
+                segment: info.seq,
+                offset: offset as u64,
+                previous,
+                sequence: None,
+            })
+        })?;
+        let mut count = 0;
+        for event in self.resume(cursor) {
+            callback(event);
+            count += 1;
+        }
+        Some(count)
+    }
+
+    pub fn iter(&self) -> SegmentedIterator<'_> {
+        SegmentedIterator {
+            segments: &self.segments,
+            current: None,
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a SegmentedReader {
+    type Item = EventView<'a>;
+    type IntoIter = SegmentedIterator<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+pub struct SegmentedIterator<'a> {
+    segments: &'a [(SegmentInfo, MmapReader)],
+    current: Option<EventIterator<'a>>,
+}
+
+impl<'a> Iterator for SegmentedIterator<'a> {
+    type Item = EventView<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(event) = self.current.as_mut().and_then(Iterator::next) {
+                return Some(event);
+            }
+
+            let ((_, reader), rest) = self.segments.split_first()?;
+            self.segments = rest;
+            self.current = Some(reader.iter());
+        }
+    }
+}
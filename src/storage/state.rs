@@ -0,0 +1,347 @@
+//! Event sourcing over a log directory. `StateReplayer` folds events through
+//! an `apply` function to rebuild state as of a point in time, optionally
+//! saving snapshots of that state next to the segments so later replays
+//! resume from the latest usable snapshot instead of the first event.
+
+use super::SegmentedReader;
+use crate::event::{EventView, crc32};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+pub const SNAPSHOT_EXTENSION: &str = "snapshot";
+
+const MAGIC: [u8; 4] = *b"RLSN";
+const VERSION: u32 = 1;
+/// Magic, version, segment, index, events, max timestamp, whether a
+/// sequence was seen and the max sequence.
+const HEADER_SIZE: usize = 4 + 4 + 8 + 8 + 8 + 8 + 1 + 8;
+
+/// Converts replayed state to and from snapshot bytes.
+pub trait StateCodec: Sized {
+    fn encode(&self) -> Vec<u8>;
+    /// `None` if `bytes` are not a valid encoding; the snapshot is then
+    /// ignored.
+    fn decode(bytes: &[u8]) -> Option<Self>;
+}
+
+/// How far a replay goes. Events are applied in log order until the first
+/// one past the target, so events that are out of order before it still
+/// count.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayTarget {
+    /// Up to and including events with this timestamp.
+    Timestamp(u64),
+    /// Up to and including the event with this sequence number. Events
+    /// without one do not stop the replay.
+    Sequence(u64),
+    End,
+}
+
+impl ReplayTarget {
+    fn reached_past(self, event: &EventView<'_>) -> bool {
+        match self {
+            Self::Timestamp(timestamp) => event.header.timestamp > timestamp,
+            Self::Sequence(seq) => event
+                .header
+                .split_sequence(event.payload)
+                .0
+                .is_some_and(|found| found > seq),
+            Self::End => false,
+        }
+    }
+
+    /// Whether every event a snapshot covers comes before the target.
+    fn covers(self, snapshot: &SnapshotHeader) -> bool {
+        match self {
+            Self::Timestamp(timestamp) => snapshot.max_timestamp <= timestamp,
+            Self::Sequence(seq) => snapshot.max_sequence.is_none_or(|max| max <= seq),
+            Self::End => true,
+        }
+    }
+}
+
+/// The outcome of `StateReplayer::replay_to`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replayed<T> {
+    pub state: T,
+    /// Events applied by this replay.
+    pub applied: u64,
+    /// Events covered by the snapshot the replay resumed from, if any.
+    pub resumed: Option<u64>,
+    pub snapshots_written: usize,
+}
+
+struct Snapshots<T> {
+    name: String,
+    every: u64,
+    encode: fn(&T) -> Vec<u8>,
+    decode: fn(&[u8]) -> Option<T>,
+}
+
+/// Position and bounds of a snapshot.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct SnapshotHeader {
+    /// Sequence number of the segment holding the next event to apply.
+    segment: u64,
+    /// Events of that segment already applied.
+    index: u64,
+    /// Events applied since the start of the log.
+    events: u64,
+    max_timestamp: u64,
+    max_sequence: Option<u64>,
+}
+
+/// Rebuilds state from a log directory, e.g. the segments written by the
+/// service, for a log used as an event-sourcing journal.
+///
+/// Snapshots are named after the replayer and the position they were taken
+/// at, `<name>.<segment>.<index>.snapshot`, and are written at most once
+/// per position. Deleting segments does not invalidate them; compacting
+/// segments does, as event positions shift, so remove them with
+/// `remove_snapshots` after `compact_deleted`.
+pub struct StateReplayer<T, F> {
+    dir: PathBuf,
+    initial: T,
+    apply: F,
+    snapshots: Option<Snapshots<T>>,
+}
+
+impl<T, F> StateReplayer<T, F>
+where
+    T: Clone,
+    F: FnMut(&mut T, &EventView<'_>),
+{
+    /// Replays start from `initial` and pass every event, heartbeats
+    /// included, to `apply`.
+    pub fn new<P: AsRef<Path>>(dir: P, initial: T, apply: F) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+            initial,
+            apply,
+            snapshots: None,
+        }
+    }
+
+    /// Saves a snapshot named `name` every `every` events. Panics if `every`
+    /// is zero or `name` is empty or holds anything but ASCII letters,
+    /// digits, `-` and `_`.
+    pub fn with_snapshots(mut self, name: &str, every: u64) -> Self
+    where
+        T: StateCodec,
+    {
+        assert!(every > 0, "snapshot interval must be non-zero");
+        assert!(
+            !name.is_empty()
+                && name
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_'),
+            "invalid snapshot name {:?}",
+            name
+        );
+        self.snapshots = Some(Snapshots {
+            name: name.to_string(),
+            every,
+            encode: T::encode,
+            decode: T::decode,
+        });
+        self
+    }
+
+    /// The state after applying every event up to `target`.
+    pub fn replay_to(&mut self, target: ReplayTarget) -> io::Result<Replayed<T>> {
+        let reader = SegmentedReader::open(&self.dir)?;
+
+        let resumed = match &self.snapshots {
+            Some(snapshots) => self.latest_snapshot(snapshots, target)?,
+            None => None,
+        };
+        let resumed_events = resumed.as_ref().map(|(_, header)| header.events);
+        let (mut state, mut position) =
+            resumed.unwrap_or_else(|| (self.initial.clone(), SnapshotHeader::default()));
+        let mut applied = 0;
+        let mut snapshots_written = 0;
+
+        'segments: for (info, segment) in reader.segments().zip(reader.readers()) {
+            if info.seq < position.segment {
+                continue;
+            }
+            let skip = if info.seq == position.segment {
+                position.index
+            } else {
+                position.segment = info.seq;
+                position.index = 0;
+                0
+            };
+            for event in segment.iter().skip(skip as usize) {
+                if target.reached_past(&event) {
+                    break 'segments;
+                }
+                (self.apply)(&mut state, &event);
+                applied += 1;
+                position.index += 1;
+                position.events += 1;
+                position.max_timestamp = position.max_timestamp.max(event.header.timestamp);
+                if let Some(seq) = event.header.split_sequence(event.payload).0 {
+                    position.max_sequence = position.max_sequence.max(Some(seq));
+                }
+
+                if let Some(snapshots) = &self.snapshots
+                    && position.events % snapshots.every == 0
+                    && save_snapshot(&self.dir, snapshots, &position, &state)?
+                {
+                    snapshots_written += 1;
+                }
+            }
+        }
+
+        Ok(Replayed {
+            state,
+            applied,
+            resumed: resumed_events,
+            snapshots_written,
+        })
+    }
+
+    /// The latest snapshot that decodes and lies before `target`.
+    fn latest_snapshot(
+        &self,
+        snapshots: &Snapshots<T>,
+        target: ReplayTarget,
+    ) -> io::Result<Option<(T, SnapshotHeader)>> {
+        let mut found = list_snapshots(&self.dir, &snapshots.name)?;
+        found.sort_by_key(|(segment, index, _)| std::cmp::Reverse((*segment, *index)));
+        for (_, _, path) in found {
+            // Unreadable or corrupt snapshots are skipped, not fatal: the
+            // replay only gets slower.
+            let Ok(bytes) = fs::read(&path) else {
+                continue;
+            };
+            if let Some((header, state)) = decode_snapshot(&bytes)
+                && target.covers(&header)
+                && let Some(state) = (snapshots.decode)(state)
+            {
+                return Ok(Some((state, header)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Deletes the snapshots of this replayer, returning how many there were.
+    pub fn remove_snapshots(&self) -> io::Result<usize> {
+        let Some(snapshots) = &self.snapshots else {
+            return Ok(0);
+        };
+        let found = list_snapshots(&self.dir, &snapshots.name)?;
+        for (_, _, path) in &found {
+            fs::remove_file(path)?;
+        }
+        Ok(found.len())
+    }
+}
+
+fn snapshot_path(dir: &Path, name: &str, segment: u64, index: u64) -> PathBuf {
+    dir.join(format!(
+        "{}.{:020}.{:020}.{}",
+        name, segment, index, SNAPSHOT_EXTENSION
+    ))
+}
+
+/// Segment, index and path of each snapshot named `name` in `dir`.
+fn list_snapshots(dir: &Path, name: &str) -> io::Result<Vec<(u64, u64, PathBuf)>> {
+    let mut found = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let Some(file_name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        let mut parts = file_name.split('.');
+        let (Some(prefix), Some(segment), Some(index), Some(extension), None) = (
+            parts.next(),
+            parts.next(),
+            parts.next(),
+            parts.next(),
+            parts.next(),
+        ) else {
+            continue;
+        };
+        if prefix != name || extension != SNAPSHOT_EXTENSION {
+            continue;
+        }
+        if let (Ok(segment), Ok(index)) = (segment.parse(), index.parse()) {
+            found.push((segment, index, path));
+        }
+    }
+    Ok(found)
+}
+
+/// Writes a snapshot unless one exists at this position already. Returns
+/// whether it wrote one.
+fn save_snapshot<T>(
+    dir: &Path,
+    snapshots: &Snapshots<T>,
+    position: &SnapshotHeader,
+    state: &T,
+) -> io::Result<bool> {
+    let path = snapshot_path(dir, &snapshots.name, position.segment, position.index);
+    if path.exists() {
+        return Ok(false);
+    }
+    let mut tmp_name = path.as_os_str().to_owned();
+    tmp_name.push(".tmp");
+    let tmp_path = Path::new(&tmp_name);
+
+    let mut bytes = Vec::with_capacity(HEADER_SIZE);
+    bytes.extend_from_slice(&MAGIC);
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    bytes.extend_from_slice(&position.segment.to_le_bytes());
+    bytes.extend_from_slice(&position.index.to_le_bytes());
+    bytes.extend_from_slice(&position.events.to_le_bytes());
+    bytes.extend_from_slice(&position.max_timestamp.to_le_bytes());
+    bytes.push(position.max_sequence.is_some() as u8);
+    bytes.extend_from_slice(&position.max_sequence.unwrap_or(0).to_le_bytes());
+    bytes.extend_from_slice(&(snapshots.encode)(state));
+    let crc = crc32(&bytes);
+    bytes.extend_from_slice(&crc.to_le_bytes());
+
+    let result = (|| {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(tmp_path)?;
+        file.write_all(&bytes)?;
+        file.sync_all()?;
+        fs::rename(tmp_path, &path)
+    })();
+
+    if let Err(e) = result {
+        let _ = fs::remove_file(tmp_path);
+        return Err(io::Error::new(
+            e.kind(),
+            format!("Saving snapshot {} failed: {}", path.display(), e),
+        ));
+    }
+    Ok(true)
+}
+
+/// The header and state bytes of a snapshot, if its magic, version and crc
+/// check out.
+fn decode_snapshot(bytes: &[u8]) -> Option<(SnapshotHeader, &[u8])> {
+    let (body, crc) = bytes.split_last_chunk::<4>()?;
+    if body.len() < HEADER_SIZE || body[..4] != MAGIC || crc32(body) != u32::from_le_bytes(*crc) {
+        return None;
+    }
+    let u64_at = |at: usize| u64::from_le_bytes(body[at..at + 8].try_into().unwrap());
+    if u32::from_le_bytes(body[4..8].try_into().unwrap()) != VERSION {
+        return None;
+    }
+    let header = SnapshotHeader {
+        segment: u64_at(8),
+        index: u64_at(16),
+        events: u64_at(24),
+        max_timestamp: u64_at(32),
+        max_sequence: (body[40] != 0).then(|| u64_at(41)),
+    };
+    Some((header, &body[HEADER_SIZE..]))
+}
@@ -0,0 +1,80 @@
+use super::MmapReader;
+use crate::event::{CompactHeader, EventHeader};
+use std::collections::BTreeMap;
+
+/// Bucket `0` counts empty payloads; bucket `i` counts payloads of
+/// `2^(i-1)..2^i` bytes. `payload_len` is a `u16`, so 17 buckets cover it.
+pub const PAYLOAD_HISTOGRAM_BUCKETS: usize = 17;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileStats {
+    pub event_count: u64,
+    pub total_bytes: u64,
+    pub payload_bytes: u64,
+    pub events_by_type: BTreeMap<u8, u64>,
+    pub min_timestamp: Option<u64>,
+    pub max_timestamp: Option<u64>,
+    pub payload_histogram: [u64; PAYLOAD_HISTOGRAM_BUCKETS],
+}
+
+impl FileStats {
+    #[inline]
+    pub fn avg_payload_size(&self) -> f64 {
+        if self.event_count == 0 {
+            0.0
+        } else {
+            self.payload_bytes as f64 / self.event_count as f64
+        }
+    }
+
+    #[inline]
+    pub fn histogram_bucket(payload_len: u16) -> usize {
+        (u16::BITS - payload_len.leading_zeros()) as usize
+    }
+
+    /// Inclusive payload length range counted by histogram bucket `bucket`.
+    pub fn histogram_bucket_range(bucket: usize) -> (usize, usize) {
+        match bucket {
+            0 => (0, 0),
+            _ => (1 << (bucket - 1), (1 << bucket) - 1),
+        }
+    }
+}
+
+impl MmapReader {
+    pub fn stats(&self) -> FileStats {
+        let mut stats = FileStats::default();
+        let mut by_type = [0u64; 256];
+        let mut min_timestamp = u64::MAX;
+        let mut max_timestamp = 0;
+
+        for event in self.iter() {
+            let header = event.header;
+            stats.event_count += 1;
+            stats.payload_bytes += header.payload_len as u64;
+            by_type[header.event_type as usize] += 1;
+            min_timestamp = min_timestamp.min(header.timestamp);
+            max_timestamp = max_timestamp.max(header.timestamp);
+            stats.payload_histogram[FileStats::histogram_bucket(header.payload_len)] += 1;
+        }
+
+        let header_size = if self.file_header().is_compact() {
+            CompactHeader::SIZE
+        } else {
+            EventHeader::SIZE
+        };
+        stats.total_bytes = stats.payload_bytes + stats.event_count * header_size as u64;
+        if stats.event_count > 0 {
+            stats.min_timestamp = Some(min_timestamp);
+            stats.max_timestamp = Some(max_timestamp);
+        }
+        stats.events_by_type = by_type
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(ty, count)| (ty as u8, *count))
+            .collect();
+
+        stats
+    }
+}
@@ -0,0 +1,62 @@
+use std::fmt;
+use std::io;
+
+/// Why the storage layer refused a write.
+#[derive(Debug)]
+pub enum StorageError {
+    /// The event needs more bytes than are left in the file.
+    Full {
+        needed: usize,
+        available: usize,
+    },
+    Io(io::Error),
+    /// The event header cannot be stored as given: its payload length does
+    /// not match the payload, its type is reserved, or it has no compact
+    /// encoding.
+    InvalidHeader {
+        reason: &'static str,
+    },
+    /// The file was sealed and takes no more events.
+    Sealed,
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Full { needed, available } => write!(
+                f,
+                "Not enough space in log file: needed {} bytes, available {} bytes",
+                needed, available
+            ),
+            Self::Io(e) => write!(f, "I/O error: {}", e),
+            Self::InvalidHeader { reason } => write!(f, "Invalid event header: {}", reason),
+            Self::Sealed => write!(f, "Log file is sealed"),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for StorageError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<StorageError> for io::Error {
+    fn from(e: StorageError) -> Self {
+        match e {
+            StorageError::Io(e) => e,
+            StorageError::Full { .. } => io::Error::new(io::ErrorKind::StorageFull, e),
+            StorageError::InvalidHeader { .. } => io::Error::new(io::ErrorKind::InvalidInput, e),
+            StorageError::Sealed => io::Error::new(io::ErrorKind::PermissionDenied, e),
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use super::{MmapReader, Watcher};
+use crate::event::EventView;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Follows a log file that another writer is appending to, handing out each
+/// event once. The file is remapped on every read, so readers see events
+/// as soon as the writer publishes them to the file header.
+pub struct TailReader {
+    path: PathBuf,
+    watcher: Watcher,
+    /// Offset of the next event and the timestamp of the one before it, or
+    /// `None` before the first event.
+    next: Option<(usize, u64)>,
+    position: u64,
+    sealed: bool,
+}
+
+impl TailReader {
+    /// Starts at the first event in the file.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut watcher = Watcher::new();
+        watcher.watch(&path)?;
+        Ok(Self {
+            path,
+            watcher,
+            next: None,
+            position: 0,
+            sealed: false,
+        })
+    }
+
+    /// Starts after the events already in the file.
+    pub fn open_at_end<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut tail = Self::open(path)?;
+        tail.skip(u64::MAX)?;
+        Ok(tail)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Events read or skipped so far.
+    #[inline]
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Whether the file was sealed and every event in it has been read.
+    #[inline]
+    pub fn is_sealed(&self) -> bool {
+        self.sealed
+    }
+
+    /// Steps over up to `count` events without reading them, returning how
+    /// many there were.
+    pub fn skip(&mut self, count: u64) -> io::Result<u64> {
+        self.advance(count, |_| Ok(()))
+    }
+
+    /// Passes every event appended since the last call to `callback`,
+    /// returning how many it accepted. An error from `callback` stops the
+    /// read and is returned; that event is handed out again next time.
+    pub fn read_new<F>(&mut self, callback: F) -> io::Result<u64>
+    where
+        F: FnMut(EventView) -> io::Result<()>,
+    {
+        self.advance(u64::MAX, callback)
+    }
+
+    /// Blocks until the file changes or `timeout` elapses. Returns whether it
+    /// changed; sealing a file without appending is only noticed by the next
+    /// `read_new`.
+    pub fn wait(&mut self, timeout: Duration) -> io::Result<bool> {
+        Ok(!self.watcher.wait(Some(timeout))?.is_empty())
+    }
+
+    fn advance<F>(&mut self, limit: u64, mut callback: F) -> io::Result<u64>
+    where
+        F: FnMut(EventView) -> io::Result<()>,
+    {
+        let reader = MmapReader::open(&self.path)?;
+        let mut frames = reader.frames();
+        if let Some((offset, previous)) = self.next {
+            frames.seek(offset, previous);
+        }
+
+        let mut count = 0;
+        while count < limit {
+            let mut ahead = frames.clone();
+            let Some(frame) = ahead.next() else {
+                self.sealed = reader.is_sealed();
+                break;
+            };
+            let frame = frame?;
+            callback(EventView {
+                header: frame.header,
+                payload: &reader.mapped_bytes()[frame.payload],
+            })?;
+
+            frames = ahead;
+            self.next = Some((frames.offset(), frames.previous()));
+            self.position += 1;
+            count += 1;
+        }
+
+        Ok(count)
+    }
+}
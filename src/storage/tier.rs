@@ -0,0 +1,177 @@
+use super::segment::{SEGMENT_EXTENSION, SegmentInfo, list_segments, segment_file_name};
+use super::{Manifest, MmapReader};
+use crate::sys;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Extension appended to a segment's file name once it is compressed.
+pub const COMPRESSED_EXTENSION: &str = "zst";
+
+/// Which sealed segments `apply_tiering` leaves alone, and what it does with
+/// the rest.
+#[derive(Debug, Clone)]
+pub struct TierPolicy {
+    /// Newest sealed segments kept uncompressed in the log directory.
+    pub hot_segments: usize,
+    /// Compresses older segments with zstd into `<seq>.log.zst`.
+    pub compress: bool,
+    pub compression_level: i32,
+    /// Moves older segments here. The move is recorded in the MANIFEST so
+    /// `SegmentedReader` still finds them.
+    pub cold_dir: Option<PathBuf>,
+}
+
+impl Default for TierPolicy {
+    fn default() -> Self {
+        Self {
+            hot_segments: 4,
+            compress: true,
+            compression_level: 3,
+            cold_dir: None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct TierReport {
+    /// `(seq, new path)` of each segment moved out of the hot tier.
+    pub tiered: Vec<(u64, PathBuf)>,
+    pub skipped_unsealed: usize,
+}
+
+/// Applies `policy` to the log directory `dir`. Each cold segment is written
+/// to its new location, fsynced and renamed into place before the original is
+/// removed, so an interrupted run leaves at most a duplicate, which readers
+/// ignore in favour of the hot copy.
+pub fn apply_tiering<P: AsRef<Path>>(dir: P, policy: &TierPolicy) -> io::Result<TierReport> {
+    let dir = dir.as_ref();
+    let mut report = TierReport::default();
+    if !policy.compress && policy.cold_dir.is_none() {
+        return Ok(report);
+    }
+
+    let mut sealed = Vec::new();
+    for segment in list_segments(dir)? {
+        if MmapReader::open(&segment.path)?.is_sealed() {
+            sealed.push(segment);
+        } else {
+            report.skipped_unsealed += 1;
+        }
+    }
+    let cold_count = sealed.len().saturating_sub(policy.hot_segments);
+
+    let target_dir = policy.cold_dir.as_deref().unwrap_or(dir);
+    fs::create_dir_all(target_dir)?;
+    let mut manifest = Manifest::load(dir)?;
+
+    for segment in &sealed[..cold_count] {
+        let mut name = segment_file_name(segment.seq);
+        if policy.compress {
+            name = format!("{}.{}", name, COMPRESSED_EXTENSION);
+        }
+        let target = target_dir.join(name);
+
+        let data = fs::read(&segment.path)?;
+        let data = if policy.compress {
+            zstd::encode_all(&data[..], policy.compression_level)?
+        } else {
+            data
+        };
+        write_atomic(&target, &data).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!(
+                    "Failed to tier segment {} to {}: {}",
+                    segment.seq,
+                    target.display(),
+                    e
+                ),
+            )
+        })?;
+
+        if policy.cold_dir.is_some() {
+            manifest.entry_mut(segment.seq).cold_path = Some(target.clone());
+            manifest.save()?;
+        }
+        fs::remove_file(&segment.path)?;
+        report.tiered.push((segment.seq, target));
+    }
+
+    Ok(report)
+}
+
+/// Every segment of the log directory `dir`, across tiers, ordered by
+/// sequence number: plain and compressed files in `dir`, then those the
+/// MANIFEST records in a cold directory. When a segment exists in more than
+/// one place, the plain file in `dir` wins, then the compressed one.
+pub fn list_tiered_segments<P: AsRef<Path>>(dir: P) -> io::Result<Vec<SegmentInfo>> {
+    let dir = dir.as_ref();
+    let mut segments = BTreeMap::new();
+
+    for (seq, entry) in Manifest::load(dir)?.entries() {
+        if let Some(path) = &entry.cold_path
+            && path.exists()
+        {
+            segments.insert(seq, path.clone());
+        }
+    }
+
+    let suffix = format!(".{}.{}", SEGMENT_EXTENSION, COMPRESSED_EXTENSION);
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let seq = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .and_then(|n| n.strip_suffix(&suffix))
+            .and_then(|s| s.parse::<u64>().ok());
+        if let Some(seq) = seq {
+            segments.insert(seq, path);
+        }
+    }
+
+    for segment in list_segments(dir)? {
+        segments.insert(segment.seq, segment.path);
+    }
+
+    Ok(segments
+        .into_iter()
+        .map(|(seq, path)| SegmentInfo { seq, path })
+        .collect())
+}
+
+/// Opens a segment in any tier, decompressing `.zst` segments into an
+/// anonymous in-memory file.
+pub fn open_tiered_segment<P: AsRef<Path>>(path: P) -> io::Result<MmapReader> {
+    let path = path.as_ref();
+    if path.extension().and_then(|e| e.to_str()) != Some(COMPRESSED_EXTENSION) {
+        return MmapReader::open(path);
+    }
+
+    let mut file = sys::anonymous_file("ringlog-segment")?;
+    zstd::stream::copy_decode(File::open(path)?, &mut file).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("Failed to decompress {}: {}", path.display(), e),
+        )
+    })?;
+    MmapReader::from_file(file)
+}
+
+fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut tmp_name = path.as_os_str().to_owned();
+    tmp_name.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_name);
+
+    let result = (|| {
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result
+}
@@ -0,0 +1,228 @@
+use super::{FileHeader, MmapReader, SegmentFooter};
+use crate::event::crc32;
+use std::io;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyIssue {
+    WriteOffsetOutOfBounds {
+        write_offset: u64,
+        file_len: usize,
+    },
+    Framing {
+        offset: usize,
+        reason: String,
+    },
+    ChecksumMismatch {
+        offset: usize,
+        expected: u32,
+        actual: u32,
+    },
+    TimestampRegression {
+        offset: usize,
+        previous: u64,
+        current: u64,
+    },
+    EventCountMismatch {
+        declared: u64,
+        actual: u64,
+    },
+    SegmentChecksumMismatch {
+        expected: u32,
+        actual: u32,
+    },
+    MissingFooter {
+        reason: String,
+    },
+    FooterMismatch {
+        field: &'static str,
+        footer: u64,
+        header: u64,
+    },
+    SegmentHashMismatch {
+        expected: u64,
+        actual: u64,
+    },
+    /// The footer predates Merkle roots.
+    MissingMerkleRoot,
+    MerkleRootMismatch {
+        expected: [u8; 32],
+        actual: [u8; 32],
+    },
+    /// `verify_signed_seal` on a segment sealed without a signing key.
+    Unsigned,
+    SignatureInvalid,
+    /// `verify_chain` on a file not written with `WriterOptions::hash_chain`.
+    NotHashChained,
+    /// The event's chain value does not follow from its predecessor: it, or
+    /// something before it, was inserted, removed or changed.
+    ChainBroken {
+        offset: usize,
+        index: u64,
+    },
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct VerifyReport {
+    pub declared_event_count: u64,
+    pub events_scanned: u64,
+    pub bytes_scanned: u64,
+    pub checksums_verified: u64,
+    pub issues: Vec<VerifyIssue>,
+}
+
+impl VerifyReport {
+    #[inline]
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl MmapReader {
+    pub fn verify(&self) -> VerifyReport {
+        let file_header = self.file_header();
+        let mut report = VerifyReport {
+            declared_event_count: file_header.event_count,
+            ..Default::default()
+        };
+
+        let write_offset = file_header.write_offset;
+        if write_offset < file_header.data_start() as u64 || write_offset > self.mapped_len() as u64
+        {
+            report.issues.push(VerifyIssue::WriteOffsetOutOfBounds {
+                write_offset,
+                file_len: self.mapped_len(),
+            });
+        }
+
+        if file_header.is_sealed() && report.issues.is_empty() {
+            let actual = crc32(&self.mapped_bytes()[FileHeader::SIZE..write_offset as usize]);
+            if actual != file_header.checksum {
+                report.issues.push(VerifyIssue::SegmentChecksumMismatch {
+                    expected: file_header.checksum,
+                    actual,
+                });
+            }
+        }
+
+        let mut frames = self.frames();
+        let mut last_timestamp = None;
+
+        loop {
+            let offset = frames.offset();
+            let frame = match frames.next() {
+                Some(Ok(frame)) => frame,
+                Some(Err(e)) => {
+                    report.issues.push(VerifyIssue::Framing {
+                        offset,
+                        reason: e.to_string(),
+                    });
+                    break;
+                }
+                None => break,
+            };
+            let payload = &self.mapped_bytes()[frame.payload.clone()];
+            let header = frame.header;
+
+            let checksum = match frame.compact {
+                Some(compact) => Some((compact.crc as u32, compact.compute_crc(&[payload]) as u32)),
+                None => header.checksum().map(|expected| (expected, crc32(payload))),
+            };
+            if let Some((expected, actual)) = checksum {
+                if actual == expected {
+                    report.checksums_verified += 1;
+                } else {
+                    report.issues.push(VerifyIssue::ChecksumMismatch {
+                        offset: frame.offset,
+                        expected,
+                        actual,
+                    });
+                }
+            }
+
+            let timestamp = header.timestamp;
+            if let Some(previous) = last_timestamp
+                && timestamp < previous
+            {
+                report.issues.push(VerifyIssue::TimestampRegression {
+                    offset: frame.offset,
+                    previous,
+                    current: timestamp,
+                });
+            }
+            last_timestamp = Some(timestamp);
+
+            report.events_scanned += 1;
+            report.bytes_scanned += (frame.end - offset) as u64;
+        }
+
+        if report.events_scanned != report.declared_event_count {
+            report.issues.push(VerifyIssue::EventCountMismatch {
+                declared: report.declared_event_count,
+                actual: report.events_scanned,
+            });
+        }
+
+        report
+    }
+
+    /// Checks a sealed segment against its `SegmentFooter`: the footer must
+    /// agree with the file header and its xxh3 hash with the event region.
+    /// Events are not framed and no CRC runs, so this is much cheaper than
+    /// `verify`. Unsealed files have no footer and fail with `MissingFooter`.
+    pub fn quick_verify(&self) -> Result<(), VerifyIssue> {
+        self.quick_verify_footer().map(|_| ())
+    }
+
+    /// `quick_verify`, returning the checked footer.
+    pub(crate) fn quick_verify_footer(&self) -> Result<SegmentFooter, VerifyIssue> {
+        let header = self.file_header();
+        let data_end = header.write_offset as usize;
+        let footer = self
+            .mapped_bytes()
+            .get(data_end..)
+            .ok_or_else(|| "write_offset is past the end of the file".to_string())
+            .and_then(|bytes| SegmentFooter::decode(bytes).map_err(|e| e.to_string()))
+            .map_err(|reason| VerifyIssue::MissingFooter { reason })?;
+
+        for (field, footer, header) in [
+            ("data_end", footer.data_end, header.write_offset),
+            ("event_count", footer.event_count, header.event_count),
+        ] {
+            if footer != header {
+                return Err(VerifyIssue::FooterMismatch {
+                    field,
+                    footer,
+                    header,
+                });
+            }
+        }
+
+        let actual = SegmentFooter::hash(&self.mapped_bytes()[FileHeader::SIZE..data_end]);
+        if actual != footer.hash {
+            return Err(VerifyIssue::SegmentHashMismatch {
+                expected: footer.hash,
+                actual,
+            });
+        }
+        Ok(footer)
+    }
+
+    pub fn open_verified<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let reader = Self::open(path)?;
+        let report = reader.verify();
+
+        if let Some(issue) = report.issues.first() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Verification failed with {} issue(s), first: {:?}",
+                    report.issues.len(),
+                    issue
+                ),
+            ));
+        }
+
+        Ok(reader)
+    }
+}
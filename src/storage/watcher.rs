@@ -0,0 +1,244 @@
+use super::FileHeader;
+use super::segment::list_segments;
+use std::collections::{BTreeSet, HashMap};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WatchEvent {
+    NewEvents { path: PathBuf, write_offset: u64 },
+    NewSegment { path: PathBuf },
+}
+
+/// Notifies a reader when a watched log file gains events or a watched
+/// directory gains segments.
+///
+/// Writers append through a shared mapping, which inotify does not report, so
+/// change detection always compares the on-disk `write_offset` and segment
+/// listing. inotify, when available, only shortens the wait between checks.
+pub struct Watcher {
+    files: HashMap<PathBuf, u64>,
+    dirs: HashMap<PathBuf, BTreeSet<PathBuf>>,
+    poll_interval: Duration,
+    inotify: Option<Inotify>,
+}
+
+impl Watcher {
+    pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+    pub fn new() -> Self {
+        Self {
+            inotify: Inotify::new().ok(),
+            ..Self::polling(Self::DEFAULT_POLL_INTERVAL)
+        }
+    }
+
+    pub fn polling(poll_interval: Duration) -> Self {
+        Self {
+            files: HashMap::new(),
+            dirs: HashMap::new(),
+            poll_interval,
+            inotify: None,
+        }
+    }
+
+    #[inline]
+    pub fn uses_inotify(&self) -> bool {
+        self.inotify.is_some()
+    }
+
+    /// Watches a log file for new events or a directory for new segments.
+    pub fn watch<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        let path = path.as_ref().to_path_buf();
+
+        if path.is_dir() {
+            let known = list_segments(&path)?.into_iter().map(|s| s.path).collect();
+            if let Some(inotify) = &self.inotify {
+                inotify.add_watch(&path, true)?;
+            }
+            self.dirs.insert(path, known);
+        } else {
+            let write_offset = read_write_offset(&path)?;
+            if let Some(inotify) = &self.inotify {
+                inotify.add_watch(&path, false)?;
+            }
+            self.files.insert(path, write_offset);
+        }
+
+        Ok(())
+    }
+
+    /// Blocks until something changes or `timeout` elapses, returning the
+    /// changes observed (empty on timeout). `None` waits indefinitely.
+    pub fn wait(&mut self, timeout: Option<Duration>) -> io::Result<Vec<WatchEvent>> {
+        let deadline = timeout.map(|t| Instant::now() + t);
+
+        loop {
+            let events = self.check()?;
+            if !events.is_empty() {
+                return Ok(events);
+            }
+
+            let mut sleep = self.poll_interval;
+            if let Some(deadline) = deadline {
+                let now = Instant::now();
+                if now >= deadline {
+                    return Ok(events);
+                }
+                sleep = sleep.min(deadline - now);
+            }
+
+            match &self.inotify {
+                Some(inotify) => inotify.wait(sleep)?,
+                None => std::thread::sleep(sleep),
+            }
+        }
+    }
+
+    fn check(&mut self) -> io::Result<Vec<WatchEvent>> {
+        let mut events = Vec::new();
+
+        for (path, last) in &mut self.files {
+            let write_offset = match read_write_offset(path) {
+                Ok(offset) => offset,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+            if write_offset != *last {
+                *last = write_offset;
+                events.push(WatchEvent::NewEvents {
+                    path: path.clone(),
+                    write_offset,
+                });
+            }
+        }
+
+        for (dir, known) in &mut self.dirs {
+            for segment in list_segments(dir)? {
+                if known.insert(segment.path.clone()) {
+                    events.push(WatchEvent::NewSegment { path: segment.path });
+                }
+            }
+        }
+
+        Ok(events)
+    }
+}
+
+impl Default for Watcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn read_write_offset(path: &Path) -> io::Result<u64> {
+    let mut buf = [0u8; FileHeader::SIZE];
+    File::open(path)?.read_exact(&mut buf)?;
+    let header = FileHeader::decode(&buf);
+
+    if !header.validate() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid file header in {}", path.display()),
+        ));
+    }
+
+    Ok(header.write_offset)
+}
+
+#[cfg(target_os = "linux")]
+struct Inotify {
+    fd: libc::c_int,
+}
+
+#[cfg(target_os = "linux")]
+impl Inotify {
+    fn new() -> io::Result<Self> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { fd })
+    }
+
+    fn add_watch(&self, path: &Path, is_dir: bool) -> io::Result<()> {
+        use std::os::unix::ffi::OsStrExt;
+
+        let c_path = std::ffi::CString::new(path.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let mask = if is_dir {
+            libc::IN_CREATE | libc::IN_MOVED_TO | libc::IN_CLOSE_WRITE
+        } else {
+            libc::IN_MODIFY | libc::IN_CLOSE_WRITE | libc::IN_ATTRIB
+        };
+
+        let wd = unsafe { libc::inotify_add_watch(self.fd, c_path.as_ptr(), mask) };
+        if wd < 0 {
+            let err = io::Error::last_os_error();
+            return Err(io::Error::new(
+                err.kind(),
+                format!("inotify watch failed for {}: {}", path.display(), err),
+            ));
+        }
+        Ok(())
+    }
+
+    fn wait(&self, timeout: Duration) -> io::Result<()> {
+        let mut pfd = libc::pollfd {
+            fd: self.fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout_ms = timeout.as_millis().min(i32::MAX as u128) as libc::c_int;
+
+        let result = unsafe { libc::poll(&mut pfd, 1, timeout_ms) };
+        if result < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                return Ok(());
+            }
+            return Err(err);
+        }
+
+        // The notification contents are irrelevant; drain them so the next
+        // poll blocks until something new happens.
+        let mut buf = [0u8; 4096];
+        while unsafe { libc::read(self.fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) } > 0 {
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for Inotify {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+struct Inotify;
+
+#[cfg(not(target_os = "linux"))]
+impl Inotify {
+    fn new() -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "inotify is only available on Linux",
+        ))
+    }
+
+    fn add_watch(&self, _path: &Path, _is_dir: bool) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn wait(&self, timeout: Duration) -> io::Result<()> {
+        std::thread::sleep(timeout);
+        Ok(())
+    }
+}
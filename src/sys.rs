@@ -0,0 +1,112 @@
+use std::io;
+
+/// Locks `len` bytes at `ptr` into RAM. On failure the error reports the
+/// soft and hard `RLIMIT_MEMLOCK`; raising the limit is left to the process
+/// (`ulimit -l`, systemd's `LimitMEMLOCK=`), as it applies process-wide.
+pub(crate) fn mlock(ptr: *const u8, len: usize) -> io::Result<()> {
+    if unsafe { libc::mlock(ptr as *const libc::c_void, len) } == 0 {
+        return Ok(());
+    }
+
+    let err = io::Error::last_os_error();
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    if unsafe { libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut limit) } != 0 {
+        return Err(err);
+    }
+
+    Err(io::Error::new(
+        err.kind(),
+        format!(
+            "mlock of {} bytes failed: {} (RLIMIT_MEMLOCK soft={} hard={})",
+            len,
+            err,
+            format_rlimit(limit.rlim_cur),
+            format_rlimit(limit.rlim_max)
+        ),
+    ))
+}
+
+pub(crate) fn munlock(ptr: *const u8, len: usize) -> io::Result<()> {
+    if unsafe { libc::munlock(ptr as *const libc::c_void, len) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// A read-write file with no name, freed once closed.
+#[cfg(target_os = "linux")]
+#[cfg(feature = "tiering")]
+pub(crate) fn anonymous_file(name: &str) -> io::Result<std::fs::File> {
+    use std::os::unix::io::FromRawFd;
+
+    let name =
+        std::ffi::CString::new(name).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let fd = unsafe { libc::memfd_create(name.as_ptr(), libc::MFD_CLOEXEC) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { std::fs::File::from_raw_fd(fd) })
+}
+
+#[cfg(not(target_os = "linux"))]
+#[cfg(feature = "tiering")]
+pub(crate) fn anonymous_file(name: &str) -> io::Result<std::fs::File> {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    let id = NEXT.fetch_add(1, Ordering::Relaxed);
+    let path = std::env::temp_dir().join(format!("{}.{}.{}", name, std::process::id(), id));
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    std::fs::remove_file(&path)?;
+    Ok(file)
+}
+
+/// Sleeps while `*word == expected`, for at most `timeout`, or until woken.
+/// Uses the shared futex ops, so `word` may live in memory mapped by several
+/// processes.
+#[cfg(target_os = "linux")]
+pub(crate) fn futex_wait(
+    word: &std::sync::atomic::AtomicU32,
+    expected: u32,
+    timeout: Option<std::time::Duration>,
+) {
+    let timespec = timeout.map(|timeout| libc::timespec {
+        tv_sec: timeout.as_secs().min(libc::time_t::MAX as u64) as libc::time_t,
+        tv_nsec: timeout.subsec_nanos() as libc::c_long,
+    });
+    unsafe {
+        libc::syscall(
+            libc::SYS_futex,
+            word.as_ptr(),
+            libc::FUTEX_WAIT,
+            expected,
+            timespec
+                .as_ref()
+                .map_or(std::ptr::null(), |t| t as *const libc::timespec),
+        );
+    }
+}
+
+/// Wakes every thread sleeping in `futex_wait` on `word`, in any process.
+#[cfg(target_os = "linux")]
+pub(crate) fn futex_wake(word: &std::sync::atomic::AtomicU32) {
+    unsafe {
+        libc::syscall(libc::SYS_futex, word.as_ptr(), libc::FUTEX_WAKE, i32::MAX);
+    }
+}
+
+fn format_rlimit(value: libc::rlim_t) -> String {
+    if value == libc::RLIM_INFINITY {
+        "unlimited".to_string()
+    } else {
+        value.to_string()
+    }
+}
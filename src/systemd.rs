@@ -0,0 +1,99 @@
+//! Supervision by systemd: readiness and watchdog notifications over
+//! `$NOTIFY_SOCKET`, and sockets passed in by socket activation. Every call
+//! is a no-op when the process was not started by systemd.
+
+use sd_notify::NotifyState;
+use std::io;
+use std::os::fd::FromRawFd;
+use std::os::unix::net::UnixListener;
+use std::time::{Duration, Instant};
+
+/// Tells systemd that startup finished; `Type=notify` units wait for this.
+pub fn notify_ready() -> io::Result<()> {
+    sd_notify::notify(false, &[NotifyState::Ready])
+}
+
+/// Tells systemd that shutdown has begun.
+pub fn notify_stopping() -> io::Result<()> {
+    sd_notify::notify(false, &[NotifyState::Stopping])
+}
+
+/// Free-form status line shown by `systemctl status`.
+pub fn notify_status(status: &str) -> io::Result<()> {
+    sd_notify::notify(false, &[NotifyState::Status(status)])
+}
+
+/// Keep-alive pings for units with `WatchdogSec=` set.
+#[derive(Debug)]
+pub struct Watchdog {
+    interval: Duration,
+    last: Option<Instant>,
+}
+
+impl Watchdog {
+    /// `None` unless systemd enabled the watchdog for this process. Pings
+    /// are sent at half the configured timeout.
+    pub fn from_env() -> Option<Self> {
+        let mut usec = 0;
+        if !sd_notify::watchdog_enabled(false, &mut usec) {
+            return None;
+        }
+        Some(Self {
+            interval: Duration::from_micros(usec) / 2,
+            last: None,
+        })
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Pings systemd if an interval has passed since the last ping. Call
+    /// from the service loop, so a stalled loop gets the service restarted.
+    pub fn tick(&mut self) -> io::Result<()> {
+        if self.last.is_some_and(|last| last.elapsed() < self.interval) {
+            return Ok(());
+        }
+        sd_notify::notify(false, &[NotifyState::Watchdog])?;
+        self.last = Some(Instant::now());
+        Ok(())
+    }
+}
+
+/// The first socket passed by systemd socket activation, as a Unix stream
+/// listener. `None` when no sockets were passed; an error when the socket
+/// is of another kind.
+pub fn activated_unix_listener() -> io::Result<Option<UnixListener>> {
+    let Some(fd) = sd_notify::listen_fds()?.next() else {
+        return Ok(None);
+    };
+
+    let mut addr: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of_val(&addr) as libc::socklen_t;
+    let mut kind: libc::c_int = 0;
+    let mut kind_len = std::mem::size_of_val(&kind) as libc::socklen_t;
+    let result = unsafe {
+        if libc::getsockname(fd, &mut addr as *mut _ as *mut libc::sockaddr, &mut len) != 0 {
+            -1
+        } else {
+            libc::getsockopt(
+                fd,
+                libc::SOL_SOCKET,
+                libc::SO_TYPE,
+                &mut kind as *mut _ as *mut libc::c_void,
+                &mut kind_len,
+            )
+        }
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    if addr.ss_family as libc::c_int != libc::AF_UNIX || kind != libc::SOCK_STREAM {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Socket-activated fd {} is not a Unix stream socket", fd),
+        ));
+    }
+
+    Ok(Some(unsafe { UnixListener::from_raw_fd(fd) }))
+}
@@ -0,0 +1,180 @@
+//! Broadcasts a log file's new events to WebSocket clients as JSON, one text
+//! message per event, so dashboards can show the live feed without polling.
+//!
+//! Clients may restrict the feed to some event types with a query string,
+//! e.g. `ws://host:port/?types=1,7`. Each message looks like
+//!
+//! ```json
+//! {"timestamp":1700000000000000000,"event_type":7,"stream":0,"sequence":null,"payload":"0a0b"}
+//! ```
+//!
+//! with the payload body hex-encoded.
+
+use crate::event::EventView;
+use crate::storage::TailReader;
+use serde::Serialize;
+use std::fmt::Write as _;
+use std::io;
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+use tungstenite::handshake::server::{Request, Response};
+use tungstenite::{Message, WebSocket};
+
+#[derive(Debug, Serialize)]
+pub struct LiveEvent {
+    pub timestamp: u64,
+    pub event_type: u8,
+    pub stream: u8,
+    pub sequence: Option<u64>,
+    pub payload: String,
+}
+
+impl LiveEvent {
+    pub fn from_view(event: &EventView) -> Self {
+        let header = &event.header;
+        let mut payload = String::with_capacity(event.payload.len() * 2);
+        for byte in header.body(event.payload) {
+            let _ = write!(payload, "{:02x}", byte);
+        }
+        Self {
+            timestamp: header.timestamp,
+            event_type: header.event_type,
+            stream: header.stream().get(),
+            sequence: header.split_sequence(event.payload).0,
+            payload,
+        }
+    }
+}
+
+/// Tails a log file and streams the events appended after each client
+/// connects.
+#[derive(Debug, Clone)]
+pub struct LiveFeed {
+    path: PathBuf,
+    heartbeat_interval: Duration,
+}
+
+impl LiveFeed {
+    pub const DEFAULT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            heartbeat_interval: Self::DEFAULT_HEARTBEAT_INTERVAL,
+        }
+    }
+
+    /// How long the log may stay idle before clients are pinged. Client
+    /// messages, such as a close, are read after every batch and ping.
+    pub fn with_heartbeat_interval(mut self, interval: Duration) -> Self {
+        self.heartbeat_interval = interval;
+        self
+    }
+
+    /// Accepts clients until the listener fails, serving each on its own
+    /// thread.
+    pub fn serve(&self, listener: TcpListener) -> io::Result<()> {
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let feed = self.clone();
+            thread::spawn(move || feed.serve_client(stream));
+        }
+        Ok(())
+    }
+
+    /// Streams events to one client until it disconnects or the log is
+    /// sealed. Returns the number of events sent.
+    // The handshake callback's error type is fixed by tungstenite.
+    #[allow(clippy::result_large_err)]
+    pub fn serve_client(&self, stream: TcpStream) -> io::Result<u64> {
+        stream.set_nodelay(true)?;
+        let mut types = None;
+        let mut socket = tungstenite::accept_hdr(stream, |req: &Request, resp: Response| {
+            types = parse_types(req.uri().query());
+            Ok(resp)
+        })
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+
+        // Client messages are drained between batches; a short timeout keeps
+        // that from blocking the feed.
+        socket
+            .get_ref()
+            .set_read_timeout(Some(Duration::from_millis(1)))?;
+
+        let mut tail = TailReader::open_at_end(&self.path)?;
+        let mut sent = 0;
+
+        loop {
+            let batch = tail.read_new(|event| {
+                if types
+                    .as_ref()
+                    .is_some_and(|t: &Vec<u8>| !t.contains(&event.header.event_type))
+                {
+                    return Ok(());
+                }
+                let json = serde_json::to_string(&LiveEvent::from_view(&event))?;
+                socket.write(Message::text(json)).map_err(into_io)?;
+                sent += 1;
+                Ok(())
+            })?;
+
+            if tail.is_sealed() {
+                socket.close(None).map_err(into_io)?;
+                let _ = socket.flush();
+                return Ok(sent);
+            }
+
+            if batch > 0 {
+                socket.flush().map_err(into_io)?;
+            } else if !tail.wait(self.heartbeat_interval)? {
+                socket
+                    .send(Message::Ping(Default::default()))
+                    .map_err(into_io)?;
+            }
+            // After every batch, so a busy feed still sees Close and Pong.
+            if !drain_client_messages(&mut socket)? {
+                return Ok(sent);
+            }
+        }
+    }
+}
+
+/// Reads whatever the client has sent. `false` once it has closed.
+fn drain_client_messages(socket: &mut WebSocket<TcpStream>) -> io::Result<bool> {
+    loop {
+        match socket.read() {
+            Ok(Message::Close(_)) => {
+                let _ = socket.flush();
+                return Ok(false);
+            }
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(e))
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                return Ok(true);
+            }
+            Err(tungstenite::Error::ConnectionClosed) => return Ok(false),
+            Err(e) => return Err(into_io(e)),
+        }
+    }
+}
+
+/// `types=1,7` from a query string. Unparseable entries are ignored.
+fn parse_types(query: Option<&str>) -> Option<Vec<u8>> {
+    let list = query?
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("types="))?;
+    Some(list.split(',').filter_map(|t| t.parse().ok()).collect())
+}
+
+fn into_io(e: tungstenite::Error) -> io::Error {
+    match e {
+        tungstenite::Error::Io(e) => e,
+        e => io::Error::other(e),
+    }
+}